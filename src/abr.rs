@@ -0,0 +1,257 @@
+use std::ffi::OsStr;
+use std::fmt::Write as _;
+use std::fs::create_dir_all;
+use std::path::Path;
+use std::process::Command;
+
+use clap::ValueEnum;
+use serde::Deserialize;
+use tracing::info_span;
+
+use crate::codecs::{Bitrate, Container};
+use crate::progress::Progress;
+use crate::{
+    command_output, console, logging, run_ffmpeg, segmented, AbrOptions, EncodeOptions, HlsOptions, HlsSegmentType,
+    VideoConversionError,
+};
+
+/// Heights of the renditions produced when none are requested
+pub const DEFAULT_LADDER: &str = "1080p,720p,480p";
+
+/// Manifest formats for adaptive streaming
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum, Debug)]
+pub enum ManifestFormat {
+    /// HLS master playlist (master.m3u8) over one media playlist per rendition
+    Hls,
+    /// MPEG-DASH manifest (manifest.mpd) over fragmented MP4 segments
+    Dash,
+    /// Both manifests over the same fragmented MP4 segments, for Safari and dash.js alike
+    Both,
+}
+
+impl ManifestFormat {
+    /// File name of the manifest players open; with both manifests, the HLS one
+    pub fn file_name(&self) -> &'static str {
+        match self {
+            ManifestFormat::Hls | ManifestFormat::Both => "master.m3u8",
+            ManifestFormat::Dash => "manifest.mpd",
+        }
+    }
+}
+
+/// Rendition heights, highest first, written like `1080p,720p,480p`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Ladder(Vec<u32>);
+
+impl std::str::FromStr for Ladder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut heights = s
+            .split(',')
+            .map(|part| {
+                let part = part.trim();
+                part.trim_end_matches(['p', 'P'])
+                    .parse::<u32>()
+                    .ok()
+                    .filter(|h| *h >= 16 && h % 2 == 0)
+                    .ok_or_else(|| format!("invalid rendition {:?} (use even heights such as 720p)", part))
+            })
+            .collect::<Result<Vec<u32>, String>>()?;
+        heights.sort_unstable_by(|a, b| b.cmp(a));
+        heights.dedup();
+        Ok(Ladder(heights))
+    }
+}
+
+/// Peak video bitrate allowed for a rendition, which players read as its bandwidth
+fn max_bitrate(height: u32) -> Bitrate {
+    let bits = match height {
+        2160.. => 16_000_000,
+        1440.. => 9_000_000,
+        1080.. => 5_000_000,
+        720.. => 2_800_000,
+        480.. => 1_400_000,
+        360.. => 800_000,
+        _ => 400_000,
+    };
+    Bitrate::from_bits_per_second(bits)
+}
+
+#[derive(Deserialize, Default)]
+struct ProbeOutput {
+    #[serde(default)]
+    streams: Vec<ProbeStream>,
+}
+
+#[derive(Deserialize)]
+struct ProbeStream {
+    codec_type: Option<String>,
+    height: Option<u32>,
+}
+
+/// Height of the first video stream and whether there is audio
+fn probe_source(input: &str) -> Result<(Option<u32>, bool), VideoConversionError> {
+    let output = command_output(
+        Command::new("ffprobe")
+            .args(["-v", "error", "-show_entries", "stream=codec_type,height", "-of", "json"])
+            .arg(input)
+            .stderr(logging::child_stderr()),
+    )?;
+    if !output.status.success() {
+        return Err(VideoConversionError::encoding(format!("ffprobe cannot read {}", input)));
+    }
+    let parsed: ProbeOutput = serde_json::from_slice(&output.stdout)
+        .map_err(|e| VideoConversionError::io("unexpected ffprobe output").caused_by(e))?;
+    let height = parsed.streams.iter().find(|s| s.codec_type.as_deref() == Some("video")).and_then(|s| s.height);
+    let audio = parsed.streams.iter().any(|s| s.codec_type.as_deref() == Some("audio"));
+    Ok((height, audio))
+}
+
+/// Renditions worth encoding for a source of `source_height`: upscaling only wastes bits, so
+/// taller ones are dropped, keeping the source's own height when nothing else is left
+fn renditions(ladder: &Ladder, source_height: Option<u32>) -> Vec<u32> {
+    let Some(source) = source_height else { return ladder.0.clone() };
+    let mut heights: Vec<u32> = ladder.0.iter().copied().filter(|h| *h <= source).collect();
+    if heights.is_empty() {
+        heights.push(source - source % 2);
+    }
+    heights
+}
+
+/// Re-encode `input` into every rendition of the ladder with a single decode, writing the
+/// manifest at `manifest` and the renditions' segments next to it
+pub fn package(
+    input: &str,
+    manifest: &str,
+    encode: &EncodeOptions,
+    hls: &HlsOptions,
+    abr: &AbrOptions,
+    progress: &Progress,
+) -> Result<(), VideoConversionError> {
+    let _span = info_span!("package", input = input, output = manifest).entered();
+    let dir = Path::new(manifest).parent().unwrap_or(Path::new("."));
+    create_dir_all(dir)
+        .map_err(|e| VideoConversionError::io(format!("Failed to create {}", dir.display())).caused_by(e))?;
+
+    // Media playlists sit one directory below the key
+    let key_info = crate::hls::key_info(dir, hls, &format!("../{}", crate::hls::KEY_NAME))?;
+    let (source_height, has_audio) = probe_source(input)?;
+    // Devices limited to smaller frames count as a source of that height
+    let limit = match (source_height, encode.max_height()) {
+        (Some(source), Some(max)) => Some(source.min(max)),
+        (source, max) => source.or(max),
+    };
+    let heights = renditions(&abr.renditions, limit);
+    let names: Vec<String> = heights.iter().map(|h| format!("{}p", h)).collect();
+    let seconds = hls.hls_segment_duration;
+    println!("Re-encoding video into {} renditions ({})...", heights.len(), names.join(", "));
+
+    // Decode once and split the frames between the scalers
+    let mut graph = format!("[0:v]split={}", heights.len());
+    for index in 0..heights.len() {
+        let _ = write!(graph, "[v{}]", index);
+    }
+    for (index, height) in heights.iter().enumerate() {
+        let _ = write!(graph, ";[v{}]scale=-2:{}[out{}]", index, height, index);
+    }
+
+    // ffmpeg runs inside the output directory: the variant patterns expand `%` sequences, which
+    // could otherwise clash with the video's name
+    let input_path = std::fs::canonicalize(input)
+        .map_err(|e| VideoConversionError::io(format!("Failed to resolve {}", input)).caused_by(e))?;
+    let mut command = encode.ffmpeg_command();
+    command
+        .current_dir(dir)
+        .args(["-progress", "pipe:1", "-nostats"])
+        .arg("-i")
+        .arg(input_path)
+        .arg("-filter_complex")
+        .arg(graph);
+    for (index, height) in heights.iter().enumerate() {
+        let peak = max_bitrate(*height);
+        command
+            .arg("-map")
+            .arg(format!("[out{}]", index))
+            .arg(format!("-maxrate:v:{}", index))
+            .arg(peak.to_string())
+            .arg(format!("-bufsize:v:{}", index))
+            .arg(Bitrate::from_bits_per_second(peak.bits_per_second() * 2).to_string());
+    }
+    if has_audio {
+        command.args(["-map", "0:a:0"]).args(encode.audio_args(Container::Abr));
+    }
+    command
+        .args(encode.video_args())
+        // Renditions must switch at the same instants, so all of them get keyframes at every boundary
+        .arg("-force_key_frames")
+        .arg(format!("expr:gte(t,n_forced*{})", seconds));
+    let fragmented = abr.abr_manifest != ManifestFormat::Hls || hls.hls_segment_type == HlsSegmentType::Fmp4;
+    if fragmented {
+        command.args(encode.video_codec().mp4_tag().map(|tag| ["-tag:v", tag]).into_iter().flatten());
+    }
+    if let Some(threads) = encode.threads {
+        command.arg("-threads").arg(threads.to_string());
+    }
+
+    match abr.abr_manifest {
+        ManifestFormat::Hls => {
+            // Every video rendition plays with the one audio rendition
+            let mut stream_map: Vec<String> = (0..heights.len())
+                .map(|index| match has_audio {
+                    true => format!("v:{},agroup:audio,name:{}", index, names[index]),
+                    false => format!("v:{},name:{}", index, names[index]),
+                })
+                .collect();
+            if has_audio {
+                stream_map.push("a:0,agroup:audio,name:audio,default:yes".to_string());
+            }
+            let extension = hls.hls_segment_type.extension();
+            command
+                .args(["-f", "hls", "-hls_playlist_type", "vod", "-hls_time"])
+                .arg(seconds.to_string())
+                .arg("-hls_segment_type")
+                .arg(hls.hls_segment_type.as_str())
+                .arg("-hls_segment_filename")
+                .arg(format!("%v/segment_%05d.{}", extension))
+                .arg("-master_pl_name")
+                .arg(ManifestFormat::Hls.file_name())
+                .arg("-var_stream_map")
+                .arg(stream_map.join(" "))
+                .args(key_info.iter().flat_map(|key_info| [OsStr::new("-hls_key_info_file"), key_info.as_os_str()]))
+                .args(encode.ffmpeg_arg.iter().flatten())
+                .arg("%v/index.m3u8");
+        }
+        ManifestFormat::Dash | ManifestFormat::Both => {
+            let adaptation_sets = match has_audio {
+                true => "id=0,streams=v id=1,streams=a",
+                false => "id=0,streams=v",
+            };
+            command
+                .args(["-f", "dash", "-seg_duration"])
+                .arg(seconds.to_string())
+                .args(["-use_template", "1", "-use_timeline", "1", "-adaptation_sets", adaptation_sets]);
+            if abr.abr_manifest == ManifestFormat::Both {
+                // The DASH muxer writes HLS media playlists over its own segments and a master playlist over them
+                command.args(["-hls_playlist", "1", "-hls_master_name", ManifestFormat::Hls.file_name()]);
+            }
+            command.args(encode.ffmpeg_arg.iter().flatten()).arg(ManifestFormat::Dash.file_name());
+        }
+    }
+    let result = run_ffmpeg(&mut command, segmented::probe_duration(input), progress);
+    if let Some(key_info) = &key_info {
+        let _ = std::fs::remove_file(key_info);
+    }
+    result.map_err(VideoConversionError::conversion)?;
+
+    match abr.abr_manifest {
+        ManifestFormat::Both => console!(
+            Success,
+            "Adaptive streaming manifests written: {} and {}",
+            manifest,
+            dir.join(ManifestFormat::Dash.file_name()).display()
+        ),
+        _ => console!(Success, "Adaptive streaming manifest written: {}", manifest),
+    }
+    Ok(())
+}
@@ -0,0 +1,246 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use serde::Deserialize;
+use tracing::{debug, info, info_span};
+
+use crate::analyze;
+use crate::codecs::{AudioCodec, Container};
+use crate::filtergraph::{FilterChain, Volume};
+use crate::playlist;
+use crate::progress::Progress;
+use crate::replaygain;
+use crate::segmented;
+use crate::thumbnail;
+use crate::{command_output, console, logging, run_command, DownloadOptions, VideoConversionError};
+
+/// Highest true peak the album gain may raise a track to, in dBTP
+const PEAK_CEILING: f64 = -1.0;
+
+/// Gains smaller than this leave the audio untouched instead of re-encoding it, in dB
+const MIN_GAIN: f64 = 0.1;
+
+/// Turning an audio playlist into an album
+#[derive(clap::Args, Debug, Clone)]
+pub struct AlbumOptions {
+    /// Treat the downloaded MP3s as one album: number them in playlist order, give them a shared
+    /// album name, album artist and cover, and bring the album to one loudness
+    #[arg(long)]
+    album: bool,
+
+    /// Album name (default: the playlist's title)
+    #[arg(long, value_name = "NAME", requires = "album")]
+    album_name: Option<String>,
+
+    /// Album artist (default: the artist most tracks share, or "Various Artists")
+    #[arg(long, value_name = "NAME", requires = "album")]
+    album_artist: Option<String>,
+
+    /// Integrated loudness of the whole album in LUFS; every track gets the same gain, so quiet
+    /// songs stay quieter than loud ones
+    #[arg(long, value_name = "LUFS", default_value_t = -14.0, allow_negative_numbers = true, requires = "album")]
+    album_loudness: f64,
+}
+
+impl AlbumOptions {
+    /// Whether the downloads make up an album
+    pub fn enabled(&self) -> bool {
+        self.album
+    }
+}
+
+/// A downloaded track of the album
+#[derive(Debug, Clone)]
+pub(crate) struct Track {
+    /// Position in the download queue, which follows the playlist
+    pub position: usize,
+    pub url: String,
+    pub output: String,
+}
+
+#[derive(Deserialize, Default)]
+struct ProbeOutput {
+    format: Option<ProbeFormat>,
+}
+
+#[derive(Deserialize, Default)]
+struct ProbeFormat {
+    #[serde(default)]
+    tags: BTreeMap<String, String>,
+}
+
+/// Tags already in `path`, by lower-case name
+pub(crate) fn read_tags(path: &Path) -> BTreeMap<String, String> {
+    let output = command_output(
+        Command::new("ffprobe")
+            .args(["-v", "error", "-show_entries", "format_tags", "-of", "json"])
+            .arg(path)
+            .stderr(logging::child_stderr()),
+    );
+    let parsed: ProbeOutput = match output {
+        Ok(output) if output.status.success() => serde_json::from_slice(&output.stdout).unwrap_or_default(),
+        _ => ProbeOutput::default(),
+    };
+    parsed
+        .format
+        .map(|format| format.tags.into_iter().map(|(key, value)| (key.to_ascii_lowercase(), value)).collect())
+        .unwrap_or_default()
+}
+
+/// The value most of `values` share, if it is more than half of them
+fn majority<'a>(values: impl Iterator<Item = &'a str>, total: usize) -> Option<String> {
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for value in values {
+        *counts.entry(value).or_insert(0) += 1;
+    }
+    counts.into_iter().max_by_key(|(_, count)| *count).filter(|(_, count)| count * 2 > total).map(|(value, _)| value.to_string())
+}
+
+/// Title for a track without one, from its file name without the `[id]` suffix of the default template
+fn title_from_name(path: &Path) -> String {
+    let stem = path.file_stem().map_or_else(String::new, |stem| stem.to_string_lossy().into_owned());
+    match stem.rsplit_once(" [") {
+        Some((title, id)) if id.ends_with(']') => title.to_string(),
+        _ => stem,
+    }
+}
+
+/// Save the first track's thumbnail as a square `cover.jpg` in the album folder, as players look for it
+fn fetch_cover(url: &str, dir: &Path, options: &DownloadOptions) -> Result<PathBuf, VideoConversionError> {
+    let thumb_stem = dir.join("cover.thumb");
+    thumbnail::download_thumbnail(url, &thumb_stem.to_string_lossy(), "jpg", &options.ytdlp)?;
+    let thumb = thumb_stem.with_extension("thumb.jpg");
+    let cover = dir.join("cover.jpg");
+    // Video thumbnails are 16:9; covers are cut square from their centre
+    let result = run_command(
+        Command::new("ffmpeg")
+            .args(["-nostdin", "-v", "error", "-y", "-i"])
+            .arg(&thumb)
+            .args(["-vf", "crop=min(iw\\,ih):min(iw\\,ih)", "-frames:v", "1"])
+            .arg(&cover)
+            .stderr(logging::child_stderr()),
+    );
+    let _ = fs::remove_file(&thumb);
+    result.map(|()| cover)
+}
+
+/// Gain bringing the album to `target` LUFS, the same for every track and lowered where it would
+/// push a peak above the ceiling
+fn album_gain(tracks: &[&Path], target: f64, progress: &Progress) -> Result<f64, VideoConversionError> {
+    let (mut parts, mut peak) = (Vec::new(), f64::NEG_INFINITY);
+    for track in tracks {
+        let loudness = analyze::loudness(track, progress)?;
+        let duration = segmented::probe_duration(&track.to_string_lossy()).unwrap_or(1.0);
+        debug!(track = %track.display(), ?loudness, "measured");
+        parts.push((loudness.integrated_lufs, duration));
+        peak = peak.max(loudness.true_peak_dbtp);
+    }
+    let album_lufs = analyze::combined_loudness(&parts);
+    let gain = (target - album_lufs).min(PEAK_CEILING - peak);
+    info!(album_lufs, gain, "album loudness");
+    println!("Album loudness {:.1} LUFS; applying {:+.1} dB to every track", album_lufs, gain);
+    Ok(gain)
+}
+
+/// Rewrite one track with its album tags, cover and gain
+fn finish_track(
+    path: &Path,
+    tags: &[(&str, String)],
+    cover: Option<&Path>,
+    gain: f64,
+    options: &DownloadOptions,
+) -> Result<(), VideoConversionError> {
+    let temp_path = path.with_extension("album.mp3");
+    let mut command = Command::new("ffmpeg");
+    command.args(["-nostdin", "-v", "error", "-y", "-i"]).arg(path);
+    if let Some(cover) = cover {
+        command.arg("-i").arg(cover).args(["-map", "0:a:0", "-map", "1:v", "-c:v", "copy"]);
+        command.args(["-metadata:s:v", "title=Album cover", "-metadata:s:v", "comment=Cover (front)"]);
+    } else {
+        command.args(["-map", "0:a:0", "-map", "0:v?", "-c:v", "copy"]);
+    }
+    if gain.abs() < MIN_GAIN {
+        command.args(["-c:a", "copy"]);
+    } else {
+        let bitrate = options.encode.audio_bitrate(Container::Mp3).unwrap_or(AudioCodec::Mp3.default_bitrate());
+        command.arg("-af").arg(FilterChain::new().then(Volume { gain_db: gain }).to_string());
+        command.args(["-c:a", AudioCodec::Mp3.encoder(), "-b:a"]).arg(bitrate.to_string());
+    }
+    command.args(["-map_metadata", "0", "-id3v2_version", "3"]);
+    for (key, value) in tags {
+        command.arg("-metadata").arg(format!("{}={}", key, value));
+    }
+    let result = run_command(command.arg(&temp_path).stderr(logging::child_stderr())).and_then(|()| {
+        fs::rename(&temp_path, path)
+            .map_err(|e| VideoConversionError::io(format!("Failed to replace {}", path.display())).caused_by(e))
+    });
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    result
+}
+
+/// Tag the downloaded tracks of `urls` as one album with track numbers out of `total`, a shared
+/// album name, album artist and cover, and one album gain
+pub(crate) fn finish(
+    tracks: &mut [Track],
+    total: usize,
+    urls: &[String],
+    options: &DownloadOptions,
+    progress: &Progress,
+) -> Result<(), VideoConversionError> {
+    let _span = info_span!("album", tracks = tracks.len()).entered();
+    if tracks.is_empty() {
+        return Ok(());
+    }
+    tracks.sort_by_key(|track| track.position);
+    let album = &options.album;
+    let existing: Vec<BTreeMap<String, String>> = tracks.iter().map(|track| read_tags(Path::new(&track.output))).collect();
+    let name = album
+        .album_name
+        .clone()
+        .or_else(|| urls.iter().find_map(|url| playlist::title(url, &options.ytdlp)))
+        // Tracks tagged by --music-tags may already agree on an album
+        .or_else(|| majority(existing.iter().filter_map(|tags| tags.get("album").map(String::as_str)), tracks.len()));
+    let artist = album.album_artist.clone().unwrap_or_else(|| {
+        majority(existing.iter().filter_map(|tags| tags.get("artist").map(String::as_str)), tracks.len())
+            .unwrap_or_else(|| "Various Artists".to_string())
+    });
+    if name.is_none() {
+        console!(Warning, "no album name found; pass --album-name to set one");
+    }
+
+    let dir = Path::new(&tracks[0].output).parent().unwrap_or(Path::new(".")).to_path_buf();
+    let cover = fetch_cover(&tracks[0].url, &dir, options)
+        .map_err(|e| console!(Warning, "could not save the album cover: {}", e))
+        .ok();
+    let paths: Vec<&Path> = tracks.iter().map(|track| Path::new(&track.output)).collect();
+    let gain = album_gain(&paths, album.album_loudness, progress)?;
+
+    for (track, tags) in tracks.iter().zip(&existing) {
+        let path = Path::new(&track.output);
+        let mut new_tags = vec![("track", format!("{}/{}", track.position + 1, total)), ("album_artist", artist.clone())];
+        new_tags.extend(name.clone().map(|name| ("album", name)));
+        if !tags.contains_key("title") {
+            new_tags.push(("title", title_from_name(path)));
+        }
+        if !tags.contains_key("artist") && album.album_artist.is_some() {
+            new_tags.push(("artist", artist.clone()));
+        }
+        if gain.abs() >= MIN_GAIN {
+            new_tags.extend(replaygain::after_gain(tags, gain));
+        }
+        finish_track(path, &new_tags, cover.as_deref(), gain, options)?;
+    }
+    console!(
+        Success,
+        "Album {} by {} ready: {} track(s) in {}",
+        name.as_deref().unwrap_or("(untitled)"),
+        artist,
+        tracks.len(),
+        dir.display()
+    );
+    Ok(())
+}
@@ -0,0 +1,258 @@
+use std::path::Path;
+use std::process::Command;
+
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info_span};
+
+use crate::filtergraph::{Ebur128, FilterChain};
+use crate::progress::Progress;
+use crate::timestamp::MediaTimestamp;
+use crate::{command_output, logging, run_ffmpeg_log, segmented, VideoConversionError};
+
+/// What `analyze loudness` measures
+#[derive(clap::Args, Debug, Clone)]
+pub struct LoudnessOptions {
+    /// Audio or video file to measure
+    file: String,
+
+    /// Print the measurements as JSON
+    #[arg(long)]
+    json: bool,
+}
+
+/// What `analyze keyframes` inspects
+#[derive(clap::Args, Debug, Clone)]
+pub struct KeyframesOptions {
+    /// Video file to inspect
+    file: String,
+
+    /// Print the keyframes and GOP statistics as JSON
+    #[arg(long)]
+    json: bool,
+}
+
+/// A keyframe and the group of pictures it starts, which lasts until the next keyframe
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct Gop {
+    /// Time of the keyframe in seconds
+    pub time: f64,
+    /// Length of the group in seconds
+    pub seconds: f64,
+    /// Frames in the group, estimated from the frame rate
+    pub frames: Option<u64>,
+}
+
+/// Keyframe layout of a file's first video stream
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct KeyframeReport {
+    /// Average frame rate, if the stream states one
+    pub frame_rate: Option<f64>,
+    pub gops: Vec<Gop>,
+    pub shortest_gop_seconds: f64,
+    pub longest_gop_seconds: f64,
+    pub average_gop_seconds: f64,
+}
+
+#[derive(Deserialize, Default)]
+struct ProbeOutput {
+    #[serde(default)]
+    streams: Vec<ProbeStream>,
+    format: Option<ProbeFormat>,
+}
+
+#[derive(Deserialize)]
+struct ProbeStream {
+    avg_frame_rate: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ProbeFormat {
+    duration: Option<String>,
+}
+
+/// Times of the video keyframes of `input` in seconds, either all of them or those from the one
+/// before the start of `interval` up to its end; only keyframes are decoded, which is fast
+pub(crate) fn keyframes(input: &Path, interval: Option<(f64, f64)>) -> Result<Vec<f64>, VideoConversionError> {
+    let mut command = Command::new("ffprobe");
+    command.args(["-v", "error", "-select_streams", "v:0", "-skip_frame", "nokey"]);
+    if let Some((from, to)) = interval {
+        // ffprobe seeks to the keyframe before the interval start
+        command.arg("-read_intervals").arg(format!("{:.3}%{:.3}", from.max(0.0), to + 0.001));
+    }
+    let output = command_output(
+        command
+            .args(["-show_entries", "frame=best_effort_timestamp_time", "-of", "csv=p=0"])
+            .arg(input)
+            .stderr(logging::child_stderr()),
+    )?;
+    if !output.status.success() {
+        return Ok(Vec::new());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| line.trim().trim_end_matches(',').parse::<f64>().ok())
+        .collect())
+}
+
+/// Frame rate and duration of `path`, to size the groups between keyframes
+fn frame_rate_and_duration(path: &Path) -> (Option<f64>, Option<f64>) {
+    let output = command_output(
+        Command::new("ffprobe")
+            .args(["-v", "error", "-select_streams", "v:0", "-show_entries", "stream=avg_frame_rate:format=duration"])
+            .args(["-of", "json"])
+            .arg(path)
+            .stderr(logging::child_stderr()),
+    );
+    let parsed: ProbeOutput = match output {
+        Ok(output) if output.status.success() => serde_json::from_slice(&output.stdout).unwrap_or_default(),
+        _ => ProbeOutput::default(),
+    };
+    // Rates are fractions such as 30000/1001; 0/0 means unknown
+    let frame_rate = parsed.streams.first().and_then(|stream| stream.avg_frame_rate.as_deref()).and_then(|rate| {
+        let (numerator, denominator) = rate.split_once('/').unwrap_or((rate, "1"));
+        let rate = numerator.parse::<f64>().ok()? / denominator.parse::<f64>().ok()?;
+        (rate.is_finite() && rate > 0.0).then_some(rate)
+    });
+    let duration = parsed.format.and_then(|format| format.duration).and_then(|duration| duration.parse().ok());
+    (frame_rate, duration)
+}
+
+/// Keyframes of `path` with the groups of pictures between them; the last group runs to the end
+pub fn keyframe_report(path: &Path) -> Result<KeyframeReport, VideoConversionError> {
+    let _span = info_span!("keyframes", input = %path.display()).entered();
+    let times = keyframes(path, None)?;
+    if times.is_empty() {
+        return Err(VideoConversionError::encoding(format!("ffprobe found no video keyframes in {}", path.display())));
+    }
+    let (frame_rate, duration) = frame_rate_and_duration(path);
+    let last = times[times.len() - 1];
+    let ends = times.iter().skip(1).copied().chain([duration.filter(|end| *end > last).unwrap_or(last)]);
+    let gops: Vec<Gop> = times
+        .iter()
+        .zip(ends)
+        .map(|(&time, end)| Gop {
+            time,
+            seconds: end - time,
+            frames: frame_rate.map(|rate| ((end - time) * rate).round() as u64),
+        })
+        .collect();
+    // The last group is cut off by the end of the video rather than by the encoder
+    let full = if gops.len() > 1 { &gops[..gops.len() - 1] } else { &gops[..] };
+    let lengths = full.iter().map(|gop| gop.seconds);
+    debug!(keyframes = gops.len(), "found");
+    Ok(KeyframeReport {
+        frame_rate,
+        shortest_gop_seconds: lengths.clone().fold(f64::INFINITY, f64::min),
+        longest_gop_seconds: lengths.clone().fold(0.0, f64::max),
+        average_gop_seconds: lengths.sum::<f64>() / full.len() as f64,
+        gops,
+    })
+}
+
+/// Print the keyframes of `options.file` and the groups of pictures between them
+pub fn run_keyframes(options: &KeyframesOptions) -> Result<(), VideoConversionError> {
+    let path = Path::new(&options.file);
+    if !path.is_file() {
+        return Err(VideoConversionError::FileNotFound(options.file.clone()));
+    }
+    let report = keyframe_report(path)?;
+    if options.json {
+        let json = serde_json::to_string_pretty(&report).map_err(|e| VideoConversionError::io(e.to_string()))?;
+        println!("{}", json);
+        return Ok(());
+    }
+    let timestamp = |seconds: f64| MediaTimestamp::from_secs_f64(seconds).unwrap_or(MediaTimestamp::ZERO);
+    println!("{:>6}  {:>12}  {:>9}  {:>7}", "#", "Keyframe", "GOP", "Frames");
+    for (index, gop) in report.gops.iter().enumerate() {
+        let frames = gop.frames.map_or_else(|| "?".to_string(), |frames| frames.to_string());
+        println!("{:>6}  {:>12}  {:>8.3}s  {:>7}", index + 1, timestamp(gop.time).to_string(), gop.seconds, frames);
+    }
+    println!();
+    println!("Keyframes:   {}", report.gops.len());
+    if let Some(rate) = report.frame_rate {
+        println!("Frame rate:  {:.3} fps", rate);
+    }
+    println!(
+        "GOP length:  {:.3}s shortest, {:.3}s average, {:.3}s longest",
+        report.shortest_gop_seconds, report.average_gop_seconds, report.longest_gop_seconds
+    );
+    println!("Lossless cuts (trim --copy) land on these keyframes; others start at the keyframe before");
+    Ok(())
+}
+
+/// EBU R128 loudness of a file's first audio stream
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct Loudness {
+    /// Integrated loudness over the whole file, in LUFS
+    pub integrated_lufs: f64,
+    /// Highest true peak across all channels, in dBTP
+    pub true_peak_dbtp: f64,
+    /// Loudness range, in LU
+    pub range_lu: f64,
+}
+
+/// Integrated loudness in LUFS of parts played one after another, given as their loudness in
+/// LUFS and their length in seconds
+pub fn combined_loudness(parts: &[(f64, f64)]) -> f64 {
+    // Loudness adds up as energy, weighted by how long each part plays
+    let energy: f64 = parts.iter().map(|(lufs, seconds)| 10f64.powf(lufs / 10.0) * seconds).sum();
+    let seconds: f64 = parts.iter().map(|(_, seconds)| seconds).sum();
+    10.0 * (energy / seconds).log10()
+}
+
+/// Read the summary ebur128 logs when it finishes, with lines such as `I: -19.5 LUFS`,
+/// `LRA: 6.1 LU` and `Peak: -0.4 dBFS`
+fn parse_summary(log: &str) -> Option<Loudness> {
+    // The same keys appear in the running status lines before the summary
+    let (_, summary) = log.rsplit_once("Summary:")?;
+    let value = |key: &str| {
+        summary
+            .lines()
+            .find_map(|line| line.trim_start().strip_prefix(key))
+            .and_then(|rest| rest.split_whitespace().next())
+            .and_then(|number| number.parse::<f64>().ok())
+    };
+    Some(Loudness { integrated_lufs: value("I:")?, true_peak_dbtp: value("Peak:")?, range_lu: value("LRA:")? })
+}
+
+/// Measure the loudness of `path` by decoding its first audio stream once
+pub fn loudness(path: &Path, progress: &Progress) -> Result<Loudness, VideoConversionError> {
+    let _span = info_span!("loudness", input = %path.display()).entered();
+    let filters = FilterChain::new().then(Ebur128 { true_peak: true });
+    let mut command = Command::new("ffmpeg");
+    command
+        .args(["-hide_banner", "-loglevel", "error", "-progress", "pipe:1", "-nostats", "-i"])
+        .arg(path)
+        .args(["-map", "0:a:0", "-vn", "-sn", "-dn", "-af"])
+        .arg(filters.to_string())
+        .args(["-f", "null", "-"]);
+    // ebur128 only logs its summary
+    let log = run_ffmpeg_log(&mut command, segmented::probe_duration(&path.to_string_lossy()), progress)
+        .map_err(VideoConversionError::conversion)?;
+    let loudness = parse_summary(&log).ok_or_else(|| {
+        VideoConversionError::encoding(format!("ffmpeg reported no loudness summary for {}", path.display()))
+    })?;
+    debug!(?loudness, "measured");
+    Ok(loudness)
+}
+
+/// Print the loudness of `options.file`
+pub fn run_loudness(options: &LoudnessOptions, progress: &Progress) -> Result<(), VideoConversionError> {
+    let path = Path::new(&options.file);
+    if !path.is_file() {
+        return Err(VideoConversionError::FileNotFound(options.file.clone()));
+    }
+    if !options.json {
+        println!("Measuring loudness of {}...", options.file);
+    }
+    let loudness = loudness(path, progress)?;
+    if options.json {
+        let json = serde_json::to_string_pretty(&loudness).map_err(|e| VideoConversionError::io(e.to_string()))?;
+        println!("{}", json);
+    } else {
+        println!("Integrated loudness: {:>7.1} LUFS", loudness.integrated_lufs);
+        println!("True peak:           {:>7.1} dBTP", loudness.true_peak_dbtp);
+        println!("Loudness range:      {:>7.1} LU", loudness.range_lu);
+    }
+    Ok(())
+}
@@ -0,0 +1,78 @@
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use crate::metadata::{self, FormatInfo};
+use crate::ytdlp::YtDlpOptions;
+use crate::VideoConversionError;
+
+/// An audio language a video is offered in
+#[derive(Serialize, Debug, Clone)]
+pub struct AudioLanguage {
+    /// Code to pass to --audio-lang; `None` for formats the site does not label
+    pub language: Option<String>,
+    /// Site's name for the track, such as "German (Germany)" or "English original (default)"
+    pub description: Option<String>,
+    /// Audio formats in this language
+    pub formats: usize,
+    /// Highest audio bitrate among them in kbit/s
+    pub best_abr: Option<f64>,
+}
+
+/// Track name from a format note, which appends the quality after a comma
+fn track_name(format: &FormatInfo) -> Option<String> {
+    let note = format.format_note.as_deref()?;
+    let name = note.split(',').next().unwrap_or(note).trim();
+    (!name.is_empty()).then(|| name.to_string())
+}
+
+/// Audio languages among `formats`, by language code
+pub fn languages(formats: &[FormatInfo]) -> Vec<AudioLanguage> {
+    let mut by_language: BTreeMap<Option<String>, AudioLanguage> = BTreeMap::new();
+    for format in formats.iter().filter(|format| format.has_audio()) {
+        let entry = by_language.entry(format.language.clone()).or_insert_with(|| AudioLanguage {
+            language: format.language.clone(),
+            description: None,
+            formats: 0,
+            best_abr: None,
+        });
+        entry.formats += 1;
+        // Audio-only formats name the track best; muxed formats note the video quality
+        if entry.description.is_none() || !format.has_video() {
+            entry.description = track_name(format).or(entry.description.take());
+        }
+        if format.abr.is_some_and(|abr| entry.best_abr.is_none_or(|best| abr > best)) {
+            entry.best_abr = format.abr;
+        }
+    }
+    by_language.into_values().collect()
+}
+
+/// Print the audio languages `url` is offered in
+pub fn list(url: &str, json: bool, ytdlp: &YtDlpOptions) -> Result<(), VideoConversionError> {
+    let info = metadata::fetch_video_info(url, "bestaudio/best", ytdlp)?;
+    let languages = languages(&info.formats);
+    if json {
+        let text = serde_json::to_string_pretty(&languages).map_err(|e| VideoConversionError::io(e.to_string()))?;
+        println!("{}", text);
+        return Ok(());
+    }
+    if let Some(title) = &info.title {
+        println!("{}", title);
+    }
+    if languages.iter().all(|language| language.language.is_none()) {
+        println!("No audio languages labelled; the video has a single audio track.");
+        return Ok(());
+    }
+    println!("{:<10} {:<40} {:>8} {:>10}", "Language", "Track", "Formats", "Best");
+    for language in &languages {
+        println!(
+            "{:<10} {:<40} {:>8} {:>10}",
+            language.language.as_deref().unwrap_or("unknown"),
+            language.description.as_deref().unwrap_or(""),
+            language.formats,
+            language.best_abr.map_or_else(|| "unknown".to_string(), |abr| format!("{:.0} kbps", abr)),
+        );
+    }
+    Ok(())
+}
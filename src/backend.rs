@@ -0,0 +1,412 @@
+use std::path::Path;
+
+use serde_json::Value;
+
+use crate::codecs::Bitrate;
+use crate::filtergraph::FilterChain;
+use crate::progress::Progress;
+use crate::ytdlp::YtDlpOptions;
+use crate::{AbrOptions, EncodeOptions, HlsOptions, VideoConversionError};
+
+/// Fetches metadata and media; implemented by yt-dlp in production and `mock::MockDownloader` in tests
+pub trait Downloader: Send + Sync {
+    /// yt-dlp style info JSON of `url` as it would be downloaded with `selector`
+    fn info(&self, url: &str, selector: &str, ytdlp: &YtDlpOptions) -> Result<Value, VideoConversionError>;
+
+    /// Download `url` as an MP4 file at `output`, reporting progress events
+    fn video(&self, url: &str, output: &str, selector: &str, ytdlp: &YtDlpOptions, progress: &Progress) -> Result<(), VideoConversionError>;
+
+    /// Download the audio of `url` as an MP3 file at `output`
+    fn audio(
+        &self,
+        url: &str,
+        output: &str,
+        selector: &str,
+        bitrate: Bitrate,
+        ytdlp: &YtDlpOptions,
+        progress: &Progress,
+    ) -> Result<(), VideoConversionError>;
+}
+
+/// Re-encodes and inspects media files; implemented by ffmpeg/ffprobe in production and
+/// `mock::MockTranscoder` in tests
+pub trait Transcoder: Send + Sync {
+    /// Re-encode `input` into a QuickTime-compatible MP4 at `output`, reporting progress events
+    fn transcode(&self, input: &str, output: &str, encode: &EncodeOptions, progress: &Progress) -> Result<(), VideoConversionError>;
+
+    /// Re-encode `input` into an HLS playlist at `playlist`, with the segments next to it
+    fn package_hls(
+        &self,
+        input: &str,
+        playlist: &str,
+        encode: &EncodeOptions,
+        hls: &HlsOptions,
+        progress: &Progress,
+    ) -> Result<(), VideoConversionError>;
+
+    /// Re-encode `input` into several renditions behind an HLS master playlist or DASH manifest
+    /// at `manifest`, with the renditions next to it
+    fn package_abr(
+        &self,
+        input: &str,
+        manifest: &str,
+        encode: &EncodeOptions,
+        hls: &HlsOptions,
+        abr: &AbrOptions,
+        progress: &Progress,
+    ) -> Result<(), VideoConversionError>;
+
+    /// Copy the streams of an HLS or DASH manifest at `url` into an MP4 at `output`; `duration`
+    /// is the stream's, if known, to report progress against
+    fn capture(&self, url: &str, output: &str, duration: Option<f64>, progress: &Progress) -> Result<(), VideoConversionError>;
+
+    /// Run the audio of `input` through `filters` into an MP3 at `output`, keeping its tags and cover art
+    fn filter_audio(
+        &self,
+        input: &str,
+        output: &str,
+        filters: &FilterChain,
+        bitrate: Bitrate,
+        progress: &Progress,
+    ) -> Result<(), VideoConversionError>;
+
+    /// Copy the streams of `input` into `output` without tags, chapters, cover art or data streams
+    fn strip_metadata(&self, input: &str, output: &str) -> Result<(), VideoConversionError>;
+
+    /// Duration and streams of a media file or stream; an `Encoding` error means it cannot be parsed
+    fn probe(&self, path: &Path) -> Result<MediaProbe, VideoConversionError>;
+}
+
+/// What a probe reports about a media file
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct MediaProbe {
+    /// Duration in seconds, if the container states one
+    pub duration: Option<f64>,
+    pub video_streams: usize,
+    pub audio_streams: usize,
+    /// First timestamp of the first video and audio stream in seconds
+    pub video_start: Option<f64>,
+    pub audio_start: Option<f64>,
+    /// Length of the first video and audio stream in seconds
+    pub video_duration: Option<f64>,
+    pub audio_duration: Option<f64>,
+}
+
+/// The yt-dlp downloader
+#[derive(Debug, Clone, Copy, Default)]
+pub struct YtDlp;
+
+impl Downloader for YtDlp {
+    fn info(&self, url: &str, selector: &str, ytdlp: &YtDlpOptions) -> Result<Value, VideoConversionError> {
+        crate::metadata::fetch_raw_info(url, selector, ytdlp)
+    }
+
+    fn video(&self, url: &str, output: &str, selector: &str, ytdlp: &YtDlpOptions, progress: &Progress) -> Result<(), VideoConversionError> {
+        crate::download_video(url, output, selector, ytdlp, progress)
+    }
+
+    fn audio(
+        &self,
+        url: &str,
+        output: &str,
+        selector: &str,
+        bitrate: Bitrate,
+        ytdlp: &YtDlpOptions,
+        progress: &Progress,
+    ) -> Result<(), VideoConversionError> {
+        crate::download_audio(url, output, selector, bitrate, ytdlp, progress)
+    }
+}
+
+/// The ffmpeg encoder with ffprobe for inspection
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Ffmpeg;
+
+impl Transcoder for Ffmpeg {
+    fn transcode(&self, input: &str, output: &str, encode: &EncodeOptions, progress: &Progress) -> Result<(), VideoConversionError> {
+        crate::convert_to_quicktime_compatible_mp4(input, output, encode, progress)
+    }
+
+    fn package_hls(
+        &self,
+        input: &str,
+        playlist: &str,
+        encode: &EncodeOptions,
+        hls: &HlsOptions,
+        progress: &Progress,
+    ) -> Result<(), VideoConversionError> {
+        crate::hls::package(input, playlist, encode, hls, progress)
+    }
+
+    fn package_abr(
+        &self,
+        input: &str,
+        manifest: &str,
+        encode: &EncodeOptions,
+        hls: &HlsOptions,
+        abr: &AbrOptions,
+        progress: &Progress,
+    ) -> Result<(), VideoConversionError> {
+        crate::abr::package(input, manifest, encode, hls, abr, progress)
+    }
+
+    fn capture(&self, url: &str, output: &str, duration: Option<f64>, progress: &Progress) -> Result<(), VideoConversionError> {
+        crate::capture_stream(url, output, duration, progress)
+    }
+
+    fn filter_audio(
+        &self,
+        input: &str,
+        output: &str,
+        filters: &FilterChain,
+        bitrate: Bitrate,
+        progress: &Progress,
+    ) -> Result<(), VideoConversionError> {
+        crate::filter_audio(input, output, filters, bitrate, progress)
+    }
+
+    fn strip_metadata(&self, input: &str, output: &str) -> Result<(), VideoConversionError> {
+        crate::strip_metadata(input, output)
+    }
+
+    fn probe(&self, path: &Path) -> Result<MediaProbe, VideoConversionError> {
+        crate::verify::ffprobe(path)
+    }
+}
+
+/// The tools a download run works with
+#[derive(Clone, Copy)]
+pub struct Backends<'a> {
+    pub downloader: &'a dyn Downloader,
+    pub transcoder: &'a dyn Transcoder,
+}
+
+impl Default for Backends<'static> {
+    /// yt-dlp and ffmpeg
+    fn default() -> Self {
+        Backends { downloader: &YtDlp, transcoder: &Ffmpeg }
+    }
+}
+
+/// Backends that need neither network access nor external binaries, for tests of code built on
+/// this crate
+///
+/// ```no_run
+/// use clap::Parser;
+/// use videelow::backend::mock::{MockDownloader, MockTranscoder};
+/// use videelow::backend::Backends;
+/// use videelow::progress::Progress;
+/// use videelow::{download_all_with, DownloadOptions};
+///
+/// #[derive(Parser)]
+/// struct Cli {
+///     #[command(flatten)]
+///     options: DownloadOptions,
+/// }
+///
+/// let downloader = MockDownloader::new();
+/// let transcoder = MockTranscoder::new();
+/// let backends = Backends { downloader: &downloader, transcoder: &transcoder };
+/// let cli = Cli::parse_from(["test", "--output-dir", "/tmp/out"]);
+/// let urls = ["https://www.youtube.com/watch?v=dQw4w9WgXcQ".to_string()];
+/// download_all_with(&urls, &cli.options, &Progress::default(), backends).unwrap();
+/// assert_eq!(downloader.requests(), ["https://www.youtube.com/watch?v=dQw4w9WgXcQ"]);
+/// ```
+pub mod mock {
+    use std::path::Path;
+    use std::sync::Mutex;
+
+    use serde_json::{json, Value};
+
+    use super::{Downloader, MediaProbe, Transcoder};
+    use crate::codecs::Bitrate;
+    use crate::filtergraph::FilterChain;
+    use crate::progress::{Progress, ProgressEvent};
+    use crate::ytdlp::YtDlpOptions;
+    use crate::{AbrOptions, EncodeOptions, HlsOptions, VideoConversionError};
+
+    /// Smallest file the output checks accept as an MP4: a lone, empty `moov` atom
+    const PLACEHOLDER: &[u8] = b"\0\0\0\x08moov";
+
+    /// VOD playlist listing a single placeholder segment
+    const PLAYLIST: &str = "#EXTM3U\n#EXT-X-VERSION:3\n#EXT-X-TARGETDURATION:60\n#EXT-X-PLAYLIST-TYPE:VOD\n#EXTINF:60.0,\nsegment_00000.ts\n#EXT-X-ENDLIST\n";
+
+    /// Downloader that writes placeholder files and records the URLs it was asked for
+    pub struct MockDownloader {
+        info: Value,
+        failure: Option<String>,
+        requests: Mutex<Vec<String>>,
+    }
+
+    impl MockDownloader {
+        /// Serve a one-minute video titled "Mock video"
+        pub fn new() -> Self {
+            MockDownloader {
+                info: json!({ "id": "mock", "title": "Mock video", "duration": 60.0, "extractor_key": "Youtube" }),
+                failure: None,
+                requests: Mutex::new(Vec::new()),
+            }
+        }
+
+        /// Serve this info JSON instead
+        pub fn with_info(mut self, info: Value) -> Self {
+            self.info = info;
+            self
+        }
+
+        /// Fail every download with an `Extraction` error with `message`
+        pub fn failing(mut self, message: &str) -> Self {
+            self.failure = Some(message.to_string());
+            self
+        }
+
+        /// URLs downloaded so far, in order
+        pub fn requests(&self) -> Vec<String> {
+            self.requests.lock().unwrap_or_else(|e| e.into_inner()).clone()
+        }
+
+        fn fetch(&self, url: &str, output: &str, progress: &Progress) -> Result<(), VideoConversionError> {
+            self.requests.lock().unwrap_or_else(|e| e.into_inner()).push(url.to_string());
+            if let Some(message) = &self.failure {
+                return Err(VideoConversionError::extraction(message.clone()));
+            }
+            let size = PLACEHOLDER.len() as u64;
+            progress.emit(ProgressEvent::DownloadProgress {
+                downloaded_bytes: size,
+                total_bytes: Some(size),
+                percent: Some(100.0),
+                speed: None,
+                eta: Some(0),
+            });
+            std::fs::write(output, PLACEHOLDER)
+                .map_err(|e| VideoConversionError::io(format!("Failed to write {}", output)).caused_by(e))
+        }
+    }
+
+    impl Default for MockDownloader {
+        fn default() -> Self {
+            MockDownloader::new()
+        }
+    }
+
+    impl Downloader for MockDownloader {
+        fn info(&self, _url: &str, _selector: &str, _ytdlp: &YtDlpOptions) -> Result<Value, VideoConversionError> {
+            Ok(self.info.clone())
+        }
+
+        fn video(&self, url: &str, output: &str, _selector: &str, _ytdlp: &YtDlpOptions, progress: &Progress) -> Result<(), VideoConversionError> {
+            self.fetch(url, output, progress)
+        }
+
+        fn audio(
+            &self,
+            url: &str,
+            output: &str,
+            _selector: &str,
+            _bitrate: Bitrate,
+            _ytdlp: &YtDlpOptions,
+            progress: &Progress,
+        ) -> Result<(), VideoConversionError> {
+            self.fetch(url, output, progress)
+        }
+    }
+
+    /// Transcoder that copies its input, writes placeholder streams and playlists and reports every
+    /// file as a clip with one video and one audio stream
+    pub struct MockTranscoder {
+        probe: MediaProbe,
+    }
+
+    impl MockTranscoder {
+        /// Report one-minute clips, matching `MockDownloader::new`
+        pub fn new() -> Self {
+            MockTranscoder { probe: MediaProbe { duration: Some(60.0), video_streams: 1, audio_streams: 1, ..MediaProbe::default() } }
+        }
+
+        /// Report this probe result for every file instead
+        pub fn with_probe(probe: MediaProbe) -> Self {
+            MockTranscoder { probe }
+        }
+    }
+
+    impl Default for MockTranscoder {
+        fn default() -> Self {
+            MockTranscoder::new()
+        }
+    }
+
+    impl Transcoder for MockTranscoder {
+        fn transcode(
+            &self,
+            input: &str,
+            output: &str,
+            _encode: &EncodeOptions,
+            _progress: &Progress,
+        ) -> Result<(), VideoConversionError> {
+            std::fs::copy(input, output)
+                .map(|_| ())
+                .map_err(|e| VideoConversionError::encoding(format!("Failed to copy {}", input)).caused_by(e))
+        }
+
+        fn package_hls(
+            &self,
+            _input: &str,
+            playlist: &str,
+            _encode: &EncodeOptions,
+            _hls: &HlsOptions,
+            _progress: &Progress,
+        ) -> Result<(), VideoConversionError> {
+            let write = |path: &Path, contents: &[u8]| {
+                std::fs::write(path, contents)
+                    .map_err(|e| VideoConversionError::io(format!("Failed to write {}", path.display())).caused_by(e))
+            };
+            let playlist = Path::new(playlist);
+            let dir = playlist.parent().unwrap_or(Path::new("."));
+            std::fs::create_dir_all(dir)
+                .map_err(|e| VideoConversionError::io(format!("Failed to create {}", dir.display())).caused_by(e))?;
+            write(&dir.join("segment_00000.ts"), PLACEHOLDER)?;
+            write(playlist, PLAYLIST.as_bytes())
+        }
+
+        fn package_abr(
+            &self,
+            input: &str,
+            manifest: &str,
+            encode: &EncodeOptions,
+            hls: &HlsOptions,
+            _abr: &AbrOptions,
+            progress: &Progress,
+        ) -> Result<(), VideoConversionError> {
+            // One rendition in place of the manifest is enough for code that only checks the output exists
+            self.package_hls(input, manifest, encode, hls, progress)
+        }
+
+        fn capture(&self, _url: &str, output: &str, _duration: Option<f64>, _progress: &Progress) -> Result<(), VideoConversionError> {
+            std::fs::write(output, PLACEHOLDER)
+                .map_err(|e| VideoConversionError::io(format!("Failed to write {}", output)).caused_by(e))
+        }
+
+        fn filter_audio(
+            &self,
+            input: &str,
+            output: &str,
+            _filters: &FilterChain,
+            _bitrate: Bitrate,
+            _progress: &Progress,
+        ) -> Result<(), VideoConversionError> {
+            std::fs::copy(input, output)
+                .map(|_| ())
+                .map_err(|e| VideoConversionError::encoding(format!("Failed to copy {}", input)).caused_by(e))
+        }
+
+        fn strip_metadata(&self, input: &str, output: &str) -> Result<(), VideoConversionError> {
+            std::fs::copy(input, output)
+                .map(|_| ())
+                .map_err(|e| VideoConversionError::encoding(format!("Failed to copy {}", input)).caused_by(e))
+        }
+
+        fn probe(&self, _path: &Path) -> Result<MediaProbe, VideoConversionError> {
+            Ok(self.probe.clone())
+        }
+    }
+}
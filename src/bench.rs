@@ -0,0 +1,138 @@
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use std::time::Instant;
+
+use clap::ValueEnum;
+use tracing::{debug, info_span};
+
+use crate::codecs::Bitrate;
+use crate::progress::{self, Progress};
+use crate::timestamp::MediaTimestamp;
+use crate::hardware::{self, EncoderInfo};
+use crate::{console, run_ffmpeg, shutdown, Preset, VideoConversionError};
+
+/// What `bench` encodes and how
+#[derive(clap::Args, Debug, Clone)]
+pub struct BenchOptions {
+    /// Video to take the sample from (default: a generated 1080p test pattern)
+    file: Option<String>,
+
+    /// Length of the sample (seconds, MM:SS or HH:MM:SS)
+    #[arg(long, value_name = "DURATION", default_value = "10")]
+    duration: MediaTimestamp,
+
+    /// Constant rate factor, or the closest quality setting of hardware encoders
+    #[arg(long, default_value_t = 23, value_parser = clap::value_parser!(u8).range(0..=51))]
+    crf: u8,
+
+    /// x264/x265 presets to try (repeatable; default: all of them)
+    #[arg(long, value_enum)]
+    preset: Vec<Preset>,
+}
+
+/// Speed and size of one encode
+struct Measurement {
+    encoder: &'static str,
+    preset: Option<&'static str>,
+    /// Seconds of video encoded per second
+    speed: f64,
+    size: u64,
+}
+
+/// Encode the sample once, returning how long it took in seconds and the output size
+fn encode(
+    options: &BenchOptions,
+    encoder: &EncoderInfo,
+    preset: Option<&str>,
+    output: &Path,
+    progress: &Progress,
+) -> Result<(f64, u64), VideoConversionError> {
+    let mut command = Command::new("ffmpeg");
+    command.args(["-progress", "pipe:1", "-nostats", "-y"]).args(encoder.accelerator.device_args());
+    match &options.file {
+        Some(file) => command.arg("-t").arg(options.duration.to_ffmpeg()).arg("-i").arg(file),
+        None => command.args(["-f", "lavfi", "-i"]).arg(format!(
+            "testsrc2=size=1920x1080:rate=30:duration={}",
+            options.duration.as_secs_f64()
+        )),
+    };
+    command.args(["-map", "0:v:0", "-an", "-sn", "-dn"]);
+    if let Some(upload) = encoder.accelerator.upload_filter() {
+        command.args(["-vf", upload]);
+    }
+    command.arg("-c:v").arg(encoder.name);
+    if let Some(preset) = preset {
+        command.args(["-preset", preset]);
+    }
+    command.args(encoder.accelerator.quality_args(options.crf)).arg(output);
+    let started = Instant::now();
+    run_ffmpeg(&mut command, Some(options.duration.as_secs_f64()), progress)?;
+    let elapsed = started.elapsed().as_secs_f64();
+    let size = fs::metadata(output).map(|metadata| metadata.len()).unwrap_or(0);
+    Ok((elapsed, size))
+}
+
+/// Encode a sample with every encoder and preset this machine offers and report speed and size
+pub fn run(options: &BenchOptions, progress: &Progress) -> Result<(), VideoConversionError> {
+    if let Some(file) = options.file.as_ref().filter(|file| !Path::new(file).is_file()) {
+        return Err(VideoConversionError::FileNotFound(file.clone()));
+    }
+    if options.duration == MediaTimestamp::ZERO {
+        return Err(VideoConversionError::config("sample duration must be positive"));
+    }
+    let _span = info_span!("bench").entered();
+    println!("Detecting encoders...");
+    let encoders: Vec<EncoderInfo> = hardware::detect_encoders().into_iter().filter(|encoder| encoder.works).collect();
+    let presets: &[Preset] = match options.preset.is_empty() {
+        true => Preset::value_variants(),
+        false => &options.preset,
+    };
+    let output = std::env::temp_dir().join(format!("videelow-bench-{}.mkv", std::process::id()));
+
+    let mut measurements = Vec::new();
+    for encoder in &encoders {
+        // Hardware encoders map several x264 presets onto the same one of their own, or have none
+        let mut tried: Vec<Option<&'static str>> = presets.iter().map(|preset| encoder.accelerator.preset(*preset)).collect();
+        tried.dedup();
+        for preset in tried {
+            let label = preset.map_or_else(|| encoder.name.to_string(), |preset| format!("{} {}", encoder.name, preset));
+            println!("Encoding with {}...", label);
+            match encode(options, encoder, preset, &output, progress) {
+                Ok((elapsed, size)) => {
+                    debug!(encoder = encoder.name, preset, elapsed, size, "encoded");
+                    measurements.push(Measurement {
+                        encoder: encoder.name,
+                        preset,
+                        speed: options.duration.as_secs_f64() / elapsed.max(0.001),
+                        size,
+                    });
+                }
+                Err(e) => {
+                    shutdown::check()?;
+                    // The other presets would most likely fail the same way
+                    console!(Warning, "{} failed: {}", label, e);
+                    break;
+                }
+            }
+        }
+    }
+    let _ = fs::remove_file(&output);
+    if measurements.is_empty() {
+        return Err(VideoConversionError::encoding("no encoder could encode the sample"));
+    }
+
+    println!("{:<20} {:<10} {:>8} {:>11} {:>10}", "ENCODER", "PRESET", "SPEED", "SIZE", "BITRATE");
+    for measurement in &measurements {
+        let bitrate = Bitrate::from_bits_per_second((measurement.size as f64 * 8.0 / options.duration.as_secs_f64()) as u64);
+        println!(
+            "{:<20} {:<10} {:>7.2}x {:>11} {:>10}",
+            measurement.encoder,
+            measurement.preset.unwrap_or("-"),
+            measurement.speed,
+            progress::human_bytes(measurement.size as f64),
+            bitrate.to_string()
+        );
+    }
+    Ok(())
+}
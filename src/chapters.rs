@@ -0,0 +1,299 @@
+use std::fmt::Write as _;
+use std::fs::{self, create_dir_all};
+use std::path::Path;
+use std::process::Command;
+
+use clap::ValueEnum;
+use tracing::{debug, info_span};
+
+use crate::metadata::VideoInfo;
+use crate::musicbrainz;
+use crate::sites::sanitize_filename;
+use crate::timestamp::MediaTimestamp;
+use crate::{console, logging, run_command, verify, VideoConversionError};
+
+/// What to do with a video's chapters
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum, Debug)]
+pub enum ChapterMode {
+    /// Embed chapter markers that players offer for navigation
+    Embed,
+    /// Embed the markers and also cut the output into one file per chapter
+    Split,
+    /// Embed the markers and also write a cue sheet next to the output, which players and
+    /// splitters read to navigate the tracks of a mix without re-encoding it
+    Cue,
+}
+
+/// A named section of a video
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chapter {
+    /// Start and end in seconds
+    pub start: f64,
+    pub end: Option<f64>,
+    pub title: String,
+}
+
+/// Seconds of a `M:SS`, `MM:SS` or `H:MM:SS` timestamp as written in descriptions
+fn parse_timestamp(token: &str) -> Option<f64> {
+    let parts: Vec<&str> = token.split(':').collect();
+    if !(2..=3).contains(&parts.len()) || parts.iter().any(|p| p.is_empty() || !p.chars().all(|c| c.is_ascii_digit())) {
+        return None;
+    }
+    // Everything after the leading field is a two-digit minute or second count
+    if parts[0].len() > 3 || parts[1..].iter().any(|p| p.len() != 2) {
+        return None;
+    }
+    let fields: Vec<u64> = parts.iter().map(|p| p.parse().ok()).collect::<Option<_>>()?;
+    if fields[1..].iter().any(|f| *f >= 60) {
+        return None;
+    }
+    Some(fields.iter().fold(0, |total, field| total * 60 + field) as f64)
+}
+
+/// The timestamp and the title around it in a line like `01. 3:25 - Artist - Song` or
+/// `Song [1:02:03]`
+fn parse_line(line: &str) -> Option<(f64, String)> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    let (index, seconds) = words.iter().enumerate().find_map(|(index, word)| {
+        let token = word.trim_matches(|c: char| matches!(c, '(' | ')' | '[' | ']' | ',' | '-' | '|' | '.') || c == ':');
+        parse_timestamp(token).map(|seconds| (index, seconds))
+    })?;
+    let separators = |c: char| c.is_whitespace() || matches!(c, '-' | '–' | '—' | '|' | ':' | '•' | '·' | '*' | '>');
+    let mut title = words.iter().enumerate().filter(|(i, _)| *i != index).map(|(_, w)| *w).collect::<Vec<_>>().join(" ");
+    title = title.trim_matches(separators).to_string();
+    // Track numbers such as `01.` or `3)` in front of the title
+    if let Some((number, rest)) = title.split_once(' ') {
+        let digits = number.trim_end_matches(['.', ')']);
+        if digits.len() < number.len() && !digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit()) {
+            title = rest.trim_matches(separators).to_string();
+        }
+    }
+    Some((seconds, title))
+}
+
+/// Chapters listed as timestamps in a description; like YouTube, the list must start at 0:00 and
+/// count up, which sets it apart from times mentioned in passing
+pub fn from_description(description: &str, duration: Option<f64>) -> Vec<Chapter> {
+    let mut chapters: Vec<Chapter> = Vec::new();
+    for (start, title) in description.lines().filter_map(parse_line) {
+        match chapters.last() {
+            None if start > 0.0 => continue,
+            Some(previous) if start <= previous.start => break,
+            _ => {}
+        }
+        if duration.is_some_and(|duration| start >= duration) {
+            break;
+        }
+        chapters.push(Chapter { start, end: None, title });
+    }
+    if chapters.len() < 2 {
+        return Vec::new();
+    }
+    for index in 0..chapters.len() {
+        chapters[index].end = chapters.get(index + 1).map(|next| next.start).or(duration);
+        if chapters[index].title.is_empty() {
+            chapters[index].title = format!("Chapter {}", index + 1);
+        }
+    }
+    chapters
+}
+
+/// The site's chapters, else those listed in the description
+pub fn find(info: &VideoInfo) -> Vec<Chapter> {
+    if let Some(listed) = info.chapters.as_ref().filter(|chapters| !chapters.is_empty()) {
+        return listed
+            .iter()
+            .enumerate()
+            .map(|(index, chapter)| Chapter {
+                start: chapter.start_time,
+                end: chapter.end_time,
+                title: chapter.title.clone().unwrap_or_else(|| format!("Chapter {}", index + 1)),
+            })
+            .collect();
+    }
+    info.description.as_deref().map_or_else(Vec::new, |description| from_description(description, info.duration))
+}
+
+/// Escape a value for ffmpeg's metadata file format
+fn escape_metadata(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '=' | ';' | '#' | '\\' | '\n') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Chapters in ffmpeg's metadata file format
+fn ffmetadata(chapters: &[Chapter]) -> String {
+    let mut text = String::from(";FFMETADATA1\n");
+    for chapter in chapters {
+        let start = (chapter.start * 1000.0).round() as u64;
+        let end = chapter.end.map_or(start, |end| (end * 1000.0).round() as u64);
+        let _ = write!(
+            text,
+            "[CHAPTER]\nTIMEBASE=1/1000\nSTART={}\nEND={}\ntitle={}\n",
+            start,
+            end.max(start),
+            escape_metadata(&chapter.title)
+        );
+    }
+    text
+}
+
+/// Replace the chapters of `output` with `chapters`, copying its streams unchanged
+fn embed(output: &Path, chapters: &[Chapter]) -> Result<(), VideoConversionError> {
+    let metadata_path = output.with_extension("ffmetadata");
+    let extension = output.extension().map_or_else(String::new, |ext| ext.to_string_lossy().into_owned());
+    let temp_path = output.with_extension(format!("chapters.{}", extension));
+    fs::write(&metadata_path, ffmetadata(chapters))
+        .map_err(|e| VideoConversionError::io(format!("Failed to write {}", metadata_path.display())).caused_by(e))?;
+
+    let mut command = Command::new("ffmpeg");
+    command
+        .args(["-nostats", "-y", "-i"])
+        .arg(output)
+        .arg("-i")
+        .arg(&metadata_path)
+        .args(["-map", "0", "-map_metadata", "0", "-map_chapters", "1", "-c", "copy"]);
+    match extension.eq_ignore_ascii_case("mp3") {
+        // Chapters go into ID3v2 CHAP frames
+        true => command.args(["-id3v2_version", "3"]),
+        false => command.args(["-movflags", "+faststart"]),
+    };
+    command.arg(&temp_path).stderr(logging::child_stderr());
+    let result = run_command(&mut command).and_then(|_| {
+        fs::rename(&temp_path, output)
+            .map_err(|e| VideoConversionError::io(format!("Failed to replace {}", output.display())).caused_by(e))
+    });
+    let _ = fs::remove_file(&metadata_path);
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    result
+}
+
+/// Cut `output` into one file per chapter in a directory named after it; video cuts snap to
+/// the keyframes before the chapter starts, as the streams are copied
+fn split(output: &Path, chapters: &[Chapter], album: Option<&str>) -> Result<(), VideoConversionError> {
+    let dir = output.with_extension("");
+    create_dir_all(&dir)
+        .map_err(|e| VideoConversionError::io(format!("Failed to create {}", dir.display())).caused_by(e))?;
+    let extension = output.extension().map_or_else(String::new, |ext| ext.to_string_lossy().into_owned());
+    for (index, chapter) in chapters.iter().enumerate() {
+        let part = dir.join(format!("{:02} - {}.{}", index + 1, sanitize_filename(&chapter.title), extension));
+        let mut command = Command::new("ffmpeg");
+        command.args(["-nostats", "-n", "-ss"]).arg(MediaTimestamp::from_secs_f64(chapter.start).unwrap_or(MediaTimestamp::ZERO).to_ffmpeg());
+        if let Some(end) = chapter.end.and_then(MediaTimestamp::from_secs_f64) {
+            command.arg("-to").arg(end.to_ffmpeg());
+        }
+        command
+            .arg("-i")
+            .arg(output)
+            .args(["-map", "0", "-map_chapters", "-1", "-c", "copy"])
+            .arg("-metadata")
+            .arg(format!("title={}", chapter.title))
+            .arg("-metadata")
+            .arg(format!("track={}/{}", index + 1, chapters.len()));
+        if let Some(album) = album {
+            command.arg("-metadata").arg(format!("album={}", album));
+        }
+        command.arg(&part).stderr(logging::child_stderr());
+        run_command(&mut command)?;
+    }
+    console!(Success, "Split into {} chapters: {}", chapters.len(), dir.display());
+    Ok(())
+}
+
+/// A value for a cue sheet, which has no escapes for its double quotes
+fn cue_string(value: &str) -> String {
+    format!("\"{}\"", value.replace('"', "'").replace(['\r', '\n'], " "))
+}
+
+/// `MM:SS:FF` position of a cue sheet index, counting frames of 1/75 s and minutes past 99
+fn cue_time(seconds: f64) -> String {
+    let frames = (seconds.max(0.0) * 75.0).round() as u64;
+    format!("{:02}:{:02}:{:02}", frames / (75 * 60), frames / 75 % 60, frames % 75)
+}
+
+/// Cue sheet listing `chapters` as the tracks of `output`, with performers taken from chapter
+/// titles such as "Artist - Song" and else from the uploader
+fn cue_sheet(info: &VideoInfo, output: &Path, chapters: &[Chapter]) -> String {
+    let uploader = info.uploader.as_deref().or(info.channel.as_deref());
+    let mut text = String::new();
+    if let Some(date) = info.upload_date.as_deref().and_then(|date| date.get(..4)) {
+        let _ = writeln!(text, "REM DATE {}", date);
+    }
+    if let Some(id) = &info.id {
+        let _ = writeln!(text, "REM COMMENT {}", cue_string(id));
+    }
+    if let Some(uploader) = uploader {
+        let _ = writeln!(text, "PERFORMER {}", cue_string(uploader));
+    }
+    if let Some(title) = &info.title {
+        let _ = writeln!(text, "TITLE {}", cue_string(title));
+    }
+    let file_name = output.file_name().map_or_else(String::new, |name| name.to_string_lossy().into_owned());
+    // Players take WAVE for any decodable audio they do not know by name
+    let file_type = match output.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("mp3")) {
+        true => "MP3",
+        false => "WAVE",
+    };
+    let _ = writeln!(text, "FILE {} {}", cue_string(&file_name), file_type);
+    for (index, chapter) in chapters.iter().enumerate() {
+        let track = musicbrainz::parse_title(&chapter.title, uploader);
+        let _ = writeln!(text, "  TRACK {:02} AUDIO", index + 1);
+        let _ = writeln!(text, "    TITLE {}", cue_string(&track.title));
+        if let Some(artist) = &track.artist {
+            let _ = writeln!(text, "    PERFORMER {}", cue_string(artist));
+        }
+        let _ = writeln!(text, "    INDEX 01 {}", cue_time(chapter.start));
+    }
+    text
+}
+
+/// Write the cue sheet for `output` beside it, named after it
+fn write_cue(info: &VideoInfo, output: &Path, chapters: &[Chapter]) -> Result<(), VideoConversionError> {
+    // The cue format numbers at most 99 tracks
+    if chapters.len() > 99 {
+        return Err(VideoConversionError::config(format!(
+            "a cue sheet holds at most 99 tracks, but the video has {} chapters",
+            chapters.len()
+        )));
+    }
+    let cue_path = output.with_extension("cue");
+    fs::write(&cue_path, cue_sheet(info, output, chapters))
+        .map_err(|e| VideoConversionError::io(format!("Failed to write {}", cue_path.display())).caused_by(e))?;
+    console!(Success, "Wrote a cue sheet of {} tracks: {}", chapters.len(), cue_path.display());
+    Ok(())
+}
+
+/// Embed the video's chapters into `output`, and with `Split` also cut it into one file per chapter
+/// or with `Cue` also write a cue sheet for it
+pub fn apply(info: &VideoInfo, output: &str, mode: ChapterMode) -> Result<(), VideoConversionError> {
+    let _span = info_span!("chapters", output = output).entered();
+    let mut chapters = find(info);
+    if chapters.is_empty() {
+        println!("No chapters listed for {}", output);
+        return Ok(());
+    }
+    debug!(count = chapters.len(), "chapters found");
+    let output = Path::new(output);
+    // Site chapters may leave the last one open, running to the end
+    if chapters.iter().any(|chapter| chapter.end.is_none()) {
+        let duration = verify::ffprobe(output).ok().and_then(|probe| probe.duration);
+        for chapter in chapters.iter_mut().filter(|chapter| chapter.end.is_none()) {
+            chapter.end = duration;
+        }
+    }
+    embed(output, &chapters)?;
+    console!(Success, "Embedded {} chapters: {}", chapters.len(), output.display());
+    match mode {
+        ChapterMode::Embed => {}
+        ChapterMode::Split => split(output, &chapters, info.title.as_deref())?,
+        ChapterMode::Cue => write_cue(info, output, &chapters)?,
+    }
+    Ok(())
+}
@@ -0,0 +1,241 @@
+use std::path::Path;
+use std::process::Command;
+
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info_span};
+
+use crate::codecs::{AudioCodec, Container, VideoCodec};
+use crate::devices::{Device, DeviceProfile};
+use crate::{command_output, console, logging, verify, VideoConversionError};
+
+/// What `check` inspects
+#[derive(clap::Args, Debug, Clone)]
+pub struct CheckOptions {
+    /// The file to check
+    file: String,
+
+    /// Device whose profile the file must fit
+    #[arg(long, value_enum, default_value = "quicktime")]
+    device: Device,
+
+    /// Print the findings as JSON
+    #[arg(long)]
+    json: bool,
+}
+
+/// The least work that makes a file playable, from cheapest to dearest
+#[derive(Serialize, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub enum Fix {
+    /// Rewriting the container with the streams copied
+    Remux,
+    /// Re-encoding the audio while copying the video
+    EncodeAudio,
+    /// Re-encoding the video
+    Encode,
+}
+
+impl Fix {
+    fn describe(&self) -> &'static str {
+        match self {
+            Fix::Remux => "a remux suffices; the streams can be copied",
+            Fix::EncodeAudio => "the audio needs re-encoding; the video can be copied",
+            Fix::Encode => "the video needs re-encoding",
+        }
+    }
+}
+
+/// One property of a file that the target device does not accept
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Violation {
+    pub property: &'static str,
+    pub found: String,
+    pub expected: String,
+    pub fix: Fix,
+}
+
+#[derive(Deserialize)]
+struct ProbeOutput {
+    #[serde(default)]
+    streams: Vec<ProbeStream>,
+    format: Option<ProbeFormat>,
+}
+
+#[derive(Deserialize)]
+struct ProbeStream {
+    codec_type: Option<String>,
+    codec_name: Option<String>,
+    codec_tag_string: Option<String>,
+    profile: Option<String>,
+    level: Option<i64>,
+    pix_fmt: Option<String>,
+    height: Option<u32>,
+}
+
+#[derive(Deserialize)]
+struct ProbeFormat {
+    format_name: Option<String>,
+}
+
+fn probe(path: &Path) -> Result<ProbeOutput, VideoConversionError> {
+    let output = command_output(
+        Command::new("ffprobe")
+            .args([
+                "-v",
+                "error",
+                "-show_entries",
+                "stream=codec_type,codec_name,codec_tag_string,profile,level,pix_fmt,height:format=format_name",
+                "-of",
+                "json",
+            ])
+            .arg(path)
+            .stderr(logging::child_stderr()),
+    )?;
+    if !output.status.success() {
+        return Err(VideoConversionError::encoding(format!("ffprobe cannot read {}", path.display())));
+    }
+    serde_json::from_slice(&output.stdout).map_err(|e| VideoConversionError::io("unexpected ffprobe output").caused_by(e))
+}
+
+/// ffprobe's name for a video codec
+fn video_codec_name(codec: VideoCodec) -> &'static str {
+    match codec {
+        VideoCodec::H264 => "h264",
+        VideoCodec::H265 => "hevc",
+    }
+}
+
+/// ffprobe's name for an audio codec
+fn audio_codec_name(codec: AudioCodec) -> &'static str {
+    match codec {
+        AudioCodec::Aac => "aac",
+        AudioCodec::Mp3 => "mp3",
+        AudioCodec::Opus => "opus",
+    }
+}
+
+/// Profiles as ffprobe reports them, each decodable by any player of the ones after it
+fn profile_order(codec: VideoCodec) -> &'static [&'static str] {
+    match codec {
+        VideoCodec::H264 => &["constrained baseline", "baseline", "main", "high"],
+        VideoCodec::H265 => &["main", "main 10"],
+    }
+}
+
+/// Whether a stream of profile `found` plays on a device decoding `allowed`
+fn profile_fits(codec: VideoCodec, found: &str, allowed: &str) -> bool {
+    let order = profile_order(codec);
+    let rank = |profile: &str| order.iter().position(|known| known.eq_ignore_ascii_case(profile));
+    matches!((rank(found), rank(allowed)), (Some(found), Some(allowed)) if found <= allowed)
+}
+
+/// Level in the dotted form `-level:v` takes; ffprobe reports H.264 levels times ten and H.265
+/// levels times thirty, and a negative number when it does not know
+fn level_value(codec: VideoCodec, level: i64) -> Option<f64> {
+    match codec {
+        _ if level <= 0 => None,
+        VideoCodec::H264 => Some(level as f64 / 10.0),
+        VideoCodec::H265 => Some(level as f64 / 30.0),
+    }
+}
+
+/// Properties of `path` that keep it from playing on a device with `profile`
+pub fn violations(path: &Path, profile: &DeviceProfile) -> Result<Vec<Violation>, VideoConversionError> {
+    let _span = info_span!("check", path = %path.display()).entered();
+    let probed = probe(path)?;
+    let video = probed
+        .streams
+        .iter()
+        .find(|stream| stream.codec_type.as_deref() == Some("video"))
+        .ok_or_else(|| VideoConversionError::config(format!("{} has no video", path.display())))?;
+    let audio = probed.streams.iter().find(|stream| stream.codec_type.as_deref() == Some("audio"));
+    let unknown = || "unknown".to_string();
+    let mut violations = Vec::new();
+    let mut violation = |property, found: String, expected: String, fix| {
+        violations.push(Violation { property, found, expected, fix });
+    };
+
+    // The MP4 demuxer also reads MOV, 3GP and the like, all of which remux into MP4 losslessly
+    let format = probed.format.and_then(|format| format.format_name).unwrap_or_else(unknown);
+    let is_mp4 = format.split(',').any(|name| name == "mp4");
+    if profile.container == Container::Mp4 && !is_mp4 {
+        violation("container", format, "mp4".to_string(), Fix::Remux);
+    }
+
+    let codec_name = video.codec_name.clone().unwrap_or_else(unknown);
+    if codec_name != video_codec_name(profile.video_codec) {
+        violation("video codec", codec_name, video_codec_name(profile.video_codec).to_string(), Fix::Encode);
+    } else {
+        let found = video.profile.clone().unwrap_or_else(unknown);
+        if !profile_fits(profile.video_codec, &found, profile.profile) {
+            violation("profile", found, format!("{} or lower", profile.profile), Fix::Encode);
+        }
+        let level = video.level.and_then(|level| level_value(profile.video_codec, level));
+        let allowed = profile.level.and_then(|level| level.parse::<f64>().ok());
+        if let (Some(level), Some(allowed)) = (level, allowed) {
+            // Levels are multiples of a tenth; compare with room for rounding
+            if level > allowed + 0.01 {
+                violation("level", format!("{:.1}", level), format!("{:.1} or lower", allowed), Fix::Encode);
+            }
+        }
+        if let (Some(tag), Some(expected)) = (video.codec_tag_string.as_deref(), profile.video_codec.mp4_tag()) {
+            if is_mp4 && tag != expected {
+                violation("codec tag", tag.to_string(), expected.to_string(), Fix::Remux);
+            }
+        }
+    }
+    let pixel_format = video.pix_fmt.clone().unwrap_or_else(unknown);
+    if pixel_format != profile.pixel_format {
+        violation("pixel format", pixel_format, profile.pixel_format.to_string(), Fix::Encode);
+    }
+    if let (Some(height), Some(max_height)) = (video.height, profile.max_height) {
+        if height > max_height {
+            violation("height", format!("{}p", height), format!("{}p or lower", max_height), Fix::Encode);
+        }
+    }
+
+    if let Some(audio) = audio {
+        let found = audio.codec_name.clone().unwrap_or_else(unknown);
+        if found != audio_codec_name(profile.audio_codec) {
+            violation("audio codec", found, audio_codec_name(profile.audio_codec).to_string(), Fix::EncodeAudio);
+        }
+    }
+
+    // Without the moov atom up front, players must fetch the end of the file before starting
+    if is_mp4 {
+        let atoms = verify::top_level_atoms(path).map_err(|e| VideoConversionError::io(e.to_string()))?;
+        let position = |kind: &[u8; 4]| atoms.iter().position(|atom| atom == kind);
+        if let (Some(moov), Some(mdat)) = (position(b"moov"), position(b"mdat")) {
+            if moov > mdat {
+                violation("faststart", "moov after mdat".to_string(), "moov before mdat".to_string(), Fix::Remux);
+            }
+        }
+    }
+    debug!(count = violations.len(), "checked");
+    Ok(violations)
+}
+
+/// Report which properties of `options.file` its target device does not accept and what it
+/// takes to fix them
+pub fn run(options: &CheckOptions) -> Result<(), VideoConversionError> {
+    let path = Path::new(&options.file);
+    if !path.is_file() {
+        return Err(VideoConversionError::FileNotFound(options.file.clone()));
+    }
+    let violations = violations(path, &options.device.profile())?;
+    let fix = violations.iter().map(|violation| violation.fix).max();
+    if options.json {
+        let report = serde_json::json!({ "compatible": violations.is_empty(), "fix": fix, "violations": violations });
+        let text = serde_json::to_string_pretty(&report).map_err(|e| VideoConversionError::io(e.to_string()))?;
+        println!("{}", text);
+        return Ok(());
+    }
+    for violation in &violations {
+        console!(Error, "{}: {}, needs {}", violation.property, violation.found, violation.expected);
+    }
+    match fix {
+        None => console!(Success, "{} plays on {}", options.file, options.device.name()),
+        Some(fix) => println!("{} does not play on {}: {}", options.file, options.device.name(), fix.describe()),
+    }
+    Ok(())
+}
@@ -0,0 +1,91 @@
+use std::collections::HashSet;
+use std::io::{self, BufRead, Write};
+use std::process::{Command, Stdio};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use crate::sites::{self, Site};
+use crate::urls;
+use crate::VideoConversionError;
+
+/// Commands that print the clipboard contents, tried in order until one works
+#[cfg(target_os = "macos")]
+const CLIPBOARD_COMMANDS: &[&[&str]] = &[&["pbpaste"]];
+
+#[cfg(windows)]
+const CLIPBOARD_COMMANDS: &[&[&str]] = &[&["powershell", "-NoProfile", "-Command", "Get-Clipboard"]];
+
+#[cfg(not(any(target_os = "macos", windows)))]
+const CLIPBOARD_COMMANDS: &[&[&str]] = &[
+    &["wl-paste", "--no-newline"],
+    &["xclip", "-selection", "clipboard", "-o"],
+    &["xsel", "--clipboard", "--output"],
+];
+
+/// Read the current clipboard text, or `None` if no clipboard tool is available
+pub fn read_clipboard() -> Option<String> {
+    CLIPBOARD_COMMANDS.iter().find_map(|command| {
+        let output = Command::new(command[0])
+            .args(&command[1..])
+            .stdin(Stdio::null())
+            .stderr(Stdio::null())
+            .output()
+            .ok()?;
+        output.status.success().then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+    })
+}
+
+/// Poll the clipboard and send every newly copied video URL to the returned channel
+pub fn watch(interval: Duration, all_urls: bool) -> Result<mpsc::Receiver<String>, VideoConversionError> {
+    // Whatever is on the clipboard at startup was copied before watching began
+    let initial = read_clipboard().ok_or_else(|| {
+        VideoConversionError::ToolNotFound(
+            CLIPBOARD_COMMANDS.iter().map(|c| c[0]).collect::<Vec<_>>().join(" or "),
+        )
+    })?;
+
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut last = initial;
+        let mut seen = HashSet::new();
+        loop {
+            thread::sleep(interval);
+            // Ending the channel stops the watch loop
+            if crate::shutdown::requested() {
+                return;
+            }
+            let Some(current) = read_clipboard() else { continue };
+            if current == last {
+                continue;
+            }
+            last = current.clone();
+
+            let Some(url) = video_url(&current, all_urls) else { continue };
+            if seen.insert(url.clone()) && sender.send(url).is_err() {
+                return;
+            }
+        }
+    });
+    Ok(receiver)
+}
+
+/// Canonical URL if the clipboard text is a single URL pointing at a supported video
+pub(crate) fn video_url(text: &str, all_urls: bool) -> Option<String> {
+    if text.is_empty() || text.contains(char::is_whitespace) || !text.starts_with("http") {
+        return None;
+    }
+    let source = urls::normalize(text).ok()?;
+    (all_urls || sites::profile_for(&source.url).site != Site::Generic).then_some(source.url)
+}
+
+/// Ask the user whether to queue a URL; anything but an explicit "n" counts as yes
+pub fn confirm(url: &str) -> bool {
+    print!("Download {}? [Y/n] ", url);
+    let _ = io::stdout().flush();
+    let mut answer = String::new();
+    if io::stdin().lock().read_line(&mut answer).is_err() {
+        return false;
+    }
+    !answer.trim().eq_ignore_ascii_case("n") && !answer.trim().eq_ignore_ascii_case("no")
+}
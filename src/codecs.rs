@@ -0,0 +1,201 @@
+use std::fmt;
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+
+use clap::ValueEnum;
+use serde::Serialize;
+
+use crate::VideoConversionError;
+
+/// Video codecs available for re-encoding
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum, Serialize, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum VideoCodec {
+    H264,
+    H265,
+}
+
+impl VideoCodec {
+    /// Software encoder producing this codec; both accept the same CRF scale and presets
+    pub fn encoder(&self) -> &'static str {
+        match self {
+            VideoCodec::H264 => "libx264",
+            VideoCodec::H265 => "libx265",
+        }
+    }
+
+    /// Sample entry tag to write into MP4, as QuickTime refuses HEVC tagged `hev1`
+    pub fn mp4_tag(&self) -> Option<&'static str> {
+        match self {
+            VideoCodec::H264 => None,
+            VideoCodec::H265 => Some("hvc1"),
+        }
+    }
+}
+
+/// Audio codecs available for re-encoding
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum, Debug)]
+pub enum AudioCodec {
+    Aac,
+    Mp3,
+    Opus,
+}
+
+impl AudioCodec {
+    /// ffmpeg encoder producing this codec
+    pub fn encoder(&self) -> &'static str {
+        match self {
+            AudioCodec::Aac => "aac",
+            AudioCodec::Mp3 => "libmp3lame",
+            AudioCodec::Opus => "libopus",
+        }
+    }
+
+    /// Bitrates the encoder accepts
+    fn bitrate_range(&self) -> RangeInclusive<Bitrate> {
+        match self {
+            AudioCodec::Aac | AudioCodec::Opus => Bitrate(8_000)..=Bitrate(512_000),
+            AudioCodec::Mp3 => Bitrate(8_000)..=Bitrate(320_000),
+        }
+    }
+
+    /// Bitrate the encoder picks when none is given
+    pub fn default_bitrate(&self) -> Bitrate {
+        match self {
+            AudioCodec::Aac => Bitrate(128_000),
+            AudioCodec::Mp3 => Bitrate(192_000),
+            AudioCodec::Opus => Bitrate(96_000),
+        }
+    }
+}
+
+impl fmt::Display for AudioCodec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            AudioCodec::Aac => "AAC",
+            AudioCodec::Mp3 => "MP3",
+            AudioCodec::Opus => "Opus",
+        })
+    }
+}
+
+/// Output file formats
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Debug)]
+pub enum Container {
+    Mp3,
+    Mp4,
+    /// HLS playlist with H.264/H.265 segments
+    Hls,
+    /// Several renditions behind an HLS master playlist, a DASH manifest or both
+    Abr,
+}
+
+impl Container {
+    /// File extension, which is also the name yt-dlp and ffmpeg use for the format (streaming
+    /// formats excepted)
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Container::Mp3 => "mp3",
+            Container::Mp4 => "mp4",
+            Container::Hls | Container::Abr => "m3u8",
+        }
+    }
+
+    /// Name of the format in messages
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            Container::Mp3 => "MP3",
+            Container::Mp4 => "MP4",
+            Container::Hls => "HLS",
+            Container::Abr => "adaptive bitrate",
+        }
+    }
+
+    /// Audio codec used when none is requested
+    pub fn default_audio_codec(&self) -> AudioCodec {
+        match self {
+            Container::Mp3 => AudioCodec::Mp3,
+            Container::Mp4 | Container::Hls | Container::Abr => AudioCodec::Aac,
+        }
+    }
+
+    fn supports_audio(&self, codec: AudioCodec) -> bool {
+        match self {
+            Container::Mp3 => codec == AudioCodec::Mp3,
+            Container::Mp4 => true,
+            // Players only support Opus in fragmented MP4 segments, and few of them at that
+            Container::Hls | Container::Abr => codec != AudioCodec::Opus,
+        }
+    }
+
+    /// Reject codec and bitrate combinations this container or its encoders cannot produce
+    pub fn check(&self, audio: AudioCodec, audio_bitrate: Option<Bitrate>) -> Result<(), VideoConversionError> {
+        if !self.supports_audio(audio) {
+            return Err(VideoConversionError::config(format!(
+                "{} audio cannot be stored in {} output",
+                audio,
+                self.name()
+            )));
+        }
+        match audio_bitrate {
+            Some(bitrate) if !audio.bitrate_range().contains(&bitrate) => {
+                let range = audio.bitrate_range();
+                Err(VideoConversionError::config(format!(
+                    "{} bitrate must be between {} and {}, got {}",
+                    audio,
+                    range.start(),
+                    range.end(),
+                    bitrate
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// A bitrate in bits per second, written like `192k` or `4M`
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub struct Bitrate(u64);
+
+impl Bitrate {
+    pub fn from_bits_per_second(bits: u64) -> Bitrate {
+        Bitrate(bits)
+    }
+
+    pub fn bits_per_second(&self) -> u64 {
+        self.0
+    }
+
+    pub fn bytes_per_second(&self) -> f64 {
+        self.0 as f64 / 8.0
+    }
+}
+
+impl FromStr for Bitrate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid bitrate {:?} (expected e.g. 192k or 4M)", s);
+        let (number, multiplier) = match s.trim().char_indices().last() {
+            Some((i, 'k' | 'K')) => (&s.trim()[..i], 1_000.0),
+            Some((i, 'm' | 'M')) => (&s.trim()[..i], 1_000_000.0),
+            _ => (s.trim(), 1.0),
+        };
+        let value: f64 = number.parse().map_err(|_| invalid())?;
+        if !value.is_finite() || value <= 0.0 {
+            return Err(invalid());
+        }
+        Ok(Bitrate((value * multiplier).round() as u64))
+    }
+}
+
+impl fmt::Display for Bitrate {
+    /// Shortest exact form, as accepted by ffmpeg
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            bits if bits % 1_000_000 == 0 => write!(f, "{}M", bits / 1_000_000),
+            bits if bits % 1_000 == 0 => write!(f, "{}k", bits / 1_000),
+            bits => write!(f, "{}", bits),
+        }
+    }
+}
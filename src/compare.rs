@@ -0,0 +1,165 @@
+use std::path::Path;
+use std::process::Command;
+
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info_span};
+
+use crate::filtergraph::{Dimension, FilterChain, FilterGraph, PixelFormat, RawFilter, Scale};
+use crate::progress::Progress;
+use crate::{command_output, console, logging, run_ffmpeg_log, verify, VideoConversionError};
+
+/// What `compare` measures
+#[derive(clap::Args, Debug, Clone)]
+pub struct CompareOptions {
+    /// The source video
+    original: String,
+
+    /// The encode to score against it
+    encoded: String,
+
+    /// Skip VMAF, which is far slower than SSIM and PSNR
+    #[arg(long)]
+    no_vmaf: bool,
+
+    /// Print the scores as JSON
+    #[arg(long)]
+    json: bool,
+}
+
+/// How closely an encode matches its source, averaged over all frames
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct QualityScores {
+    /// VMAF from 0 to 100, if ffmpeg was built with libvmaf
+    pub vmaf: Option<f64>,
+    /// SSIM from 0 to 1 across all planes
+    pub ssim: f64,
+    /// Average PSNR in dB; infinite for identical videos
+    pub psnr: f64,
+}
+
+#[derive(Deserialize)]
+struct ProbeOutput {
+    #[serde(default)]
+    streams: Vec<ProbeStream>,
+}
+
+#[derive(Deserialize)]
+struct ProbeStream {
+    width: Option<u32>,
+    height: Option<u32>,
+}
+
+/// Frame size of the first video stream of `path`
+fn frame_size(path: &Path) -> Result<(u32, u32), VideoConversionError> {
+    let output = command_output(
+        Command::new("ffprobe")
+            .args(["-v", "error", "-select_streams", "v:0", "-show_entries", "stream=width,height", "-of", "json"])
+            .arg(path)
+            .stderr(logging::child_stderr()),
+    )?;
+    let parsed: ProbeOutput = serde_json::from_slice(&output.stdout)
+        .map_err(|e| VideoConversionError::io("unexpected ffprobe output").caused_by(e))?;
+    parsed
+        .streams
+        .first()
+        .and_then(|stream| Some((stream.width?, stream.height?)))
+        .ok_or_else(|| VideoConversionError::config(format!("{} has no video", path.display())))
+}
+
+/// Whether this ffmpeg was built with the libvmaf filter
+fn has_libvmaf() -> bool {
+    command_output(Command::new("ffmpeg").args(["-hide_banner", "-filters"]).stderr(logging::child_stderr()))
+        .map(|output| String::from_utf8_lossy(&output.stdout).split_whitespace().any(|word| word == "libvmaf"))
+        .unwrap_or(false)
+}
+
+/// The number after `label` in the last log line mentioning `marker`, as in
+/// `SSIM Y:0.99 (20.1) ... All:0.98 (17.9)` or `VMAF score: 93.4`
+fn logged_value(log: &str, marker: &str, label: &str) -> Option<f64> {
+    let line = log.lines().rev().find(|line| line.contains(marker))?;
+    let (_, rest) = line.split_once(label)?;
+    rest.split_whitespace().next()?.parse().ok()
+}
+
+/// Score `encoded` against `original`, decoding both once; the encode is scaled to the original's
+/// frame size first, so renditions of other heights can be compared too
+pub fn compare(original: &Path, encoded: &Path, vmaf: bool, progress: &Progress) -> Result<QualityScores, VideoConversionError> {
+    let _span = info_span!("compare", original = %original.display(), encoded = %encoded.display()).entered();
+    let (width, height) = frame_size(original)?;
+    // An encode without video fails here with a clearer message than ffmpeg's
+    frame_size(encoded)?;
+    let duration = verify::ffprobe(original)?.duration;
+
+    // The reference feeds every metric, while each metric passes the encoded frames on to the next
+    let metrics: &[&str] = if vmaf { &["ssim", "psnr", "libvmaf"] } else { &["ssim", "psnr"] };
+    let references: Vec<String> = (0..metrics.len()).map(|index| format!("ref{}", index)).collect();
+    // Both inputs start at zero on a common time base, as the metrics pair frames by timestamp
+    let prepare = |chain: FilterChain| {
+        chain
+            .then(RawFilter { name: "settb".to_string(), options: vec![("expr", "AVTB".to_string())] })
+            .then(RawFilter { name: "setpts".to_string(), options: vec![("expr", "PTS-STARTPTS".to_string())] })
+            .then(PixelFormat("yuv420p".to_string()))
+    };
+    let split = RawFilter { name: "split".to_string(), options: vec![("outputs", metrics.len().to_string())] };
+    let scale = Scale { width: Dimension::Pixels(width), height: Dimension::Pixels(height) };
+    let mut graph = FilterGraph::new()
+        .chain(&["0:v:0"], prepare(FilterChain::new()).then(split), &references.iter().map(String::as_str).collect::<Vec<_>>())
+        .chain(&["1:v:0"], prepare(FilterChain::new().then(scale)), &["enc0"]);
+    for (index, metric) in metrics.iter().enumerate() {
+        let input = format!("enc{}", index);
+        let output = format!("enc{}", index + 1);
+        let chain = FilterChain::new().then(RawFilter { name: metric.to_string(), options: Vec::new() });
+        // The last metric's output goes to the null muxer
+        let outputs: &[&str] = if index + 1 < metrics.len() { &[&output] } else { &[] };
+        graph = graph.chain(&[&input, &references[index]], chain, outputs);
+    }
+    debug!(graph = %graph, "comparing");
+
+    let mut command = Command::new("ffmpeg");
+    command
+        .args(["-hide_banner", "-loglevel", "error", "-progress", "pipe:1", "-nostats", "-i"])
+        .arg(original)
+        .arg("-i")
+        .arg(encoded)
+        .args(graph.args())
+        .args(["-f", "null", "-"]);
+    let log = run_ffmpeg_log(&mut command, duration, progress).map_err(VideoConversionError::conversion)?;
+
+    let missing = |metric: &str| VideoConversionError::encoding(format!("ffmpeg reported no {} score", metric));
+    Ok(QualityScores {
+        vmaf: match vmaf {
+            true => Some(logged_value(&log, "VMAF score", "VMAF score:").ok_or_else(|| missing("VMAF"))?),
+            false => None,
+        },
+        ssim: logged_value(&log, "SSIM Y:", "All:").ok_or_else(|| missing("SSIM"))?,
+        psnr: logged_value(&log, "PSNR y:", "average:").ok_or_else(|| missing("PSNR"))?,
+    })
+}
+
+/// Print how closely `options.encoded` matches `options.original`
+pub fn run(options: &CompareOptions, progress: &Progress) -> Result<(), VideoConversionError> {
+    for file in [&options.original, &options.encoded] {
+        if !Path::new(file).is_file() {
+            return Err(VideoConversionError::FileNotFound(file.clone()));
+        }
+    }
+    let vmaf = !options.no_vmaf && has_libvmaf();
+    if !options.no_vmaf && !vmaf {
+        console!(Warning, "ffmpeg was built without libvmaf; reporting SSIM and PSNR only");
+    }
+    if !options.json {
+        println!("Comparing {} with {}...", options.encoded, options.original);
+    }
+    let scores = compare(Path::new(&options.original), Path::new(&options.encoded), vmaf, progress)?;
+    if options.json {
+        let json = serde_json::to_string_pretty(&scores).map_err(|e| VideoConversionError::io(e.to_string()))?;
+        println!("{}", json);
+        return Ok(());
+    }
+    if let Some(vmaf) = scores.vmaf {
+        println!("VMAF: {:>8.2}", vmaf);
+    }
+    println!("SSIM: {:>8.4}", scores.ssim);
+    println!("PSNR: {:>8.2} dB", scores.psnr);
+    Ok(())
+}
@@ -0,0 +1,167 @@
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::VideoConversionError;
+
+/// Environment variable overriding the config file location
+const CONFIG_ENV: &str = "VIDEELOW_CONFIG";
+
+/// Persistent user settings, stored as JSON in the platform's config directory
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct Config {
+    /// Named option sets, stored as the command line arguments they expand to
+    pub profiles: BTreeMap<String, Vec<String>>,
+    /// Channels and playlists `videelow sync` downloads new videos of, by name
+    pub sources: BTreeMap<String, SyncSource>,
+    /// YouTube Data API key used for channel and playlist listings unless `--youtube-api-key` is given
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub youtube_api_key: Option<String>,
+    /// AcoustID API key used by `--music-tags` unless `--acoustid-key` is given
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub acoustid_key: Option<String>,
+}
+
+/// A channel or playlist kept in sync
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SyncSource {
+    pub url: String,
+    /// Download options for its videos, as command line arguments
+    #[serde(default)]
+    pub options: Vec<String>,
+}
+
+/// Location of the config file: `$VIDEELOW_CONFIG`, else `videelow/config.json` in the user config directory
+pub fn config_path() -> Result<PathBuf, VideoConversionError> {
+    if let Some(path) = std::env::var_os(CONFIG_ENV) {
+        return Ok(PathBuf::from(path));
+    }
+    let base = if cfg!(windows) {
+        std::env::var_os("APPDATA").map(PathBuf::from)
+    } else {
+        std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+    };
+    base.map(|dir| dir.join("videelow").join("config.json")).ok_or_else(|| {
+        VideoConversionError::config(format!("cannot locate the config directory; set {}", CONFIG_ENV))
+    })
+}
+
+/// Directory for job records and logs: `videelow` in the user state directory
+pub fn state_dir() -> Result<PathBuf, VideoConversionError> {
+    let base = if cfg!(windows) {
+        std::env::var_os("LOCALAPPDATA").map(PathBuf::from)
+    } else {
+        std::env::var_os("XDG_STATE_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local").join("state")))
+    };
+    base.map(|dir| dir.join("videelow"))
+        .ok_or_else(|| VideoConversionError::io("cannot locate the state directory"))
+}
+
+impl Config {
+    /// Read the config file; a missing file yields the defaults
+    pub fn load() -> Result<Config, VideoConversionError> {
+        let path = config_path()?;
+        match std::fs::read_to_string(&path) {
+            Ok(text) => serde_json::from_str(&text).map_err(|e| {
+                VideoConversionError::config(format!("invalid config file {}", path.display())).caused_by(e)
+            }),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Config::default()),
+            Err(e) => Err(VideoConversionError::io(format!("Failed to read {}", path.display())).caused_by(e)),
+        }
+    }
+
+    /// Write the config file, creating its directory if needed
+    pub fn save(&self) -> Result<PathBuf, VideoConversionError> {
+        let path = config_path()?;
+        let io_error = |e: std::io::Error| VideoConversionError::io(format!("Failed to write {}", path.display())).caused_by(e);
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(io_error)?;
+        }
+        let json = serde_json::to_string_pretty(self).map_err(|e| VideoConversionError::io(e.to_string()))?;
+        std::fs::write(&path, json + "\n").map_err(io_error)?;
+        Ok(path)
+    }
+
+    /// Arguments saved under `name`
+    pub fn profile(&self, name: &str) -> Result<&[String], VideoConversionError> {
+        self.profiles.get(name).map(Vec::as_slice).ok_or_else(|| {
+            VideoConversionError::config(format!(
+                "unknown profile {:?} (see `videelow profile list`)",
+                name
+            ))
+        })
+    }
+
+    /// Source saved under `name`
+    pub fn source(&self, name: &str) -> Result<&SyncSource, VideoConversionError> {
+        self.sources.get(name).ok_or_else(|| {
+            VideoConversionError::config(format!("unknown sync source {:?} (see `videelow sync list`)", name))
+        })
+    }
+}
+
+/// Insert the saved arguments of every `--profile NAME` right after it, so later options override the profile
+pub fn expand_profiles(args: Vec<OsString>) -> Result<Vec<OsString>, VideoConversionError> {
+    let mut config = None;
+    let mut expanded = Vec::with_capacity(args.len());
+    let mut args = args.into_iter();
+
+    while let Some(arg) = args.next() {
+        let name = match arg.to_str() {
+            Some("--") => {
+                expanded.push(arg);
+                expanded.extend(args.by_ref());
+                break;
+            }
+            Some("--profile") => {
+                expanded.push(arg);
+                match args.next() {
+                    Some(name) => {
+                        expanded.push(name.clone());
+                        name.to_string_lossy().into_owned()
+                    }
+                    // Let clap report the missing value
+                    None => break,
+                }
+            }
+            Some(flag) if flag.starts_with("--profile=") => {
+                expanded.push(arg.clone());
+                flag["--profile=".len()..].to_string()
+            }
+            _ => {
+                expanded.push(arg);
+                continue;
+            }
+        };
+
+        if config.is_none() {
+            config = Some(Config::load()?);
+        }
+        if let Some(config) = &config {
+            expanded.extend(config.profile(&name)?.iter().map(OsString::from));
+        }
+    }
+
+    Ok(expanded)
+}
+
+/// Render arguments for display, quoting the ones a shell would split
+pub fn display_args(args: &[String]) -> String {
+    args.iter()
+        .map(|arg| {
+            if arg.is_empty() || arg.contains(|c: char| c.is_whitespace() || c == '"' || c == '\'') {
+                format!("{:?}", arg)
+            } else {
+                arg.clone()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
@@ -0,0 +1,194 @@
+use std::fs::{create_dir_all, read_dir};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use tracing::{error, info_span};
+
+use crate::backend::Ffmpeg;
+use crate::codecs::Container;
+use crate::devices::Device;
+use crate::pipeline::{Encode, Pipeline};
+use crate::progress::{Progress, ProgressEvent};
+use crate::{check, command_output, console, logging, shutdown, EncodeOptions, VideoConversionError};
+
+/// Extensions of the videos converted when no `--extension` is given
+pub(crate) const VIDEO_EXTENSIONS: &[&str] =
+    &["mp4", "m4v", "mov", "mkv", "webm", "avi", "wmv", "flv", "mpg", "mpeg", "ts", "mts", "m2ts", "3gp"];
+
+/// What `convert-dir` converts and where to
+#[derive(clap::Args, Debug, Clone)]
+pub struct ConvertDirOptions {
+    /// Folder to convert, including its subfolders
+    dir: String,
+
+    /// Output directory; the folder structure below `dir` is recreated in it
+    #[arg(short, long, default_value = "Processed")]
+    output_dir: String,
+
+    /// Only convert files with this extension (repeatable; default: common video extensions)
+    #[arg(long = "extension", value_name = "EXT")]
+    extensions: Vec<String>,
+
+    /// Only convert videos in this codec, as ffprobe names it: h264, hevc, vp9, av1... (repeatable)
+    #[arg(long = "codec", value_name = "CODEC")]
+    codecs: Vec<String>,
+
+    /// Skip videos that already play on the --device (default: QuickTime) without changes
+    #[arg(long)]
+    skip_compatible: bool,
+
+    #[command(flatten)]
+    encode: EncodeOptions,
+}
+
+/// Why a file was left alone
+enum Skipped {
+    Filtered,
+    Compatible,
+    Converted,
+}
+
+/// Videos below `dir` with one of `extensions`, in a stable order; `exclude` is not entered, so
+/// an output directory inside the source tree is not converted again
+fn collect_videos(dir: &Path, extensions: &[String], exclude: Option<&Path>, videos: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    let mut entries: Vec<_> = read_dir(dir)?.collect::<Result<_, _>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        let path = entry.path();
+        // Symbolic links to folders are not followed, as they may loop
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            if exclude.is_some_and(|exclude| path.canonicalize().is_ok_and(|path| path == exclude)) {
+                continue;
+            }
+            collect_videos(&path, extensions, exclude, videos)?;
+        } else if path.is_file() {
+            let matches = path
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .is_some_and(|ext| extensions.iter().any(|wanted| wanted.eq_ignore_ascii_case(ext)));
+            if matches {
+                videos.push(path);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// ffprobe's name for the codec of the first video stream of `path`
+fn video_codec(path: &Path) -> Result<Option<String>, VideoConversionError> {
+    let output = command_output(
+        Command::new("ffprobe")
+            .args(["-v", "error", "-select_streams", "v:0", "-show_entries", "stream=codec_name", "-of", "csv=p=0"])
+            .arg(path)
+            .stderr(logging::child_stderr()),
+    )?;
+    if !output.status.success() {
+        return Err(VideoConversionError::encoding(format!("ffprobe cannot read {}", path.display())));
+    }
+    let codec = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    Ok(Some(codec).filter(|codec| !codec.is_empty()))
+}
+
+/// Convert one video unless the options rule it out
+fn convert_one(
+    options: &ConvertDirOptions,
+    source: &Path,
+    output: &Path,
+    progress: &Progress,
+) -> Result<Option<Skipped>, VideoConversionError> {
+    if output.exists() {
+        return Ok(Some(Skipped::Converted));
+    }
+    if !options.codecs.is_empty() {
+        let codec = video_codec(source)?;
+        if !codec.is_some_and(|codec| options.codecs.iter().any(|wanted| wanted.eq_ignore_ascii_case(&codec))) {
+            return Ok(Some(Skipped::Filtered));
+        }
+    }
+    if options.skip_compatible {
+        let profile = options.encode.device.unwrap_or(Device::Quicktime).profile();
+        if check::violations(source, &profile)?.is_empty() {
+            return Ok(Some(Skipped::Compatible));
+        }
+    }
+    if let Some(parent) = output.parent() {
+        create_dir_all(parent).map_err(|e| VideoConversionError::io(e.to_string()))?;
+    }
+    let output = output.to_string_lossy();
+    Pipeline::new()
+        .then(Encode { output: &output, encode: &options.encode, transcoder: &Ffmpeg })
+        .run_on(source, progress)?;
+    Ok(None)
+}
+
+/// Convert every matching video below `options.dir` into an MP4 at the same relative path below
+/// the output directory, continuing past failures and reporting the first error at the end
+pub fn run(options: &ConvertDirOptions, progress: &Progress) -> Result<(), VideoConversionError> {
+    let dir = Path::new(&options.dir);
+    if !dir.is_dir() {
+        return Err(VideoConversionError::FileNotFound(options.dir.clone()));
+    }
+    Container::Mp4.check(options.encode.audio_codec(Container::Mp4), options.encode.audio_bitrate)?;
+    let extensions: Vec<String> = match options.extensions.is_empty() {
+        true => VIDEO_EXTENSIONS.iter().map(|ext| ext.to_string()).collect(),
+        false => options.extensions.iter().map(|ext| ext.trim_start_matches('.').to_string()).collect(),
+    };
+    let output_dir = Path::new(&options.output_dir);
+    let exclude = output_dir.canonicalize().ok();
+    let _span = info_span!("convert_dir", dir = %dir.display(), output = %output_dir.display()).entered();
+
+    let mut videos = Vec::new();
+    collect_videos(dir, &extensions, exclude.as_deref(), &mut videos)
+        .map_err(|e| VideoConversionError::io(format!("Failed to read {}", dir.display())).caused_by(e))?;
+    let total = videos.len();
+    println!("Found {} videos in {}", total, dir.display());
+
+    let (mut converted, mut filtered, mut compatible, mut existing, mut failed) = (0, 0, 0, 0, 0);
+    let mut first_error = None;
+    for (index, source) in videos.iter().enumerate() {
+        if shutdown::requested() {
+            return Err(VideoConversionError::Interrupted);
+        }
+        let relative = source.strip_prefix(dir).unwrap_or(source);
+        let output = output_dir.join(relative).with_extension(Container::Mp4.extension());
+        console!(Download, "[{}/{}] {}", index + 1, total, relative.display());
+        progress.emit(ProgressEvent::ItemStarted { index: index + 1, total, url: source.display().to_string() });
+
+        match convert_one(options, source, &output, progress) {
+            Ok(None) => {
+                converted += 1;
+                progress.emit(ProgressEvent::Finished { output: output.display().to_string() });
+            }
+            Ok(Some(Skipped::Filtered)) => {
+                println!("Skipping {}: codec not selected by --codec", relative.display());
+                filtered += 1;
+            }
+            Ok(Some(Skipped::Compatible)) => {
+                println!("Skipping {}: already compatible", relative.display());
+                compatible += 1;
+            }
+            Ok(Some(Skipped::Converted)) => {
+                println!("Skipping {}: {} exists", relative.display(), output.display());
+                existing += 1;
+            }
+            Err(VideoConversionError::Interrupted) => return Err(VideoConversionError::Interrupted),
+            Err(e) => {
+                error!(path = %source.display(), error = %e, "conversion failed");
+                console!(Error, "{}: {}", relative.display(), e);
+                progress.emit(ProgressEvent::Failed { error: e.to_string(), exit_code: e.exit_code() });
+                failed += 1;
+                first_error.get_or_insert(e);
+            }
+        }
+    }
+
+    println!(
+        "{} converted, {} already compatible, {} previously converted, {} not matching --codec, {} failed",
+        converted, compatible, existing, filtered, failed
+    );
+    match first_error {
+        Some(first) => Err(VideoConversionError::BatchFailed { failed, total, first: Box::new(first) }),
+        None => Ok(()),
+    }
+}
@@ -0,0 +1,147 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::metadata::VideoInfo;
+
+/// A calendar date in the proleptic Gregorian calendar
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Date {
+    pub year: i32,
+    pub month: u32,
+    pub day: u32,
+}
+
+impl Date {
+    /// Parse yt-dlp's compact `YYYYMMDD` form
+    pub fn from_compact(s: &str) -> Option<Date> {
+        if s.len() != 8 || !s.chars().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+        Date::new(s[0..4].parse().ok()?, s[4..6].parse().ok()?, s[6..8].parse().ok()?)
+    }
+
+    /// Build a date, rejecting impossible month/day combinations
+    pub fn new(year: i32, month: u32, day: u32) -> Option<Date> {
+        let days_in_month = match month {
+            1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+            4 | 6 | 9 | 11 => 30,
+            2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+            2 => 28,
+            _ => return None,
+        };
+        (1..=days_in_month).contains(&day).then_some(Date { year, month, day })
+    }
+
+    /// Parse a command-line date given as `YYYY-MM-DD` or `YYYYMMDD`
+    pub fn parse(s: &str) -> Result<Date, String> {
+        let compact: String = s.chars().filter(|c| *c != '-').collect();
+        // Bytes rather than slices, which panic inside a multibyte character
+        let dashes_ok = s.len() == 8 || (s.len() == 10 && s.as_bytes()[4] == b'-' && s.as_bytes()[7] == b'-');
+        dashes_ok
+            .then(|| Date::from_compact(&compact))
+            .flatten()
+            .ok_or_else(|| format!("invalid date {:?} (expected YYYY-MM-DD)", s))
+    }
+
+    /// The date as yt-dlp's compact `YYYYMMDD`
+    pub fn compact(self) -> String {
+        format!("{:04}{:02}{:02}", self.year, self.month, self.day)
+    }
+
+    /// The calendar day before this one
+    pub fn previous_day(self) -> Date {
+        Date::from_days_since_epoch(self.days_since_epoch() - 1)
+    }
+
+    /// Inverse of `days_since_epoch` (Howard Hinnant's civil_from_days)
+    pub fn from_days_since_epoch(days: i64) -> Date {
+        let z = days + 719468;
+        let era = if z >= 0 { z } else { z - 146096 } / 146097;
+        let day_of_era = z - era * 146097;
+        let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let mp = (5 * day_of_year + 2) / 153;
+        let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+        let year = (year_of_era + era * 400 + if month <= 2 { 1 } else { 0 }) as i32;
+        Date { year, month, day }
+    }
+
+    /// Days since 1970-01-01 (Howard Hinnant's days_from_civil)
+    pub fn days_since_epoch(self) -> i64 {
+        let year = if self.month <= 2 { self.year - 1 } else { self.year } as i64;
+        let era = if year >= 0 { year } else { year - 399 } / 400;
+        let year_of_era = year - era * 400;
+        let month = self.month as i64;
+        let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + self.day as i64 - 1;
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+        era * 146097 + day_of_era - 719468
+    }
+
+    /// Midnight UTC at the start of this date
+    pub fn to_system_time(self) -> SystemTime {
+        let seconds = self.days_since_epoch() * 86_400;
+        if seconds >= 0 {
+            UNIX_EPOCH + Duration::from_secs(seconds as u64)
+        } else {
+            UNIX_EPOCH - Duration::from_secs(seconds.unsigned_abs())
+        }
+    }
+}
+
+impl std::fmt::Display for Date {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+}
+
+/// `time` as an RFC 2822 date in UTC, e.g. `Thu, 01 Jan 1970 00:00:00 +0000`, as RSS expects
+pub fn rfc2822(time: SystemTime) -> String {
+    const WEEKDAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+    const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+    let seconds = time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let days = (seconds / 86_400) as i64;
+    let date = Date::from_days_since_epoch(days);
+    let time = seconds % 86_400;
+    format!(
+        "{}, {:02} {} {:04} {:02}:{:02}:{:02} +0000",
+        WEEKDAYS[(days % 7) as usize],
+        date.day,
+        MONTHS[date.month as usize - 1],
+        date.year,
+        time / 3600,
+        time / 60 % 60,
+        time % 60
+    )
+}
+
+/// When the video was published: the exact timestamp if the site reports one, else the upload date
+pub fn upload_time(info: &VideoInfo) -> Option<SystemTime> {
+    match info.timestamp {
+        Some(ts) if ts >= 0 => Some(UNIX_EPOCH + Duration::from_secs(ts as u64)),
+        _ => info.upload_date.as_deref().and_then(Date::from_compact).map(|d| d.to_system_time()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_dashed_and_compact_dates() {
+        assert_eq!(Date::parse("2024-02-29"), Ok(Date { year: 2024, month: 2, day: 29 }));
+        assert_eq!(Date::parse("20240131"), Ok(Date { year: 2024, month: 1, day: 31 }));
+    }
+
+    #[test]
+    fn rejects_impossible_or_malformed_dates() {
+        for input in ["2023-02-29", "2024-13-01", "2024-0101", "2024/01/01", "24-01-01", "20é-01-01", "202é-0101-"] {
+            assert!(Date::parse(input).is_err(), "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn previous_day_crosses_months_and_years() {
+        assert_eq!(Date::parse("2024-03-01").unwrap().previous_day(), Date { year: 2024, month: 2, day: 29 });
+        assert_eq!(Date::parse("2024-01-01").unwrap().previous_day(), Date { year: 2023, month: 12, day: 31 });
+    }
+}
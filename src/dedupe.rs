@@ -0,0 +1,288 @@
+use std::collections::BTreeMap;
+use std::fs::{self, read_dir};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::UNIX_EPOCH;
+
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info, info_span};
+
+use crate::convert_dir::VIDEO_EXTENSIONS;
+use crate::{command_output, config, console, logging, quota, segmented, VideoConversionError};
+
+/// Frames sampled across each video for its fingerprint
+const FRAMES: usize = 16;
+
+/// Default largest average number of differing bits, out of 64 per frame, between near-duplicates
+const DEFAULT_THRESHOLD: f64 = 10.0;
+
+/// What `dedupe` scans and does with the duplicates it finds
+#[derive(clap::Args, Debug, Clone)]
+pub struct DedupeOptions {
+    /// Library folder to scan, including its subfolders
+    #[arg(default_value = "Processed")]
+    dir: String,
+
+    /// Largest average number of differing bits per sampled frame (out of 64) for two videos to
+    /// count as the same; raise it to catch heavier re-encodes, lower it on false matches
+    #[arg(long, default_value_t = DEFAULT_THRESHOLD)]
+    threshold: f64,
+
+    /// Delete the duplicates and their sidecars, keeping the oldest copy of each video
+    #[arg(long)]
+    remove: bool,
+
+    /// Print the duplicates as JSON
+    #[arg(long)]
+    json: bool,
+}
+
+/// Perceptual hashes of frames sampled evenly across a video
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Fingerprint {
+    pub duration: f64,
+    /// One 64-bit difference hash per sampled frame, which survives re-encoding and rescaling
+    pub frames: Vec<u64>,
+}
+
+impl Fingerprint {
+    /// Average number of differing bits per frame, or `None` when the lengths differ too much
+    /// for the videos to be copies of each other
+    pub fn distance(&self, other: &Fingerprint) -> Option<f64> {
+        let tolerance = (self.duration.max(other.duration) * 0.02).max(2.0);
+        if (self.duration - other.duration).abs() > tolerance {
+            return None;
+        }
+        let pairs = self.frames.len().min(other.frames.len());
+        if pairs == 0 {
+            return None;
+        }
+        let bits: u32 = self.frames.iter().zip(&other.frames).map(|(a, b)| (a ^ b).count_ones()).sum();
+        Some(bits as f64 / pairs as f64)
+    }
+}
+
+/// A fingerprint stored with what identifies the file version it was computed from
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct CachedFingerprint {
+    size: u64,
+    modified: u64,
+    fingerprint: Fingerprint,
+}
+
+/// Fingerprints are kept in the state directory, as computing them decodes part of every video
+fn cache_path() -> Result<PathBuf, VideoConversionError> {
+    Ok(config::state_dir()?.join("fingerprints.json"))
+}
+
+fn load_cache() -> BTreeMap<PathBuf, CachedFingerprint> {
+    let Ok(path) = cache_path() else { return BTreeMap::new() };
+    match fs::read_to_string(&path) {
+        Ok(text) => serde_json::from_str(&text).unwrap_or_else(|e| {
+            debug!(error = %e, "ignoring invalid fingerprint cache");
+            BTreeMap::new()
+        }),
+        Err(_) => BTreeMap::new(),
+    }
+}
+
+fn save_cache(cache: &BTreeMap<PathBuf, CachedFingerprint>) -> Result<(), VideoConversionError> {
+    let path = cache_path()?;
+    let io_error = |e: std::io::Error| VideoConversionError::io(format!("Failed to write {}", path.display())).caused_by(e);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(io_error)?;
+    }
+    let json = serde_json::to_string_pretty(cache).map_err(|e| VideoConversionError::io(e.to_string()))?;
+    fs::write(&path, json + "\n").map_err(io_error)
+}
+
+/// Difference hash of a 9x8 grayscale frame: one bit per pixel brighter than its right neighbour
+fn difference_hash(pixels: &[u8]) -> u64 {
+    let mut hash = 0;
+    for row in pixels.chunks_exact(9) {
+        for pair in row.windows(2) {
+            hash = hash << 1 | u64::from(pair[0] > pair[1]);
+        }
+    }
+    hash
+}
+
+/// Fingerprint of `path`, or `None` for files without video
+pub fn fingerprint(path: &Path) -> Result<Option<Fingerprint>, VideoConversionError> {
+    let _span = info_span!("fingerprint", path = %path.display()).entered();
+    let Some(duration) = segmented::probe_duration(&path.to_string_lossy()).filter(|duration| *duration > 0.0) else {
+        return Ok(None);
+    };
+    // One frame from the middle of each of FRAMES equal parts, shrunk to 9x8 gray pixels
+    let rate = FRAMES as f64 / duration;
+    let filter = format!("fps=fps={}:start_time={},scale=9:8:flags=area,format=gray", rate, duration / (2 * FRAMES) as f64);
+    let output = command_output(
+        Command::new("ffmpeg")
+            .args(["-hide_banner", "-v", "error", "-nostdin", "-i"])
+            .arg(path)
+            .args(["-map", "0:V:0?", "-vf", &filter, "-frames:v", &FRAMES.to_string(), "-f", "rawvideo", "pipe:1"])
+            .stderr(logging::child_stderr()),
+    )?;
+    if !output.status.success() {
+        return Err(VideoConversionError::io(format!("ffmpeg could not sample frames of {}", path.display())));
+    }
+    let frames: Vec<u64> = output.stdout.chunks_exact(72).map(difference_hash).collect();
+    debug!(frames = frames.len(), "fingerprinted");
+    Ok((!frames.is_empty()).then_some(Fingerprint { duration, frames }))
+}
+
+/// Fingerprint of `path` from the cache while the file is unchanged, computed and cached otherwise
+fn cached_fingerprint(
+    path: &Path,
+    cache: &mut BTreeMap<PathBuf, CachedFingerprint>,
+) -> Result<Option<Fingerprint>, VideoConversionError> {
+    let key = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let metadata = fs::metadata(path).map_err(|e| VideoConversionError::io(format!("{}", path.display())).caused_by(e))?;
+    let size = metadata.len();
+    let modified = metadata.modified().ok().and_then(|time| time.duration_since(UNIX_EPOCH).ok()).map_or(0, |d| d.as_secs());
+    if let Some(cached) = cache.get(&key).filter(|cached| cached.size == size && cached.modified == modified) {
+        return Ok(Some(cached.fingerprint.clone()));
+    }
+    println!("Fingerprinting {}...", path.display());
+    let fingerprint = fingerprint(path)?;
+    match &fingerprint {
+        Some(fingerprint) => cache.insert(key, CachedFingerprint { size, modified, fingerprint: fingerprint.clone() }),
+        None => cache.remove(&key),
+    };
+    Ok(fingerprint)
+}
+
+/// Videos below `dir`, without following symbolic links to folders
+fn collect_videos(dir: &Path, videos: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    let mut entries: Vec<_> = read_dir(dir)?.collect::<Result<_, _>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        let path = entry.path();
+        if entry.file_type()?.is_dir() {
+            collect_videos(&path, videos)?;
+        } else if path.is_file()
+            && path
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .is_some_and(|ext| VIDEO_EXTENSIONS.iter().any(|known| known.eq_ignore_ascii_case(ext)))
+        {
+            videos.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Fingerprints of the videos below `dir`, oldest first; files that cannot be read are skipped
+fn library(dir: &Path) -> Result<Vec<(PathBuf, Fingerprint)>, VideoConversionError> {
+    let mut videos = Vec::new();
+    collect_videos(dir, &mut videos)
+        .map_err(|e| VideoConversionError::io(format!("Failed to read {}", dir.display())).caused_by(e))?;
+    videos.sort_by_key(|path| fs::metadata(path).and_then(|metadata| metadata.modified()).ok());
+
+    let mut cache = load_cache();
+    let mut library = Vec::new();
+    for path in videos {
+        match cached_fingerprint(&path, &mut cache) {
+            Ok(Some(fingerprint)) => library.push((path, fingerprint)),
+            Ok(None) => {}
+            Err(e) => console!(Warning, "skipping {}: {}", path.display(), e),
+        }
+    }
+    cache.retain(|path, _| path.exists());
+    save_cache(&cache)?;
+    Ok(library)
+}
+
+/// A copy of an earlier video
+#[derive(Serialize, Debug, Clone)]
+pub struct Duplicate {
+    pub path: PathBuf,
+    /// Average number of differing bits per sampled frame
+    pub distance: f64,
+}
+
+/// A video with the later copies of it
+#[derive(Serialize, Debug, Clone)]
+pub struct DuplicateGroup {
+    pub original: PathBuf,
+    pub duplicates: Vec<Duplicate>,
+}
+
+/// Group the videos below `dir` that are near-duplicates, each under the oldest copy
+pub fn find(dir: &Path, threshold: f64) -> Result<Vec<DuplicateGroup>, VideoConversionError> {
+    let library = library(dir)?;
+    let mut groups: Vec<(usize, DuplicateGroup)> = Vec::new();
+    for (index, (path, fingerprint)) in library.iter().enumerate() {
+        let closest = groups
+            .iter_mut()
+            .filter_map(|(original, group)| Some((fingerprint.distance(&library[*original].1)?, group)))
+            .filter(|(distance, _)| *distance <= threshold)
+            .min_by(|(a, _), (b, _)| a.total_cmp(b));
+        match closest {
+            Some((distance, group)) => group.duplicates.push(Duplicate { path: path.clone(), distance }),
+            None => groups.push((index, DuplicateGroup { original: path.clone(), duplicates: Vec::new() })),
+        }
+    }
+    Ok(groups.into_iter().map(|(_, group)| group).filter(|group| !group.duplicates.is_empty()).collect())
+}
+
+/// Warn if the freshly downloaded `output` looks like a video already below `library_dir`;
+/// failures only warn, as the download itself is complete
+pub(crate) fn flag_duplicate(output: &Path, library_dir: &Path) {
+    let result = (|| {
+        let mut cache = load_cache();
+        let Some(fingerprint) = cached_fingerprint(output, &mut cache)? else { return Ok(None) };
+        save_cache(&cache)?;
+        let output = fs::canonicalize(output).unwrap_or_else(|_| output.to_path_buf());
+        let closest = library(library_dir)?
+            .into_iter()
+            .filter(|(path, _)| fs::canonicalize(path).map_or(true, |path| path != output))
+            .filter_map(|(path, other)| Some((fingerprint.distance(&other)?, path)))
+            .filter(|(distance, _)| *distance <= DEFAULT_THRESHOLD)
+            .min_by(|(a, _), (b, _)| a.total_cmp(b));
+        Ok::<_, VideoConversionError>(closest)
+    })();
+    match result {
+        Ok(Some((distance, path))) => {
+            info!(original = %path.display(), distance, "near-duplicate downloaded");
+            console!(Warning, "{} looks like a copy of {} already in the library", output.display(), path.display());
+        }
+        Ok(None) => {}
+        Err(e) => console!(Warning, "could not check {} for duplicates: {}", output.display(), e),
+    }
+}
+
+/// List near-duplicate videos in a library, optionally deleting all but the oldest copy
+pub fn run(options: &DedupeOptions) -> Result<(), VideoConversionError> {
+    let groups = find(Path::new(&options.dir), options.threshold)?;
+    if options.json {
+        let text = serde_json::to_string_pretty(&groups).map_err(|e| VideoConversionError::io(e.to_string()))?;
+        println!("{}", text);
+    } else if groups.is_empty() {
+        println!("No duplicates found in {}", options.dir);
+    } else {
+        for group in &groups {
+            println!("{}", group.original.display());
+            for duplicate in &group.duplicates {
+                println!("  duplicate: {} (distance {:.1})", duplicate.path.display(), duplicate.distance);
+            }
+        }
+    }
+    if !options.remove {
+        return Ok(());
+    }
+    let mut removed = 0;
+    for duplicate in groups.iter().flat_map(|group| &group.duplicates) {
+        for path in std::iter::once(duplicate.path.clone()).chain(quota::sidecars(&duplicate.path)) {
+            match fs::remove_file(&path) {
+                Ok(()) => info!(path = %path.display(), "removed duplicate"),
+                Err(e) => console!(Warning, "could not delete {}: {}", path.display(), e),
+            }
+        }
+        removed += 1;
+    }
+    if removed > 0 {
+        console!(Success, "Removed {} duplicate(s)", removed);
+    }
+    Ok(())
+}
@@ -0,0 +1,71 @@
+use clap::ValueEnum;
+
+use crate::codecs::{AudioCodec, Container, VideoCodec};
+
+/// Playback devices with known-good encoding settings
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum, Debug)]
+pub enum Device {
+    /// QuickTime Player and other Apple software players on macOS
+    Quicktime,
+    /// iPhone and iPad
+    Iphone,
+    /// Apple TV 4K
+    Appletv,
+    /// Android TV and Google TV boxes, many of which decode 1080p at most
+    AndroidTv,
+    /// PlayStation 5 media player
+    Ps5,
+    /// Browsers through the HTML5 video element
+    Web,
+}
+
+/// Encoding settings a device is known to play
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeviceProfile {
+    pub video_codec: VideoCodec,
+    /// Codec profile as named by ffmpeg's `-profile:v`
+    pub profile: &'static str,
+    /// Highest codec level the device decodes, if it handles any the encoder picks
+    pub level: Option<&'static str>,
+    pub pixel_format: &'static str,
+    pub audio_codec: AudioCodec,
+    pub container: Container,
+    /// Tallest frames the device plays; larger sources are scaled down
+    pub max_height: Option<u32>,
+}
+
+impl Device {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Device::Quicktime => "QuickTime",
+            Device::Iphone => "iPhone",
+            Device::Appletv => "Apple TV",
+            Device::AndroidTv => "Android TV",
+            Device::Ps5 => "PS5",
+            Device::Web => "web browsers",
+        }
+    }
+
+    pub fn profile(&self) -> DeviceProfile {
+        let h264 = DeviceProfile {
+            video_codec: VideoCodec::H264,
+            profile: "high",
+            level: None,
+            // 4:2:0 is the only chroma subsampling hardware decoders handle across the board
+            pixel_format: "yuv420p",
+            audio_codec: AudioCodec::Aac,
+            container: Container::Mp4,
+            max_height: None,
+        };
+        match self {
+            Device::Quicktime => h264,
+            // 4K at 30 fps fits level 5.1
+            Device::Iphone => DeviceProfile { level: Some("5.1"), ..h264 },
+            Device::Appletv => DeviceProfile { video_codec: VideoCodec::H265, profile: "main", level: Some("5.1"), ..h264 },
+            // The Android TV compatibility definition only guarantees 1080p H.264
+            Device::AndroidTv => DeviceProfile { level: Some("4.2"), max_height: Some(1080), ..h264 },
+            Device::Ps5 => DeviceProfile { level: Some("5.2"), ..h264 },
+            Device::Web => DeviceProfile { level: Some("5.1"), ..h264 },
+        }
+    }
+}
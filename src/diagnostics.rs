@@ -0,0 +1,252 @@
+use std::cell::Cell;
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, ExitStatus};
+use std::sync::Mutex;
+
+use miette::{Diagnostic, GraphicalReportHandler, GraphicalTheme, LabeledSpan, NamedSource, SourceCode};
+
+use crate::progress::Stage;
+use crate::{logging, metrics, VideoConversionError};
+
+/// stderr lines quoted when a tool fails; the end of the output is where tools explain their failure
+pub(crate) const STDERR_EXCERPT_LINES: usize = 12;
+
+/// Failed tool runs kept to explain the error that ends the program
+const FAILURES_KEPT: usize = 16;
+
+/// Most recent failed tool runs, newest last
+static FAILURES: Mutex<VecDeque<ToolFailure>> = Mutex::new(VecDeque::new());
+
+thread_local! {
+    /// Pipeline stage running on this thread, naming the step tools fail in
+    static STAGE: Cell<Option<Stage>> = const { Cell::new(None) };
+}
+
+/// A tool that exited unsuccessfully
+#[derive(Debug, Clone)]
+struct ToolFailure {
+    /// Base name of the program, which error messages mention
+    program: String,
+    /// Command line as it could be pasted into a shell
+    command: String,
+    status: String,
+    step: Option<String>,
+    stderr: Vec<String>,
+}
+
+/// Marks the pipeline stage running on this thread until dropped
+pub(crate) struct StageGuard(Option<Stage>);
+
+impl Drop for StageGuard {
+    fn drop(&mut self) {
+        STAGE.set(self.0);
+    }
+}
+
+/// Attribute tool failures on this thread to `stage` while the guard lives
+pub(crate) fn enter_stage(stage: Stage) -> StageGuard {
+    StageGuard(STAGE.replace(Some(stage)))
+}
+
+/// The pipeline step running on this thread
+fn current_step() -> Option<String> {
+    STAGE.get().map(|stage| format!("{:?}", stage).to_lowercase())
+}
+
+/// Quote `arg` for a POSIX shell when it needs it
+fn shell_quote(arg: &str) -> String {
+    let plain = |c: char| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | '/' | ':' | '=' | ',' | '+' | '@' | '%');
+    match !arg.is_empty() && arg.chars().all(plain) {
+        true => arg.to_string(),
+        false => format!("'{}'", arg.replace('\'', "'\\''")),
+    }
+}
+
+/// `command` as a shell command line, to rerun it by hand
+fn command_line(command: &Command) -> String {
+    let program = command.get_program().to_string_lossy();
+    let args = command.get_args().map(|arg| shell_quote(&arg.to_string_lossy()));
+    std::iter::once(shell_quote(&program)).chain(args).collect::<Vec<_>>().join(" ")
+}
+
+/// A tool as started, kept to describe it should it fail
+#[derive(Debug)]
+pub(crate) struct ToolRun {
+    program: String,
+    command: String,
+}
+
+impl ToolRun {
+    pub fn new(command: &Command) -> ToolRun {
+        ToolRun {
+            program: Path::new(command.get_program()).file_name().unwrap_or_default().to_string_lossy().into_owned(),
+            command: command_line(command),
+        }
+    }
+
+    /// Remember that the tool exited with `status`, with the last lines of its stderr
+    pub fn failed(&self, status: ExitStatus, stderr: Vec<String>) {
+        let failure = ToolFailure {
+            program: self.program.clone(),
+            command: self.command.clone(),
+            status: status.to_string(),
+            step: current_step(),
+            stderr,
+        };
+        let mut failures = FAILURES.lock().unwrap_or_else(|e| e.into_inner());
+        if failures.len() == FAILURES_KEPT {
+            failures.pop_front();
+        }
+        failures.push_back(failure);
+    }
+}
+
+/// The failed tool run `error` reports, recognized by the program and exit status in its message
+fn failure_for(error: &VideoConversionError) -> Option<ToolFailure> {
+    let message = error.to_string();
+    let failures = FAILURES.lock().unwrap_or_else(|e| e.into_inner());
+    failures.iter().rev().find(|failure| message.contains(&failure.program) && message.contains(&failure.status)).cloned()
+}
+
+/// What to try next, from the error and what the failing tool printed
+fn suggestion(error: &VideoConversionError, failure: Option<&ToolFailure>) -> Option<String> {
+    let stderr = failure.map_or_else(String::new, |failure| failure.stderr.join("\n"));
+    let says = |needles: &[&str]| needles.iter().any(|needle| stderr.contains(needle));
+    let ytdlp = failure.is_some_and(|failure| failure.program.starts_with("yt-dlp"));
+    let help = match error {
+        VideoConversionError::BatchFailed { first, .. } => return suggestion(first, failure),
+        VideoConversionError::ToolNotFound(tool) => {
+            format!("install {} and make sure it is on PATH; `videelow doctor` checks every tool videelow uses", tool)
+        }
+        VideoConversionError::InsufficientDiskSpace { .. } => "free up space there or pass another --output-dir".to_string(),
+        VideoConversionError::FileConflict(_) => "move the existing output away or choose another --name".to_string(),
+        VideoConversionError::Unsupported { .. } => "check the URL; `yt-dlp --list-extractors` lists the sites it knows".to_string(),
+        VideoConversionError::Config { .. } => {
+            "check the arguments and the config file; `videelow help` lists every option".to_string()
+        }
+        _ if says(&["No space left on device"]) => {
+            "free up disk space in the output and temporary directories, then retry".to_string()
+        }
+        _ if says(&["Sign in to confirm", "not a bot", "Private video", "members-only", "age-restricted", "inappropriate"]) => {
+            let cookies = "--ytdlp-arg \"--cookies-from-browser firefox\"";
+            format!("the site wants a signed-in session: pass your browser's cookies with {}", cookies)
+        }
+        _ if says(&["HTTP Error 429", "Too Many Requests"]) => {
+            "the site is rate-limiting requests; wait a while or slow down with --ytdlp-arg \"--sleep-requests 2\"".to_string()
+        }
+        _ if error.is_retryable() => "the connection failed; check the network, or a proxy in between, and retry".to_string(),
+        _ if says(&["Unknown encoder", "Encoder not found", "Error while opening encoder"]) => {
+            "this ffmpeg lacks the encoder or the hardware for it; `videelow doctor` lists the encoders that work here".to_string()
+        }
+        _ if says(&["Invalid data found when processing input", "moov atom not found"]) => {
+            "the downloaded file is damaged; retry the download".to_string()
+        }
+        // Extraction breaks whenever a site changes, and newer yt-dlp releases follow quickly
+        _ if ytdlp => "sites change often, so update yt-dlp (`yt-dlp -U` or your package manager) and retry".to_string(),
+        _ => return None,
+    };
+    Some(help)
+}
+
+/// Step and command line of the failed tool run, shown as the cause of the error
+#[derive(Debug)]
+struct ToolContext {
+    step: Option<String>,
+    command: String,
+}
+
+impl fmt::Display for ToolContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.step {
+            Some(step) => write!(f, "{} step failed running: {}", step, self.command),
+            None => write!(f, "failed running: {}", self.command),
+        }
+    }
+}
+
+impl std::error::Error for ToolContext {}
+
+/// A fatal error as shown to the user: the message, the step and command that failed, the end
+/// of the tool's stderr with its error line marked, and a suggested fix
+#[derive(Debug)]
+pub struct Report {
+    message: String,
+    code: String,
+    context: Option<ToolContext>,
+    stderr: Option<NamedSource<String>>,
+    label: Option<LabeledSpan>,
+    help: Option<String>,
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for Report {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.context.as_ref().map(|context| context as &(dyn std::error::Error + 'static))
+    }
+}
+
+impl Diagnostic for Report {
+    fn code<'a>(&'a self) -> Option<Box<dyn fmt::Display + 'a>> {
+        Some(Box::new(&self.code))
+    }
+
+    fn help<'a>(&'a self) -> Option<Box<dyn fmt::Display + 'a>> {
+        self.help.as_ref().map(|help| Box::new(help) as Box<dyn fmt::Display>)
+    }
+
+    fn source_code(&self) -> Option<&dyn SourceCode> {
+        self.stderr.as_ref().map(|stderr| stderr as &dyn SourceCode)
+    }
+
+    fn labels(&self) -> Option<Box<dyn Iterator<Item = LabeledSpan> + '_>> {
+        self.label.clone().map(|label| Box::new(std::iter::once(label)) as Box<dyn Iterator<Item = LabeledSpan>>)
+    }
+}
+
+/// Build the report for `error`, attaching the failed tool run it came from
+pub fn report(error: &VideoConversionError) -> Report {
+    // A wrong request is explained by its message; tool output from earlier steps would mislead
+    let failure = failure_for(error).filter(|_| !error.is_user_error());
+    let help = suggestion(error, failure.as_ref());
+    let (context, stderr, label) = match failure {
+        Some(failure) if !failure.stderr.is_empty() => {
+            let text = failure.stderr.join("\n");
+            // Mark the line where the tool states its error, else its last word
+            let stated = failure.stderr.iter().rposition(|line| line.to_ascii_lowercase().contains("error"));
+            let line = stated.unwrap_or(failure.stderr.len() - 1);
+            let offset: usize = failure.stderr[..line].iter().map(|line| line.len() + 1).sum();
+            let label = LabeledSpan::new(Some(format!("{} reported this", failure.program)), offset, failure.stderr[line].len());
+            let source = NamedSource::new(format!("{} stderr", failure.program), text);
+            (Some(ToolContext { step: failure.step, command: failure.command }), Some(source), Some(label))
+        }
+        Some(failure) => (Some(ToolContext { step: failure.step, command: failure.command }), None, None),
+        None => (None, None, None),
+    };
+    let code = format!("videelow::{}", metrics::category(error).unwrap_or("interrupted"));
+    Report { message: error.to_string(), code, context, stderr, label, help }
+}
+
+/// Print `error` to stderr as a diagnostic, in color when the console uses colors
+pub fn print(error: &VideoConversionError) {
+    let theme = match logging::stderr_color() {
+        true => GraphicalTheme::unicode(),
+        false => GraphicalTheme::unicode_nocolor(),
+    };
+    let mut rendered = String::new();
+    // Command lines and URLs stay copyable on one line
+    let handler = GraphicalReportHandler::new_themed(theme).with_wrap_lines(false);
+    match handler.render_report(&mut rendered, &report(error)) {
+        Ok(()) => {
+            let _ = write!(std::io::stderr(), "{}", rendered);
+        }
+        Err(_) => crate::console!(Error, "{}", error),
+    }
+}
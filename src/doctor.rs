@@ -0,0 +1,72 @@
+use std::process::{Command, Stdio};
+
+use serde::Serialize;
+use serde_json::json;
+
+use crate::hardware::{self, Accelerator, EncoderInfo};
+use crate::{console, VideoConversionError};
+
+/// An external tool and the first line of its version output
+#[derive(Serialize, Debug, Clone)]
+struct Tool {
+    name: &'static str,
+    /// `None` when the tool is not installed or does not run
+    version: Option<String>,
+}
+
+fn tool(name: &'static str, flag: &str) -> Tool {
+    let version = Command::new(name)
+        .arg(flag)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8_lossy(&output.stdout).lines().next().map(str::to_string));
+    Tool { name, version }
+}
+
+fn yes_no(value: bool) -> &'static str {
+    if value {
+        "yes"
+    } else {
+        "no"
+    }
+}
+
+/// Report the external tools and which encoders work on this machine
+pub fn run(json: bool) -> Result<(), VideoConversionError> {
+    let tools = [tool("yt-dlp", "--version"), tool("ffmpeg", "-version"), tool("ffprobe", "-version")];
+    if !json {
+        for tool in &tools {
+            match &tool.version {
+                Some(version) => console!(Success, "{}: {}", tool.name, version),
+                None => console!(Error, "{}: not found", tool.name),
+            }
+        }
+        println!("Testing encoders...");
+    }
+    let encoders: Vec<EncoderInfo> = hardware::detect_encoders();
+    if json {
+        let report = json!({ "tools": tools, "encoders": encoders });
+        let text = serde_json::to_string_pretty(&report).map_err(|e| VideoConversionError::io(e.to_string()))?;
+        println!("{}", text);
+        return Ok(());
+    }
+    println!("{:<20} {:<6} {:<14} {:<7} {:<8} {:<6}", "ENCODER", "CODEC", "HARDWARE", "BUILT", "HWACCEL", "WORKS");
+    for encoder in &encoders {
+        println!(
+            "{:<20} {:<6} {:<14} {:<7} {:<8} {:<6}",
+            encoder.name,
+            format!("{:?}", encoder.codec).to_lowercase(),
+            encoder.accelerator.name(),
+            yes_no(encoder.listed),
+            match encoder.accelerator {
+                Accelerator::Software => "-",
+                _ => yes_no(encoder.hwaccel),
+            },
+            yes_no(encoder.works)
+        );
+    }
+    Ok(())
+}
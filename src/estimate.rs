@@ -0,0 +1,121 @@
+use std::collections::BTreeMap;
+
+use crate::metadata::{self, FormatInfo, VideoInfo};
+use crate::progress::human_bytes;
+use crate::sites::{self, Quality};
+use crate::ytdlp::YtDlpOptions;
+use crate::codecs::{AudioCodec, Container};
+use crate::{EncodeOptions, VideoConversionError};
+
+/// Bits per pixel an x264 encode at CRF 23 / preset medium typically needs for web video
+const BASELINE_BITS_PER_PIXEL: f64 = 0.05;
+
+/// Expected sizes for one quality tier
+struct TierEstimate {
+    label: String,
+    download: Option<u64>,
+    converted: Option<u64>,
+}
+
+/// Print expected download and converted sizes per quality tier for `url`
+pub fn run(url: &str, encode: &EncodeOptions, ytdlp: &YtDlpOptions) -> Result<(), VideoConversionError> {
+    let info = metadata::fetch_video_info(url, &sites::profile_for(url).format_selector(Container::Mp4, Quality::Best), ytdlp)?;
+    let tiers = estimate_tiers(&info, encode);
+
+    if let Some(title) = &info.title {
+        println!("{}", title);
+    }
+    println!(
+        "{:<10} {:>14} {:>28}",
+        "Quality",
+        "Download",
+        format!("Converted (CRF {}, {})", encode.crf, encode.preset)
+    );
+    for tier in tiers {
+        println!(
+            "{:<10} {:>14} {:>28}",
+            tier.label,
+            tier.download.map_or("unknown".to_string(), |b| human_bytes(b as f64)),
+            tier.converted.map_or("unknown".to_string(), |b| human_bytes(b as f64)),
+        );
+    }
+    println!("Converted sizes are rough predictions; actual results depend on the content.");
+    Ok(())
+}
+
+/// Build one estimate per available video height, plus an audio-only tier
+fn estimate_tiers(info: &VideoInfo, encode: &EncodeOptions) -> Vec<TierEstimate> {
+    let duration = info.duration;
+    let best_audio = best_audio_format(&info.formats);
+    let audio_size = best_audio.and_then(|f| f.estimated_size(duration));
+
+    // Keep the largest format per height, preferring MP4 as the download selector does
+    let mut by_height: BTreeMap<u32, &FormatInfo> = BTreeMap::new();
+    for format in info.formats.iter().filter(|f| f.has_video()) {
+        let Some(height) = format.height else { continue };
+        let candidate_rank = rank(format, duration);
+        by_height
+            .entry(height)
+            .and_modify(|current| {
+                if candidate_rank > rank(current, duration) {
+                    *current = format;
+                }
+            })
+            .or_insert(format);
+    }
+
+    let mut tiers: Vec<TierEstimate> = by_height
+        .into_iter()
+        .rev()
+        .map(|(height, format)| {
+            let video_size = format.estimated_size(duration);
+            let download = if format.has_audio() {
+                video_size
+            } else {
+                video_size.zip(audio_size).map(|(v, a)| v + a)
+            };
+            TierEstimate {
+                label: format!("{}p", height),
+                download,
+                converted: predict_converted_size(format, duration, encode),
+            }
+        })
+        .collect();
+
+    let mp3_bitrate = encode.audio_bitrate(Container::Mp3).unwrap_or(AudioCodec::Mp3.default_bitrate());
+    tiers.push(TierEstimate {
+        label: "audio".to_string(),
+        download: audio_size,
+        converted: duration.map(|d| (d * mp3_bitrate.bytes_per_second()) as u64),
+    });
+    tiers
+}
+
+/// Ordering key: MP4 before other containers, then larger files
+fn rank(format: &FormatInfo, duration: Option<f64>) -> (bool, u64) {
+    (format.ext.as_deref() == Some("mp4"), format.estimated_size(duration).unwrap_or(0))
+}
+
+/// Audio format matching the `bestaudio[ext=m4a]` half of the download selector, or any audio
+fn best_audio_format(formats: &[FormatInfo]) -> Option<&FormatInfo> {
+    let audio_only = || formats.iter().filter(|f| f.has_audio() && !f.has_video());
+    audio_only()
+        .filter(|f| f.ext.as_deref() == Some("m4a"))
+        .max_by(|a, b| a.tbr.partial_cmp(&b.tbr).unwrap_or(std::cmp::Ordering::Equal))
+        .or_else(|| audio_only().max_by(|a, b| a.tbr.partial_cmp(&b.tbr).unwrap_or(std::cmp::Ordering::Equal)))
+}
+
+/// Predict the H.264 + AAC output size using a bits-per-pixel model scaled by CRF and preset
+fn predict_converted_size(format: &FormatInfo, duration: Option<f64>, encode: &EncodeOptions) -> Option<u64> {
+    let duration = duration?;
+    let height = format.height? as f64;
+    let width = format.width.map_or(height * 16.0 / 9.0, |w| w as f64);
+    let fps = format.fps.unwrap_or(30.0);
+
+    // x264 roughly halves the bitrate for every +6 CRF
+    let bpp = BASELINE_BITS_PER_PIXEL * 2f64.powf((23.0 - encode.crf as f64) / 6.0) * encode.preset.size_factor();
+    let video_kbps = width * height * fps * bpp / 1000.0;
+
+    let audio = encode.audio_bitrate(Container::Mp4).unwrap_or(encode.audio_codec(Container::Mp4).default_bitrate());
+    Some(((video_kbps * 1000.0 / 8.0 + audio.bytes_per_second()) * duration) as u64)
+}
@@ -0,0 +1,282 @@
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use serde_json::Value;
+use tracing::debug;
+
+use crate::dates::{self, rfc2822};
+use crate::jobs;
+use crate::metadata::VideoInfo;
+use crate::nfo::escape;
+use crate::{console, verify, VideoConversionError};
+
+/// Characters left alone when a file name becomes part of a URL
+const PATH_SEGMENT: &AsciiSet = &NON_ALPHANUMERIC.remove(b'-').remove(b'_').remove(b'.').remove(b'~');
+
+/// Media files podcast apps play, with their MIME types
+const MEDIA_TYPES: &[(&str, &str)] = &[
+    ("mp3", "audio/mpeg"),
+    ("m4a", "audio/mp4"),
+    ("aac", "audio/aac"),
+    ("opus", "audio/ogg"),
+    ("ogg", "audio/ogg"),
+    ("flac", "audio/flac"),
+    ("mp4", "video/mp4"),
+];
+
+/// Extensions of the artwork looked for next to episodes and in the directory
+const IMAGE_EXTENSIONS: &[&str] = &["jpg", "jpeg", "png"];
+
+/// What `feed` publishes
+#[derive(clap::Args, Debug, Clone)]
+pub struct FeedOptions {
+    /// Directory of downloaded audio to publish
+    dir: String,
+
+    /// URL the directory is served at; enclosure and artwork links are built from it
+    #[arg(long, value_name = "URL")]
+    base_url: String,
+
+    /// Podcast title (default: the directory name)
+    #[arg(long)]
+    title: Option<String>,
+
+    /// Podcast description
+    #[arg(long)]
+    description: Option<String>,
+
+    /// Podcast author (default: the uploader of the newest episode)
+    #[arg(long)]
+    author: Option<String>,
+
+    /// Podcast artwork URL (default: cover.jpg, folder.jpg or the like in the directory)
+    #[arg(long, value_name = "URL")]
+    image: Option<String>,
+
+    /// Apple Podcasts category, e.g. Technology
+    #[arg(long)]
+    category: Option<String>,
+
+    /// Language code of the podcast
+    #[arg(long, default_value = "en")]
+    language: String,
+
+    /// Where to write the feed (default: feed.xml in the directory)
+    #[arg(short, long)]
+    output: Option<String>,
+}
+
+/// One entry of the feed
+struct Episode {
+    file_name: String,
+    size: u64,
+    mime_type: &'static str,
+    title: String,
+    description: Option<String>,
+    author: Option<String>,
+    /// Page the episode was downloaded from
+    link: Option<String>,
+    published: SystemTime,
+    duration: Option<f64>,
+    image: Option<String>,
+}
+
+/// URL of `file_name` inside the served directory
+fn file_url(base_url: &str, file_name: &str) -> String {
+    format!("{}/{}", base_url.trim_end_matches('/'), utf8_percent_encode(file_name, PATH_SEGMENT))
+}
+
+/// Name of the first of `stems` with an image extension that exists in `dir`
+fn find_image(dir: &Path, stems: &[String]) -> Option<String> {
+    stems.iter().flat_map(|stem| IMAGE_EXTENSIONS.iter().map(move |ext| format!("{}.{}", stem, ext))).find(|name| dir.join(name).is_file())
+}
+
+/// Source URL and finish time of every file the download history knows, by file name
+fn download_history() -> HashMap<String, (String, Option<u64>)> {
+    let records = match jobs::list() {
+        Ok(records) => records,
+        Err(e) => {
+            debug!(error = %e, "cannot read download history");
+            return HashMap::new();
+        }
+    };
+    let mut history = HashMap::new();
+    for item in records.into_iter().flat_map(|record| record.items) {
+        let Some(output) = &item.output else { continue };
+        if let Some(name) = Path::new(output).file_name() {
+            // Records are listed oldest first, so the latest download of a file wins
+            history.insert(name.to_string_lossy().into_owned(), (item.url, item.finished));
+        }
+    }
+    history
+}
+
+/// The `.info.json` sidecar written with `--write-info-json`, if any
+fn read_info(path: &Path) -> Option<VideoInfo> {
+    let text = fs::read_to_string(path).ok()?;
+    let raw: Value = serde_json::from_str(&text).map_err(|e| debug!(path = %path.display(), error = %e, "invalid info JSON")).ok()?;
+    VideoInfo::from_raw(raw).ok()
+}
+
+/// Collect the episodes in `dir` with what their sidecars, the download history and ffprobe tell
+fn scan(dir: &Path) -> Result<Vec<Episode>, VideoConversionError> {
+    let entries = fs::read_dir(dir)
+        .map_err(|e| VideoConversionError::config(format!("cannot read {}", dir.display())).caused_by(e))?;
+    let history = download_history();
+    let mut episodes = Vec::new();
+    for entry in entries.filter_map(Result::ok) {
+        let path = entry.path();
+        let extension = path.extension().map(|ext| ext.to_string_lossy().to_ascii_lowercase());
+        let Some(&(_, mime_type)) = MEDIA_TYPES.iter().find(|(ext, _)| extension.as_deref() == Some(*ext)) else {
+            continue;
+        };
+        let Some(metadata) = fs::metadata(&path).ok().filter(|metadata| metadata.is_file()) else { continue };
+        let file_name = entry.file_name().to_string_lossy().into_owned();
+        let stem = path.file_stem().map_or_else(|| file_name.clone(), |stem| stem.to_string_lossy().into_owned());
+        let info = read_info(&dir.join(format!("{}.info.json", stem)));
+        let downloaded = history.get(&file_name);
+
+        let link = info
+            .as_ref()
+            .and_then(|info| info.raw.get("webpage_url").and_then(Value::as_str).map(str::to_string))
+            .or_else(|| downloaded.map(|(url, _)| url.clone()));
+        let published = info
+            .as_ref()
+            .and_then(dates::upload_time)
+            .or_else(|| downloaded.and_then(|(_, finished)| *finished).map(|finished| UNIX_EPOCH + Duration::from_secs(finished)))
+            .or_else(|| metadata.modified().ok())
+            .unwrap_or(UNIX_EPOCH);
+        let duration = info.as_ref().and_then(|info| info.duration).or_else(|| match verify::ffprobe(&path) {
+            Ok(probe) => probe.duration,
+            Err(e) => {
+                debug!(path = %path.display(), error = %e, "cannot probe episode duration");
+                None
+            }
+        });
+        episodes.push(Episode {
+            size: metadata.len(),
+            mime_type,
+            title: info.as_ref().and_then(|info| info.title.clone()).unwrap_or_else(|| stem.clone()),
+            description: info.as_ref().and_then(|info| info.description.clone()),
+            author: info.as_ref().and_then(|info| info.uploader.clone().or_else(|| info.channel.clone())),
+            link,
+            published,
+            duration,
+            image: find_image(dir, &[stem.clone(), format!("{}-thumb", stem)]),
+            file_name,
+        });
+    }
+    // Newest first, as podcast apps expect
+    episodes.sort_by(|a, b| b.published.cmp(&a.published).then_with(|| a.file_name.cmp(&b.file_name)));
+    Ok(episodes)
+}
+
+/// Duration as `HH:MM:SS` for `itunes:duration`
+fn format_duration(seconds: f64) -> String {
+    let seconds = seconds.round() as u64;
+    format!("{:02}:{:02}:{:02}", seconds / 3600, seconds / 60 % 60, seconds % 60)
+}
+
+/// Append `<name>value</name>` when a value is present
+fn element(xml: &mut String, indent: &str, name: &str, value: Option<&str>) {
+    if let Some(value) = value.filter(|v| !v.is_empty()) {
+        let _ = writeln!(xml, "{}<{}>{}</{}>", indent, name, escape(value), name);
+    }
+}
+
+/// Render the RSS 2.0 document with the iTunes podcast extensions
+fn render(options: &FeedOptions, title: &str, feed_url: &str, image: Option<&str>, episodes: &[Episode]) -> String {
+    let author = options.author.as_deref().or_else(|| episodes.iter().find_map(|e| e.author.as_deref()));
+    let description = options.description.clone().unwrap_or_else(|| format!("Episodes archived with videelow: {}", title));
+
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str(concat!(
+        "<rss version=\"2.0\" xmlns:itunes=\"http://www.itunes.com/dtds/podcast-1.0.dtd\"",
+        " xmlns:atom=\"http://www.w3.org/2005/Atom\">\n",
+    ));
+    xml.push_str("  <channel>\n");
+    element(&mut xml, "    ", "title", Some(title));
+    element(&mut xml, "    ", "link", Some(&options.base_url));
+    let _ = writeln!(xml, "    <atom:link href=\"{}\" rel=\"self\" type=\"application/rss+xml\"/>", escape(feed_url));
+    element(&mut xml, "    ", "description", Some(&description));
+    element(&mut xml, "    ", "language", Some(&options.language));
+    element(&mut xml, "    ", "lastBuildDate", Some(&rfc2822(SystemTime::now())));
+    element(&mut xml, "    ", "itunes:author", author);
+    element(&mut xml, "    ", "itunes:summary", Some(&description));
+    let _ = writeln!(xml, "    <itunes:explicit>false</itunes:explicit>");
+    if let Some(category) = &options.category {
+        let _ = writeln!(xml, "    <itunes:category text=\"{}\"/>", escape(category));
+    }
+    if let Some(image) = image {
+        let _ = writeln!(xml, "    <itunes:image href=\"{}\"/>", escape(image));
+        xml.push_str("    <image>\n");
+        element(&mut xml, "      ", "url", Some(image));
+        element(&mut xml, "      ", "title", Some(title));
+        element(&mut xml, "      ", "link", Some(&options.base_url));
+        xml.push_str("    </image>\n");
+    }
+
+    for episode in episodes {
+        let url = file_url(&options.base_url, &episode.file_name);
+        xml.push_str("    <item>\n");
+        element(&mut xml, "      ", "title", Some(&episode.title));
+        element(&mut xml, "      ", "description", episode.description.as_deref());
+        element(&mut xml, "      ", "link", episode.link.as_deref());
+        // The source page identifies an episode even when the archive moves
+        let _ = writeln!(
+            xml,
+            "      <guid isPermaLink=\"false\">{}</guid>",
+            escape(episode.link.as_deref().unwrap_or(&url))
+        );
+        element(&mut xml, "      ", "pubDate", Some(&rfc2822(episode.published)));
+        let _ = writeln!(
+            xml,
+            "      <enclosure url=\"{}\" length=\"{}\" type=\"{}\"/>",
+            escape(&url),
+            episode.size,
+            episode.mime_type
+        );
+        element(&mut xml, "      ", "itunes:author", episode.author.as_deref());
+        element(&mut xml, "      ", "itunes:duration", episode.duration.map(format_duration).as_deref());
+        if let Some(image) = &episode.image {
+            let _ = writeln!(xml, "      <itunes:image href=\"{}\"/>", escape(&file_url(&options.base_url, image)));
+        }
+        xml.push_str("    </item>\n");
+    }
+    xml.push_str("  </channel>\n</rss>\n");
+    xml
+}
+
+/// Write a podcast feed listing the audio in a directory, so that any podcast app can subscribe
+/// to the archive once the directory is served at the base URL
+pub fn run(options: &FeedOptions) -> Result<(), VideoConversionError> {
+    let dir = Path::new(&options.dir);
+    if !dir.is_dir() {
+        return Err(VideoConversionError::FileNotFound(options.dir.clone()));
+    }
+    let episodes = scan(dir)?;
+    if episodes.is_empty() {
+        console!(Warning, "no audio found in {}; the feed has no episodes", dir.display());
+    }
+
+    let output = options.output.as_ref().map_or_else(|| dir.join("feed.xml"), PathBuf::from);
+    let title = options.title.clone().unwrap_or_else(|| {
+        let absolute = fs::canonicalize(dir).unwrap_or_else(|_| dir.to_path_buf());
+        absolute.file_name().map_or_else(|| "Podcast".to_string(), |name| name.to_string_lossy().into_owned())
+    });
+    let feed_name = output.file_name().map_or_else(|| "feed.xml".to_string(), |name| name.to_string_lossy().into_owned());
+    let image = options.image.clone().or_else(|| {
+        let stems = ["cover", "folder", "poster", "artwork"].map(String::from);
+        find_image(dir, &stems).map(|name| file_url(&options.base_url, &name))
+    });
+
+    let xml = render(options, &title, &file_url(&options.base_url, &feed_name), image.as_deref(), &episodes);
+    fs::write(&output, xml)
+        .map_err(|e| VideoConversionError::io(format!("Failed to write {}", output.display())).caused_by(e))?;
+    console!(Success, "Podcast feed with {} episodes written: {}", episodes.len(), output.display());
+    Ok(())
+}
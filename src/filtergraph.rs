@@ -0,0 +1,346 @@
+use std::fmt;
+
+/// A single ffmpeg filter with named options
+pub trait Filter {
+    /// ffmpeg's name for the filter
+    fn name(&self) -> &str;
+
+    /// Option names and values, in the order they are passed
+    fn options(&self) -> Vec<(&'static str, String)>;
+}
+
+/// Render a filter as `name=key=value:key=value`, escaping values for use inside a filter graph
+fn render(filter: &dyn Filter) -> String {
+    let options = filter.options();
+    if options.is_empty() {
+        return filter.name().to_string();
+    }
+    let options: Vec<String> = options.iter().map(|(key, value)| format!("{}={}", key, escape_value(value))).collect();
+    format!("{}={}", filter.name(), options.join(":"))
+}
+
+/// Escape a value twice over: once for the filter's option list, once for the surrounding graph
+pub fn escape_value(value: &str) -> String {
+    let escape = |s: &str, special: &[char]| {
+        s.chars().fold(String::new(), |mut out, c| {
+            if special.contains(&c) {
+                out.push('\\');
+            }
+            out.push(c);
+            out
+        })
+    };
+    let option_level = escape(value, &['\\', '\'', ':']);
+    escape(&option_level, &['\\', '\'', '[', ']', ',', ';'])
+}
+
+/// A size that can be left for ffmpeg to derive from the aspect ratio
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dimension {
+    Pixels(u32),
+    /// Keep the aspect ratio
+    Auto,
+    /// Keep the aspect ratio, rounded to an even number as most encoders require
+    AutoEven,
+}
+
+impl fmt::Display for Dimension {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Dimension::Pixels(pixels) => write!(f, "{}", pixels),
+            Dimension::Auto => f.write_str("-1"),
+            Dimension::AutoEven => f.write_str("-2"),
+        }
+    }
+}
+
+/// Resize video
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Scale {
+    pub width: Dimension,
+    pub height: Dimension,
+}
+
+impl Scale {
+    /// Scale to a height, keeping the aspect ratio
+    pub fn to_height(height: u32) -> Scale {
+        Scale { width: Dimension::AutoEven, height: Dimension::Pixels(height) }
+    }
+
+    /// Scale to a width, keeping the aspect ratio
+    pub fn to_width(width: u32) -> Scale {
+        Scale { width: Dimension::Pixels(width), height: Dimension::AutoEven }
+    }
+}
+
+impl Filter for Scale {
+    fn name(&self) -> &str {
+        "scale"
+    }
+
+    fn options(&self) -> Vec<(&'static str, String)> {
+        vec![("w", self.width.to_string()), ("h", self.height.to_string())]
+    }
+}
+
+/// Cut a rectangle out of the video; without an offset the crop is centered
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Crop {
+    pub width: u32,
+    pub height: u32,
+    pub offset: Option<(u32, u32)>,
+}
+
+impl Filter for Crop {
+    fn name(&self) -> &str {
+        "crop"
+    }
+
+    fn options(&self) -> Vec<(&'static str, String)> {
+        let mut options = vec![("w", self.width.to_string()), ("h", self.height.to_string())];
+        if let Some((x, y)) = self.offset {
+            options.extend([("x", x.to_string()), ("y", y.to_string())]);
+        }
+        options
+    }
+}
+
+/// Draw the second input on top of the first at a position
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Overlay {
+    pub x: i32,
+    pub y: i32,
+}
+
+impl Filter for Overlay {
+    fn name(&self) -> &str {
+        "overlay"
+    }
+
+    fn options(&self) -> Vec<(&'static str, String)> {
+        vec![("x", self.x.to_string()), ("y", self.y.to_string())]
+    }
+}
+
+/// Change the frame rate
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Fps(pub f64);
+
+impl Filter for Fps {
+    fn name(&self) -> &str {
+        "fps"
+    }
+
+    fn options(&self) -> Vec<(&'static str, String)> {
+        vec![("fps", self.0.to_string())]
+    }
+}
+
+/// Join video segments read from the chain's input pads one after another
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Concat {
+    pub segments: usize,
+}
+
+impl Filter for Concat {
+    fn name(&self) -> &str {
+        "concat"
+    }
+
+    fn options(&self) -> Vec<(&'static str, String)> {
+        vec![("n", self.segments.to_string()), ("v", "1".to_string()), ("a", "0".to_string())]
+    }
+}
+
+/// Convert to a pixel format, such as `yuv420p`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PixelFormat(pub String);
+
+impl Filter for PixelFormat {
+    fn name(&self) -> &str {
+        "format"
+    }
+
+    fn options(&self) -> Vec<(&'static str, String)> {
+        vec![("pix_fmts", self.0.clone())]
+    }
+}
+
+/// EBU R128 loudness normalization
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Loudnorm {
+    /// Integrated loudness target in LUFS
+    pub integrated: f64,
+    /// Maximum true peak in dBTP
+    pub true_peak: f64,
+    /// Loudness range target in LU
+    pub range: f64,
+}
+
+impl Default for Loudnorm {
+    /// The common streaming target of -16 LUFS
+    fn default() -> Self {
+        Loudnorm { integrated: -16.0, true_peak: -1.5, range: 11.0 }
+    }
+}
+
+impl Filter for Loudnorm {
+    fn name(&self) -> &str {
+        "loudnorm"
+    }
+
+    fn options(&self) -> Vec<(&'static str, String)> {
+        vec![("I", self.integrated.to_string()), ("TP", self.true_peak.to_string()), ("LRA", self.range.to_string())]
+    }
+}
+
+/// Constant gain
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Volume {
+    /// Gain in dB; negative values attenuate
+    pub gain_db: f64,
+}
+
+impl Filter for Volume {
+    fn name(&self) -> &str {
+        "volume"
+    }
+
+    fn options(&self) -> Vec<(&'static str, String)> {
+        vec![("volume", format!("{:.2}dB", self.gain_db))]
+    }
+}
+
+/// EBU R128 loudness measurement, logging a summary when the stream ends
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Ebur128 {
+    /// Also measure the true peak, which needs oversampling
+    pub true_peak: bool,
+}
+
+impl Filter for Ebur128 {
+    fn name(&self) -> &str {
+        "ebur128"
+    }
+
+    fn options(&self) -> Vec<(&'static str, String)> {
+        match self.true_peak {
+            true => vec![("peak", "true".to_string())],
+            false => Vec::new(),
+        }
+    }
+}
+
+/// Drop silent stretches from audio: leading silence and every pause longer than `min_duration`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SilenceRemove {
+    /// Level in dBFS below which audio counts as silence
+    pub threshold_db: f64,
+    /// Seconds of silence needed before it is removed
+    pub min_duration: f64,
+    /// Seconds of each removed pause left in place, so speech does not run together
+    pub keep: f64,
+}
+
+impl Filter for SilenceRemove {
+    fn name(&self) -> &str {
+        "silenceremove"
+    }
+
+    fn options(&self) -> Vec<(&'static str, String)> {
+        let threshold = format!("{}dB", self.threshold_db);
+        let keep = self.keep.min(self.min_duration).to_string();
+        vec![
+            ("start_periods", "1".to_string()),
+            ("start_threshold", threshold.clone()),
+            ("start_silence", keep.clone()),
+            // Negative periods remove every pause in the stream, not just trailing silence
+            ("stop_periods", "-1".to_string()),
+            ("stop_duration", self.min_duration.to_string()),
+            ("stop_threshold", threshold),
+            ("stop_silence", keep),
+        ]
+    }
+}
+
+/// Any filter without a typed wrapper yet
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RawFilter {
+    pub name: String,
+    pub options: Vec<(&'static str, String)>,
+}
+
+impl Filter for RawFilter {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn options(&self) -> Vec<(&'static str, String)> {
+        self.options.clone()
+    }
+}
+
+/// Filters applied one after another to a single stream, as passed to `-vf` or `-af`
+#[derive(Default)]
+pub struct FilterChain {
+    filters: Vec<Box<dyn Filter>>,
+}
+
+impl FilterChain {
+    pub fn new() -> Self {
+        FilterChain::default()
+    }
+
+    /// Append a filter
+    pub fn then(mut self, filter: impl Filter + 'static) -> Self {
+        self.filters.push(Box::new(filter));
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.filters.is_empty()
+    }
+}
+
+impl fmt::Display for FilterChain {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let rendered: Vec<String> = self.filters.iter().map(|filter| render(filter.as_ref())).collect();
+        f.write_str(&rendered.join(","))
+    }
+}
+
+/// A `-filter_complex` graph of chains connected through labelled pads such as `0:v` or `scaled`
+#[derive(Default)]
+pub struct FilterGraph {
+    chains: Vec<(Vec<String>, FilterChain, Vec<String>)>,
+}
+
+impl FilterGraph {
+    pub fn new() -> Self {
+        FilterGraph::default()
+    }
+
+    /// Add a chain reading the `inputs` pads and producing the `outputs` pads
+    pub fn chain(mut self, inputs: &[&str], chain: FilterChain, outputs: &[&str]) -> Self {
+        let labels = |pads: &[&str]| pads.iter().map(|pad| pad.to_string()).collect();
+        self.chains.push((labels(inputs), chain, labels(outputs)));
+        self
+    }
+
+    /// Arguments passing the graph to ffmpeg
+    pub fn args(&self) -> [String; 2] {
+        ["-filter_complex".to_string(), self.to_string()]
+    }
+}
+
+impl fmt::Display for FilterGraph {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let pads = |labels: &[String]| labels.iter().map(|label| format!("[{}]", label)).collect::<String>();
+        let rendered: Vec<String> = self
+            .chains
+            .iter()
+            .map(|(inputs, chain, outputs)| format!("{}{}{}", pads(inputs), chain, pads(outputs)))
+            .collect();
+        f.write_str(&rendered.join(";"))
+    }
+}
@@ -0,0 +1,136 @@
+use crate::dates::Date;
+use crate::metadata::VideoInfo;
+use crate::progress::human_bytes;
+use crate::timestamp::MediaTimestamp;
+
+/// Criteria deciding which videos of a run get downloaded
+#[derive(Debug, Clone, Default)]
+pub struct ItemFilter {
+    /// Only videos uploaded on or after this date
+    pub since: Option<Date>,
+    /// Only videos uploaded strictly before this date
+    pub before: Option<Date>,
+    pub min_duration: Option<MediaTimestamp>,
+    pub max_duration: Option<MediaTimestamp>,
+    /// Maximum estimated download size in bytes
+    pub max_filesize: Option<u64>,
+}
+
+impl ItemFilter {
+    /// Whether any criterion is set
+    pub fn is_active(&self) -> bool {
+        self.since.is_some()
+            || self.before.is_some()
+            || self.min_duration.is_some()
+            || self.max_duration.is_some()
+            || self.max_filesize.is_some()
+    }
+
+    /// Whether the criteria need per-video metadata that flat playlist listings lack
+    pub fn needs_full_extraction(&self) -> bool {
+        self.since.is_some() || self.before.is_some()
+    }
+
+    /// Equivalent yt-dlp options, so filtered entries are skipped during extraction
+    pub fn ytdlp_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        if let Some(since) = self.since {
+            args.extend(["--dateafter".to_string(), since.compact()]);
+        }
+        if let Some(before) = self.before {
+            // yt-dlp's --datebefore is inclusive
+            args.extend(["--datebefore".to_string(), before.previous_day().compact()]);
+        }
+        // `?` lets entries without a known duration through; they are checked again before downloading
+        let mut durations = Vec::new();
+        if let Some(min) = self.min_duration {
+            durations.push(format!("duration>=?{}", min.as_secs_f64()));
+        }
+        if let Some(max) = self.max_duration {
+            durations.push(format!("duration<=?{}", max.as_secs_f64()));
+        }
+        if !durations.is_empty() {
+            args.extend(["--match-filters".to_string(), durations.join(" & ")]);
+        }
+        args
+    }
+
+    /// Reason for skipping a video, or `None` if it passes every criterion
+    pub fn rejects(&self, info: &VideoInfo) -> Option<String> {
+        let uploaded = info.upload_date.as_deref().and_then(Date::from_compact);
+        match (uploaded, self.since, self.before) {
+            (Some(date), Some(since), _) if date < since => Some(format!("uploaded {}, before --since {}", date, since)),
+            (Some(date), _, Some(before)) if date >= before => {
+                Some(format!("uploaded {}, not before --before {}", date, before))
+            }
+            _ => None,
+        }
+        .or_else(|| {
+            let duration = MediaTimestamp::from_secs_f64(info.duration?)?;
+            match (self.min_duration, self.max_duration) {
+                (Some(min), _) if duration < min => {
+                    Some(format!("{} long, shorter than --min-duration {}", duration, min))
+                }
+                (_, Some(max)) if duration > max => {
+                    Some(format!("{} long, longer than --max-duration {}", duration, max))
+                }
+                _ => None,
+            }
+        })
+        .or_else(|| {
+            let max = self.max_filesize?;
+            let size = info.estimated_download_size()?;
+            (size > max).then(|| {
+                format!(
+                    "about {}, larger than --max-filesize {}",
+                    human_bytes(size as f64),
+                    human_bytes(max as f64)
+                )
+            })
+        })
+    }
+}
+
+/// Parse a size such as `500M` or `1.5G` (binary units; a bare number is bytes)
+pub fn parse_size(s: &str) -> Result<u64, String> {
+    let invalid = || format!("invalid size {:?} (use bytes or a K, M or G suffix such as 500M)", s);
+    let s = s.trim();
+    let upper = s.to_ascii_uppercase();
+    let number = upper.trim_end_matches(['B', 'I']);
+    let (number, multiplier) = match number.chars().last() {
+        Some('K') => (&number[..number.len() - 1], 1u64 << 10),
+        Some('M') => (&number[..number.len() - 1], 1 << 20),
+        Some('G') => (&number[..number.len() - 1], 1 << 30),
+        Some('T') => (&number[..number.len() - 1], 1 << 40),
+        _ if upper.ends_with('B') || upper.chars().all(|c| c.is_ascii_digit() || c == '.') => (number, 1),
+        _ => return Err(invalid()),
+    };
+    number
+        .parse::<f64>()
+        .ok()
+        .filter(|n| n.is_finite() && *n >= 0.0)
+        .map(|n| (n * multiplier as f64) as u64)
+        .ok_or_else(invalid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_sizes_with_binary_suffixes() {
+        assert_eq!(parse_size("1024"), Ok(1024));
+        assert_eq!(parse_size("500M"), Ok(500 << 20));
+        assert_eq!(parse_size("1.5k"), Ok(1536));
+        assert_eq!(parse_size(" 2GiB "), Ok(2 << 30));
+        assert_eq!(parse_size("10MB"), Ok(10 << 20));
+        assert_eq!(parse_size("1T"), Ok(1 << 40));
+    }
+
+    #[test]
+    fn rejects_malformed_sizes() {
+        for input in ["", "M", "-1M", "12X", "1.5.5K", "infK", "5 é"] {
+            assert!(parse_size(input).is_err(), "{:?}", input);
+        }
+    }
+}
@@ -0,0 +1,185 @@
+use std::fs::read_dir;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use crate::VideoConversionError;
+
+/// Files directly inside `dir`, leaving out subfolders and hidden files such as the partial
+/// downloads of browsers and sync tools
+fn list_files(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = read_dir(dir) else { return Vec::new() };
+    let mut files: Vec<PathBuf> = entries
+        .flatten()
+        .filter(|entry| !entry.file_name().to_string_lossy().starts_with('.'))
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file())
+        .collect();
+    files.sort();
+    files
+}
+
+/// Send the files already in `dir`, then every file finished writing there, to the returned
+/// channel; the channel ends on shutdown. Uses inotify on Linux and otherwise scans the folder
+/// every `interval`, taking a file as finished once its size stops changing between scans
+pub fn watch(dir: &Path, interval: Duration) -> Result<mpsc::Receiver<PathBuf>, VideoConversionError> {
+    let (sender, receiver) = mpsc::channel();
+    #[cfg(target_os = "linux")]
+    {
+        let watch = inotify::Watch::new(dir)
+            .map_err(|e| VideoConversionError::io(format!("cannot watch {}", dir.display())).caused_by(e))?;
+        let dir = dir.to_path_buf();
+        thread::spawn(move || inotify::run(&dir, &watch, interval, &sender));
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        let dir = dir.to_path_buf();
+        thread::spawn(move || scan::run(&dir, interval, &sender));
+    }
+    Ok(receiver)
+}
+
+#[cfg(not(target_os = "linux"))]
+mod scan {
+    use std::collections::HashMap;
+    use std::path::{Path, PathBuf};
+    use std::sync::mpsc::Sender;
+    use std::thread;
+    use std::time::{Duration, SystemTime};
+
+    use super::list_files;
+
+    /// Scan `dir` until shutdown, sending each file once its size and modification time held
+    /// still for one interval
+    pub fn run(dir: &Path, interval: Duration, sender: &Sender<PathBuf>) {
+        // Size and modification time of each file at the last scan, and whether it was sent
+        let mut seen: HashMap<PathBuf, (u64, Option<SystemTime>, bool)> = HashMap::new();
+        while !crate::shutdown::requested() {
+            let files = list_files(dir);
+            seen.retain(|path, _| files.contains(path));
+            for file in files {
+                let Ok(metadata) = file.metadata() else { continue };
+                let state = (metadata.len(), metadata.modified().ok());
+                match seen.get_mut(&file) {
+                    Some((len, modified, sent)) if (*len, *modified) == state => {
+                        if !*sent {
+                            *sent = true;
+                            if sender.send(file).is_err() {
+                                return;
+                            }
+                        }
+                    }
+                    Some(entry) => *entry = (state.0, state.1, false),
+                    None => {
+                        seen.insert(file, (state.0, state.1, false));
+                    }
+                }
+            }
+            thread::sleep(interval);
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod inotify {
+    use std::ffi::{CString, OsString};
+    use std::io;
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+    use std::os::unix::ffi::{OsStrExt, OsStringExt};
+    use std::path::{Path, PathBuf};
+    use std::sync::mpsc::Sender;
+    use std::time::Duration;
+
+    use tracing::debug;
+
+    use super::list_files;
+
+    /// Size of `struct inotify_event` before the name
+    const HEADER_SIZE: usize = 16;
+
+    /// An inotify instance reporting files closed after writing or moved into one folder
+    pub struct Watch {
+        fd: OwnedFd,
+    }
+
+    impl Watch {
+        pub fn new(dir: &Path) -> io::Result<Watch> {
+            // SAFETY: inotify_init1 takes no pointers; the descriptor is owned from here on
+            let raw = unsafe { libc::inotify_init1(libc::IN_CLOEXEC) };
+            if raw < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            // SAFETY: `raw` is a freshly opened descriptor nothing else owns
+            let fd = unsafe { OwnedFd::from_raw_fd(raw) };
+            let c_path = CString::new(dir.as_os_str().as_bytes()).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+            // Writes end with a close, while finished downloads are often renamed into place
+            let mask = libc::IN_CLOSE_WRITE | libc::IN_MOVED_TO | libc::IN_ONLYDIR;
+            // SAFETY: `c_path` is a valid NUL-terminated string
+            if unsafe { libc::inotify_add_watch(fd.as_raw_fd(), c_path.as_ptr(), mask) } < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(Watch { fd })
+        }
+
+        /// Names of the files finished within `timeout`, or `None` when the kernel dropped events
+        pub fn wait(&self, timeout: Duration) -> io::Result<Option<Vec<OsString>>> {
+            let mut pollfd = libc::pollfd { fd: self.fd.as_raw_fd(), events: libc::POLLIN, revents: 0 };
+            // SAFETY: `pollfd` is a single valid entry
+            let ready = unsafe { libc::poll(&mut pollfd, 1, timeout.as_millis().min(i32::MAX as u128) as i32) };
+            if ready < 0 {
+                let error = io::Error::last_os_error();
+                // A signal such as SIGINT woke the poll; the caller checks for shutdown
+                return if error.kind() == io::ErrorKind::Interrupted { Ok(Some(Vec::new())) } else { Err(error) };
+            }
+            if ready == 0 {
+                return Ok(Some(Vec::new()));
+            }
+            let mut buffer = [0u8; 8192];
+            // SAFETY: the buffer is writable for its whole length
+            let read = unsafe { libc::read(self.fd.as_raw_fd(), buffer.as_mut_ptr().cast(), buffer.len()) };
+            if read < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let mut names = Vec::new();
+            let mut offset = 0;
+            let field = |at: usize| u32::from_ne_bytes([buffer[at], buffer[at + 1], buffer[at + 2], buffer[at + 3]]);
+            while offset + HEADER_SIZE <= read as usize {
+                let mask = field(offset + 4);
+                let len = field(offset + 12) as usize;
+                if mask & libc::IN_Q_OVERFLOW != 0 {
+                    return Ok(None);
+                }
+                // The name is padded with NULs to align the next event
+                let name = &buffer[offset + HEADER_SIZE..offset + HEADER_SIZE + len];
+                let name = &name[..name.iter().position(|&byte| byte == 0).unwrap_or(name.len())];
+                if mask & libc::IN_ISDIR == 0 && !name.is_empty() && !name.starts_with(b".") {
+                    names.push(OsString::from_vec(name.to_vec()));
+                }
+                offset += HEADER_SIZE + len;
+            }
+            Ok(Some(names))
+        }
+    }
+
+    /// Send the files in `dir`, then each one `watch` reports, until shutdown
+    pub fn run(dir: &Path, watch: &Watch, interval: Duration, sender: &Sender<PathBuf>) {
+        let mut files = list_files(dir);
+        while !crate::shutdown::requested() {
+            for file in files.drain(..) {
+                if sender.send(file).is_err() {
+                    return;
+                }
+            }
+            files = match watch.wait(interval) {
+                Ok(Some(names)) => names.into_iter().map(|name| dir.join(name)).filter(|path| path.is_file()).collect(),
+                // Events were lost, so look at everything again
+                Ok(None) => list_files(dir),
+                Err(e) => {
+                    debug!(error = %e, "inotify failed");
+                    return;
+                }
+            };
+        }
+    }
+}
@@ -0,0 +1,203 @@
+use std::collections::HashSet;
+use std::process::{Command, Stdio};
+use std::sync::OnceLock;
+
+use serde::Serialize;
+use tracing::{debug, info_span};
+
+use crate::codecs::VideoCodec;
+use crate::{command_output, console, logging, Preset};
+
+/// Where an encoder does its work
+#[derive(Serialize, Copy, Clone, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Accelerator {
+    Software,
+    /// Apple's media engine on macOS
+    VideoToolbox,
+    /// NVIDIA GPUs
+    Nvenc,
+    /// Intel Quick Sync
+    Qsv,
+    /// VA-API on Linux, for Intel and AMD GPUs
+    Vaapi,
+}
+
+impl Accelerator {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Accelerator::Software => "software",
+            Accelerator::VideoToolbox => "VideoToolbox",
+            Accelerator::Nvenc => "NVENC",
+            Accelerator::Qsv => "Quick Sync",
+            Accelerator::Vaapi => "VA-API",
+        }
+    }
+
+    /// Name of the matching decoder acceleration in `ffmpeg -hwaccels`
+    fn hwaccel(&self) -> Option<&'static str> {
+        match self {
+            Accelerator::Software => None,
+            Accelerator::VideoToolbox => Some("videotoolbox"),
+            Accelerator::Nvenc => Some("cuda"),
+            Accelerator::Qsv => Some("qsv"),
+            Accelerator::Vaapi => Some("vaapi"),
+        }
+    }
+
+    /// Arguments before the input selecting the device to encode on
+    pub(crate) fn device_args(&self) -> &'static [&'static str] {
+        match self {
+            Accelerator::Vaapi => &["-vaapi_device", "/dev/dri/renderD128"],
+            _ => &[],
+        }
+    }
+
+    /// Filter moving frames into device memory, for encoders that cannot read them from the CPU
+    pub(crate) fn upload_filter(&self) -> Option<&'static str> {
+        match self {
+            Accelerator::Vaapi => Some("format=nv12,hwupload"),
+            _ => None,
+        }
+    }
+
+    /// Quality option and value roughly matching x264's `crf`
+    pub(crate) fn quality_args(&self, crf: u8) -> [String; 2] {
+        match self {
+            Accelerator::Software => ["-crf".to_string(), crf.to_string()],
+            Accelerator::Nvenc => ["-cq".to_string(), crf.to_string()],
+            Accelerator::Qsv => ["-global_quality".to_string(), crf.to_string()],
+            Accelerator::Vaapi => ["-qp".to_string(), crf.to_string()],
+            // A quality from 1 to 100, higher being better
+            Accelerator::VideoToolbox => ["-q:v".to_string(), (100 - 2 * i32::from(crf)).clamp(1, 100).to_string()],
+        }
+    }
+
+    /// The encoder's own preset closest to an x264 preset, if it has presets
+    pub(crate) fn preset(&self, preset: Preset) -> Option<&'static str> {
+        match self {
+            Accelerator::Software => Some(preset.as_str()),
+            Accelerator::Nvenc => Some(match preset {
+                Preset::Ultrafast => "p1",
+                Preset::Superfast => "p2",
+                Preset::Veryfast | Preset::Faster => "p3",
+                Preset::Fast | Preset::Medium => "p4",
+                Preset::Slow => "p5",
+                Preset::Slower => "p6",
+                Preset::Veryslow => "p7",
+            }),
+            // Quick Sync names its presets like x264, without the two fastest
+            Accelerator::Qsv => Some(match preset {
+                Preset::Ultrafast | Preset::Superfast => "veryfast",
+                other => other.as_str(),
+            }),
+            Accelerator::VideoToolbox | Accelerator::Vaapi => None,
+        }
+    }
+}
+
+/// Encoders videelow knows how to drive, software first
+const KNOWN: &[(&str, VideoCodec, Accelerator)] = &[
+    ("libx264", VideoCodec::H264, Accelerator::Software),
+    ("libx265", VideoCodec::H265, Accelerator::Software),
+    ("h264_videotoolbox", VideoCodec::H264, Accelerator::VideoToolbox),
+    ("hevc_videotoolbox", VideoCodec::H265, Accelerator::VideoToolbox),
+    ("h264_nvenc", VideoCodec::H264, Accelerator::Nvenc),
+    ("hevc_nvenc", VideoCodec::H265, Accelerator::Nvenc),
+    ("h264_qsv", VideoCodec::H264, Accelerator::Qsv),
+    ("hevc_qsv", VideoCodec::H265, Accelerator::Qsv),
+    ("h264_vaapi", VideoCodec::H264, Accelerator::Vaapi),
+    ("hevc_vaapi", VideoCodec::H265, Accelerator::Vaapi),
+];
+
+/// What this machine's ffmpeg can do with one encoder
+#[derive(Serialize, Debug, Clone)]
+pub struct EncoderInfo {
+    /// ffmpeg's name for the encoder, such as `h264_nvenc`
+    pub name: &'static str,
+    pub codec: VideoCodec,
+    pub accelerator: Accelerator,
+    /// Whether ffmpeg was built with the encoder
+    pub listed: bool,
+    /// Whether ffmpeg lists decoding on the same hardware
+    pub hwaccel: bool,
+    /// Whether a test encode succeeded, which needs the device and its drivers as well
+    pub works: bool,
+}
+
+/// Names in the table of `ffmpeg -encoders` or `ffmpeg -hwaccels`
+fn ffmpeg_list(flag: &str) -> HashSet<String> {
+    match command_output(Command::new("ffmpeg").args(["-hide_banner", flag]).stderr(logging::child_stderr())) {
+        // Encoder lines look like ` V....D libx264   libx264 H.264 / AVC ...`, hwaccel lines
+        // are just the name
+        Ok(output) => String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .filter_map(|line| {
+                let mut words = line.split_whitespace();
+                let first = words.next()?;
+                Some(words.next().unwrap_or(first).to_string())
+            })
+            .collect(),
+        Err(e) => {
+            debug!(error = %e, "cannot query ffmpeg {}", flag);
+            HashSet::new()
+        }
+    }
+}
+
+/// Encode a few frames of a generated picture, the only sure way to tell a usable device from
+/// an encoder that is merely compiled in
+fn test_encode(name: &str, accelerator: Accelerator) -> bool {
+    let mut command = Command::new("ffmpeg");
+    command
+        .args(["-hide_banner", "-loglevel", "error", "-nostdin"])
+        .args(accelerator.device_args())
+        .args(["-f", "lavfi", "-i", "color=c=black:s=640x360:r=30:d=0.2"]);
+    if let Some(upload) = accelerator.upload_filter() {
+        command.args(["-vf", upload]);
+    }
+    command.args(["-c:v", name, "-frames:v", "5", "-f", "null", "-"]).stdin(Stdio::null()).stderr(logging::child_stderr());
+    command_output(&mut command).is_ok_and(|output| output.status.success())
+}
+
+/// Encoders known to videelow and whether each works on this machine; hardware encoders are
+/// test-encoded, which takes a moment per encoder ffmpeg lists
+pub fn detect_encoders() -> Vec<EncoderInfo> {
+    let _span = info_span!("detect_encoders").entered();
+    let listed = ffmpeg_list("-encoders");
+    let hwaccels = ffmpeg_list("-hwaccels");
+    KNOWN
+        .iter()
+        .map(|&(name, codec, accelerator)| {
+            let is_listed = listed.contains(name);
+            let works = is_listed && test_encode(name, accelerator);
+            debug!(encoder = name, listed = is_listed, works, "encoder detected");
+            EncoderInfo {
+                name,
+                codec,
+                accelerator,
+                listed: is_listed,
+                hwaccel: accelerator.hwaccel().is_some_and(|hwaccel| hwaccels.contains(hwaccel)),
+                works,
+            }
+        })
+        .collect()
+}
+
+/// The working hardware encoder for `codec` that `--hardware-encode` uses, detected once per run;
+/// VA-API is left out, as uploading frames would clash with the scaling of adaptive renditions
+pub(crate) fn auto_encoder(codec: VideoCodec) -> Option<(&'static str, Accelerator)> {
+    static DETECTED: OnceLock<Vec<EncoderInfo>> = OnceLock::new();
+    let usable = |info: &&EncoderInfo| info.works && !matches!(info.accelerator, Accelerator::Software | Accelerator::Vaapi);
+    let encoders = DETECTED.get_or_init(|| {
+        println!("Detecting hardware encoders...");
+        let encoders = detect_encoders();
+        if !encoders.iter().any(|info| usable(&info)) {
+            console!(Warning, "no working hardware encoder found; encoding in software");
+        }
+        encoders
+    });
+    let found = encoders.iter().filter(usable).find(|info| info.codec == codec);
+    debug!(encoder = found.map(|info| info.name), "hardware encoder selected");
+    found.map(|info| (info.name, info.accelerator))
+}
@@ -0,0 +1,159 @@
+use std::io::Write;
+
+use clap::ValueEnum;
+use serde::Serialize;
+
+use crate::dates::Date;
+use crate::jobs::{self, Phase};
+use crate::VideoConversionError;
+
+/// File formats of `history export`
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum, Debug)]
+pub enum ExportFormat {
+    /// Comma-separated values with a header row, for spreadsheets
+    Csv,
+    /// An array of objects
+    Json,
+}
+
+/// What `history export` writes and where
+#[derive(clap::Args, Debug, Clone)]
+pub struct ExportOptions {
+    /// Output format
+    #[arg(long, value_enum, default_value = "csv")]
+    format: ExportFormat,
+
+    /// File to write instead of standard output
+    #[arg(short, long, value_name = "FILE")]
+    output: Option<String>,
+}
+
+/// One downloaded or failed video of the history
+#[derive(Serialize, Debug, Clone)]
+pub struct HistoryEntry {
+    pub job: u64,
+    pub url: String,
+    pub status: &'static str,
+    pub video_id: Option<String>,
+    pub title: Option<String>,
+    pub uploader: Option<String>,
+    /// As YYYY-MM-DD
+    pub upload_date: Option<String>,
+    /// Seconds
+    pub duration: Option<f64>,
+    /// UTC times as YYYY-MM-DDTHH:MM:SSZ
+    pub started: Option<String>,
+    pub finished: Option<String>,
+    pub output: Option<String>,
+    pub bytes: Option<u64>,
+    pub download_seconds: Option<f64>,
+    pub convert_seconds: Option<f64>,
+    pub error: Option<String>,
+}
+
+/// Column names of the CSV export, in the order of `HistoryEntry`
+const COLUMNS: &[&str] = &[
+    "job", "url", "status", "video_id", "title", "uploader", "upload_date", "duration", "started", "finished", "output",
+    "bytes", "download_seconds", "convert_seconds", "error",
+];
+
+/// Unix time as an ISO 8601 UTC timestamp
+fn iso8601(time: u64) -> String {
+    let date = Date::from_days_since_epoch((time / 86_400) as i64);
+    let seconds = time % 86_400;
+    format!("{}T{:02}:{:02}:{:02}Z", date, seconds / 3600, seconds / 60 % 60, seconds % 60)
+}
+
+/// Every item of the job records, oldest job first; items that never ran are left out
+pub fn entries() -> Result<Vec<HistoryEntry>, VideoConversionError> {
+    let mut records = jobs::list()?;
+    records.sort_by_key(|record| record.id);
+    Ok(records
+        .into_iter()
+        .flat_map(|record| record.items.into_iter().map(move |item| (record.id, item)))
+        .filter(|(_, item)| item.started.is_some() || item.finished.is_some())
+        .map(|(job, item)| HistoryEntry {
+            job,
+            status: match item.phase {
+                Phase::Done => "done",
+                Phase::Failed => "failed",
+                // Items of jobs that were killed or are still running
+                _ => "incomplete",
+            },
+            upload_date: item.upload_date.as_deref().and_then(Date::from_compact).map(|date| date.to_string()),
+            started: item.started.map(iso8601),
+            finished: item.finished.map(iso8601),
+            url: item.url,
+            video_id: item.video_id,
+            title: item.title,
+            uploader: item.uploader,
+            duration: item.duration,
+            output: item.output,
+            bytes: item.bytes,
+            download_seconds: item.download_seconds,
+            convert_seconds: item.convert_seconds,
+            error: item.error,
+        })
+        .collect())
+}
+
+/// Quote a CSV field when it holds a separator, quote or line break (RFC 4180)
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn render_csv(entries: &[HistoryEntry]) -> String {
+    let optional = |value: Option<String>| value.unwrap_or_default();
+    let mut csv = COLUMNS.join(",") + "\n";
+    for entry in entries {
+        let fields = [
+            entry.job.to_string(),
+            entry.url.clone(),
+            entry.status.to_string(),
+            optional(entry.video_id.clone()),
+            optional(entry.title.clone()),
+            optional(entry.uploader.clone()),
+            optional(entry.upload_date.clone()),
+            optional(entry.duration.map(|duration| duration.to_string())),
+            optional(entry.started.clone()),
+            optional(entry.finished.clone()),
+            optional(entry.output.clone()),
+            optional(entry.bytes.map(|bytes| bytes.to_string())),
+            optional(entry.download_seconds.map(|seconds| format!("{:.1}", seconds))),
+            optional(entry.convert_seconds.map(|seconds| format!("{:.1}", seconds))),
+            optional(entry.error.clone()),
+        ];
+        csv.push_str(&fields.iter().map(|field| csv_field(field)).collect::<Vec<_>>().join(","));
+        csv.push('\n');
+    }
+    csv
+}
+
+/// Write the whole download history as CSV or JSON
+pub fn export(options: &ExportOptions) -> Result<(), VideoConversionError> {
+    let entries = entries()?;
+    let text = match options.format {
+        ExportFormat::Csv => render_csv(&entries),
+        ExportFormat::Json => {
+            serde_json::to_string_pretty(&entries).map_err(|e| VideoConversionError::io(e.to_string()))? + "\n"
+        }
+    };
+    match &options.output {
+        Some(path) => {
+            std::fs::write(path, text)
+                .map_err(|e| VideoConversionError::io(format!("Failed to write {}", path)).caused_by(e))?;
+            eprintln!("Exported {} entries to {}", entries.len(), path);
+        }
+        None => {
+            let mut stdout = std::io::stdout().lock();
+            stdout
+                .write_all(text.as_bytes())
+                .map_err(|e| VideoConversionError::io("Failed to write the history").caused_by(e))?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,168 @@
+use std::fmt::Write as _;
+use std::fs::{self, create_dir_all};
+use std::io::Write as _;
+use std::path::{Path, PathBuf};
+
+use tracing::info_span;
+
+use crate::codecs::Container;
+use crate::progress::Progress;
+use crate::{console, run_ffmpeg, segmented, EncodeOptions, HlsOptions, HlsSegmentType, VideoConversionError};
+
+/// File name of the media playlist inside the output directory
+pub const PLAYLIST_NAME: &str = "index.m3u8";
+
+/// File name of the AES-128 key inside the output directory when segments are encrypted
+pub const KEY_NAME: &str = "enc.key";
+
+/// File telling ffmpeg's HLS muxer the key URI and key file; removed once packaging ends
+const KEY_INFO_NAME: &str = "enc.keyinfo";
+
+/// Length of an AES-128 key in bytes
+const KEY_LENGTH: usize = 16;
+
+/// Write `key` to a new file at `path` that only its owner can read
+fn write_key(path: &Path, key: &[u8]) -> Result<(), VideoConversionError> {
+    // Permissions only apply to new files
+    let _ = fs::remove_file(path);
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options
+        .open(path)
+        .and_then(|mut file| file.write_all(key))
+        .map_err(|e| VideoConversionError::io(format!("Failed to write {}", path.display())).caused_by(e))
+}
+
+/// Prepare `--hls-encrypt` for playlists written into `dir` and write the key info file ffmpeg
+/// reads. The key comes from `--hls-key` or is generated. Playlists reference it by `--hls-key-uri`,
+/// and the key stays out of `dir` so it is not published with the segments: a given key is used
+/// where it is, a generated one is saved as `<dir>.key` next to the folder. Without a URI, the key
+/// is put at `dir/enc.key` and playlists reach it by `default_uri`, relative to themselves. Returns
+/// the key info file to pass as `-hls_key_info_file`, or `None` when encryption is off
+pub(crate) fn key_info(dir: &Path, hls: &HlsOptions, default_uri: &str) -> Result<Option<PathBuf>, VideoConversionError> {
+    if !hls.hls_encrypt {
+        return Ok(None);
+    }
+    let key: Vec<u8> = match &hls.hls_key {
+        Some(path) => {
+            let key = fs::read(path)
+                .map_err(|e| VideoConversionError::io(format!("Failed to read {}", path.display())).caused_by(e))?;
+            if key.len() != KEY_LENGTH {
+                return Err(VideoConversionError::config(format!(
+                    "{} holds {} bytes, but an AES-128 key is {} bytes",
+                    path.display(),
+                    key.len(),
+                    KEY_LENGTH
+                )));
+            }
+            key
+        }
+        None => {
+            let mut key = vec![0; KEY_LENGTH];
+            getrandom::fill(&mut key)
+                .map_err(|e| VideoConversionError::io(format!("Failed to generate an encryption key: {}", e)))?;
+            key
+        }
+    };
+    // ffmpeg may run in another directory, so the key info names the key by its absolute path
+    let resolve = |path: &Path| {
+        fs::canonicalize(path)
+            .map_err(|e| VideoConversionError::io(format!("Failed to resolve {}", path.display())).caused_by(e))
+    };
+    let dir = resolve(dir)?;
+    let key_path = match (&hls.hls_key_uri, &hls.hls_key) {
+        (Some(_), Some(given)) => resolve(given)?,
+        (Some(_), None) => {
+            let mut name = dir.file_name().unwrap_or_default().to_os_string();
+            name.push(".key");
+            let path = dir.with_file_name(name);
+            write_key(&path, &key)?;
+            path
+        }
+        (None, _) => {
+            let path = dir.join(KEY_NAME);
+            write_key(&path, &key)?;
+            path
+        }
+    };
+    let uri = hls.hls_key_uri.as_deref().unwrap_or(default_uri);
+    let mut info = String::new();
+    let _ = writeln!(info, "{}\n{}", uri, key_path.display());
+    let info_path = dir.join(KEY_INFO_NAME);
+    fs::write(&info_path, info)
+        .map_err(|e| VideoConversionError::io(format!("Failed to write {}", info_path.display())).caused_by(e))?;
+    match &hls.hls_key_uri {
+        Some(uri) => console!(
+            Warning,
+            "the key at {} decrypts the segments; serve it at {} to authorized viewers only",
+            key_path.display(),
+            uri
+        ),
+        None => console!(
+            Warning,
+            "the key at {} decrypts the segments; host it where only authorized viewers can fetch it",
+            key_path.display()
+        ),
+    }
+    Ok(Some(info_path))
+}
+
+/// Re-encode `input` into a VOD playlist at `playlist` with its segments in the same directory
+pub fn package(
+    input: &str,
+    playlist: &str,
+    encode: &EncodeOptions,
+    hls: &HlsOptions,
+    progress: &Progress,
+) -> Result<(), VideoConversionError> {
+    let _span = info_span!("package", input = input, output = playlist).entered();
+    let dir = Path::new(playlist).parent().unwrap_or(Path::new("."));
+    create_dir_all(dir)
+        .map_err(|e| VideoConversionError::io(format!("Failed to create {}", dir.display())).caused_by(e))?;
+    let key_info = key_info(dir, hls, KEY_NAME)?;
+    let seconds = hls.hls_segment_duration;
+    println!("Re-encoding video into HLS segments of {}s...", seconds);
+
+    let mut command = encode.ffmpeg_command();
+    command
+        .args(["-progress", "pipe:1", "-nostats"])
+        .arg("-i")
+        .arg(input)
+        .args(encode.video_filter_args())
+        .args(encode.video_args())
+        .args(encode.audio_args(Container::Hls))
+        // Segments can only start at keyframes, so put one at every boundary
+        .arg("-force_key_frames")
+        .arg(format!("expr:gte(t,n_forced*{})", seconds));
+    if hls.hls_segment_type == HlsSegmentType::Fmp4 {
+        command.args(encode.video_codec().mp4_tag().map(|tag| ["-tag:v", tag]).into_iter().flatten());
+    }
+    command
+        .args(["-f", "hls", "-hls_playlist_type", "vod", "-hls_time"])
+        .arg(seconds.to_string())
+        .arg("-hls_segment_type")
+        .arg(hls.hls_segment_type.as_str())
+        .arg("-hls_segment_filename")
+        // `%` starts a placeholder in this pattern, so literal ones in the directory are doubled
+        .arg(format!("{}/segment_%05d.{}", dir.to_string_lossy().replace('%', "%%"), hls.hls_segment_type.extension()));
+    if let Some(key_info) = &key_info {
+        command.arg("-hls_key_info_file").arg(key_info);
+    }
+    if let Some(threads) = encode.threads {
+        command.arg("-threads").arg(threads.to_string());
+    }
+    command.args(encode.ffmpeg_arg.iter().flatten()).arg(playlist);
+    let result = run_ffmpeg(&mut command, segmented::probe_duration(input), progress);
+    if let Some(key_info) = &key_info {
+        let _ = fs::remove_file(key_info);
+    }
+    result.map_err(VideoConversionError::conversion)?;
+
+    console!(Success, "HLS playlist written: {}", playlist);
+    Ok(())
+}
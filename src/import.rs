@@ -0,0 +1,209 @@
+use std::collections::HashSet;
+
+use serde_json::Value;
+use tracing::{debug, info_span};
+
+use crate::jobs::{self, Phase};
+use crate::progress::Progress;
+use crate::{clipboard, download_all, urls, DownloadOptions, VideoConversionError};
+
+/// What `import` reads
+#[derive(clap::Args, Debug, Clone)]
+pub struct ImportOptions {
+    /// Bookmarks exported as HTML, a CSV export, a Google Takeout watch-later CSV or JSON, or a
+    /// plain list of URLs
+    file: String,
+
+    /// Also import URLs of sites without first-class support, which bookmarks are full of
+    #[arg(long)]
+    all_urls: bool,
+
+    /// List the URLs that would be downloaded without downloading them
+    #[arg(long)]
+    dry_run: bool,
+}
+
+/// Kinds of export `import` understands
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ExportKind {
+    /// Netscape bookmark file, as every browser exports
+    Bookmarks,
+    /// JSON such as Takeout's watch history, with URLs in string fields
+    Json,
+    /// CSV with a video ID column, as Takeout exports playlists including Watch Later
+    TakeoutCsv,
+    /// Anything else: URLs found anywhere in the text
+    Text,
+}
+
+fn detect(text: &str) -> ExportKind {
+    let trimmed = text.trim_start();
+    if trimmed.starts_with('[') || trimmed.starts_with('{') {
+        ExportKind::Json
+    } else if trimmed.starts_with('<') || text.contains("NETSCAPE-Bookmark-file") {
+        ExportKind::Bookmarks
+    } else if takeout_id_column(text).is_some() {
+        ExportKind::TakeoutCsv
+    } else {
+        ExportKind::Text
+    }
+}
+
+/// Undo the entity escaping of HTML attribute values
+fn unescape_html(text: &str) -> String {
+    text.replace("&quot;", "\"").replace("&#39;", "'").replace("&lt;", "<").replace("&gt;", ">").replace("&amp;", "&")
+}
+
+/// `HREF` targets of the links in a bookmark file
+fn bookmark_urls(html: &str) -> Vec<String> {
+    let lower = html.to_ascii_lowercase();
+    let mut urls = Vec::new();
+    let mut from = 0;
+    while let Some(position) = lower[from..].find("href=") {
+        let start = from + position + "href=".len();
+        let quote = html[start..].chars().next().filter(|c| *c == '"' || *c == '\'');
+        let value_start = start + quote.map_or(0, char::len_utf8);
+        let end = match quote {
+            Some(quote) => html[value_start..].find(quote),
+            None => html[value_start..].find(|c: char| c.is_whitespace() || c == '>'),
+        }
+        .map_or(html.len(), |end| value_start + end);
+        urls.push(unescape_html(&html[value_start..end]));
+        from = end;
+    }
+    urls
+}
+
+/// Strings in a JSON document that look like URLs, in document order
+fn json_urls(value: &Value, urls: &mut Vec<String>) {
+    match value {
+        Value::String(text) if text.starts_with("http") => urls.push(text.clone()),
+        Value::Array(values) => values.iter().for_each(|value| json_urls(value, urls)),
+        Value::Object(map) => map.values().for_each(|value| json_urls(value, urls)),
+        _ => {}
+    }
+}
+
+/// Fields of one CSV line, honouring quotes (RFC 4180; quoted line breaks are not supported)
+fn csv_fields(line: &str) -> Vec<String> {
+    let mut fields = vec![String::new()];
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                fields.last_mut().unwrap().push('"');
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(String::new()),
+            c => fields.last_mut().unwrap().push(c),
+        }
+    }
+    fields
+}
+
+/// Line and column of the video ID header in a Takeout playlist CSV; older exports put a block
+/// describing the playlist above it
+fn takeout_id_column(text: &str) -> Option<(usize, usize)> {
+    text.lines().enumerate().find_map(|(index, line)| {
+        let column = csv_fields(line).iter().position(|field| field.trim().eq_ignore_ascii_case("video id"))?;
+        Some((index, column))
+    })
+}
+
+/// Watch URLs of the video IDs below the header of a Takeout playlist CSV
+fn takeout_urls(text: &str) -> Vec<String> {
+    let Some((header, column)) = takeout_id_column(text) else { return Vec::new() };
+    text.lines()
+        .skip(header + 1)
+        .filter_map(|line| csv_fields(line).get(column).map(|id| id.trim().to_string()))
+        .filter(|id| id.len() == 11 && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'))
+        .map(|id| format!("https://www.youtube.com/watch?v={}", id))
+        .collect()
+}
+
+/// `http(s)://` URLs anywhere in `text`, ended by whitespace, quotes, commas or angle brackets
+fn text_urls(text: &str) -> Vec<String> {
+    text.split(|c: char| c.is_whitespace() || matches!(c, '"' | '\'' | ',' | '<' | '>'))
+        .filter(|token| token.starts_with("http://") || token.starts_with("https://"))
+        .map(str::to_string)
+        .collect()
+}
+
+/// Normalized URLs and video IDs of every video downloaded before
+fn downloaded() -> Result<HashSet<String>, VideoConversionError> {
+    let mut known = HashSet::new();
+    for item in jobs::list()?.into_iter().flat_map(|record| record.items).filter(|item| item.phase == Phase::Done) {
+        if let Ok(source) = urls::normalize(&item.url) {
+            known.extend(source.video_id);
+            known.insert(source.url);
+        }
+        known.extend(item.video_id);
+    }
+    Ok(known)
+}
+
+/// Video URLs of an export file, canonical and without repeats or videos already downloaded
+pub fn read(options: &ImportOptions) -> Result<Vec<String>, VideoConversionError> {
+    let text = std::fs::read_to_string(&options.file)
+        .map_err(|e| VideoConversionError::config(format!("cannot read {}", options.file)).caused_by(e))?;
+    let kind = detect(&text);
+    debug!(kind = ?kind, "detected export format");
+    let found = match kind {
+        ExportKind::Bookmarks => bookmark_urls(&text),
+        ExportKind::Json => {
+            let value: Value = serde_json::from_str(&text)
+                .map_err(|e| VideoConversionError::config(format!("invalid JSON in {}", options.file)).caused_by(e))?;
+            let mut urls = Vec::new();
+            json_urls(&value, &mut urls);
+            urls
+        }
+        ExportKind::TakeoutCsv => takeout_urls(&text),
+        ExportKind::Text => text_urls(&text),
+    };
+
+    let downloaded = downloaded()?;
+    let mut seen = HashSet::new();
+    let (mut already, mut other) = (0, 0);
+    let mut urls = Vec::new();
+    for url in found {
+        let Some(url) = clipboard::video_url(url.trim(), options.all_urls) else {
+            other += 1;
+            continue;
+        };
+        if !seen.insert(url.clone()) {
+            continue;
+        }
+        let id = urls::normalize(&url).ok().and_then(|source| source.video_id);
+        if downloaded.contains(&url) || id.is_some_and(|id| downloaded.contains(&id)) {
+            already += 1;
+            continue;
+        }
+        urls.push(url);
+    }
+    println!(
+        "Found {} new video URL(s) in {}; {} already downloaded, {} other link(s) skipped",
+        urls.len(),
+        options.file,
+        already,
+        other
+    );
+    Ok(urls)
+}
+
+/// Download the new videos of a bookmark, CSV or Takeout export
+pub fn run(options: &ImportOptions, download: &DownloadOptions, progress: &Progress) -> Result<(), VideoConversionError> {
+    let _span = info_span!("import", file = %options.file).entered();
+    let urls = read(options)?;
+    if options.dry_run {
+        for url in &urls {
+            println!("{}", url);
+        }
+        return Ok(());
+    }
+    if urls.is_empty() {
+        return Ok(());
+    }
+    download_all(&urls, download, progress)
+}
@@ -0,0 +1,605 @@
+use std::cell::Cell;
+use std::fs::{create_dir_all, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::metadata::VideoInfo;
+use crate::progress::{ProgressEvent, Stage};
+use crate::VideoConversionError;
+
+/// Where a job or one of its items stands
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Phase {
+    Queued,
+    Downloading,
+    Converting,
+    Done,
+    Failed,
+}
+
+impl Phase {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Phase::Queued => "queued",
+            Phase::Downloading => "downloading",
+            Phase::Converting => "converting",
+            Phase::Done => "done",
+            Phase::Failed => "failed",
+        }
+    }
+
+    fn is_finished(&self) -> bool {
+        matches!(self, Phase::Done | Phase::Failed)
+    }
+}
+
+impl std::fmt::Display for Phase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.pad(self.as_str())
+    }
+}
+
+/// State of a single video within a job
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ItemStatus {
+    pub url: String,
+    pub phase: Phase,
+    /// Progress of the download or conversion in percent, when known
+    #[serde(default)]
+    pub progress: Option<f64>,
+    /// Unix times the item started and finished
+    #[serde(default)]
+    pub started: Option<u64>,
+    #[serde(default)]
+    pub finished: Option<u64>,
+    #[serde(default)]
+    pub output: Option<String>,
+    #[serde(default)]
+    pub error: Option<String>,
+    /// Bytes received from media servers
+    #[serde(default)]
+    pub bytes: Option<u64>,
+    /// Seconds spent downloading and converting
+    #[serde(default)]
+    pub download_seconds: Option<f64>,
+    #[serde(default)]
+    pub convert_seconds: Option<f64>,
+    /// What the site reported about the video, when its metadata was read
+    #[serde(default)]
+    pub video_id: Option<String>,
+    #[serde(default)]
+    pub title: Option<String>,
+    #[serde(default)]
+    pub uploader: Option<String>,
+    /// Upload date as YYYYMMDD
+    #[serde(default)]
+    pub upload_date: Option<String>,
+    /// Duration in seconds
+    #[serde(default)]
+    pub duration: Option<f64>,
+    /// Progress of the stream being downloaded, which restarts from zero for every stream
+    #[serde(skip)]
+    stream_bytes: u64,
+    /// Start of the running download or conversion
+    #[serde(skip)]
+    stage_started: Option<(Stage, Instant)>,
+}
+
+impl ItemStatus {
+    /// Average download speed in bytes per second, when both the bytes and the time are known
+    pub fn download_speed(&self) -> Option<f64> {
+        match (self.bytes, self.download_seconds) {
+            (Some(bytes), Some(seconds)) if seconds > 0.0 => Some(bytes as f64 / seconds),
+            _ => None,
+        }
+    }
+
+    /// Time from start to finish
+    pub fn elapsed(&self) -> Option<Duration> {
+        Some(Duration::from_secs(self.finished?.saturating_sub(self.started?)))
+    }
+
+    /// Add the time since the running stage started to its total
+    fn end_stage(&mut self) {
+        let Some((stage, started)) = self.stage_started.take() else { return };
+        let total = match stage {
+            Stage::Download => &mut self.download_seconds,
+            Stage::Convert => &mut self.convert_seconds,
+            Stage::Record | Stage::Cleanup => return,
+        };
+        *total.get_or_insert(0.0) += started.elapsed().as_secs_f64();
+    }
+}
+
+/// A download job, persisted so that other invocations can inspect and control it
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct JobStatus {
+    pub id: u64,
+    /// Process running the job
+    pub pid: u32,
+    /// External tools (yt-dlp, ffmpeg) currently running for the job
+    #[serde(default)]
+    pub children: Vec<u32>,
+    #[serde(default)]
+    pub paused: bool,
+    pub phase: Phase,
+    /// Unix times the job started and finished
+    pub started: u64,
+    #[serde(default)]
+    pub finished: Option<u64>,
+    /// URLs the job was submitted with
+    pub urls: Vec<String>,
+    /// Videos the URLs expanded to, in download order
+    #[serde(default)]
+    pub items: Vec<ItemStatus>,
+    #[serde(default)]
+    pub error: Option<String>,
+    /// `--library-priority` of the downloads, which decides what `--evict priority` deletes first
+    #[serde(default)]
+    pub library_priority: i32,
+    /// Working directory of the job, which relative outputs are relative to
+    #[serde(default)]
+    pub dir: Option<PathBuf>,
+}
+
+impl JobStatus {
+    /// Overall progress in percent, counting finished items fully and the running one by its download progress
+    pub fn progress(&self) -> Option<f64> {
+        if self.items.is_empty() {
+            return None;
+        }
+        let done: f64 = self
+            .items
+            .iter()
+            .map(|item| match item.phase {
+                Phase::Done | Phase::Failed => 1.0,
+                Phase::Downloading => item.progress.unwrap_or(0.0) / 100.0,
+                Phase::Converting | Phase::Queued => 0.0,
+            })
+            .sum();
+        Some(done / self.items.len() as f64 * 100.0)
+    }
+
+    /// Time spent so far, or in total once the job finished
+    pub fn elapsed(&self) -> Duration {
+        Duration::from_secs(self.finished.unwrap_or_else(now).saturating_sub(self.started))
+    }
+
+    /// Derive the job phase from its items: the most advanced running item, else done or failed once over
+    fn refresh_phase(&mut self) {
+        let running = |phase| self.items.iter().any(|item| item.phase == phase);
+        self.phase = if self.finished.is_some() {
+            if self.error.is_some() || running(Phase::Failed) {
+                Phase::Failed
+            } else {
+                Phase::Done
+            }
+        } else if running(Phase::Converting) {
+            Phase::Converting
+        } else if running(Phase::Downloading) {
+            Phase::Downloading
+        } else {
+            Phase::Queued
+        };
+    }
+
+    /// The item progress events refer to: the one this thread works on, else the one in flight,
+    /// else the next queued one
+    fn current_item(&mut self) -> Option<&mut ItemStatus> {
+        if let Some(index) = ITEM.get() {
+            return self.items.get_mut(index);
+        }
+        let index = self
+            .items
+            .iter()
+            .position(|item| matches!(item.phase, Phase::Downloading | Phase::Converting))
+            .or_else(|| self.items.iter().position(|item| item.phase == Phase::Queued))?;
+        self.items.get_mut(index)
+    }
+}
+
+/// Handle to a job, by ID, for querying and controlling it from any process
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct JobHandle {
+    id: u64,
+}
+
+impl JobHandle {
+    pub fn new(id: u64) -> JobHandle {
+        JobHandle { id }
+    }
+
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Current phase, progress and timing of the job
+    pub fn status(&self) -> Result<JobStatus, VideoConversionError> {
+        load(self.id)
+    }
+
+    /// Suspend the job and the tools it is running
+    pub fn pause(&self) -> Result<(), VideoConversionError> {
+        let mut record = running(self.id)?;
+        if record.paused {
+            return Err(VideoConversionError::config(format!("job {} is already paused", self.id)));
+        }
+        // Stop the job first so it cannot start another tool in between
+        signal(record.pid, Signal::Stop)?;
+        for child in &record.children {
+            let _ = signal(*child, Signal::Stop);
+        }
+        record.paused = true;
+        save(&record)
+    }
+
+    /// Continue a job suspended with `pause`
+    pub fn resume(&self) -> Result<(), VideoConversionError> {
+        let mut record = running(self.id)?;
+        if !record.paused {
+            return Err(VideoConversionError::config(format!("job {} is not paused", self.id)));
+        }
+        record.paused = false;
+        save(&record)?;
+        for child in &record.children {
+            let _ = signal(*child, Signal::Continue);
+        }
+        signal(record.pid, Signal::Continue)
+    }
+}
+
+/// Finished jobs kept for `status`; older records are removed when a new job starts, unless they
+/// are recent enough for `stats`
+const MAX_FINISHED_JOBS: usize = 50;
+
+/// Age in seconds up to which finished jobs are kept for `stats`, covering five full weeks
+const HISTORY_AGE: u64 = 36 * 86_400;
+
+/// Minimum time between record writes caused by download or conversion progress alone
+const PROGRESS_WRITE_INTERVAL: Duration = Duration::from_millis(500);
+
+/// The job run by this process, if any, and when its record was last written
+static CURRENT: Mutex<Option<(JobStatus, Instant)>> = Mutex::new(None);
+
+thread_local! {
+    /// Index of the item this thread works on, when items of the job run on several threads
+    static ITEM: Cell<Option<usize>> = const { Cell::new(None) };
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+/// Directory holding one `{id}.json` record per job
+pub fn jobs_dir() -> Result<PathBuf, VideoConversionError> {
+    Ok(crate::config::state_dir()?.join("jobs"))
+}
+
+fn record_path(id: u64) -> Result<PathBuf, VideoConversionError> {
+    Ok(jobs_dir()?.join(format!("{}.json", id)))
+}
+
+fn io_error(path: &std::path::Path) -> impl Fn(std::io::Error) -> VideoConversionError + '_ {
+    move |e| VideoConversionError::io(format!("Failed to write {}", path.display())).caused_by(e)
+}
+
+/// Write a record, replacing the previous one atomically so readers never see a partial file
+fn save(record: &JobStatus) -> Result<(), VideoConversionError> {
+    let path = record_path(record.id)?;
+    let temp = path.with_extension("json.tmp");
+    let json = serde_json::to_string_pretty(record).map_err(|e| VideoConversionError::io(e.to_string()))?;
+    std::fs::write(&temp, json + "\n").map_err(io_error(&temp))?;
+    std::fs::rename(&temp, &path).map_err(io_error(&path))
+}
+
+/// Read the record of job `id`; jobs whose process died without finishing are reported as failed
+fn load(id: u64) -> Result<JobStatus, VideoConversionError> {
+    let path = record_path(id)?;
+    let text = std::fs::read_to_string(&path).map_err(|e| match e.kind() {
+        ErrorKind::NotFound => VideoConversionError::config(format!("no job with ID {}", id)),
+        _ => VideoConversionError::io(format!("Failed to read {}", path.display())).caused_by(e),
+    })?;
+    let mut record: JobStatus = serde_json::from_str(&text)
+        .map_err(|e| VideoConversionError::io(format!("invalid job record {}", path.display())).caused_by(e))?;
+
+    if record.finished.is_none() && !process_alive(record.pid) {
+        record.finished = Some(now());
+        record.error.get_or_insert_with(|| "interrupted".to_string());
+        record.children.clear();
+        record.paused = false;
+        for item in record.items.iter_mut().filter(|item| !item.phase.is_finished()) {
+            item.phase = Phase::Failed;
+            item.error.get_or_insert_with(|| "interrupted".to_string());
+        }
+        record.refresh_phase();
+        let _ = save(&record);
+    }
+    Ok(record)
+}
+
+/// Records of every known job, ordered by ID
+pub fn list() -> Result<Vec<JobStatus>, VideoConversionError> {
+    let dir = jobs_dir()?;
+    let entries = match std::fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(VideoConversionError::io(format!("Failed to read {}", dir.display())).caused_by(e)),
+    };
+
+    let mut records: Vec<JobStatus> = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| entry.file_name().to_str()?.strip_suffix(".json")?.parse::<u64>().ok())
+        .filter_map(|id| load(id).ok())
+        .collect();
+    records.sort_by_key(|record| record.id);
+    Ok(records)
+}
+
+/// Registration of this process as a job; the job is marked finished on drop
+pub struct JobGuard {
+    pub id: u64,
+}
+
+/// Register this process as a job under the next free ID
+pub fn start(urls: &[String]) -> Result<JobGuard, VideoConversionError> {
+    let dir = jobs_dir()?;
+    create_dir_all(&dir).map_err(io_error(&dir))?;
+
+    let records = list()?;
+    let finished: Vec<&JobStatus> = records.iter().filter(|record| record.finished.is_some()).collect();
+    for old in finished.iter().take(finished.len().saturating_sub(MAX_FINISHED_JOBS - 1)) {
+        if old.finished.is_some_and(|finished| finished + HISTORY_AGE < now()) {
+            let _ = record_path(old.id).map(std::fs::remove_file);
+        }
+    }
+
+    let mut id = records.last().map_or(1, |record| record.id + 1);
+    // Claim the ID with an exclusive create, so concurrent starts never share one
+    loop {
+        let path = record_path(id)?;
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(mut file) => {
+                file.write_all(b"{}").map_err(io_error(&path))?;
+                break;
+            }
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => id += 1,
+            Err(e) => return Err(io_error(&path)(e)),
+        }
+    }
+
+    let record = JobStatus {
+        id,
+        pid: std::process::id(),
+        children: Vec::new(),
+        paused: false,
+        phase: Phase::Queued,
+        started: now(),
+        finished: None,
+        urls: urls.to_vec(),
+        items: Vec::new(),
+        error: None,
+        library_priority: 0,
+        dir: std::env::current_dir().ok(),
+    };
+    save(&record)?;
+    *CURRENT.lock().unwrap_or_else(|e| e.into_inner()) = Some((record, Instant::now()));
+    Ok(JobGuard { id })
+}
+
+impl JobGuard {
+    /// Mark the job finished, recording the error it failed with
+    pub fn finish(self, error: Option<&VideoConversionError>) {
+        update(true, |record| record.error = error.map(|e| e.to_string()));
+    }
+}
+
+impl Drop for JobGuard {
+    fn drop(&mut self) {
+        update(true, |record| {
+            record.finished = Some(now());
+            record.children.clear();
+            let reason = record.error.clone().unwrap_or_else(|| "not completed".to_string());
+            for item in record.items.iter_mut().filter(|item| !item.phase.is_finished()) {
+                item.phase = Phase::Failed;
+                item.error.get_or_insert_with(|| reason.clone());
+            }
+        });
+        CURRENT.lock().unwrap_or_else(|e| e.into_inner()).take();
+    }
+}
+
+/// Apply a change to the current job's record and persist it; a no-op outside of jobs
+///
+/// Unless `force` is set, writes are rate limited so frequent progress updates don't thrash the disk.
+fn update(force: bool, change: impl FnOnce(&mut JobStatus)) {
+    let mut current = CURRENT.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some((record, last_write)) = current.as_mut() {
+        change(record);
+        record.refresh_phase();
+        if force || last_write.elapsed() >= PROGRESS_WRITE_INTERVAL {
+            // The record only helps other invocations; failing to write it must not fail the job
+            let _ = save(record);
+            *last_write = Instant::now();
+        }
+    }
+}
+
+/// Add videos about to be downloaded to the current job; returns the index of the first one, for `enter_item`
+pub fn queue<'a>(urls: impl IntoIterator<Item = &'a str>) -> usize {
+    let mut first = 0;
+    update(true, |record| {
+        first = record.items.len();
+        record.items.extend(urls.into_iter().map(|url| ItemStatus {
+            url: url.to_string(),
+            phase: Phase::Queued,
+            progress: None,
+            started: None,
+            finished: None,
+            output: None,
+            error: None,
+            bytes: None,
+            download_seconds: None,
+            convert_seconds: None,
+            video_id: None,
+            title: None,
+            uploader: None,
+            upload_date: None,
+            duration: None,
+            stream_bytes: 0,
+            stage_started: None,
+        }))
+    });
+    first
+}
+
+/// Attributes this thread's progress events and item info to item `index` of the current job until dropped
+pub(crate) struct ItemGuard(Option<usize>);
+
+impl Drop for ItemGuard {
+    fn drop(&mut self) {
+        ITEM.set(self.0);
+    }
+}
+
+/// Work on item `index` of the current job on this thread
+pub(crate) fn enter_item(index: usize) -> ItemGuard {
+    ItemGuard(ITEM.replace(Some(index)))
+}
+
+/// Record the `--library-priority` of the current job's downloads
+pub fn set_library_priority(priority: i32) {
+    update(true, |record| record.library_priority = priority);
+}
+
+/// Record what the site reported about the current item
+pub(crate) fn set_item_info(info: &VideoInfo) {
+    update(true, |record| {
+        let Some(item) = record.current_item() else { return };
+        item.video_id = info.id.clone();
+        item.title = info.title.clone();
+        item.uploader = info.uploader.clone().or_else(|| info.channel.clone());
+        item.upload_date = info.upload_date.clone();
+        item.duration = info.duration;
+    });
+}
+
+/// Mirror a progress event into the current job's record
+pub fn observe(event: &ProgressEvent) {
+    let force = !matches!(event, ProgressEvent::DownloadProgress { .. } | ProgressEvent::ConvertProgress { .. });
+    update(force, |record| {
+        let Some(item) = record.current_item() else { return };
+        match event {
+            ProgressEvent::ItemStarted { .. } => {
+                item.phase = Phase::Downloading;
+                item.started.get_or_insert_with(now);
+            }
+            ProgressEvent::StageStarted { stage: Stage::Download } => {
+                item.phase = Phase::Downloading;
+                item.started.get_or_insert_with(now);
+                item.stream_bytes = 0;
+                item.end_stage();
+                item.stage_started = Some((Stage::Download, Instant::now()));
+            }
+            ProgressEvent::StageStarted { stage: Stage::Convert } => {
+                item.phase = Phase::Converting;
+                item.progress = None;
+                item.end_stage();
+                item.stage_started = Some((Stage::Convert, Instant::now()));
+            }
+            ProgressEvent::DownloadProgress { downloaded_bytes, percent, .. } => {
+                let new = downloaded_bytes.checked_sub(item.stream_bytes).unwrap_or(*downloaded_bytes);
+                *item.bytes.get_or_insert(0) += new;
+                item.stream_bytes = *downloaded_bytes;
+                item.progress = *percent
+            }
+            ProgressEvent::ConvertProgress { percent, .. } => item.progress = *percent,
+            ProgressEvent::StageFinished { .. } => item.end_stage(),
+            ProgressEvent::Finished { output } => {
+                item.end_stage();
+                item.phase = Phase::Done;
+                item.finished = Some(now());
+                item.output = Some(output.clone());
+            }
+            ProgressEvent::Failed { error, .. } => {
+                item.end_stage();
+                item.phase = Phase::Failed;
+                item.finished = Some(now());
+                item.error = Some(error.clone());
+            }
+            ProgressEvent::StageStarted { .. } | ProgressEvent::Aggregate { .. } => {}
+        }
+    });
+}
+
+/// Tracks a child process of the current job so that it gets paused along with the job
+pub struct ChildGuard {
+    pid: u32,
+}
+
+impl ChildGuard {
+    pub fn new(pid: u32) -> ChildGuard {
+        update(true, |record| record.children.push(pid));
+        ChildGuard { pid }
+    }
+}
+
+impl Drop for ChildGuard {
+    fn drop(&mut self) {
+        update(true, |record| record.children.retain(|pid| *pid != self.pid));
+    }
+}
+
+/// Load the record of job `id`, failing unless it is still running
+fn running(id: u64) -> Result<JobStatus, VideoConversionError> {
+    let record = load(id)?;
+    if record.finished.is_some() {
+        return Err(VideoConversionError::config(format!("job {} is no longer running", id)));
+    }
+    Ok(record)
+}
+
+#[derive(Clone, Copy)]
+enum Signal {
+    Stop,
+    Continue,
+}
+
+#[cfg(unix)]
+fn signal(pid: u32, signal: Signal) -> Result<(), VideoConversionError> {
+    let signal = match signal {
+        Signal::Stop => libc::SIGSTOP,
+        Signal::Continue => libc::SIGCONT,
+    };
+    // SAFETY: kill has no memory-safety preconditions
+    if unsafe { libc::kill(pid as libc::pid_t, signal) } == 0 {
+        Ok(())
+    } else {
+        Err(VideoConversionError::io(format!(
+            "cannot signal process {}: {}",
+            pid,
+            std::io::Error::last_os_error()
+        )))
+    }
+}
+
+#[cfg(not(unix))]
+fn signal(_pid: u32, _signal: Signal) -> Result<(), VideoConversionError> {
+    Err(VideoConversionError::config("pausing jobs is only supported on Unix"))
+}
+
+#[cfg(unix)]
+fn process_alive(pid: u32) -> bool {
+    // SAFETY: signal 0 only checks whether the process exists
+    let exists = unsafe { libc::kill(pid as libc::pid_t, 0) } == 0;
+    exists || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+#[cfg(not(unix))]
+fn process_alive(_pid: u32) -> bool {
+    true
+}
@@ -0,0 +1,74 @@
+use std::path::Path;
+
+use clap::ValueEnum;
+
+use crate::dates::Date;
+use crate::metadata::VideoInfo;
+use crate::nfo;
+use crate::sites::sanitize_filename;
+use crate::VideoConversionError;
+
+/// Library layouts for `--organize`
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum, Debug)]
+pub enum Layout {
+    /// `Channel/Season YYYY/Channel - YYYY-MM-DD - Title`, one season per upload year
+    Jellyfin,
+}
+
+/// Where a download lands inside a library layout
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Placement {
+    /// Directory of the output file
+    pub dir: String,
+    /// File name without extension
+    pub name: String,
+    /// Top-level show directory, for show-wide sidecars
+    pub show_dir: String,
+    /// Display name of the show (the channel)
+    pub show_title: String,
+}
+
+/// Compute the directory and file name of a video within `base_dir` for `layout`
+pub fn place(layout: Layout, base_dir: &str, info: &VideoInfo) -> Placement {
+    match layout {
+        Layout::Jellyfin => {
+            let channel = info
+                .channel
+                .as_ref()
+                .or(info.uploader.as_ref())
+                .map_or("Unknown Channel", String::as_str);
+            let channel_dir = sanitize_filename(channel);
+            let title = info.title.as_deref().unwrap_or("Untitled");
+            let date = info.upload_date.as_deref().and_then(Date::from_compact);
+
+            // Undated uploads go to season 0, which Jellyfin shows as "Specials"
+            let season = date.map_or("Specials".to_string(), |d| format!("Season {}", d.year));
+            let name = match date {
+                Some(date) => format!("{} - {} - {}", channel, date, title),
+                None => format!("{} - {}", channel, title),
+            };
+
+            let show_dir = format!("{}/{}", base_dir, channel_dir);
+            Placement {
+                dir: format!("{}/{}", show_dir, season),
+                name: sanitize_filename(&name),
+                show_dir,
+                show_title: channel.to_string(),
+            }
+        }
+    }
+}
+
+/// Write `tvshow.nfo` into the show directory unless one exists, so the channel is recognized as a series
+pub fn write_show_nfo(placement: &Placement) -> Result<(), VideoConversionError> {
+    let path = Path::new(&placement.show_dir).join("tvshow.nfo");
+    if path.exists() {
+        return Ok(());
+    }
+    let xml = format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n<tvshow>\n  <title>{}</title>\n</tvshow>\n",
+        nfo::escape(&placement.show_title)
+    );
+    std::fs::write(&path, xml)
+        .map_err(|e| VideoConversionError::io(format!("Failed to write {}", path.display())).caused_by(e))
+}
@@ -0,0 +1,1956 @@
+//! Download videos with yt-dlp and convert them into QuickTime-compatible MP4 or MP3 files with ffmpeg
+
+use std::fs::{create_dir_all, File};
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use clap::ValueEnum;
+use thiserror::Error;
+use tracing::{error, info, info_span, Span};
+
+pub mod backend;
+mod abr;
+mod album;
+pub mod analyze;
+pub mod audio_lang;
+pub mod bench;
+pub mod check;
+mod chapters;
+mod clipboard;
+pub mod codecs;
+pub mod compare;
+pub mod config;
+pub mod convert_dir;
+mod dates;
+pub mod dedupe;
+mod devices;
+pub mod diagnostics;
+mod disk;
+pub mod doctor;
+pub mod estimate;
+pub mod feed;
+mod filters;
+mod folder;
+pub mod filtergraph;
+pub mod hardware;
+pub mod history;
+mod hls;
+pub mod import;
+pub mod jobs;
+mod layout;
+pub mod logging;
+mod metadata;
+pub mod metrics;
+mod musicbrainz;
+mod nfo;
+pub mod organize;
+mod playlist;
+mod pool;
+mod preview;
+mod priority;
+mod quota;
+mod process;
+mod proxy;
+mod replaygain;
+pub mod pipeline;
+pub mod progress;
+pub mod radio;
+pub mod record;
+mod report;
+pub mod restream;
+pub mod scenes;
+mod segmented;
+pub mod service;
+pub mod sheet;
+pub mod shutdown;
+pub mod stats;
+pub mod sync;
+mod sites;
+mod thumbnail;
+pub mod timestamp;
+mod transcribe;
+pub mod translate;
+pub mod trim;
+mod twitch;
+pub mod update;
+pub mod urls;
+mod verify;
+pub mod watch_folder;
+mod youtube_api;
+pub mod ytdlp;
+
+use backend::{Backends, MediaProbe, Transcoder};
+use codecs::{AudioCodec, Bitrate, Container, VideoCodec};
+use pipeline::{
+    Cleanup, DownloadAudio, DownloadStream, DownloadVideo, Encode, FilterAudio, PackageAbr, PackageHls, Pipeline, StripMetadata,
+};
+use process::ChildProcess;
+use progress::{FfmpegProgress, Progress, ProgressEvent};
+use sites::{Quality, Site};
+use timestamp::MediaTimestamp;
+use twitch::ChatFormat;
+use ytdlp::YtDlpOptions;
+
+/// Settings shared by every item of a download run
+#[derive(clap::Args, Debug, Clone)]
+pub struct DownloadOptions {
+    /// Custom name for the output video and audio files (without extension); defaults to the site's filename template
+    #[arg(short, long)]
+    name: Option<String>,
+
+    /// Output directory where the files will be saved
+    #[arg(short, long, default_value = "Processed")]
+    output_dir: String,
+
+    /// Output format: mp4, mp3, or hls/abr for a playlist with segments (abr: several renditions
+    /// behind a master manifest) in a directory named after the video
+    #[arg(short, long, value_enum, default_value = "mp4")]
+    format: Container,
+
+    /// Maximum video quality to download: best, or a height such as 1080p or 720p
+    #[arg(short, long, default_value = "best")]
+    quality: Quality,
+
+    /// Audio track to download from videos with several dubs, as a language code such as de or
+    /// en-US; the default track is kept when the language is not offered (see list-audio-langs)
+    #[arg(long, value_name = "LANG")]
+    audio_lang: Option<String>,
+
+    /// Save the chat replay of a Twitch VOD as a sidecar file
+    #[arg(long, value_enum, value_name = "FORMAT")]
+    twitch_chat: Option<ChatFormat>,
+
+    /// Write the video's metadata (source, formats, description) to {name}.info.json
+    #[arg(long)]
+    write_info_json: bool,
+
+    /// Write the video's description to {name}.description.txt
+    #[arg(long)]
+    write_description: bool,
+
+    /// Save up to N top comments (default: 100) to {name}.comments.json
+    #[arg(long, value_name = "N", num_args = 0..=1, default_missing_value = "100", value_parser = clap::value_parser!(u32).range(1..))]
+    write_comments: Option<u32>,
+
+    /// Save the video's best thumbnail next to the output under the same name, converted to JPEG
+    /// or PNG so media servers use it as poster art
+    #[arg(long, value_enum, value_name = "FORMAT", num_args = 0..=1, default_missing_value = "jpg")]
+    write_thumbnail: Option<thumbnail::ThumbnailFormat>,
+
+    /// Write a Kodi/Jellyfin/Plex .nfo file next to the output (episode or movie)
+    #[arg(long, value_enum, value_name = "KIND", num_args = 0..=1, default_missing_value = "episode")]
+    write_nfo: Option<nfo::NfoKind>,
+
+    /// Arrange output in a media-server library layout, with NFO and thumbnail sidecars
+    #[arg(long, value_enum, value_name = "LAYOUT", conflicts_with = "name")]
+    organize: Option<layout::Layout>,
+
+    /// Embed the video's chapters, taken from the site or from timestamps in the description,
+    /// and optionally also split the output into one file per chapter or write a cue sheet for it
+    #[arg(long, value_enum, value_name = "MODE", num_args = 0..=1, default_missing_value = "embed")]
+    chapters: Option<chapters::ChapterMode>,
+
+    /// Set the output file's modification time to the video's upload date
+    #[arg(long)]
+    mtime_from_upload: bool,
+
+    /// Remove container and stream tags (title, creation time, GPS location, encoder), cover art
+    /// and data streams from the output before sharing it; chapters stay only with --chapters
+    #[arg(long)]
+    strip_metadata: bool,
+
+    /// Warn when a download looks like a video already in the output directory, such as a
+    /// re-upload or mirror; see `videelow dedupe` for the whole library
+    #[arg(long)]
+    flag_duplicates: bool,
+
+    /// Tag MP3 downloads with the artist, title, album and date MusicBrainz has for the song, found
+    /// by its AcoustID fingerprint (needs fpcalc and --acoustid-key) or else by the video title
+    #[arg(long)]
+    music_tags: bool,
+
+    /// Tag MP3 downloads with their ReplayGain track gain and peak, and with the album gain and
+    /// peak when several are downloaded together, so players level them without altering the audio
+    #[arg(long)]
+    replaygain: bool,
+
+    /// AcoustID API key for --music-tags (default: `acoustid_key` in the config file)
+    #[arg(long, value_name = "KEY")]
+    acoustid_key: Option<String>,
+
+    /// YouTube Data API key for listing channels and playlists, which is faster and more reliable
+    /// than scraping large channels (default: `youtube_api_key` in the config file)
+    #[arg(long, value_name = "KEY")]
+    youtube_api_key: Option<String>,
+
+    /// Skip the free disk space check before downloading
+    #[arg(long)]
+    skip_space_check: bool,
+
+    /// Pick which playlist items to download from a checklist
+    #[arg(short, long)]
+    interactive: bool,
+
+    /// Playlist items to download, e.g. 1-10,15,20- (numbering follows playlist positions)
+    #[arg(long, value_name = "SPEC", value_parser = playlist::parse_items_spec)]
+    playlist_items: Option<String>,
+
+    /// Download playlist items in reverse order
+    #[arg(long)]
+    playlist_reverse: bool,
+
+    /// Only download videos uploaded on or after this date (YYYY-MM-DD)
+    #[arg(long, value_name = "DATE", value_parser = dates::Date::parse)]
+    since: Option<dates::Date>,
+
+    /// Only download videos uploaded before this date (YYYY-MM-DD)
+    #[arg(long, value_name = "DATE", value_parser = dates::Date::parse)]
+    before: Option<dates::Date>,
+
+    /// Skip videos shorter than this (seconds, MM:SS or HH:MM:SS)
+    #[arg(long, value_name = "DURATION")]
+    min_duration: Option<MediaTimestamp>,
+
+    /// Skip videos longer than this (seconds, MM:SS or HH:MM:SS)
+    #[arg(long, value_name = "DURATION")]
+    max_duration: Option<MediaTimestamp>,
+
+    /// Skip videos whose estimated download is larger than this (e.g. 500M, 2G)
+    #[arg(long, value_name = "SIZE", value_parser = filters::parse_size)]
+    max_filesize: Option<u64>,
+
+    /// Download again up to N times when a download is truncated or unreadable, or the connection fails
+    #[arg(long, value_name = "N", default_value_t = 2)]
+    corrupt_retries: u32,
+
+    /// When a download fails, write its tool command lines, stderr, tool versions and probes of
+    /// partial files to a new directory in DIR
+    #[arg(long, value_name = "DIR")]
+    failure_report: Option<String>,
+
+    /// Keep the output directory at most this size (e.g. 500G) by deleting videos and their
+    /// sidecars after each run, as --evict picks them; files of the run itself are spared
+    #[arg(long, value_name = "SIZE", value_parser = filters::parse_size)]
+    max_library_size: Option<u64>,
+
+    /// Which videos --max-library-size deletes first
+    #[arg(long, value_enum, value_name = "POLICY", default_value = "oldest", requires = "max_library_size")]
+    evict: quota::Eviction,
+
+    /// Priority of this run's downloads for `--evict priority`, which deletes the lowest first
+    #[arg(long, value_name = "N", default_value_t = 0, allow_negative_numbers = true)]
+    library_priority: i32,
+
+    /// Conversions to run at once when several videos are queued, while the next one downloads;
+    /// downloads wait for a free encoder (default: half the physical cores, or 1 with
+    /// --hardware-encode)
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    convert_jobs: Option<u32>,
+
+    #[command(flatten)]
+    encode: EncodeOptions,
+
+    #[command(flatten)]
+    hls: HlsOptions,
+
+    #[command(flatten)]
+    abr: AbrOptions,
+
+    #[command(flatten)]
+    silence: SilenceOptions,
+
+    #[command(flatten)]
+    transcribe: transcribe::TranscribeOptions,
+
+    #[command(flatten)]
+    translate: translate::SubtitleTranslationOptions,
+
+    #[command(flatten)]
+    album: album::AlbumOptions,
+
+    #[command(flatten)]
+    preview: preview::PreviewOptions,
+
+    #[command(flatten)]
+    proxy: proxy::ProxyOptions,
+
+    #[command(flatten)]
+    ytdlp: YtDlpOptions,
+}
+
+impl DownloadOptions {
+    /// Criteria selecting which videos to download
+    fn filter(&self) -> filters::ItemFilter {
+        filters::ItemFilter {
+            since: self.since,
+            before: self.before,
+            min_duration: self.min_duration,
+            max_duration: self.max_duration,
+            max_filesize: self.max_filesize,
+        }
+    }
+
+    /// API key for YouTube listings, from the command line or the config file
+    fn youtube_api_key(&self) -> Option<String> {
+        self.youtube_api_key.clone().or_else(|| config::Config::load().ok()?.youtube_api_key)
+    }
+
+    /// API key for fingerprint lookups, from the command line or the config file
+    fn acoustid_key(&self) -> Option<String> {
+        self.acoustid_key.clone().or_else(|| config::Config::load().ok()?.acoustid_key)
+    }
+
+    /// Conversions to run side by side in a queue
+    fn convert_jobs(&self) -> usize {
+        match self.convert_jobs {
+            Some(jobs) => jobs as usize,
+            None => pool::default_converts(
+                self.encode.hardware_encode && hardware::auto_encoder(self.encode.video_codec()).is_some(),
+            ),
+        }
+    }
+
+    /// Directory the files are saved to, relative to the working directory unless absolute
+    pub fn output_dir(&self) -> &str {
+        &self.output_dir
+    }
+
+    /// Apply --max-library-size, sparing what was downloaded since `since`; failures only warn, as
+    /// the downloads themselves are complete
+    fn enforce_quota(&self, since: std::time::SystemTime) {
+        if let Some(max_bytes) = self.max_library_size {
+            if let Err(e) = quota::enforce(Path::new(&self.output_dir), max_bytes, self.evict, since) {
+                console!(Warning, "could not apply --max-library-size: {}", e);
+            }
+        }
+    }
+
+    /// Reject settings the output format cannot satisfy before any tool runs, and start recording
+    /// tool runs when failures should be reported
+    fn validate(&self) -> Result<(), VideoConversionError> {
+        if let Some(device) = self.encode.device {
+            // Streaming output carries the same codecs in its segments
+            if !matches!(self.format, Container::Hls | Container::Abr) && self.format != device.profile().container {
+                return Err(VideoConversionError::config(format!(
+                    "the {} profile of --device needs {} output",
+                    device.name(),
+                    device.profile().container.name()
+                )));
+            }
+        }
+        self.format.check(self.encode.audio_codec(self.format), self.encode.audio_bitrate)?;
+        if self.silence.trim_silence && self.format != Container::Mp3 {
+            return Err(VideoConversionError::config("--trim-silence requires --format mp3"));
+        }
+        if self.strip_metadata && matches!(self.format, Container::Hls | Container::Abr) {
+            return Err(VideoConversionError::config(format!(
+                "--strip-metadata is not supported for {} output",
+                self.format.name()
+            )));
+        }
+        if self.transcribe.enabled() && matches!(self.format, Container::Hls | Container::Abr) {
+            return Err(VideoConversionError::config(format!(
+                "--transcribe is not supported for {} output",
+                self.format.name()
+            )));
+        }
+        if self.translate.enabled() && matches!(self.format, Container::Hls | Container::Abr) {
+            return Err(VideoConversionError::config(format!(
+                "--translate-to is not supported for {} output",
+                self.format.name()
+            )));
+        }
+        self.translate.validate(self.format)?;
+        if self.music_tags && self.format != Container::Mp3 {
+            return Err(VideoConversionError::config("--music-tags requires --format mp3"));
+        }
+        if self.replaygain && self.format != Container::Mp3 {
+            return Err(VideoConversionError::config("--replaygain requires --format mp3"));
+        }
+        if self.album.enabled() && self.format != Container::Mp3 {
+            return Err(VideoConversionError::config("--album requires --format mp3"));
+        }
+        if self.preview.enabled() && self.format != Container::Mp4 {
+            return Err(VideoConversionError::config("--preview requires --format mp4"));
+        }
+        if self.proxy.enabled() && self.format != Container::Mp4 {
+            return Err(VideoConversionError::config("--make-proxy requires --format mp4"));
+        }
+        // Only the HLS muxer encrypts; DASH manifests have no way to reference the key
+        let hls_muxer = match self.format {
+            Container::Hls => true,
+            Container::Abr => self.abr.abr_manifest == abr::ManifestFormat::Hls,
+            _ => false,
+        };
+        if self.hls.hls_encrypt && !hls_muxer {
+            return Err(VideoConversionError::config("--hls-encrypt requires --format hls, or --format abr with --abr-manifest hls"));
+        }
+        if self.flag_duplicates && self.format != Container::Mp4 {
+            return Err(VideoConversionError::config("--flag-duplicates requires --format mp4"));
+        }
+        if self.failure_report.is_some() {
+            report::enable();
+        }
+        Ok(())
+    }
+}
+
+/// Encoder settings used when re-encoding video
+#[derive(clap::Args, Debug, Clone)]
+pub struct EncodeOptions {
+    /// x264 constant rate factor (0-51, lower means higher quality and larger files)
+    #[arg(long, default_value_t = 23, value_parser = clap::value_parser!(u8).range(0..=51))]
+    crf: u8,
+
+    /// x264 preset trading encoding speed for compression efficiency
+    #[arg(long, value_enum, default_value = "medium")]
+    preset: Preset,
+
+    /// Video codec for re-encoding; H.265 is tagged so QuickTime plays it
+    #[arg(long, value_enum, default_value = "h264")]
+    video_codec: VideoCodec,
+
+    /// Encode with settings known to play on a device: codec, profile and level, pixel format,
+    /// audio codec and, for devices limited to 1080p, frame size
+    #[arg(long, value_enum, conflicts_with_all = ["video_codec", "audio_codec"])]
+    device: Option<devices::Device>,
+
+    /// Encode on a working VideoToolbox, NVENC or Quick Sync encoder when one is found (see
+    /// `videelow doctor`), mapping --crf and --preset to its settings; falls back to software
+    #[arg(long)]
+    hardware_encode: bool,
+
+    /// Audio codec (default: AAC for MP4, MP3 for MP3 output)
+    #[arg(long, value_enum)]
+    audio_codec: Option<AudioCodec>,
+
+    /// Audio bitrate such as 128k or 0.32M (default: 192k for MP3, the encoder's default otherwise)
+    #[arg(long, value_name = "RATE")]
+    audio_bitrate: Option<Bitrate>,
+
+    /// Extra ffmpeg arguments inserted before the output file, split like a shell would (repeatable)
+    #[arg(long, value_name = "ARGS", allow_hyphen_values = true, value_parser = ytdlp::split_args)]
+    ffmpeg_arg: Vec<Vec<String>>,
+
+    /// Encode long videos as keyframe-aligned chunks on JOBS concurrent encoders (default: from the CPU count)
+    #[arg(long, value_name = "JOBS", num_args = 0..=1, default_missing_value = "0")]
+    parallel_encode: Option<usize>,
+
+    /// Limit ffmpeg to this many encoder threads in total (default: all cores)
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    threads: Option<u32>,
+
+    /// Check each encoded file with ffprobe against its source (duration, streams, moov atom)
+    /// and warn or fail when it was cut short
+    #[arg(long, value_enum, value_name = "MODE", default_value = "warn")]
+    verify: verify::VerifyMode,
+
+    /// While verifying, warn when audio and video of an encoded file start or end more than MS
+    /// milliseconds apart
+    #[arg(long, value_name = "MS", default_value_t = 100)]
+    max_av_drift: u32,
+
+    /// Run ffmpeg at a lower CPU priority, from 0 (normal) to 19 (idle); uses priority classes on Windows
+    #[arg(long, value_name = "N", default_value_t = 0, value_parser = clap::value_parser!(u8).range(0..=19))]
+    nice: u8,
+}
+
+impl EncodeOptions {
+    /// An ffmpeg command running at the requested priority
+    fn ffmpeg_command(&self) -> Command {
+        let mut command = Command::new("ffmpeg");
+        priority::set_niceness(&mut command, self.nice);
+        command
+    }
+
+    /// ffmpeg arguments selecting the video encoder with the requested quality and speed
+    fn video_args(&self) -> Vec<String> {
+        let codec = self.video_codec();
+        let (encoder, accelerator) = match self.hardware_encode {
+            true => hardware::auto_encoder(codec),
+            false => None,
+        }
+        .unwrap_or((codec.encoder(), hardware::Accelerator::Software));
+        let mut args = vec!["-c:v".to_string(), encoder.to_string()];
+        args.extend(accelerator.quality_args(self.crf));
+        if let Some(preset) = accelerator.preset(self.preset) {
+            args.extend(["-preset".to_string(), preset.to_string()]);
+        }
+        if let Some(profile) = self.device.map(|device| device.profile()) {
+            args.extend(["-profile:v".to_string(), profile.profile.to_string()]);
+            match profile.level {
+                // The x265 wrapper ignores ffmpeg's generic level option
+                Some(level) if encoder == VideoCodec::H265.encoder() => {
+                    args.extend(["-x265-params".to_string(), format!("level-idc={}", level)])
+                }
+                Some(level) => args.extend(["-level:v".to_string(), level.to_string()]),
+                None => {}
+            }
+            args.extend(["-pix_fmt".to_string(), profile.pixel_format.to_string()]);
+        }
+        args
+    }
+
+    /// ffmpeg arguments scaling the video down to what the target device plays, if it is limited
+    fn video_filter_args(&self) -> Vec<String> {
+        match self.max_height() {
+            Some(height) => vec!["-vf".to_string(), format!("scale=-2:'min(ih,{})'", height)],
+            None => Vec::new(),
+        }
+    }
+
+    /// Tallest frames the target device plays
+    fn max_height(&self) -> Option<u32> {
+        self.device.and_then(|device| device.profile().max_height)
+    }
+
+    /// Video codec to produce
+    fn video_codec(&self) -> VideoCodec {
+        self.device.map_or(self.video_codec, |device| device.profile().video_codec)
+    }
+
+    /// Audio codec to produce in `container`
+    fn audio_codec(&self, container: Container) -> AudioCodec {
+        let device = self.device.map(|device| device.profile().audio_codec);
+        self.audio_codec.or(device).unwrap_or(container.default_audio_codec())
+    }
+
+    /// Audio bitrate to pass to the encoder, if it should not pick its own
+    fn audio_bitrate(&self, container: Container) -> Option<Bitrate> {
+        match (self.audio_bitrate, self.audio_codec(container)) {
+            (Some(bitrate), _) => Some(bitrate),
+            // yt-dlp otherwise extracts MP3 as variable bitrate
+            (None, AudioCodec::Mp3) => Some(AudioCodec::Mp3.default_bitrate()),
+            (None, _) => None,
+        }
+    }
+
+    /// ffmpeg arguments selecting the audio encoder for `container`
+    fn audio_args(&self, container: Container) -> Vec<String> {
+        let mut args = vec!["-c:a".to_string(), self.audio_codec(container).encoder().to_string()];
+        if let Some(bitrate) = self.audio_bitrate(container) {
+            args.extend(["-b:a".to_string(), bitrate.to_string()]);
+        }
+        args
+    }
+}
+
+/// Segmenting settings for HLS output
+#[derive(clap::Args, Debug, Clone)]
+pub struct HlsOptions {
+    /// Target length of HLS segments in seconds; a keyframe is forced at every boundary
+    #[arg(long, value_name = "SECONDS", default_value_t = 6, value_parser = clap::value_parser!(u32).range(1..))]
+    hls_segment_duration: u32,
+
+    /// Container of HLS segments; HEVC only plays on Apple devices in fmp4 segments
+    #[arg(long, value_enum, value_name = "TYPE", default_value = "mpegts")]
+    hls_segment_type: HlsSegmentType,
+
+    /// Encrypt HLS segments with AES-128; without --hls-key a random key is generated, saved next to
+    /// the playlist, or next to the output folder with --hls-key-uri, and readable by you only
+    #[arg(long)]
+    hls_encrypt: bool,
+
+    /// 16-byte key file to encrypt with, e.g. made by `openssl rand 16`
+    #[arg(long, value_name = "FILE", requires = "hls_encrypt")]
+    hls_key: Option<PathBuf>,
+
+    /// URI players fetch the key from, written into the playlists, when the key is hosted apart from
+    /// the segments (default: the key file next to the playlist)
+    #[arg(long, value_name = "URI", requires = "hls_encrypt")]
+    hls_key_uri: Option<String>,
+}
+
+/// Renditions and manifest of adaptive bitrate output
+#[derive(clap::Args, Debug, Clone)]
+pub struct AbrOptions {
+    /// Rendition heights for abr output, e.g. 1080p,720p,480p; those taller than the source are skipped
+    #[arg(long, value_name = "HEIGHTS", default_value = abr::DEFAULT_LADDER)]
+    renditions: abr::Ladder,
+
+    /// Manifest to write for abr output
+    #[arg(long, value_enum, value_name = "FORMAT", default_value = "hls")]
+    abr_manifest: abr::ManifestFormat,
+}
+
+/// Removal of long pauses from audio output
+#[derive(clap::Args, Debug, Clone)]
+pub struct SilenceOptions {
+    /// Cut long silent stretches out of MP3 output, such as pauses in lectures and meetings
+    #[arg(long)]
+    trim_silence: bool,
+
+    /// Level in dB below which audio counts as silence; raise it for noisy recordings
+    #[arg(long, value_name = "DB", default_value_t = -50.0, allow_negative_numbers = true, value_parser = parse_silence_threshold)]
+    silence_threshold: f64,
+
+    /// Shortest pause to cut (seconds, MM:SS or HH:MM:SS)
+    #[arg(long, value_name = "DURATION", default_value = "2")]
+    silence_duration: MediaTimestamp,
+
+    /// Seconds of each cut pause to keep, so sentences do not run together
+    #[arg(long, value_name = "SECONDS", default_value_t = 0.5, value_parser = parse_silence_keep)]
+    silence_keep: f64,
+}
+
+impl SilenceOptions {
+    /// The filter removing silence, if requested
+    fn filter(&self) -> Option<filtergraph::SilenceRemove> {
+        self.trim_silence.then(|| filtergraph::SilenceRemove {
+            threshold_db: self.silence_threshold,
+            min_duration: self.silence_duration.as_secs_f64(),
+            keep: self.silence_keep,
+        })
+    }
+}
+
+fn parse_silence_threshold(s: &str) -> Result<f64, String> {
+    match s.trim_end_matches("dB").parse::<f64>() {
+        Ok(threshold) if (-100.0..=0.0).contains(&threshold) => Ok(threshold),
+        _ => Err(format!("invalid threshold {:?} (expected dB from -100 to 0)", s)),
+    }
+}
+
+fn parse_silence_keep(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(keep) if keep >= 0.0 && keep.is_finite() => Ok(keep),
+        _ => Err(format!("invalid duration {:?} (expected seconds, 0 or more)", s)),
+    }
+}
+
+/// Containers for HLS segments
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum, Debug)]
+enum HlsSegmentType {
+    /// MPEG transport stream segments (.ts)
+    Mpegts,
+    /// Fragmented MP4 segments (.m4s) with a shared init.mp4
+    Fmp4,
+}
+
+impl HlsSegmentType {
+    /// Name of the segment type as understood by ffmpeg's HLS muxer
+    fn as_str(&self) -> &'static str {
+        match self {
+            HlsSegmentType::Mpegts => "mpegts",
+            HlsSegmentType::Fmp4 => "fmp4",
+        }
+    }
+
+    fn extension(&self) -> &'static str {
+        match self {
+            HlsSegmentType::Mpegts => "ts",
+            HlsSegmentType::Fmp4 => "m4s",
+        }
+    }
+}
+
+/// x264 encoder presets, from fastest to slowest
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum, Debug)]
+enum Preset {
+    Ultrafast,
+    Superfast,
+    Veryfast,
+    Faster,
+    Fast,
+    Medium,
+    Slow,
+    Slower,
+    Veryslow,
+}
+
+impl Preset {
+    /// Name of the preset as understood by ffmpeg
+    fn as_str(&self) -> &'static str {
+        match self {
+            Preset::Ultrafast => "ultrafast",
+            Preset::Superfast => "superfast",
+            Preset::Veryfast => "veryfast",
+            Preset::Faster => "faster",
+            Preset::Fast => "fast",
+            Preset::Medium => "medium",
+            Preset::Slow => "slow",
+            Preset::Slower => "slower",
+            Preset::Veryslow => "veryslow",
+        }
+    }
+
+    /// Approximate output size relative to `medium` at the same CRF
+    fn size_factor(&self) -> f64 {
+        match self {
+            Preset::Ultrafast => 1.6,
+            Preset::Superfast => 1.35,
+            Preset::Veryfast => 1.05,
+            Preset::Faster => 1.05,
+            Preset::Fast => 1.02,
+            Preset::Medium => 1.0,
+            Preset::Slow => 0.97,
+            Preset::Slower => 0.95,
+            Preset::Veryslow => 0.93,
+        }
+    }
+}
+
+impl std::fmt::Display for Preset {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Underlying error kept as the source of a `VideoConversionError`
+pub type ErrorSource = Box<dyn std::error::Error + Send + Sync + 'static>;
+
+/// Broad kind of a failure, which decides whether trying again can help
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ErrorCategory {
+    /// A connection, HTTP request or remote service failed
+    Network,
+    /// yt-dlp or a site could not provide the media
+    Extraction,
+    /// ffmpeg or another media tool could not produce the output
+    Encoding,
+    /// Reading or writing local files, or running a tool, failed
+    Io,
+    /// Arguments, configuration or the installed tools do not allow the request
+    Config,
+    /// The input or the requested output is not supported
+    Unsupported,
+}
+
+/// Custom error type for improved error handling; the category variants keep the error that
+/// caused them as their source, which their message ends with
+#[derive(Error, Debug)]
+pub enum VideoConversionError {
+    #[error("Network error: {message}{}", source_suffix(.source))]
+    Network { message: String, #[source] source: Option<ErrorSource> },
+
+    #[error("Download failed: {message}{}", source_suffix(.source))]
+    Extraction { message: String, #[source] source: Option<ErrorSource> },
+
+    #[error("Conversion failed: {message}{}", source_suffix(.source))]
+    Encoding { message: String, #[source] source: Option<ErrorSource> },
+
+    #[error("{message}{}", source_suffix(.source))]
+    Io { message: String, #[source] source: Option<ErrorSource> },
+
+    #[error("Invalid argument: {message}{}", source_suffix(.source))]
+    Config { message: String, #[source] source: Option<ErrorSource> },
+
+    #[error("Unsupported: {message}{}", source_suffix(.source))]
+    Unsupported { message: String, #[source] source: Option<ErrorSource> },
+
+    #[error("File not found: {0}")]
+    FileNotFound(String),
+
+    #[error("Required tool not found: {0} (is it installed and on PATH?)")]
+    ToolNotFound(String),
+
+    #[error("Output file already exists: {0}")]
+    FileConflict(String),
+
+    #[error("{failed} of {total} items failed; first error: {first}")]
+    BatchFailed { failed: usize, total: usize, first: Box<VideoConversionError> },
+
+    #[error("Not enough disk space in {path}: about {needed} needed, {available} available")]
+    InsufficientDiskSpace { path: String, needed: String, available: String },
+
+    #[error("Interrupted")]
+    Interrupted,
+}
+
+/// `: source` after a message, or nothing without a source
+fn source_suffix(source: &Option<ErrorSource>) -> String {
+    source.as_ref().map_or_else(String::new, |source| format!(": {}", source))
+}
+
+impl VideoConversionError {
+    pub fn network(message: impl Into<String>) -> Self {
+        VideoConversionError::Network { message: message.into(), source: None }
+    }
+
+    pub fn extraction(message: impl Into<String>) -> Self {
+        VideoConversionError::Extraction { message: message.into(), source: None }
+    }
+
+    pub fn encoding(message: impl Into<String>) -> Self {
+        VideoConversionError::Encoding { message: message.into(), source: None }
+    }
+
+    pub fn io(message: impl Into<String>) -> Self {
+        VideoConversionError::Io { message: message.into(), source: None }
+    }
+
+    pub fn config(message: impl Into<String>) -> Self {
+        VideoConversionError::Config { message: message.into(), source: None }
+    }
+
+    pub fn unsupported(message: impl Into<String>) -> Self {
+        VideoConversionError::Unsupported { message: message.into(), source: None }
+    }
+
+    /// Keep `cause` as the source of a category error; other errors are returned unchanged
+    pub fn caused_by(mut self, cause: impl Into<ErrorSource>) -> Self {
+        match &mut self {
+            VideoConversionError::Network { source, .. }
+            | VideoConversionError::Extraction { source, .. }
+            | VideoConversionError::Encoding { source, .. }
+            | VideoConversionError::Io { source, .. }
+            | VideoConversionError::Config { source, .. }
+            | VideoConversionError::Unsupported { source, .. } => *source = Some(cause.into()),
+            _ => {}
+        }
+        self
+    }
+
+    /// Category of the failure; `None` for an interruption, which is no failure of the work
+    pub fn category(&self) -> Option<ErrorCategory> {
+        match self {
+            VideoConversionError::Network { .. } => Some(ErrorCategory::Network),
+            VideoConversionError::Extraction { .. } => Some(ErrorCategory::Extraction),
+            VideoConversionError::Encoding { .. } => Some(ErrorCategory::Encoding),
+            VideoConversionError::Io { .. }
+            | VideoConversionError::FileNotFound(_)
+            | VideoConversionError::FileConflict(_)
+            | VideoConversionError::InsufficientDiskSpace { .. } => Some(ErrorCategory::Io),
+            VideoConversionError::Config { .. } | VideoConversionError::ToolNotFound(_) => Some(ErrorCategory::Config),
+            VideoConversionError::Unsupported { .. } => Some(ErrorCategory::Unsupported),
+            VideoConversionError::BatchFailed { first, .. } => first.category(),
+            VideoConversionError::Interrupted => None,
+        }
+    }
+
+    /// Whether the same request may succeed when tried again, as after a dropped connection
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            // Every item of a batch gets its own attempts
+            VideoConversionError::BatchFailed { .. } => false,
+            _ => self.category() == Some(ErrorCategory::Network),
+        }
+    }
+
+    /// Whether the user has to change the request, its input or the setup rather than wait for
+    /// the tools or the network
+    pub fn is_user_error(&self) -> bool {
+        match self {
+            VideoConversionError::FileNotFound(_) | VideoConversionError::FileConflict(_) => true,
+            _ => matches!(self.category(), Some(ErrorCategory::Config | ErrorCategory::Unsupported)),
+        }
+    }
+
+    /// Stable process exit code for this error category, so scripts can branch on it
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            VideoConversionError::BatchFailed { first, .. } => first.exit_code(),
+            VideoConversionError::Config { .. } | VideoConversionError::Unsupported { .. } => 2,
+            VideoConversionError::ToolNotFound(_) => 3,
+            VideoConversionError::Network { .. }
+            | VideoConversionError::Extraction { .. }
+            | VideoConversionError::FileNotFound(_) => 4,
+            VideoConversionError::Encoding { .. } => 5,
+            VideoConversionError::FileConflict(_) => 6,
+            VideoConversionError::Io { .. } | VideoConversionError::InsufficientDiskSpace { .. } => 1,
+            // Conventional shell status for termination by SIGINT
+            VideoConversionError::Interrupted => 130,
+        }
+    }
+
+    /// Re-categorize a generic I/O or tool failure as a failure to get the media
+    fn download(self) -> Self {
+        match self {
+            VideoConversionError::Io { message, source } => VideoConversionError::Extraction { message, source },
+            other => other,
+        }
+    }
+
+    /// Re-categorize a generic I/O or tool failure as a failure to encode
+    fn conversion(self) -> Self {
+        match self {
+            VideoConversionError::Io { message, source } => VideoConversionError::Encoding { message, source },
+            other => other,
+        }
+    }
+}
+
+/// Error for a tool that exited unsuccessfully, which is expected when shutdown stopped it
+fn exit_error(command: &Command, status: std::process::ExitStatus) -> VideoConversionError {
+    match shutdown::requested() {
+        true => VideoConversionError::Interrupted,
+        false => VideoConversionError::io(format!("{} exited with {}", command.get_program().to_string_lossy(), status)),
+    }
+}
+
+/// Helper function to run external commands
+fn run_command(command: &mut Command) -> Result<(), VideoConversionError> {
+    let status = ChildProcess::spawn(command)?
+        .wait().map_err(|e| VideoConversionError::io(e.to_string()))?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(exit_error(command, status))
+    }
+}
+
+/// Run an external tool and capture its standard output
+fn command_output(command: &mut Command) -> Result<Output, VideoConversionError> {
+    let output = ChildProcess::spawn(command.stdout(Stdio::piped()))?
+        .wait_with_output().map_err(|e| VideoConversionError::io(e.to_string()))?;
+    if !output.status.success() {
+        shutdown::check()?;
+    }
+    Ok(output)
+}
+
+/// Escape a literal path for use in a yt-dlp output template, where `%` starts a field
+fn ytdlp_literal(path: &str) -> String {
+    path.replace('%', "%%")
+}
+
+/// Helper function to run yt-dlp while turning its progress output into progress events
+fn run_ytdlp(command: &mut Command, progress: &Progress) -> Result<(), VideoConversionError> {
+    let mut child = ChildProcess::spawn(
+        command
+            .arg("--newline")
+            .arg("--progress-template")
+            .arg(progress::ytdlp_progress_template())
+            .stdout(Stdio::piped())
+            .stderr(logging::child_stderr()),
+    )?;
+
+    if let Some(stdout) = child.stdout() {
+        for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+            match ProgressEvent::from_ytdlp_line(&line) {
+                Some(event) => progress.emit(event),
+                None => println!("{}", line),
+            }
+        }
+    }
+
+    let status = child.wait().map_err(|e| VideoConversionError::io(e.to_string()))?;
+    if status.success() {
+        return Ok(());
+    }
+    match exit_error(command, status) {
+        VideoConversionError::Io { message, .. } => Err(ytdlp_error(message, &child.stderr_tail().join("\n"))),
+        error => Err(error),
+    }
+}
+
+/// yt-dlp failure described by `message`: a network error, which trying again may fix, when its
+/// `stderr` blames the connection rather than the site or the video
+pub(crate) fn ytdlp_error(message: impl Into<String>, stderr: &str) -> VideoConversionError {
+    const NETWORK_ERRORS: [&str; 10] = [
+        "timed out",
+        "Connection reset",
+        "Connection refused",
+        "Connection aborted",
+        "Temporary failure in name resolution",
+        "Name or service not known",
+        "Network is unreachable",
+        "IncompleteRead",
+        "HTTP Error 5",
+        "Unable to download webpage",
+    ];
+    match NETWORK_ERRORS.iter().any(|needle| stderr.contains(needle)) {
+        true => VideoConversionError::network(message),
+        false => VideoConversionError::extraction(message),
+    }
+}
+
+/// Copy the streams of an HLS or DASH manifest into an MP4 without re-encoding them
+fn capture_stream(url: &str, output_path: &str, duration: Option<f64>, progress: &Progress) -> Result<(), VideoConversionError> {
+    run_ffmpeg(
+        Command::new("ffmpeg")
+            .args(["-progress", "pipe:1", "-nostats", "-n"])
+            .arg("-i")
+            .arg(url)
+            // WebVTT subtitles and timed metadata cannot be copied into MP4
+            .args(["-c", "copy", "-sn", "-dn", "-movflags", "+faststart"])
+            .arg(output_path),
+        duration,
+        progress,
+    )
+}
+
+/// Helper function to run an ffmpeg command that has `-progress pipe:1` among its options, turning
+/// that output into progress events measured against the source's `duration`
+fn run_ffmpeg(command: &mut Command, duration: Option<f64>, progress: &Progress) -> Result<(), VideoConversionError> {
+    let mut child = ChildProcess::spawn(command.stdout(Stdio::piped()).stderr(logging::child_stderr()))?;
+
+    if let Some(stdout) = child.stdout() {
+        let mut parser = FfmpegProgress::new(duration);
+        for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+            if let Some(event) = parser.parse_line(&line) {
+                progress.emit(event);
+            }
+        }
+    }
+
+    let status = child.wait().map_err(|e| VideoConversionError::io(e.to_string()))?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(exit_error(command, status))
+    }
+}
+
+/// Run an ffmpeg command like `run_ffmpeg` and return what it logged at info level, for filters
+/// such as ebur128 and ssim that only report their results in the log; `-loglevel error` among the
+/// options keeps that log off the terminal
+fn run_ffmpeg_log(command: &mut Command, duration: Option<f64>, progress: &Progress) -> Result<String, VideoConversionError> {
+    let report_path = std::env::temp_dir().join(format!("videelow-ffmpeg-{}-{}.log", std::process::id(), next_log_id()));
+    // FFREPORT separates its options with colons, as in Windows drive letters
+    let escaped = report_path.to_string_lossy().replace('\\', "\\\\").replace(':', "\\:");
+    command.env("FFREPORT", format!("file={}:level=32", escaped));
+    let result = run_ffmpeg(command, duration, progress);
+    let log = std::fs::read_to_string(&report_path).unwrap_or_default();
+    let _ = std::fs::remove_file(&report_path);
+    result.map(|_| log)
+}
+
+/// Distinguishes the log files of ffmpeg runs in parallel threads
+fn next_log_id() -> usize {
+    static NEXT: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+    NEXT.fetch_add(1, std::sync::atomic::Ordering::Relaxed)
+}
+
+/// Function to download a video as MP4 with yt-dlp
+fn download_video(url: &str, output_path: &str, selector: &str, ytdlp: &YtDlpOptions, progress: &Progress) -> Result<(), VideoConversionError> {
+    run_ytdlp(
+        ytdlp
+            .command()
+            .arg("-f")
+            .arg(selector) // Site-specific selector preferring MP4 streams for compatibility
+            .arg("--merge-output-format")
+            .arg(Container::Mp4.extension())
+            .arg("-o")
+            .arg(ytdlp_literal(output_path))
+            .args(ytdlp.extra_args())
+            .arg(url),
+        progress,
+    )
+}
+
+/// Function to download audio directly as MP3 with yt-dlp
+fn download_audio(
+    url: &str,
+    output_path: &str,
+    selector: &str,
+    bitrate: Bitrate,
+    ytdlp: &YtDlpOptions,
+    progress: &Progress,
+) -> Result<(), VideoConversionError> {
+    run_ytdlp(
+        ytdlp
+            .command()
+            .arg("-f")
+            .arg(selector)                 // Choose the best audio quality available
+            .arg("--extract-audio")        // Extract audio only
+            .arg("--audio-format")
+            .arg(Container::Mp3.extension()) // Convert audio to MP3
+            .arg("--audio-quality")
+            .arg(format!("{}K", bitrate.bits_per_second() / 1000)) // Constant bitrate instead of VBR
+            .arg("-o")
+            .arg(ytdlp_literal(output_path))
+            .args(ytdlp.extra_args())
+            .arg(url),
+        progress,
+    )
+}
+
+/// Estimate the disk space a download needs, including the intermediate file kept during conversion
+fn required_space(info: &metadata::VideoInfo, format: Container, encode: &EncodeOptions) -> Option<u64> {
+    let download = info.estimated_download_size()?;
+    let needed = match format {
+        // The original download and its re-encoded copy coexist until cleanup
+        Container::Mp4 | Container::Hls => download * 2,
+        // Lower renditions shrink quickly, so the whole ladder rarely outgrows twice the source
+        Container::Abr => download * 3,
+        // The downloaded audio stream is kept until yt-dlp has written the MP3
+        Container::Mp3 => {
+            let bitrate = encode.audio_bitrate(format).unwrap_or(AudioCodec::Mp3.default_bitrate());
+            download + (info.duration? * bitrate.bytes_per_second()) as u64
+        }
+    };
+    // Leave headroom for container overhead and estimation error
+    Some(needed + needed / 10)
+}
+
+/// Fail fast when the output directory cannot hold the download and its conversion
+fn check_disk_space(
+    info: &metadata::VideoInfo,
+    format: Container,
+    encode: &EncodeOptions,
+    output_dir: &str,
+) -> Result<(), VideoConversionError> {
+    let Some(available) = disk::available_space(Path::new(output_dir)) else {
+        return Ok(());
+    };
+
+    match required_space(info, format, encode) {
+        Some(needed) if needed > available => Err(VideoConversionError::InsufficientDiskSpace {
+            path: output_dir.to_string(),
+            needed: progress::human_bytes(needed as f64),
+            available: progress::human_bytes(available as f64),
+        }),
+        Some(_) => Ok(()),
+        None => {
+            console!(Warning, "yt-dlp did not report a file size; skipping disk space check");
+            Ok(())
+        }
+    }
+}
+
+/// Pixel formats QuickTime plays in H.264 and HEVC: 8-bit 4:2:0
+const QUICKTIME_PIXEL_FORMATS: [&str; 3] = ["yuv420p", "yuvj420p", "nv12"];
+
+/// Encoder arguments turning a 10-bit, 4:2:2 or 4:4:4 source into 8-bit 4:2:0 tagged as BT.709;
+/// the encoders keep such formats, and QuickTime refuses the result even though it is H.264.
+/// A device profile's pixel format wins and is already set by `video_args`, so `-pix_fmt` is only
+/// added without one
+fn pixel_format_args(input_path: &str, encode: &EncodeOptions) -> Vec<String> {
+    let profile_format = encode.device.map(|device| device.profile().pixel_format);
+    match segmented::probe_pixel_format(input_path) {
+        Some(format) if !QUICKTIME_PIXEL_FORMATS.contains(&format.as_str()) => {
+            let target = profile_format.unwrap_or("yuv420p");
+            info!(pixel_format = %format, target, "normalizing pixel format and BT.709");
+            println!("Source uses pixel format {}; converting to {} with BT.709 colors", format, target);
+            let pixel_format = profile_format.is_none().then_some(["-pix_fmt", target]).into_iter().flatten();
+            pixel_format
+                .chain(["-color_primaries", "bt709", "-color_trc", "bt709", "-colorspace", "bt709"])
+                .map(String::from)
+                .collect()
+        }
+        _ => Vec::new(),
+    }
+}
+
+/// Function to convert MP4 to a QuickTime-compatible format
+fn convert_to_quicktime_compatible_mp4(
+    input_path: &str,
+    output_path: &str,
+    encode: &EncodeOptions,
+    progress: &Progress,
+) -> Result<(), VideoConversionError> {
+    let _span = info_span!("convert", input = input_path, output = output_path).entered();
+    println!("Re-encoding video to QuickTime-compatible MP4...");
+    let pixel_args = pixel_format_args(input_path, encode);
+
+    if let Some(jobs) = encode.parallel_encode {
+        match segmented::probe_duration(input_path) {
+            Some(duration) if duration >= segmented::MIN_DURATION_SECONDS => {
+                segmented::convert(input_path, output_path, encode, &pixel_args, jobs, duration)?;
+                console!(Success, "Re-encoding successful: {}", output_path);
+                return Ok(());
+            }
+            Some(_) => println!("Video is short; encoding it in one piece"),
+            None => console!(Warning, "could not determine the video duration; encoding it in one piece"),
+        }
+    }
+
+    let mut command = encode.ffmpeg_command();
+    command
+        .args(["-progress", "pipe:1", "-nostats"])
+        .arg("-i")
+        .arg(input_path)
+        .args(encode.video_filter_args())
+        .args(encode.video_args())
+        .args(&pixel_args)
+        .args(encode.video_codec().mp4_tag().map(|tag| ["-tag:v", tag]).into_iter().flatten())
+        .args(encode.audio_args(Container::Mp4))
+        .arg("-movflags")
+        .arg("+faststart"); // For streaming compatibility
+    if let Some(threads) = encode.threads {
+        command.arg("-threads").arg(threads.to_string());
+    }
+    run_ffmpeg(
+        command
+            .args(encode.ffmpeg_arg.iter().flatten()) // User overrides win over the defaults above
+            .arg(output_path),
+        segmented::probe_duration(input_path),
+        progress,
+    )
+    .map_err(VideoConversionError::conversion)?;
+
+    console!(Success, "Re-encoding successful: {}", output_path);
+    Ok(())
+}
+
+/// Run the audio of `input_path` through `filters` into an MP3, keeping its tags and cover art
+fn filter_audio(
+    input_path: &str,
+    output_path: &str,
+    filters: &filtergraph::FilterChain,
+    bitrate: Bitrate,
+    progress: &Progress,
+) -> Result<(), VideoConversionError> {
+    let _span = info_span!("filter_audio", input = input_path, output = output_path).entered();
+    println!("Processing audio ({})...", filters);
+    run_ffmpeg(
+        Command::new("ffmpeg")
+            .args(["-progress", "pipe:1", "-nostats", "-n"])
+            .arg("-i")
+            .arg(input_path)
+            .args(["-map", "0:a:0", "-map", "0:v?", "-c:v", "copy", "-af"])
+            .arg(filters.to_string())
+            .args(["-c:a", AudioCodec::Mp3.encoder(), "-b:a"])
+            .arg(bitrate.to_string())
+            .args(["-map_metadata", "0", "-id3v2_version", "3"])
+            .arg(output_path),
+        segmented::probe_duration(input_path),
+        progress,
+    )?;
+    console!(Success, "Audio processed successfully: {}", output_path);
+    Ok(())
+}
+
+/// Tags some muxers write or copy even without global metadata, cleared by name
+const PRIVATE_TAGS: &[&str] = &["creation_time", "location", "location-eng", "com.apple.quicktime.location.ISO6709", "encoder"];
+
+/// Copy the video, audio and subtitle streams of `input_path` into `output_path` without any tags,
+/// chapters, cover art or data streams such as camera GPS tracks
+fn strip_metadata(input_path: &str, output_path: &str) -> Result<(), VideoConversionError> {
+    let _span = info_span!("strip_metadata", input = input_path, output = output_path).entered();
+    let mut command = Command::new("ffmpeg");
+    command
+        .args(["-nostdin", "-v", "error", "-n", "-i"])
+        .arg(input_path)
+        // `V` leaves out attached pictures, which `v` would include
+        .args(["-map", "0:V?", "-map", "0:a?", "-map", "0:s?", "-c", "copy"])
+        .args(["-map_metadata", "-1", "-map_metadata:s", "-1", "-map_chapters", "-1"])
+        // Bit-exact output leaves the muxer's and encoders' version strings out
+        .args(["-fflags", "+bitexact", "-flags:v", "+bitexact", "-flags:a", "+bitexact"]);
+    for tag in PRIVATE_TAGS {
+        command.arg("-metadata").arg(format!("{}=", tag));
+    }
+    if output_path.ends_with(".mp3") {
+        command.args(["-id3v2_version", "0", "-write_id3v1", "0"]);
+    } else {
+        command.args(["-movflags", "+faststart"]);
+    }
+    run_command(command.arg(output_path).stdin(Stdio::null()).stderr(logging::child_stderr()))
+        .map_err(VideoConversionError::conversion)
+}
+
+/// Write a pretty-printed JSON document to `path`
+fn write_json(path: &str, value: &serde_json::Value) -> Result<(), VideoConversionError> {
+    let json = serde_json::to_string_pretty(value).map_err(|e| VideoConversionError::io(e.to_string()))?;
+    std::fs::write(path, json + "\n")
+        .map_err(|e| VideoConversionError::io(format!("Failed to write {}", path)).caused_by(e))
+}
+
+/// Set a file's modification time
+fn set_modified(path: &str, time: std::time::SystemTime) -> Result<(), VideoConversionError> {
+    File::options()
+        .write(true)
+        .open(path)
+        .and_then(|file| file.set_modified(time))
+        .map_err(|e| VideoConversionError::io(format!("Failed to set modification time of {}", path)).caused_by(e))
+}
+
+/// Register this run as a job so it can be paused and inspected; failures only cost that ability
+fn start_job(urls: &[String], announce: bool) -> Option<jobs::JobGuard> {
+    match jobs::start(urls) {
+        Ok(job) => {
+            if announce {
+                println!("Started job {} (pause with `videelow pause {}`)", job.id, job.id);
+            }
+            Some(job)
+        }
+        Err(e) => {
+            console!(Warning, "could not register job: {}", e);
+            None
+        }
+    }
+}
+
+/// A single video queued for download
+struct DownloadItem {
+    url: String,
+    name: Option<String>,
+}
+
+/// Whether a listing is ordered newest first: YouTube channel tabs list uploads that way, while
+/// playlists have arbitrary order
+fn newest_first(source: &urls::SourceUrl) -> bool {
+    source.playlist_id.is_none() && sites::profile_for(&source.url).site == Site::YouTube
+}
+
+/// Expand playlist and channel URLs into their videos; single videos pass through unchanged
+fn expand_url(url: &str, name: Option<String>, options: &DownloadOptions) -> Result<Vec<DownloadItem>, VideoConversionError> {
+    let source = urls::normalize(url)?;
+    if !source.listing {
+        return Ok(vec![DownloadItem { url: url.to_string(), name }]);
+    }
+
+    console!(Download, "Listing playlist entries of {}...", source.url);
+    let selection = playlist::PlaylistSelection {
+        items: options.playlist_items.clone(),
+        reverse: options.playlist_reverse,
+        filter: options.filter(),
+        newest_first: newest_first(&source),
+    };
+    let mut entries = playlist::list_entries(&source.url, &selection, &options.ytdlp, options.youtube_api_key().as_deref())?;
+    if options.interactive {
+        entries = playlist::select_interactively(entries)?;
+    }
+
+    Ok(entries
+        .into_iter()
+        .map(|entry| DownloadItem {
+            url: entry.url,
+            // Number custom names by playlist position so items don't overwrite each other
+            name: name.as_ref().map(|name| format!("{}-{:03}", name, entry.index)),
+        })
+        .collect())
+}
+
+/// Download every URL as one job, continuing past failures and reporting the first error at the end
+pub fn download_all(urls: &[String], options: &DownloadOptions, progress: &Progress) -> Result<(), VideoConversionError> {
+    download_all_with(urls, options, progress, Backends::default())
+}
+
+/// `download_all` with other tools than yt-dlp and ffmpeg, such as the mocks in `backend::mock`
+pub fn download_all_with(
+    urls: &[String],
+    options: &DownloadOptions,
+    progress: &Progress,
+    backends: Backends,
+) -> Result<(), VideoConversionError> {
+    download_tracked(urls, options, progress, backends, &Mutex::new(Vec::new()))
+}
+
+/// `download_all_with` that also collects the URLs of the videos downloaded successfully
+fn download_tracked(
+    urls: &[String],
+    options: &DownloadOptions,
+    progress: &Progress,
+    backends: Backends,
+    succeeded: &Mutex<Vec<String>>,
+) -> Result<(), VideoConversionError> {
+    options.validate()?;
+    let started = std::time::SystemTime::now();
+    let job = start_job(urls, urls.len() > 1);
+    jobs::set_library_priority(options.library_priority);
+    let _span = info_span!("job", job_id = job.as_ref().map(|job| job.id)).entered();
+    let result = download_queue(urls, options, progress, backends, succeeded);
+    if !matches!(result, Err(VideoConversionError::Interrupted)) {
+        options.enforce_quota(started);
+    }
+    if let Some(job) = job {
+        job.finish(result.as_ref().err());
+    }
+    result
+}
+
+fn download_queue(
+    urls: &[String],
+    options: &DownloadOptions,
+    progress: &Progress,
+    backends: Backends,
+    succeeded: &Mutex<Vec<String>>,
+) -> Result<(), VideoConversionError> {
+    let mut items = Vec::new();
+    let mut first_error = None;
+    let mut failed = 0;
+    for (index, url) in urls.iter().enumerate() {
+        // A shared custom name would make every URL overwrite the previous one
+        let name = match &options.name {
+            Some(name) if urls.len() > 1 => Some(format!("{}-{}", name, index + 1)),
+            name => name.clone(),
+        };
+        match expand_url(url, name, options) {
+            Ok(expanded) => items.extend(expanded),
+            Err(e) if urls.len() == 1 => {
+                metrics::record_failure(&e);
+                return Err(e);
+            }
+            Err(e) => {
+                metrics::record_failure(&e);
+                console!(Error, "{}: {}", url, e);
+                failed += 1;
+                first_error.get_or_insert(e);
+            }
+        }
+    }
+
+    let first_item = jobs::queue(items.iter().map(|item| item.url.as_str()));
+    metrics::queued(items.len());
+
+    if items.len() == 1 && failed == 0 {
+        let item = items.remove(0);
+        let _item = jobs::enter_item(first_item);
+        let result = download(&item.url, item.name, options, progress, backends);
+        metrics::record_result(&result);
+        let output = result?;
+        succeeded.lock().unwrap_or_else(|e| e.into_inner()).push(item.url.clone());
+        let tracks = output.map(|output| album::Track { position: 0, url: item.url, output }).into_iter().collect();
+        finish_album(tracks, 1, urls, options, progress);
+        return Ok(());
+    }
+
+    let queued = items.len();
+    let total = queued + failed;
+    let jobs = options.convert_jobs();
+    // One worker more than encoders keeps the next download going, and no more: a finished
+    // download waits for an encoder instead of piling up unconverted files
+    let workers = (jobs + 1).min(queued);
+    let mut shared = options.clone();
+    if workers > 1 {
+        // Share the cores between the encoders instead of letting each claim all of them
+        let budget = options.encode.threads.map_or_else(|| thread::available_parallelism().map_or(1, |n| n.get()), |t| t as usize);
+        shared.encode.threads = Some((budget / jobs).max(1) as u32);
+        println!("Converting up to {} videos at once", jobs);
+    }
+    let _limits = (workers > 1).then(|| pool::limit(jobs));
+    let queue = Mutex::new(items.into_iter().enumerate());
+    let outcome = Mutex::new((failed, first_error));
+    let tracks = Mutex::new(Vec::new());
+    let interrupted = AtomicBool::new(false);
+    // Workers log under the caller's job span
+    let span = Span::current();
+    if workers > 1 {
+        // Items running at once share one status line instead of overwriting each other's
+        progress.start_parallel(queued);
+    }
+    thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| loop {
+                let _entered = span.enter();
+                let next = queue.lock().unwrap_or_else(|e| e.into_inner()).next();
+                let Some((index, item)) = next else { break };
+                if shutdown::requested() {
+                    metrics::dequeued(1);
+                    interrupted.store(true, Ordering::Relaxed);
+                    break;
+                }
+                // Concurrent items each update their own entry of the job record
+                let _item = jobs::enter_item(first_item + index);
+                console!(Download, "[{}/{}] {}", index + 1, queued, item.url);
+                progress.emit(ProgressEvent::ItemStarted { index: index + 1, total: queued, url: item.url.clone() });
+
+                let result = download(&item.url, item.name, &shared, progress, backends);
+                metrics::record_result(&result);
+                match result {
+                    Ok(output) => {
+                        if let Some(output) = output {
+                            let track = album::Track { position: index, url: item.url.clone(), output };
+                            tracks.lock().unwrap_or_else(|e| e.into_inner()).push(track);
+                        }
+                        succeeded.lock().unwrap_or_else(|e| e.into_inner()).push(item.url);
+                    }
+                    Err(VideoConversionError::Interrupted) => {
+                        interrupted.store(true, Ordering::Relaxed);
+                        break;
+                    }
+                    Err(e) => {
+                        error!(url = %item.url, error = %e, "download failed");
+                        console!(Error, "{}: {}", item.url, e);
+                        progress.emit(ProgressEvent::Failed { error: e.to_string(), exit_code: e.exit_code() });
+                        let mut outcome = outcome.lock().unwrap_or_else(|e| e.into_inner());
+                        outcome.0 += 1;
+                        outcome.1.get_or_insert(e);
+                    }
+                }
+            });
+        }
+    });
+    progress.end_parallel();
+    if interrupted.into_inner() {
+        metrics::dequeued(queue.into_inner().unwrap_or_else(|e| e.into_inner()).count());
+        return Err(VideoConversionError::Interrupted);
+    }
+    let (failed, first_error) = outcome.into_inner().unwrap_or_else(|e| e.into_inner());
+
+    println!("{} of {} downloads succeeded", total - failed, total);
+    finish_album(tracks.into_inner().unwrap_or_else(|e| e.into_inner()), queued, urls, options, progress);
+    match first_error {
+        Some(first) => Err(VideoConversionError::BatchFailed { failed, total, first: Box::new(first) }),
+        None => Ok(()),
+    }
+}
+
+/// Tag the downloaded tracks as one album when --album asks for it, then with their ReplayGain
+/// album gain for --replaygain; the downloads stand either way
+fn finish_album(mut tracks: Vec<album::Track>, total: usize, urls: &[String], options: &DownloadOptions, progress: &Progress) {
+    if options.album.enabled() {
+        if let Err(e) = album::finish(&mut tracks, total, urls, options, progress) {
+            console!(Warning, "could not finish the album: {}", e);
+        }
+    }
+    if options.replaygain && tracks.len() > 1 {
+        let paths: Vec<&Path> = tracks.iter().map(|track| Path::new(&track.output)).collect();
+        if let Err(e) = replaygain::tag_album(&paths) {
+            console!(Warning, "could not write the ReplayGain album gain: {}", e);
+        }
+    }
+}
+
+/// Download copied URLs one after another until interrupted
+pub fn watch_clipboard(
+    confirm: bool,
+    all_urls: bool,
+    interval_ms: u64,
+    options: &DownloadOptions,
+    progress: &Progress,
+) -> Result<(), VideoConversionError> {
+    options.validate()?;
+    let copied = clipboard::watch(std::time::Duration::from_millis(interval_ms), all_urls)?;
+    let job = start_job(&[], true);
+    jobs::set_library_priority(options.library_priority);
+    let _span = info_span!("job", job_id = job.as_ref().map(|job| job.id)).entered();
+    println!("Watching the clipboard for video URLs (Ctrl-C to stop)...");
+
+    for (index, url) in copied.iter().enumerate() {
+        if confirm && !clipboard::confirm(&url) {
+            continue;
+        }
+        console!(Download, "Queued {}", url);
+        let started = std::time::SystemTime::now();
+        let name = options.name.as_ref().map(|name| format!("{}-{}", name, index + 1));
+        let result = expand_url(&url, name, options).inspect_err(metrics::record_failure).and_then(|items| {
+            let first_item = jobs::queue(items.iter().map(|item| item.url.as_str()));
+            metrics::queued(items.len());
+            let mut items = items.into_iter().enumerate();
+            let result = items.try_for_each(|(index, item)| {
+                let _item = jobs::enter_item(first_item + index);
+                let result = download(&item.url, item.name, options, progress, Backends::default());
+                metrics::record_result(&result);
+                result.map(|_| ())
+            });
+            // Items after a failure are dropped with it
+            metrics::dequeued(items.len());
+            result
+        });
+        if let Err(VideoConversionError::Interrupted) = result {
+            break;
+        }
+        options.enforce_quota(started);
+        if let Err(e) = result {
+            error!(url = %url, error = %e, "download failed");
+            console!(Error, "{}: {}", url, e);
+            progress.emit(ProgressEvent::Failed { error: e.to_string(), exit_code: e.exit_code() });
+        }
+    }
+
+    // The watch only ends on shutdown
+    let result = shutdown::check();
+    if let Some(job) = job {
+        job.finish(result.as_ref().err());
+    }
+    result
+}
+
+/// Download a single URL, writing a failure report if one was requested; returns the output,
+/// or `None` when a filter skipped the video
+fn download(
+    url: &str,
+    name: Option<String>,
+    options: &DownloadOptions,
+    progress: &Progress,
+    backends: Backends,
+) -> Result<Option<String>, VideoConversionError> {
+    report::reset();
+    let result = download_one(url, name, options, progress, backends);
+    match (&result, &options.failure_report) {
+        (Err(VideoConversionError::Interrupted), _) | (Ok(_), _) | (_, None) => {}
+        // Nothing a tool did needs explaining when the request itself was wrong
+        (Err(e), _) if e.is_user_error() => {}
+        (Err(e), Some(dir)) => match report::write_report(dir, url, e) {
+            Ok(bundle) => println!("Failure report written to {}", bundle.display()),
+            Err(report_error) => console!(Warning, "could not write failure report: {}", report_error),
+        },
+    }
+    result
+}
+
+/// Duration of the stream behind a manifest URL, announcing it; `None` for live streams
+fn stream_duration(url: &str, transcoder: &dyn Transcoder) -> Option<f64> {
+    match transcoder.probe(Path::new(url)) {
+        Ok(MediaProbe { duration: Some(duration), .. }) => {
+            let length = MediaTimestamp::from_secs_f64(duration).map_or_else(|| format!("{:.1}s", duration), |t| t.to_string());
+            println!("Stream duration: {}", length);
+            Some(duration)
+        }
+        Ok(_) => {
+            console!(Warning, "stream has no fixed duration (live?); capturing until it ends");
+            None
+        }
+        Err(e) => {
+            console!(Warning, "could not probe the stream: {}", e);
+            None
+        }
+    }
+}
+
+/// Download a single URL and convert it into the requested format
+fn download_one(
+    url: &str,
+    name: Option<String>,
+    options: &DownloadOptions,
+    progress: &Progress,
+    backends: Backends,
+) -> Result<Option<String>, VideoConversionError> {
+    if url.trim().is_empty() {
+        return Err(VideoConversionError::config("URL must not be empty"));
+    }
+    let source = urls::normalize(url)?;
+    let url = source.url;
+    let span = info_span!("download", url = %url, output = tracing::field::Empty);
+    let _entered = span.enter();
+    let site = sites::profile_for(&url);
+    site.check_format(options.format)?;
+    let mut selector = site.format_selector(options.format, options.quality);
+    if let Some(language) = &options.audio_lang {
+        selector = sites::with_audio_language(&selector, language);
+    }
+    let manifest = urls::is_manifest(&url);
+    if manifest && options.format == Container::Mp3 {
+        return Err(VideoConversionError::config(
+            "HLS and DASH manifests cannot be converted to MP3".to_string(),
+        ));
+    }
+
+    let chat_vod_id = match (options.twitch_chat, site.site, source.video_id) {
+        (None, _, _) => None,
+        (Some(_), Site::Twitch, Some(id)) => Some(id),
+        (Some(_), _, _) => {
+            return Err(VideoConversionError::config(
+                "--twitch-chat requires a Twitch VOD URL (twitch.tv/videos/...)".to_string(),
+            ))
+        }
+    };
+
+    // Metadata is needed to name the output after the site's template and to size the download
+    let fetch_info = || metadata::VideoInfo::from_raw(backends.downloader.info(&url, &selector, &options.ytdlp)?);
+    let wants_info = !options.skip_space_check
+        || options.mtime_from_upload
+        || options.write_info_json
+        || options.write_description
+        || options.write_nfo.is_some()
+        || options.chapters.is_some()
+        || options.organize.is_some()
+        || options.music_tags
+        || options.filter().is_active();
+    let (name, info) = match &name {
+        Some(name) => {
+            if name.is_empty() || name.contains(['/', '\\']) {
+                return Err(VideoConversionError::config(format!(
+                    "name must be a plain file name without path separators: {:?}",
+                    name
+                )));
+            }
+            let info = if wants_info && !manifest {
+                fetch_info().map_err(|e| console!(Warning, "could not read video metadata: {}", e)).ok()
+            } else {
+                None
+            };
+            (name.clone(), info)
+        }
+        // Manifests come without metadata; ffmpeg reads them directly
+        None if manifest => (urls::manifest_name(&url), None),
+        None => {
+            let info = fetch_info()?;
+            (site.filename(&info), Some(info))
+        }
+    };
+
+    if let Some(reason) = info.as_ref().and_then(|info| options.filter().rejects(info)) {
+        info!(reason = %reason, "skipped by filter");
+        println!("Skipping {}: {}", url, reason);
+        return Ok(None);
+    }
+    if let Some(info) = &info {
+        jobs::set_item_info(info);
+    }
+    if let (Some(language), Some(info)) = (&options.audio_lang, &info) {
+        // Merged downloads name the language per format, single formats at the top level
+        let audio = info.requested_formats.iter().flatten().find(|format| format.has_audio());
+        let picked = audio.and_then(|format| format.language.as_deref()).or_else(|| info.raw["language"].as_str());
+        if !picked.is_some_and(|picked| picked.to_ascii_lowercase().starts_with(&language.to_ascii_lowercase())) {
+            console!(Warning, "no {} audio track offered; downloading the default track", language);
+        }
+    }
+
+    // Library layouts decide both directory and name; otherwise everything goes flat into the output directory
+    let placement = match (options.organize, &info) {
+        (Some(layout), Some(info)) => Some(layout::place(layout, &options.output_dir, info)),
+        _ => None,
+    };
+
+    // Define paths
+    let (processed_dir, name) = match &placement {
+        Some(placement) => (&placement.dir, &placement.name),
+        None => (&options.output_dir, &name),
+    };
+    let (video_path, compatible_mp4_path) = if placement.is_some() {
+        // Library files must carry their final name, so the download gets the suffix instead
+        (format!("{}/{}.source.mp4", processed_dir, name), format!("{}/{}.mp4", processed_dir, name))
+    } else {
+        (format!("{}/{}.mp4", processed_dir, name), format!("{}/{}_complete.mp4", processed_dir, name))
+    };
+    let mp3_path = format!("{}/{}.mp3", processed_dir, name);
+    let unfiltered_mp3_path = format!("{}/{}.unfiltered.mp3", processed_dir, name);
+    let stream_dir = format!("{}/{}", processed_dir, name);
+    let playlist_path = format!("{}/{}", stream_dir, hls::PLAYLIST_NAME);
+    let manifest_path = format!("{}/{}", stream_dir, options.abr.abr_manifest.file_name());
+
+    // Ensure the output directory exists
+    create_dir_all(processed_dir).map_err(|e| VideoConversionError::io(e.to_string()))?;
+
+    // Refuse to clobber existing output instead of letting ffmpeg prompt for it
+    let final_path = match options.format {
+        Container::Mp4 => &compatible_mp4_path,
+        Container::Mp3 => &mp3_path,
+        Container::Hls => &playlist_path,
+        Container::Abr => &manifest_path,
+    };
+    // Streaming output owns its whole directory
+    let conflict_path = match options.format {
+        Container::Hls | Container::Abr => &stream_dir,
+        Container::Mp4 | Container::Mp3 => final_path,
+    };
+    if Path::new(conflict_path).exists() {
+        return Err(VideoConversionError::FileConflict(conflict_path.clone()));
+    }
+    span.record("output", final_path.as_str());
+
+    if let Some(info) = info.as_ref().filter(|_| !options.skip_space_check) {
+        check_disk_space(info, options.format, &options.encode, processed_dir)?;
+    }
+
+    let expected_duration = match manifest {
+        true => stream_duration(&url, backends.transcoder),
+        false => info.as_ref().and_then(|info| info.duration),
+    };
+    let audio_filter = options.silence.filter();
+    let mp3_bitrate = options.encode.audio_bitrate(Container::Mp3).unwrap_or(AudioCodec::Mp3.default_bitrate());
+    let pipeline = match (options.format, manifest) {
+        (Container::Mp3, _) => Pipeline::new().then(DownloadAudio {
+            url: &url,
+            // Filtered audio is written by a later step, from a download under a temporary name
+            output: if audio_filter.is_some() { &unfiltered_mp3_path } else { &mp3_path },
+            site,
+            selector: &selector,
+            bitrate: mp3_bitrate,
+            ytdlp: &options.ytdlp,
+            expected_duration,
+            retries: options.corrupt_retries,
+            backends,
+        }),
+        (_, true) => Pipeline::new().then(DownloadStream {
+            url: &url,
+            output: &video_path,
+            expected_duration,
+            retries: options.corrupt_retries,
+            transcoder: backends.transcoder,
+        }),
+        (_, false) => Pipeline::new().then(DownloadVideo {
+            url: &url,
+            output: &video_path,
+            site,
+            selector: &selector,
+            ytdlp: &options.ytdlp,
+            expected_duration,
+            retries: options.corrupt_retries,
+            backends,
+        }),
+    };
+    let pipeline = match (options.format, audio_filter) {
+        (Container::Mp3, None) => pipeline,
+        (Container::Mp3, Some(filter)) => pipeline
+            .then(FilterAudio {
+                output: &mp3_path,
+                filters: filtergraph::FilterChain::new().then(filter),
+                bitrate: mp3_bitrate,
+                transcoder: backends.transcoder,
+            })
+            .then(Cleanup),
+        (Container::Mp4, _) => pipeline
+            .then(Encode { output: &compatible_mp4_path, encode: &options.encode, transcoder: backends.transcoder })
+            .then(Cleanup),
+        (Container::Hls, _) => pipeline
+            .then(PackageHls {
+                playlist: &playlist_path,
+                encode: &options.encode,
+                hls: &options.hls,
+                transcoder: backends.transcoder,
+            })
+            .then(Cleanup),
+        (Container::Abr, _) => pipeline
+            .then(PackageAbr {
+                manifest: &manifest_path,
+                encode: &options.encode,
+                hls: &options.hls,
+                abr: &options.abr,
+                transcoder: backends.transcoder,
+            })
+            .then(Cleanup),
+    };
+    let pipeline = match options.strip_metadata {
+        true => pipeline.then(StripMetadata { transcoder: backends.transcoder }),
+        false => pipeline,
+    };
+    pipeline.run(progress)?;
+
+    if options.music_tags {
+        match &info {
+            Some(info) => {
+                if let Err(e) = musicbrainz::tag(Path::new(final_path), info, options.acoustid_key().as_deref()) {
+                    console!(Warning, "could not tag {}: {}", final_path, e);
+                }
+            }
+            None => console!(Warning, "metadata unavailable; not looking up music tags"),
+        }
+    }
+
+    if options.replaygain {
+        if let Err(e) = replaygain::tag_track(Path::new(final_path), progress) {
+            console!(Warning, "could not write ReplayGain tags to {}: {}", final_path, e);
+        }
+    }
+
+    if let Some(mode) = options.chapters {
+        match (&info, options.format) {
+            (_, Container::Hls | Container::Abr) => {
+                console!(Warning, "chapters are not supported for {} output", options.format.name())
+            }
+            // Cutting pauses moves everything after them
+            (Some(_), _) if options.silence.trim_silence => {
+                console!(Warning, "chapter times no longer match once silence is trimmed; not adding chapters")
+            }
+            (Some(info), _) => {
+                if let Err(e) = chapters::apply(info, final_path, mode) {
+                    console!(Warning, "could not add chapters: {}", e);
+                }
+            }
+            (None, _) => console!(Warning, "metadata unavailable; not adding chapters"),
+        }
+    }
+
+    if options.write_info_json {
+        match &info {
+            Some(info) => {
+                let info_path = format!("{}/{}.info.json", processed_dir, name);
+                write_json(&info_path, &info.sidecar_json())?;
+                console!(Success, "Metadata saved: {}", info_path);
+            }
+            None => console!(Warning, "metadata unavailable; not writing info JSON"),
+        }
+    }
+
+    if options.write_description {
+        match info.as_ref().and_then(|info| info.description.as_deref()) {
+            Some(description) => {
+                let description_path = format!("{}/{}.description.txt", processed_dir, name);
+                std::fs::write(&description_path, format!("{}\n", description.trim_end())).map_err(|e| {
+                    VideoConversionError::io(format!("Failed to write {}", description_path)).caused_by(e)
+                })?;
+                console!(Success, "Description saved: {}", description_path);
+            }
+            None => console!(Warning, "no description available; not writing description file"),
+        }
+    }
+
+    if options.write_comments.is_some() && manifest {
+        console!(Warning, "manifests have no comments; not writing comments file");
+    } else if let Some(max) = options.write_comments {
+        let comments_path = format!("{}/{}.comments.json", processed_dir, name);
+        println!("Saving top comments to {}...", comments_path);
+        match metadata::fetch_comments(&url, max, &options.ytdlp)
+            .and_then(|comments| write_json(&comments_path, &serde_json::Value::Array(comments)))
+        {
+            Ok(()) => console!(Success, "Comments saved: {}", comments_path),
+            Err(e) => console!(Warning, "could not save comments: {}", e),
+        }
+    }
+
+    if let Some(format) = options.write_thumbnail {
+        // Streaming output is a directory; its poster sits next to it
+        let thumb_stem = match options.format {
+            Container::Hls | Container::Abr => stream_dir.clone(),
+            Container::Mp4 | Container::Mp3 => Path::new(final_path).with_extension("").to_string_lossy().into_owned(),
+        };
+        if manifest {
+            console!(Warning, "manifests have no thumbnail; not writing thumbnail");
+        } else {
+            match thumbnail::download_thumbnail(&url, &thumb_stem, format.extension(), &options.ytdlp) {
+                Ok(()) => console!(Success, "Thumbnail saved: {}.{}", thumb_stem, format.extension()),
+                Err(e) => console!(Warning, "could not save thumbnail: {}", e),
+            }
+        }
+    }
+
+    if options.preview.enabled() {
+        if let Err(e) = preview::write(Path::new(final_path), &options.preview) {
+            console!(Warning, "could not write a preview of {}: {}", final_path, e);
+        }
+    }
+
+    if options.proxy.enabled() {
+        if let Err(e) = proxy::write(Path::new(final_path), &options.proxy, progress) {
+            console!(Warning, "could not write an editing proxy of {}: {}", final_path, e);
+        }
+    }
+
+    if let Some(placement) = &placement {
+        layout::write_show_nfo(placement)?;
+        let thumb_stem = format!("{}/{}-thumb", processed_dir, name);
+        if let Err(e) = thumbnail::download_thumbnail(&url, &thumb_stem, "jpg", &options.ytdlp) {
+            console!(Warning, "could not save thumbnail: {}", e);
+        }
+    }
+
+    let nfo_kind = options.write_nfo.or(placement.as_ref().map(|_| nfo::NfoKind::Episode));
+    if let Some(kind) = nfo_kind {
+        match &info {
+            Some(info) => {
+                // Media servers pair NFO files with the video by base name
+                let nfo_path = Path::new(final_path).with_extension("nfo");
+                std::fs::write(&nfo_path, nfo::render(info, kind)).map_err(|e| {
+                    VideoConversionError::io(format!("Failed to write {}", nfo_path.display())).caused_by(e)
+                })?;
+                console!(Success, "NFO saved: {}", nfo_path.display());
+            }
+            None => console!(Warning, "metadata unavailable; not writing NFO"),
+        }
+    }
+
+    if let (Some(vod_id), Some(chat_format)) = (chat_vod_id, options.twitch_chat) {
+        let chat_path = format!("{}/{}.{}", processed_dir, name, chat_format.extension());
+        println!("Saving chat replay to {}...", chat_path);
+        match twitch::fetch_chat(&vod_id).and_then(|messages| twitch::write_chat(&messages, &chat_path, chat_format)) {
+            Ok(()) => console!(Success, "Chat replay saved: {}", chat_path),
+            Err(e) => console!(Warning, "could not save chat replay: {}", e),
+        }
+    }
+
+    if options.transcribe.enabled() {
+        if let Err(e) = transcribe::transcribe(Path::new(final_path), &options.transcribe) {
+            console!(Warning, "could not transcribe {}: {}", final_path, e);
+        }
+    }
+
+    if options.translate.enabled() {
+        if let Err(e) = translate::translate_download(Path::new(final_path), &options.translate) {
+            console!(Warning, "could not translate the subtitles of {}: {}", final_path, e);
+        }
+    }
+
+    if options.flag_duplicates {
+        dedupe::flag_duplicate(Path::new(final_path), Path::new(&options.output_dir));
+    }
+
+    // Last, as muxing subtitles rewrites the output
+    if options.mtime_from_upload {
+        match info.as_ref().and_then(dates::upload_time) {
+            Some(time) => set_modified(final_path, time)?,
+            None => console!(Warning, "upload date unknown; keeping the download time as modification time"),
+        }
+    }
+
+    info!("download finished");
+    progress.emit(ProgressEvent::Finished { output: final_path.clone() });
+    Ok(Some(final_path.clone()))
+}
\ No newline at end of file
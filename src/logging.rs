@@ -0,0 +1,244 @@
+use std::fmt;
+use std::fs::{create_dir_all, rename, File, OpenOptions};
+use std::io::{IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+use clap::ValueEnum;
+use tracing_subscriber::filter::EnvFilter;
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{Layer, Registry};
+pub use tracing_subscriber::filter::LevelFilter;
+
+use crate::VideoConversionError;
+
+/// Environment variable overriding the log level with a filter such as `videelow=debug`
+pub const LOG_ENV: &str = "VIDEELOW_LOG";
+
+/// Whether console messages on stdout and stderr are colored, decided once in `init`
+static STDOUT_COLOR: AtomicBool = AtomicBool::new(false);
+static STDERR_COLOR: AtomicBool = AtomicBool::new(false);
+
+/// Status line kept below the console lines on a terminal, while items run in parallel
+static STATUS: Mutex<Option<String>> = Mutex::new(None);
+
+/// How structured log records are written to stderr and the log file
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum, Debug, Default)]
+pub enum LogFormat {
+    /// Human-readable lines
+    #[default]
+    Text,
+    /// One JSON object per line including the enclosing job and download spans, for Loki or ELK
+    Json,
+}
+
+/// Size at which the log file is rotated
+const LOG_FILE_MAX_BYTES: u64 = 10 * 1024 * 1024;
+
+/// Rotated log files kept next to the current one, as `FILE.1` (newest) to `FILE.5`
+const LOG_FILE_KEEP: usize = 5;
+
+/// Install the global log subscriber and pick console colors; without a level, text logging stays
+/// off as the console output already covers it, while JSON logging records info and above.
+/// A log file additionally records debug and above, including every child command line and its
+/// stderr. Colors are used on terminals unless `color` is false or `NO_COLOR` is set
+pub fn init(format: LogFormat, level: Option<LevelFilter>, color: bool, log_file: Option<&Path>) -> Result<(), VideoConversionError> {
+    let color = color && std::env::var_os("NO_COLOR").is_none_or(|value| value.is_empty());
+    STDOUT_COLOR.store(color && std::io::stdout().is_terminal(), Ordering::Relaxed);
+    STDERR_COLOR.store(color && std::io::stderr().is_terminal(), Ordering::Relaxed);
+
+    let mut layers = vec![layer(format, std::io::stderr, STDERR_COLOR.load(Ordering::Relaxed))
+        .with_filter(filter(level.unwrap_or(match format {
+            LogFormat::Text => LevelFilter::OFF,
+            LogFormat::Json => LevelFilter::INFO,
+        }))?)
+        .boxed()];
+    if let Some(path) = log_file {
+        let writer = Mutex::new(RotatingFile::open(path)?);
+        layers.push(layer(format, writer, false).with_filter(filter(level.unwrap_or(LevelFilter::DEBUG))?).boxed());
+    }
+    tracing_subscriber::registry()
+        .with(layers)
+        .try_init()
+        .map_err(|e| VideoConversionError::io("Failed to set up logging").caused_by(e))
+}
+
+/// Filter at `default`, unless `VIDEELOW_LOG` says otherwise
+fn filter(default: LevelFilter) -> Result<EnvFilter, VideoConversionError> {
+    EnvFilter::builder()
+        .with_default_directive(default.into())
+        .with_env_var(LOG_ENV)
+        .from_env()
+        .map_err(|e| VideoConversionError::config(format!("invalid {}", LOG_ENV)).caused_by(e))
+}
+
+fn layer<W>(format: LogFormat, writer: W, ansi: bool) -> Box<dyn Layer<Registry> + Send + Sync>
+where
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    let layer = tracing_subscriber::fmt::layer().with_writer(writer).with_ansi(ansi);
+    match format {
+        LogFormat::Text => layer.boxed(),
+        LogFormat::Json => layer.json().flatten_event(true).boxed(),
+    }
+}
+
+/// stderr setting for a child tool: piped, as `ChildProcess` passes it on to the terminal while
+/// recording its lines for the log file, failure reports and error messages
+pub(crate) fn child_stderr() -> Stdio {
+    Stdio::piped()
+}
+
+/// Whether warnings and errors on stderr are colored
+pub(crate) fn stderr_color() -> bool {
+    STDERR_COLOR.load(Ordering::Relaxed)
+}
+
+/// Append-only log file that moves itself to `FILE.1` once it reaches `LOG_FILE_MAX_BYTES`
+struct RotatingFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+}
+
+impl RotatingFile {
+    fn open(path: &Path) -> Result<RotatingFile, VideoConversionError> {
+        let error = |e: std::io::Error| VideoConversionError::config(format!("cannot open log file {}", path.display())).caused_by(e);
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            create_dir_all(dir).map_err(error)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(path).map_err(error)?;
+        let size = file.metadata().map_err(error)?.len();
+        Ok(RotatingFile { path: path.to_path_buf(), file, size })
+    }
+
+    fn rotated(&self, index: usize) -> PathBuf {
+        let mut name = self.path.as_os_str().to_os_string();
+        name.push(format!(".{}", index));
+        PathBuf::from(name)
+    }
+
+    fn rotate(&mut self) -> std::io::Result<()> {
+        for index in (1..LOG_FILE_KEEP).rev() {
+            let from = self.rotated(index);
+            if from.exists() {
+                rename(&from, self.rotated(index + 1))?;
+            }
+        }
+        rename(&self.path, self.rotated(1))?;
+        self.file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if self.size > 0 && self.size + buf.len() as u64 > LOG_FILE_MAX_BYTES {
+            self.rotate()?;
+        }
+        let written = self.file.write(buf)?;
+        self.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.file.flush()
+    }
+}
+
+/// Kind of console message, which decides its color and stream
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Tone {
+    /// Uncolored status on stdout
+    Plain,
+    /// Download activity on stdout, in cyan
+    Download,
+    /// Completed work on stdout, in green
+    Success,
+    /// `Warning:` line on stderr, in yellow
+    Warning,
+    /// `Error:` line on stderr, in red
+    Error,
+}
+
+impl Tone {
+    fn ansi(self) -> Option<&'static str> {
+        match self {
+            Tone::Plain => None,
+            Tone::Download => Some("36"),
+            Tone::Success => Some("32"),
+            Tone::Warning => Some("33"),
+            Tone::Error => Some("31"),
+        }
+    }
+
+    fn prefix(self) -> &'static str {
+        match self {
+            Tone::Warning => "Warning: ",
+            Tone::Error => "Error: ",
+            _ => "",
+        }
+    }
+}
+
+/// Wrap `text` in the tone's color when the stream it goes to shows colors
+pub fn paint(tone: Tone, text: &str) -> String {
+    let color = match tone {
+        Tone::Warning | Tone::Error => STDERR_COLOR.load(Ordering::Relaxed),
+        _ => STDOUT_COLOR.load(Ordering::Relaxed),
+    };
+    match tone.ansi() {
+        Some(code) if color => format!("\x1b[{}m{}\x1b[0m", code, text),
+        _ => text.to_string(),
+    }
+}
+
+/// Print one console line; use the `console!` macro instead of calling this directly
+pub fn console(tone: Tone, args: fmt::Arguments) {
+    let line = paint(tone, &format!("{}{}", tone.prefix(), args));
+    let status = STATUS.lock().unwrap_or_else(|e| e.into_inner());
+    let mut stdout = std::io::stdout().lock();
+    if status.is_some() {
+        // The line takes the status line's place, which is drawn again below it
+        let _ = write!(stdout, "\r\x1b[K").and_then(|_| stdout.flush());
+    }
+    // A closed pipe must not abort the actual work
+    let _ = match tone {
+        Tone::Warning | Tone::Error => writeln!(std::io::stderr(), "{}", line),
+        _ => writeln!(stdout, "{}", line),
+    };
+    if let Some(status) = status.as_ref() {
+        let _ = write!(stdout, "{}", status).and_then(|_| stdout.flush());
+    }
+}
+
+/// Show `line` as the status line below the console lines when stdout is a terminal, replacing
+/// the previous one; `None` leaves the last one in place and ends it
+pub(crate) fn set_status(line: Option<String>) {
+    if !std::io::stdout().is_terminal() {
+        return;
+    }
+    let mut status = STATUS.lock().unwrap_or_else(|e| e.into_inner());
+    let mut stdout = std::io::stdout().lock();
+    let _ = match &line {
+        Some(line) => write!(stdout, "\r\x1b[K{}", line),
+        None if status.is_some() => writeln!(stdout),
+        None => Ok(()),
+    };
+    let _ = stdout.flush();
+    *status = line;
+}
+
+/// Print a console line in the given tone, e.g. `console!(Warning, "could not remove {}", path)`;
+/// warnings and errors get their `Warning:`/`Error:` prefix and go to stderr
+#[macro_export]
+macro_rules! console {
+    ($tone:ident, $($arg:tt)*) => {
+        $crate::logging::console($crate::logging::Tone::$tone, format_args!($($arg)*))
+    };
+}
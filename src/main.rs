@@ -1,155 +1,799 @@
-use std::fs::{create_dir_all, remove_file};
-use std::path::Path;
-use std::process::{Command, Stdio};
-use clap::{Parser, ValueEnum};
-use thiserror::Error;
+use std::io::BufRead;
+use std::path::PathBuf;
+use std::process::ExitCode;
+use clap::{Parser, Subcommand};
+
+use videelow::analyze::{self, KeyframesOptions, LoudnessOptions};
+use videelow::audio_lang;
+use videelow::bench::{self, BenchOptions};
+use videelow::check::{self, CheckOptions};
+use videelow::compare::{self, CompareOptions};
+use videelow::convert_dir::{self, ConvertDirOptions};
+use videelow::dedupe::{self, DedupeOptions};
+use videelow::diagnostics;
+use videelow::feed::{self, FeedOptions};
+use videelow::history::{self, ExportOptions};
+use videelow::import::{self, ImportOptions};
+use videelow::jobs::{JobHandle, JobStatus};
+use videelow::logging::{self, LevelFilter, LogFormat};
+use videelow::organize::{self, OrganizeOptions};
+use videelow::progress::{self, Progress, ProgressEvent, ProgressTarget};
+use videelow::radio::{self, RadioOptions};
+use videelow::record::{self, CameraOptions, ScreenOptions};
+use videelow::restream::{self, RestreamOptions};
+use videelow::scenes::{self, SceneOptions};
+use videelow::service::{self, ServiceSpec};
+use videelow::sheet::{self, SheetOptions};
+use videelow::stats::{self, StatsOptions};
+use videelow::sync;
+use videelow::timestamp::MediaTimestamp;
+use videelow::translate::{self, TranslateOptions};
+use videelow::trim::{self, TrimOptions};
+use videelow::watch_folder::{self, WatchFolderOptions};
+use videelow::ytdlp::YtDlpOptions;
+use videelow::config::{self, SyncSource};
+use videelow::{console, doctor, metrics, shutdown, update, download_all, estimate, jobs, urls, watch_clipboard, DownloadOptions, EncodeOptions, VideoConversionError};
 
 /// Struct to parse command line arguments using clap
 #[derive(Parser, Debug)]
-#[command(author, version, about = "Video downloader and converter")]
+#[command(
+    author,
+    version,
+    about = "Video downloader and converter",
+    after_help = "Exit codes:\n  0  success\n  1  unexpected error\n  2  bad arguments\n  3  missing external tool (yt-dlp/ffmpeg)\n  4  download failed\n  5  conversion failed\n  6  output file already exists\n  130  interrupted by SIGINT/SIGTERM",
+    subcommand_negates_reqs = true,
+    args_conflicts_with_subcommands = true,
+    args_override_self = true
+)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Commands>,
+
     /// URL of the video to download
-    #[arg(short, long)]
-    url: String,
+    #[arg(short, long, required = true)]
+    url: Option<String>,
+
+    /// Write newline-delimited JSON progress events to a file descriptor (fd:3) or file path
+    #[arg(long, value_name = "TARGET", global = true)]
+    progress_json: Option<ProgressTarget>,
+
+    /// Apply options saved with `videelow profile save`; options given after it take precedence
+    #[arg(long, value_name = "NAME", global = true)]
+    profile: Option<String>,
+
+    /// Serve Prometheus metrics at http://ADDR/metrics while running, e.g. 127.0.0.1:9185
+    #[arg(long, value_name = "ADDR", global = true)]
+    metrics_listen: Option<std::net::SocketAddr>,
+
+    /// Format of the structured logs written to stderr
+    #[arg(long, value_enum, value_name = "FORMAT", default_value = "text", global = true)]
+    log_format: LogFormat,
+
+    /// Also write detailed logs, including tool command lines and their stderr, to this file;
+    /// it is rotated at 10 MiB, keeping five old files
+    #[arg(long, value_name = "PATH", global = true)]
+    log_file: Option<PathBuf>,
+
+    /// Print plain console output without colors (also when NO_COLOR is set)
+    #[arg(long, global = true)]
+    no_color: bool,
+
+    /// Log level: off, error, warn, info, debug or trace (default: off for text, info for JSON; VIDEELOW_LOG overrides)
+    #[arg(long, value_name = "LEVEL", global = true)]
+    log_level: Option<LevelFilter>,
+
+    #[command(flatten)]
+    options: DownloadOptions,
+}
+
+/// Subcommands besides the default download-and-convert run
+#[derive(Subcommand, Debug)]
+enum Commands {
+    /// Download and convert one or more URLs with shared output settings
+    #[command(args_override_self = true)]
+    Download {
+        /// URLs of the videos (or HLS/DASH manifests) to download; `-` reads URLs from stdin, one per line
+        #[arg(required = true)]
+        urls: Vec<String>,
+
+        #[command(flatten)]
+        options: DownloadOptions,
+    },
+
+    /// Download the video URLs of exported bookmarks, a CSV export or a Google Takeout Watch Later
+    /// list, skipping videos downloaded before
+    #[command(args_override_self = true)]
+    Import {
+        #[command(flatten)]
+        import: ImportOptions,
+
+        #[command(flatten)]
+        options: DownloadOptions,
+    },
+
+    /// Watch the clipboard and download every video URL that gets copied
+    #[command(args_override_self = true)]
+    WatchClipboard {
+        /// Ask before downloading each copied URL
+        #[arg(long)]
+        confirm: bool,
+
+        /// Also accept URLs of sites without first-class support
+        #[arg(long)]
+        all_urls: bool,
+
+        /// How often to check the clipboard, in milliseconds
+        #[arg(long, default_value_t = 500)]
+        interval_ms: u64,
+
+        #[command(flatten)]
+        options: DownloadOptions,
+    },
+
+    /// Watch a drop folder and convert every video placed in it into the output directory
+    WatchFolder(WatchFolderOptions),
+
+    /// Measure properties of a media file
+    Analyze {
+        #[command(subcommand)]
+        measure: AnalyzeCommand,
+    },
+
+    /// Time every encoder and preset this machine offers on a short sample and compare output sizes
+    Bench(BenchOptions),
+
+    /// Report which properties of a file a device does not play and whether a remux fixes them
+    Check(CheckOptions),
+
+    /// Score an encode against its source with VMAF (if ffmpeg has libvmaf), SSIM and PSNR
+    Compare(CompareOptions),
+
+    /// Convert every video in a folder tree, recreating its structure in the output directory
+    ConvertDir(ConvertDirOptions),
+
+    /// Check the external tools and test which hardware encoders work on this machine
+    Doctor {
+        /// Print the findings as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Report expected download sizes per quality tier and the predicted size after conversion
+    Estimate {
+        /// URL of the video to estimate
+        url: String,
+
+        #[command(flatten)]
+        encode: EncodeOptions,
+
+        #[command(flatten)]
+        ytdlp: YtDlpOptions,
+    },
+
+    /// List the audio languages of a video with several dubbed tracks, for --audio-lang
+    ListAudioLangs {
+        /// URL of the video
+        url: String,
+
+        /// Print the languages as JSON
+        #[arg(long)]
+        json: bool,
+
+        #[command(flatten)]
+        ytdlp: YtDlpOptions,
+    },
+
+    /// Write a podcast RSS feed for a directory of downloaded audio, to subscribe to it in any podcast app
+    Feed(FeedOptions),
+
+    /// List the scene changes in a video, optionally splitting it into one file per scene
+    Scenes(SceneOptions),
+
+    /// Push a video or live stream to an RTMP ingest such as Owncast or nginx-rtmp, copying or transcoding it
+    Restream(RestreamOptions),
+
+    /// Tile frames sampled across a video into one image, to review it without a player
+    Sheet(SheetOptions),
 
-    /// Custom name for the output video and audio files (without extension)
-    #[arg(short, long, default_value = "video")]
-    name: String,
+    /// Cut a clip out of a video, re-encoding it for exact cuts or copying the streams with --copy
+    Trim(TrimOptions),
 
-    /// Output directory where the files will be saved
-    #[arg(short, long, default_value = "Processed")]
-    output_dir: String,
+    /// Translate an SRT or WebVTT subtitle file into other languages, optionally adding the results to a video
+    Translate(TranslateOptions),
 
-    /// Output format (mp3 or mp4)
-    #[arg(short, long, value_enum, default_value = "mp4")]
-    format: OutputFormat,
+    /// Show the phase, progress and timing of recent download jobs, or the details of one
+    Status {
+        /// Job ID; lists recent jobs when omitted
+        id: Option<u64>,
+
+        /// Print the job records as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Summarize bytes downloaded, average speed and time spent per day or week, e.g. on metered connections
+    Stats(StatsOptions),
+
+    /// List videos in a library that look the same, such as re-uploads and mirrors, and optionally remove them
+    Dedupe(DedupeOptions),
+
+    /// Move and rename the videos of a library into a folder layout built from their metadata
+    Organize(OrganizeOptions),
+
+    /// Suspend a running download job and the tools it runs (Unix only)
+    Pause {
+        /// Job ID, as printed when the job started
+        id: u64,
+    },
+
+    /// Continue a paused download job
+    Resume {
+        /// Job ID
+        id: u64,
+    },
+
+    /// Manage named sets of download options stored in the config file
+    Profile {
+        #[command(subcommand)]
+        action: ProfileCommand,
+    },
+
+    /// Replace this binary with the latest GitHub release after checking its SHA-256 against the
+    /// release's SHA256SUMS, which catches corrupted downloads but is not a signature
+    SelfUpdate {
+        /// Only report whether a newer release exists
+        #[arg(long)]
+        check: bool,
+    },
+
+    /// Record the screen, a camera or an internet radio stream
+    Record {
+        #[command(subcommand)]
+        source: RecordCommand,
+    },
+
+    /// Work with the record of past downloads
+    History {
+        #[command(subcommand)]
+        action: HistoryCommand,
+    },
+
+    /// Run a download periodically as a systemd user timer or launchd agent
+    Service {
+        #[command(subcommand)]
+        action: ServiceCommand,
+    },
+
+    /// Download the videos uploaded to saved channels and playlists since their last sync; syncs
+    /// every saved source when no action is given, e.g. from cron or `service install --sync`
+    Sync {
+        #[command(subcommand)]
+        action: Option<SyncCommand>,
+    },
 }
 
-/// Enum to define allowed output formats
-#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Debug)]
-enum OutputFormat {
-    Mp3,
-    Mp4,
+/// Actions of the `profile` subcommand
+#[derive(Subcommand, Debug)]
+enum ProfileCommand {
+    /// Save download options under a name, e.g. `profile save podcast -f mp3 --write-info-json`
+    Save {
+        /// Profile name, used as `--profile NAME`
+        name: String,
+
+        /// Download options to save
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        options: Vec<String>,
+    },
+
+    /// List saved profiles
+    List,
+
+    /// Delete a saved profile
+    Delete {
+        /// Profile name
+        name: String,
+    },
 }
 
-/// Custom error type for improved error handling
-#[derive(Error, Debug)]
-enum VideoConversionError {
-    #[error("Failed to execute command: {0}")]
-    CommandError(String),
+/// Measurements of the `analyze` subcommand
+#[derive(Subcommand, Debug)]
+enum AnalyzeCommand {
+    /// Integrated loudness, true peak and loudness range after EBU R128, using ffmpeg's ebur128 filter
+    Loudness(LoudnessOptions),
 
-    #[error("File not found: {0}")]
-    FileNotFound(String),
+    /// Keyframe times and GOP sizes of a video, to choose trim points that cut without re-encoding
+    Keyframes(KeyframesOptions),
 }
 
-/// Helper function to run external commands
-fn run_command(command: &mut Command) -> Result<(), VideoConversionError> {
-    let status = command.status().map_err(|e| VideoConversionError::CommandError(e.to_string()))?;
-    if status.success() {
-        Ok(())
-    } else {
-        Err(VideoConversionError::CommandError("Command failed".to_string()))
-    }
+/// Actions of the `history` subcommand
+#[derive(Subcommand, Debug)]
+enum HistoryCommand {
+    /// Write every download with its metadata, timing and outcome as CSV or JSON, e.g. for spreadsheets
+    Export(ExportOptions),
 }
 
-/// Function to download YouTube video as MP4 with yt-dlp
-fn download_youtube_video(url: &str, output_path: &str) -> Result<(), VideoConversionError> {
-    println!("Downloading video from YouTube as MP4...");
+/// Sources of the `record` subcommand
+#[derive(Subcommand, Debug)]
+enum RecordCommand {
+    /// Record the screen with ffmpeg's x11grab (Linux), avfoundation (macOS) or gdigrab (Windows)
+    #[command(args_override_self = true)]
+    Screen(ScreenOptions),
 
-    run_command(
-        Command::new("yt-dlp")
-            .arg("-f")
-            .arg("bestvideo[ext=mp4]+bestaudio[ext=m4a]/best[ext=mp4]/best") // Use MP4 format for compatibility
-            .arg("-o")
-            .arg(output_path)
-            .arg(url)
-            .stdout(Stdio::inherit())
-            .stderr(Stdio::inherit()),
-    )?;
+    /// Record a camera and microphone, or list them with --list
+    #[command(args_override_self = true)]
+    Camera(CameraOptions),
 
-    println!("Video downloaded successfully: {}", output_path);
-    Ok(())
+    /// Record an Icecast/SHOUTcast or HLS radio stream into tagged MP3s, one per announced title
+    #[command(args_override_self = true)]
+    Radio(RadioOptions),
 }
 
-/// Function to download YouTube audio directly as MP3 with yt-dlp
-fn download_youtube_audio(url: &str, output_path: &str) -> Result<(), VideoConversionError> {
-    println!("Downloading audio from YouTube as MP3...");
-
-    run_command(
-        Command::new("yt-dlp")
-            .arg("-f")
-            .arg("bestaudio")             // Choose the best audio quality available
-            .arg("--extract-audio")        // Extract audio only
-            .arg("--audio-format")
-            .arg("mp3")                    // Convert audio to MP3
-            .arg("--audio-quality")
-            .arg("192K")                   // Set a standard bitrate for quality
-            .arg("-o")
-            .arg(output_path)
-            .arg(url)
-            .stdout(Stdio::inherit())
-            .stderr(Stdio::inherit()),
-    )?;
-
-    println!("Audio downloaded successfully as MP3: {}", output_path);
-    Ok(())
+/// Actions of the `service` subcommand
+#[derive(Subcommand, Debug)]
+enum ServiceCommand {
+    /// Install and start a service, e.g. `service install channel --every 6:00:00 -- URL -f mp3`
+    Install {
+        /// Service name
+        name: String,
+
+        /// Time between runs (HH:MM:SS), counted from the end of the previous run
+        #[arg(long, value_name = "INTERVAL", default_value = "1:00:00")]
+        every: MediaTimestamp,
+
+        /// Run `videelow sync` instead of a download, for the sources named after `--` or all of them
+        #[arg(long)]
+        sync: bool,
+
+        /// Arguments of `videelow download` for each run, after `--`
+        #[arg(last = true, required_unless_present = "sync")]
+        args: Vec<String>,
+    },
+
+    /// Show whether a service is scheduled and how its last run went
+    Status {
+        /// Service name
+        name: String,
+    },
+
+    /// Stop and remove a service
+    Uninstall {
+        /// Service name
+        name: String,
+    },
 }
 
-/// Function to convert MP4 to a QuickTime-compatible format
-fn convert_to_quicktime_compatible_mp4(input_path: &str, output_path: &str) -> Result<(), VideoConversionError> {
-    println!("Re-encoding video to QuickTime-compatible MP4...");
-
-    run_command(
-        Command::new("ffmpeg")
-            .arg("-i")
-            .arg(input_path)
-            .arg("-c:v")
-            .arg("libx264") // H.264 codec for video
-            .arg("-c:a")
-            .arg("aac")     // AAC codec for audio
-            .arg("-movflags")
-            .arg("+faststart") // For streaming compatibility
-            .arg(output_path)
-            .stdout(Stdio::inherit())
-            .stderr(Stdio::inherit()),
-    )?;
-
-    println!("Re-encoding successful: {}", output_path);
-    Ok(())
+/// Actions of the `sync` subcommand
+#[derive(Subcommand, Debug)]
+enum SyncCommand {
+    /// Save a channel or playlist to sync, e.g. `sync add news URL -f mp3 --since 2024-01-01`
+    Add {
+        /// Source name
+        name: String,
+
+        /// URL of the channel or playlist
+        url: String,
+
+        /// Count the videos listed now as synced, so only later uploads are downloaded
+        #[arg(long)]
+        from_now: bool,
+
+        /// Download options for its videos
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        options: Vec<String>,
+    },
+
+    /// Download the new videos of the named sources, or of all of them
+    Run {
+        /// Source names
+        names: Vec<String>,
+    },
+
+    /// List saved sources and how far each is synced
+    List,
+
+    /// Remove a source and forget how far it was synced
+    Remove {
+        /// Source name
+        name: String,
+    },
+}
+
+/// Parser used to validate the options of a profile before saving it
+#[derive(Parser, Debug)]
+#[command(name = "videelow profile save", no_binary_name = true, args_override_self = true)]
+struct ProfileOptions {
+    #[command(flatten)]
+    options: DownloadOptions,
+}
+
+
+fn main() -> ExitCode {
+    let args = match config::expand_profiles(std::env::args_os().collect()) {
+        Ok(argv) => Args::parse_from(argv),
+        Err(e) => {
+            console!(Error, "{}", e);
+            return ExitCode::from(e.exit_code());
+        }
+    };
+
+    if let Err(e) = logging::init(args.log_format, args.log_level, !args.no_color, args.log_file.as_deref()) {
+        console!(Error, "{}", e);
+        return ExitCode::from(e.exit_code());
+    }
+
+    let progress = match Progress::new(args.progress_json.as_ref()) {
+        Ok(progress) => progress,
+        Err(e) => {
+            console!(Error, "{}", e);
+            return ExitCode::from(e.exit_code());
+        }
+    };
+
+    if let Err(e) = shutdown::install() {
+        console!(Error, "{}", e);
+        return ExitCode::from(e.exit_code());
+    }
+
+    if let Some(addr) = args.metrics_listen {
+        if let Err(e) = metrics::serve(addr) {
+            console!(Error, "{}", e);
+            return ExitCode::from(e.exit_code());
+        }
+    }
+
+    match run(args, &progress) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            diagnostics::print(&e);
+            progress.emit(ProgressEvent::Failed { error: e.to_string(), exit_code: e.exit_code() });
+            ExitCode::from(e.exit_code())
+        }
+    }
+}
+
+fn run(args: Args, progress: &Progress) -> Result<(), VideoConversionError> {
+    match args.command {
+        Some(Commands::Download { urls, options }) => download_all(&read_stdin_urls(urls)?, &options, progress),
+        Some(Commands::Import { import, options }) => import::run(&import, &options, progress),
+        Some(Commands::WatchClipboard { confirm, all_urls, interval_ms, options }) => {
+            watch_clipboard(confirm, all_urls, interval_ms, &options, progress)
+        }
+        Some(Commands::WatchFolder(options)) => watch_folder::run(&options, progress),
+        Some(Commands::Analyze { measure: AnalyzeCommand::Loudness(options) }) => analyze::run_loudness(&options, progress),
+        Some(Commands::Analyze { measure: AnalyzeCommand::Keyframes(options) }) => analyze::run_keyframes(&options),
+        Some(Commands::Bench(options)) => bench::run(&options, progress),
+        Some(Commands::Check(options)) => check::run(&options),
+        Some(Commands::Compare(options)) => compare::run(&options, progress),
+        Some(Commands::ConvertDir(options)) => convert_dir::run(&options, progress),
+        Some(Commands::Doctor { json }) => doctor::run(json),
+        Some(Commands::Estimate { url, encode, ytdlp }) => estimate::run(&urls::normalize(&url)?.url, &encode, &ytdlp),
+        Some(Commands::ListAudioLangs { url, json, ytdlp }) => audio_lang::list(&urls::normalize(&url)?.url, json, &ytdlp),
+        Some(Commands::Feed(options)) => feed::run(&options),
+        Some(Commands::Scenes(options)) => scenes::run(&options, progress),
+        Some(Commands::Restream(options)) => restream::run(&options, progress),
+        Some(Commands::Sheet(options)) => sheet::run(&options),
+        Some(Commands::Trim(options)) => trim::run(&options, progress),
+        Some(Commands::Translate(options)) => translate::run(&options),
+        Some(Commands::Profile { action }) => manage_profiles(action),
+        Some(Commands::Record { source: RecordCommand::Screen(options) }) => record::record_screen(&options, progress),
+        Some(Commands::Record { source: RecordCommand::Camera(options) }) => record::record_camera(&options, progress),
+        Some(Commands::Record { source: RecordCommand::Radio(options) }) => radio::record_radio(&options, progress),
+        Some(Commands::History { action: HistoryCommand::Export(options) }) => history::export(&options),
+        Some(Commands::Service { action }) => manage_service(action),
+        Some(Commands::Sync { action }) => manage_sync(action, progress),
+        Some(Commands::SelfUpdate { check }) => update::run(check),
+        Some(Commands::Status { id, json }) => show_status(id, json),
+        Some(Commands::Stats(options)) => stats::run(&options),
+        Some(Commands::Dedupe(options)) => dedupe::run(&options),
+        Some(Commands::Organize(options)) => organize::run(&options),
+        Some(Commands::Pause { id }) => {
+            JobHandle::new(id).pause()?;
+            println!("Paused job {}", id);
+            Ok(())
+        }
+        Some(Commands::Resume { id }) => {
+            JobHandle::new(id).resume()?;
+            println!("Resumed job {}", id);
+            Ok(())
+        }
+        None => download_all(&read_stdin_urls(vec![args.url.unwrap_or_default()])?, &args.options, progress),
+    }
 }
 
-fn main() -> Result<(), VideoConversionError> {
-    let args = Args::parse();
+/// Save, list or delete option profiles in the config file
+fn manage_profiles(action: ProfileCommand) -> Result<(), VideoConversionError> {
+    let mut config = config::Config::load()?;
+    match action {
+        ProfileCommand::Save { name, options } => {
+            if name.is_empty() || name.starts_with('-') {
+                return Err(VideoConversionError::config(format!("invalid profile name {:?}", name)));
+            }
+            // Reject options that would fail every later run using the profile
+            ProfileOptions::try_parse_from(&options)
+                .map_err(|e| VideoConversionError::config("invalid profile options").caused_by(clap_message(&e)))?;
+            config.profiles.insert(name.clone(), options);
+            let path = config.save()?;
+            println!("Saved profile {} to {}", name, path.display());
+        }
+        ProfileCommand::List => {
+            if config.profiles.is_empty() {
+                println!("No profiles saved; create one with `videelow profile save NAME OPTIONS...`");
+            }
+            for (name, options) in &config.profiles {
+                println!("{}: {}", name, config::display_args(options));
+            }
+        }
+        ProfileCommand::Delete { name } => {
+            if config.profiles.remove(&name).is_none() {
+                return Err(VideoConversionError::config(format!("unknown profile {:?}", name)));
+            }
+            config.save()?;
+            println!("Deleted profile {}", name);
+        }
+    }
+    Ok(())
+}
 
-    // Define paths
-    let processed_dir = &args.output_dir;
-    let video_path = format!("{}/{}.mp4", processed_dir, args.name);
-    let compatible_mp4_path = format!("{}/{}_complete.mp4", processed_dir, args.name);
-    let mp3_path = format!("{}/{}.mp3", processed_dir, args.name);
+/// Download options saved with a sync source, profiles included
+fn sync_options(name: &str, source: &SyncSource) -> Result<DownloadOptions, VideoConversionError> {
+    let options = config::expand_profiles(source.options.iter().map(Into::into).collect())?;
+    ProfileOptions::try_parse_from(options).map(|parsed| parsed.options).map_err(|e| {
+        VideoConversionError::config(format!("invalid options for sync source {}", name)).caused_by(clap_message(&e))
+    })
+}
 
-    // Ensure the output directory exists
-    create_dir_all(processed_dir).map_err(|e| VideoConversionError::CommandError(e.to_string()))?;
+/// First line of a clap parse error without its `error: ` prefix, to nest under our own message
+fn clap_message(error: &clap::Error) -> String {
+    let message = error.to_string();
+    message.lines().next().unwrap_or_default().trim_start_matches("error: ").to_string()
+}
 
-    match args.format {
-        OutputFormat::Mp4 => {
-            // Download and process MP4
-            download_youtube_video(&args.url, &video_path)?;
+/// Save, run, list or remove sync sources
+fn manage_sync(action: Option<SyncCommand>, progress: &Progress) -> Result<(), VideoConversionError> {
+    let mut config = config::Config::load()?;
+    match action.unwrap_or(SyncCommand::Run { names: Vec::new() }) {
+        SyncCommand::Add { name, url, from_now, options } => {
+            if name.is_empty() || name.starts_with('-') {
+                return Err(VideoConversionError::config(format!("invalid source name {:?}", name)));
+            }
+            if !urls::normalize(&url)?.listing {
+                return Err(VideoConversionError::config(format!("{} is not a channel or playlist", url)));
+            }
+            let source = SyncSource { url, options };
+            let options = sync_options(&name, &source)?;
+            if from_now {
+                sync::mark_synced(&name, &source, &options)?;
+            }
+            config.sources.insert(name.clone(), source);
+            let path = config.save()?;
+            println!("Saved sync source {} to {}", name, path.display());
+        }
+        SyncCommand::Run { names } => {
+            let names = match names.is_empty() {
+                true => config.sources.keys().cloned().collect(),
+                false => names,
+            };
+            if names.is_empty() {
+                println!("No sources saved; add one with `videelow sync add NAME URL [OPTIONS...]`");
+            }
+            // One failing source does not hold back the others
+            let mut first_error = None;
+            for name in &names {
+                let result = config.source(name).and_then(|source| {
+                    let options = sync_options(name, source)?;
+                    sync::run(name, source, &options, progress)
+                });
+                match result {
+                    Err(VideoConversionError::Interrupted) => return Err(VideoConversionError::Interrupted),
+                    Err(e) => {
+                        if names.len() > 1 {
+                            console!(Error, "{}: {}", name, e);
+                        }
+                        first_error.get_or_insert(e);
+                    }
+                    Ok(()) => {}
+                }
+            }
+            if let Some(e) = first_error {
+                return Err(e);
+            }
+        }
+        SyncCommand::List => sync::list(&config.sources)?,
+        SyncCommand::Remove { name } => {
+            if config.sources.remove(&name).is_none() {
+                return Err(VideoConversionError::config(format!("unknown sync source {:?}", name)));
+            }
+            config.save()?;
+            sync::forget(&name)?;
+            println!("Removed sync source {}", name);
+        }
+    }
+    Ok(())
+}
 
-            if Path::new(&video_path).exists() {
-                convert_to_quicktime_compatible_mp4(&video_path, &compatible_mp4_path)?;
+/// Install, inspect or remove a periodic download service
+fn manage_service(action: ServiceCommand) -> Result<(), VideoConversionError> {
+    match action {
+        ServiceCommand::Install { name, every, sync, args } => {
+            service::validate_name(&name)?;
+            let working_dir = std::env::current_dir().map_err(|e| VideoConversionError::io(e.to_string()))?;
+            let (command, mut args, log_file, output_dirs) = match sync {
+                // The arguments name the sources to sync, all of them when there are none
+                true => {
+                    let config = config::Config::load()?;
+                    let names = match args.is_empty() {
+                        true => config.sources.keys().cloned().collect(),
+                        false => args.clone(),
+                    };
+                    let mut output_dirs = Vec::new();
+                    for name in &names {
+                        output_dirs.push(working_dir.join(sync_options(name, config.source(name)?)?.output_dir()));
+                    }
+                    let args = match args.is_empty() {
+                        true => Vec::new(),
+                        false => std::iter::once("run".to_string()).chain(args).collect(),
+                    };
+                    ("sync", args, None, output_dirs)
+                }
+                false => {
+                    if args.iter().any(|arg| arg == "-") {
+                        return Err(VideoConversionError::config("services cannot read URLs from stdin"));
+                    }
+                    // Parse the run exactly as the service will, profiles included, so mistakes surface now
+                    let argv = ["videelow", "download"].into_iter().map(String::from).chain(args.iter().cloned());
+                    let argv = config::expand_profiles(argv.map(Into::into).collect())?;
+                    match Args::try_parse_from(argv) {
+                        Ok(Args { command: Some(Commands::Download { options, .. }), log_file, .. }) => {
+                            ("download", args, log_file, vec![working_dir.join(options.output_dir())])
+                        }
+                        Ok(_) => return Err(VideoConversionError::config("invalid download arguments")),
+                        Err(e) => {
+                            return Err(VideoConversionError::config("invalid download arguments").caused_by(clap_message(&e)));
+                        }
+                    }
+                }
+            };
 
-                // Cleanup: Delete original video file after successful re-encoding
-                remove_file(&video_path).map_err(|e| VideoConversionError::CommandError(format!("Failed to delete file: {}", e)))?;
-                println!("Original file {} deleted after re-encoding.", video_path);
-            } else {
-                return Err(VideoConversionError::FileNotFound(video_path));
+            // Unattended runs always keep a log to diagnose failures with later
+            let log_file = match log_file {
+                Some(path) => working_dir.join(path),
+                None => {
+                    let path = config::state_dir()?.join("logs").join(format!("{}.log", name));
+                    args.push("--log-file".to_string());
+                    args.push(path.to_string_lossy().into_owned());
+                    path
+                }
+            };
+            // The sandbox can only grant write access to directories that exist
+            let mut writable = Vec::new();
+            let log_dir = log_file.parent().map(PathBuf::from).unwrap_or_else(|| working_dir.clone());
+            for dir in output_dirs.into_iter().chain([config::state_dir()?, log_dir]) {
+                if writable.iter().any(|known: &PathBuf| dir.starts_with(known)) {
+                    continue;
+                }
+                std::fs::create_dir_all(&dir)
+                    .map_err(|e| VideoConversionError::io(format!("Failed to create {}", dir.display())).caused_by(e))?;
+                writable.push(dir);
             }
+
+            let spec = ServiceSpec { name: name.clone(), command, interval: every, args, working_dir, writable };
+            service::install(&spec)?;
+            println!("Service {} runs every {}", name, every);
         }
-        OutputFormat::Mp3 => {
-            // Download and process MP3 directly
-            download_youtube_audio(&args.url, &mp3_path)?;
+        ServiceCommand::Status { name } => service::status(&name)?,
+        ServiceCommand::Uninstall { name } => {
+            service::uninstall(&name)?;
+            println!("Removed service {}", name);
         }
     }
+    Ok(())
+}
 
+/// Print one job in detail, or a table of recent jobs
+fn show_status(id: Option<u64>, json: bool) -> Result<(), VideoConversionError> {
+    let records = match id {
+        Some(id) => vec![JobHandle::new(id).status()?],
+        None => jobs::list()?,
+    };
+    if json {
+        let json = match id {
+            Some(_) => serde_json::to_string_pretty(&records[0]),
+            None => serde_json::to_string_pretty(&records),
+        };
+        println!("{}", json.map_err(|e| VideoConversionError::io(e.to_string()))?);
+        return Ok(());
+    }
+
+    if id.is_none() {
+        if records.is_empty() {
+            println!("No jobs");
+            return Ok(());
+        }
+        println!("{:>4}  {:<12} {:>8}  {:>8}  {:>7}  SOURCE", "ID", "PHASE", "PROGRESS", "ELAPSED", "ITEMS");
+        for record in &records {
+            let done = record.items.iter().filter(|item| item.finished.is_some()).count();
+            println!(
+                "{:>4}  {:<12} {:>8}  {:>8}  {:>7}  {}",
+                record.id,
+                phase_label(record),
+                record.progress().map_or_else(|| "-".to_string(), |p| format!("{:.1}%", p)),
+                clock(record.elapsed().as_secs()),
+                format!("{}/{}", done, record.items.len()),
+                match record.urls.as_slice() {
+                    [] => "clipboard".to_string(),
+                    [url] => url.clone(),
+                    [url, rest @ ..] => format!("{} (+{} more)", url, rest.len()),
+                }
+            );
+        }
+        return Ok(());
+    }
+
+    let record = &records[0];
+    println!("Job {}: {}", record.id, phase_label(record));
+    if let Some(progress) = record.progress() {
+        println!("Progress: {:.1}%", progress);
+    }
+    println!("Elapsed: {}", clock(record.elapsed().as_secs()));
+    if let Some(error) = &record.error {
+        println!("Error: {}", error);
+    }
+    for (index, item) in record.items.iter().enumerate() {
+        let detail = match (&item.output, &item.error, item.progress) {
+            (Some(output), _, _) => format!(" -> {}", output),
+            (_, Some(error), _) => format!(": {}", error),
+            (_, _, Some(progress)) if item.finished.is_none() => format!(" ({:.1}%)", progress),
+            _ => String::new(),
+        };
+        println!("  {:>3}. {:<12} {}{}", index + 1, item.phase, item.url, detail);
+        if let (Some(bytes), Some(elapsed)) = (item.bytes, item.elapsed()) {
+            let speed = item.download_speed().map_or_else(String::new, |speed| format!(" at {}/s", progress::human_bytes(speed)));
+            println!("       received {}{} in {}", progress::human_bytes(bytes as f64), speed, clock(elapsed.as_secs()));
+        }
+    }
     Ok(())
-}
\ No newline at end of file
+}
+
+/// Phase of a job as shown to users, marking suspended jobs
+fn phase_label(record: &JobStatus) -> String {
+    match record.paused {
+        true => format!("{} (paused)", record.phase),
+        false => record.phase.to_string(),
+    }
+}
+
+/// Format seconds as `H:MM:SS`
+fn clock(seconds: u64) -> String {
+    format!("{}:{:02}:{:02}", seconds / 3600, seconds / 60 % 60, seconds % 60)
+}
+
+/// Replace a `-` argument with the URLs piped on stdin, one per line; blank lines and `#` comments are skipped
+fn read_stdin_urls(urls: Vec<String>) -> Result<Vec<String>, VideoConversionError> {
+    if !urls.iter().any(|url| url == "-") {
+        return Ok(urls);
+    }
+
+    let stdin_urls = std::io::stdin()
+        .lock()
+        .lines()
+        .map(|line| line.map(|l| l.trim().to_string()))
+        .filter(|line| line.as_ref().map_or(true, |l| !l.is_empty() && !l.starts_with('#')))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| VideoConversionError::config("cannot read URLs from stdin").caused_by(e))?;
+
+    let mut expanded = Vec::new();
+    let mut stdin_urls = Some(stdin_urls);
+    for url in urls {
+        if url == "-" {
+            // stdin can only be consumed once, so repeated `-` arguments add nothing
+            expanded.extend(stdin_urls.take().unwrap_or_default());
+        } else {
+            expanded.push(url);
+        }
+    }
+
+    if expanded.is_empty() {
+        return Err(VideoConversionError::config("no URLs given on stdin"));
+    }
+    Ok(expanded)
+}
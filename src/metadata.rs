@@ -0,0 +1,219 @@
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+use crate::ytdlp::YtDlpOptions;
+use crate::{command_output, logging, ytdlp_error, VideoConversionError};
+
+/// Top-level info-json fields worth keeping in sidecar files; the rest is signed URLs and internals
+const SIDECAR_FIELDS: &[&str] = &[
+    "id", "title", "fulltitle", "description", "uploader", "uploader_id", "uploader_url", "channel",
+    "channel_id", "channel_url", "channel_follower_count", "upload_date", "release_date", "timestamp",
+    "duration", "duration_string", "webpage_url", "original_url", "extractor", "extractor_key", "tags",
+    "categories", "chapters", "view_count", "like_count", "comment_count", "thumbnail", "language", "license",
+    "age_limit", "live_status", "was_live", "playlist", "playlist_id", "playlist_index", "format_id", "format",
+    "ext", "width", "height", "fps", "resolution", "vcodec", "acodec", "abr", "vbr", "tbr", "filesize",
+    "filesize_approx",
+];
+
+/// Per-format fields kept in sidecar files
+const SIDECAR_FORMAT_FIELDS: &[&str] = &[
+    "format_id", "format_note", "ext", "protocol", "width", "height", "fps", "vcodec", "acodec", "abr", "vbr",
+    "tbr", "asr", "audio_channels", "filesize", "filesize_approx", "language", "dynamic_range",
+];
+
+/// Per-comment fields kept in comment sidecars
+const COMMENT_FIELDS: &[&str] = &[
+    "id", "parent", "author", "author_id", "author_is_uploader", "text", "like_count", "timestamp", "is_pinned",
+    "is_favorited",
+];
+
+/// Subset of yt-dlp's info JSON describing a single format
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct FormatInfo {
+    pub format_id: Option<String>,
+    pub ext: Option<String>,
+    pub filesize: Option<u64>,
+    pub filesize_approx: Option<u64>,
+    /// Total bitrate in kbit/s
+    pub tbr: Option<f64>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub fps: Option<f64>,
+    pub vcodec: Option<String>,
+    pub acodec: Option<String>,
+    /// Audio bitrate in kbit/s
+    pub abr: Option<f64>,
+    /// Language of the audio, e.g. "de" or "en-US", for sites serving several dubs
+    pub language: Option<String>,
+    /// Site's description of the format, such as "German (Germany), medium"
+    pub format_note: Option<String>,
+}
+
+/// A chapter as listed in yt-dlp's info JSON
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct ChapterInfo {
+    /// Start and end in seconds
+    pub start_time: f64,
+    pub end_time: Option<f64>,
+    pub title: Option<String>,
+}
+
+/// Subset of yt-dlp's info JSON for a single video
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct VideoInfo {
+    pub id: Option<String>,
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub uploader: Option<String>,
+    pub channel: Option<String>,
+    /// Upload date as YYYYMMDD
+    pub upload_date: Option<String>,
+    /// Upload time as a Unix timestamp, when the site reports one
+    pub timestamp: Option<i64>,
+    /// Duration in seconds
+    pub duration: Option<f64>,
+    /// Name of the yt-dlp extractor, e.g. "Youtube" or "TwitchVod"
+    pub extractor_key: Option<String>,
+    pub tags: Vec<String>,
+    /// Chapters the site lists, if any
+    pub chapters: Option<Vec<ChapterInfo>>,
+    pub filesize: Option<u64>,
+    pub filesize_approx: Option<u64>,
+    pub tbr: Option<f64>,
+    /// Formats yt-dlp picked for the selector when several are merged (e.g. video+audio)
+    pub requested_formats: Option<Vec<FormatInfo>>,
+    /// Every format the site offers
+    pub formats: Vec<FormatInfo>,
+    /// The complete info JSON as returned by yt-dlp
+    #[serde(skip)]
+    pub raw: Value,
+}
+
+impl FormatInfo {
+    /// Whether the format carries a video stream
+    pub fn has_video(&self) -> bool {
+        self.vcodec.as_deref().is_some_and(|c| c != "none")
+    }
+
+    /// Whether the format carries an audio stream
+    pub fn has_audio(&self) -> bool {
+        self.acodec.as_deref().is_some_and(|c| c != "none")
+    }
+
+    /// Best known size of this format, falling back to bitrate times duration
+    pub fn estimated_size(&self, duration: Option<f64>) -> Option<u64> {
+        self.filesize
+            .or(self.filesize_approx)
+            .or_else(|| Some((self.tbr? * 1000.0 / 8.0 * duration?) as u64))
+    }
+}
+
+impl VideoInfo {
+    /// Pick the known fields out of yt-dlp's info JSON, keeping the whole document for sidecars
+    pub fn from_raw(raw: Value) -> Result<VideoInfo, VideoConversionError> {
+        let mut info: VideoInfo = serde_json::from_value(raw.clone())
+            .map_err(|e| VideoConversionError::extraction("Invalid metadata from yt-dlp").caused_by(e))?;
+        info.raw = raw;
+        Ok(info)
+    }
+
+    /// The info JSON reduced to fields useful for archival: source, description and available formats
+    pub fn sidecar_json(&self) -> Value {
+        let mut sidecar = pick_fields(&self.raw, SIDECAR_FIELDS);
+        if let Some(formats) = self.raw.get("formats").and_then(Value::as_array) {
+            let formats = formats.iter().map(|f| Value::Object(pick_fields(f, SIDECAR_FORMAT_FIELDS))).collect();
+            sidecar.insert("formats".to_string(), Value::Array(formats));
+        }
+        Value::Object(sidecar)
+    }
+
+    /// Estimated size in bytes of what yt-dlp will download for the selected format
+    pub fn estimated_download_size(&self) -> Option<u64> {
+        match &self.requested_formats {
+            Some(formats) if !formats.is_empty() => formats
+                .iter()
+                .map(|f| f.estimated_size(self.duration))
+                .sum::<Option<u64>>(),
+            _ => self
+                .filesize
+                .or(self.filesize_approx)
+                .or_else(|| Some((self.tbr? * 1000.0 / 8.0 * self.duration?) as u64)),
+        }
+    }
+}
+
+/// Query yt-dlp for the metadata of `url` as it would be downloaded with `format_selector`
+pub fn fetch_video_info(url: &str, format_selector: &str, ytdlp: &YtDlpOptions) -> Result<VideoInfo, VideoConversionError> {
+    VideoInfo::from_raw(fetch_raw_info(url, format_selector, ytdlp)?)
+}
+
+/// yt-dlp's complete info JSON for `url` as it would be downloaded with `format_selector`
+pub fn fetch_raw_info(url: &str, format_selector: &str, ytdlp: &YtDlpOptions) -> Result<Value, VideoConversionError> {
+    let output = command_output(
+        ytdlp
+            .command()
+            .arg("--dump-single-json")
+            .arg("--no-playlist")
+            .arg("-f")
+            .arg(format_selector)
+            .args(ytdlp.extra_args())
+            .arg(url)
+            .stderr(logging::child_stderr()),
+    )?;
+
+    if !output.status.success() {
+        return Err(ytdlp_error(
+            format!("yt-dlp could not read metadata (exited with {})", output.status),
+            &String::from_utf8_lossy(&output.stderr),
+        ));
+    }
+
+    serde_json::from_slice(&output.stdout)
+        .map_err(|e| VideoConversionError::extraction("Invalid metadata from yt-dlp").caused_by(e))
+}
+
+/// Up to `max` top comments on `url`, without replies, in the site's ranking
+pub fn fetch_comments(url: &str, max: u32, ytdlp: &YtDlpOptions) -> Result<Vec<Value>, VideoConversionError> {
+    let output = command_output(
+        ytdlp
+            .command()
+            .arg("--dump-single-json")
+            .arg("--no-playlist")
+            .arg("--skip-download")
+            .arg("--write-comments")
+            // Comment limits are per extractor; YouTube's is the one that matters for size
+            .arg("--extractor-args")
+            .arg(format!("youtube:max_comments={},{},0,0;comment_sort=top", max, max))
+            .args(ytdlp.extra_args())
+            .arg(url)
+            .stderr(logging::child_stderr()),
+    )?;
+
+    if !output.status.success() {
+        return Err(ytdlp_error(
+            format!("yt-dlp could not read comments (exited with {})", output.status),
+            &String::from_utf8_lossy(&output.stderr),
+        ));
+    }
+
+    let raw: Value = serde_json::from_slice(&output.stdout)
+        .map_err(|e| VideoConversionError::extraction("Invalid metadata from yt-dlp").caused_by(e))?;
+    let comments = raw.get("comments").and_then(Value::as_array).map_or(&[][..], Vec::as_slice);
+    Ok(comments
+        .iter()
+        .filter(|comment| comment.get("parent").and_then(Value::as_str).is_none_or(|parent| parent == "root"))
+        .take(max as usize)
+        .map(|comment| Value::Object(pick_fields(comment, COMMENT_FIELDS)))
+        .collect())
+}
+
+/// Copy the listed keys of a JSON object, skipping missing and null values
+fn pick_fields(value: &Value, fields: &[&str]) -> Map<String, Value> {
+    fields
+        .iter()
+        .filter_map(|&key| value.get(key).filter(|v| !v.is_null()).map(|v| (key.to_string(), v.clone())))
+        .collect()
+}
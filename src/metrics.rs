@@ -0,0 +1,158 @@
+use std::cell::Cell;
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Instant;
+
+use crate::progress::{ProgressEvent, Stage};
+use crate::{ErrorCategory, VideoConversionError};
+
+static DOWNLOADS_SUCCEEDED: AtomicU64 = AtomicU64::new(0);
+static DOWNLOADED_BYTES: AtomicU64 = AtomicU64::new(0);
+static ENCODES: AtomicU64 = AtomicU64::new(0);
+static ENCODE_MILLIS: AtomicU64 = AtomicU64::new(0);
+static QUEUE_DEPTH: AtomicI64 = AtomicI64::new(0);
+static FAILURES: Mutex<BTreeMap<&'static str, u64>> = Mutex::new(BTreeMap::new());
+
+thread_local! {
+    /// Progress of the stream this thread downloads and start of its running encode, to turn events
+    /// into totals; every concurrent download emits its events on its own thread
+    static CURRENT: Cell<(u64, Option<Instant>)> = const { Cell::new((0, None)) };
+}
+
+/// Label for the failure counter, one per error category; `None` for interruptions, which are no failures
+pub(crate) fn category(error: &VideoConversionError) -> Option<&'static str> {
+    error.category().map(|category| match category {
+        ErrorCategory::Network => "network",
+        ErrorCategory::Extraction => "extraction",
+        ErrorCategory::Encoding => "encoding",
+        ErrorCategory::Io => "io",
+        ErrorCategory::Config => "config",
+        ErrorCategory::Unsupported => "unsupported",
+    })
+}
+
+/// Count items added to the download queue
+pub fn queued(items: usize) {
+    QUEUE_DEPTH.fetch_add(items as i64, Ordering::Relaxed);
+}
+
+/// Remove items from the queue that will not be attempted
+pub fn dequeued(items: usize) {
+    QUEUE_DEPTH.fetch_sub(items as i64, Ordering::Relaxed);
+}
+
+/// Count the outcome of one queued item; an interrupted item is neither a success nor a failure
+pub fn record_result<T>(result: &Result<T, VideoConversionError>) {
+    dequeued(1);
+    match result {
+        Ok(_) => {
+            DOWNLOADS_SUCCEEDED.fetch_add(1, Ordering::Relaxed);
+        }
+        Err(e) => record_failure(e),
+    }
+}
+
+/// Count a failure that happened before anything was queued, such as an unsupported URL
+pub fn record_failure(error: &VideoConversionError) {
+    if let Some(category) = category(error) {
+        *FAILURES.lock().unwrap_or_else(|e| e.into_inner()).entry(category).or_insert(0) += 1;
+    }
+}
+
+/// Accumulate downloaded bytes and encode durations from progress events
+pub fn observe(event: &ProgressEvent) {
+    let mut current = CURRENT.get();
+    match event {
+        ProgressEvent::DownloadProgress { downloaded_bytes, .. } => {
+            // Progress restarts from zero for every stream of a download
+            let new = downloaded_bytes.checked_sub(current.0).unwrap_or(*downloaded_bytes);
+            DOWNLOADED_BYTES.fetch_add(new, Ordering::Relaxed);
+            current.0 = *downloaded_bytes;
+        }
+        ProgressEvent::StageStarted { stage: Stage::Download } => current.0 = 0,
+        ProgressEvent::StageStarted { stage: Stage::Convert } => current.1 = Some(Instant::now()),
+        ProgressEvent::StageFinished { stage: Stage::Convert } => {
+            if let Some(started) = current.1.take() {
+                ENCODES.fetch_add(1, Ordering::Relaxed);
+                ENCODE_MILLIS.fetch_add(started.elapsed().as_millis() as u64, Ordering::Relaxed);
+            }
+        }
+        _ => {}
+    }
+    CURRENT.set(current);
+}
+
+/// Current values in the Prometheus text exposition format
+pub fn render() -> String {
+    let mut out = String::new();
+    let failures = FAILURES.lock().unwrap_or_else(|e| e.into_inner()).clone();
+
+    out.push_str("# HELP videelow_downloads_total Downloads finished, by result.\n# TYPE videelow_downloads_total counter\n");
+    let _ = writeln!(out, "videelow_downloads_total{{result=\"success\"}} {}", DOWNLOADS_SUCCEEDED.load(Ordering::Relaxed));
+    let _ = writeln!(out, "videelow_downloads_total{{result=\"failed\"}} {}", failures.values().sum::<u64>());
+
+    out.push_str("# HELP videelow_failures_total Failed downloads, by error category.\n# TYPE videelow_failures_total counter\n");
+    for (category, count) in &failures {
+        let _ = writeln!(out, "videelow_failures_total{{category=\"{}\"}} {}", category, count);
+    }
+
+    out.push_str("# HELP videelow_downloaded_bytes_total Bytes received from media servers.\n# TYPE videelow_downloaded_bytes_total counter\n");
+    let _ = writeln!(out, "videelow_downloaded_bytes_total {}", DOWNLOADED_BYTES.load(Ordering::Relaxed));
+
+    out.push_str("# HELP videelow_encode_duration_seconds Time spent re-encoding videos.\n# TYPE videelow_encode_duration_seconds summary\n");
+    let _ = writeln!(out, "videelow_encode_duration_seconds_sum {}", ENCODE_MILLIS.load(Ordering::Relaxed) as f64 / 1000.0);
+    let _ = writeln!(out, "videelow_encode_duration_seconds_count {}", ENCODES.load(Ordering::Relaxed));
+
+    out.push_str("# HELP videelow_queue_depth Queued items not yet finished.\n# TYPE videelow_queue_depth gauge\n");
+    let _ = writeln!(out, "videelow_queue_depth {}", QUEUE_DEPTH.load(Ordering::Relaxed).max(0));
+    out
+}
+
+/// Serve `GET /metrics` on `addr` from a background thread for the rest of the process
+pub fn serve(addr: SocketAddr) -> Result<(), VideoConversionError> {
+    let listener = TcpListener::bind(addr)
+        .map_err(|e| VideoConversionError::config(format!("cannot listen on {}", addr)).caused_by(e))?;
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            // One slow scraper must not block the next
+            thread::spawn(move || {
+                if let Err(e) = respond(stream) {
+                    tracing::debug!(error = %e, "metrics request failed");
+                }
+            });
+        }
+    });
+    Ok(())
+}
+
+fn respond(mut stream: TcpStream) -> std::io::Result<()> {
+    let mut request_line = String::new();
+    let mut reader = BufReader::new(stream.try_clone()?);
+    reader.read_line(&mut request_line)?;
+    // Drain the headers so the client sees a clean response
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        header.clear();
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let (status, content_type, body) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/metrics")) => ("200 OK", "text/plain; version=0.0.4", render()),
+        (Some("GET"), _) => ("404 Not Found", "text/plain", "not found\n".to_string()),
+        _ => ("405 Method Not Allowed", "text/plain", "method not allowed\n".to_string()),
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
@@ -0,0 +1,329 @@
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use tracing::{debug, info, info_span};
+
+use crate::metadata::VideoInfo;
+use crate::{command_output, console, logging, run_command, VideoConversionError};
+
+/// Endpoint of AcoustID's fingerprint lookup
+const ACOUSTID_URL: &str = "https://api.acoustid.org/v2/lookup";
+
+/// Endpoint of MusicBrainz' recording search
+const MUSICBRAINZ_URL: &str = "https://musicbrainz.org/ws/2/recording";
+
+/// MusicBrainz asks every client to identify itself with a contact
+const USER_AGENT: &str = concat!("videelow/", env!("CARGO_PKG_VERSION"), " ( https://github.com/mrtngranger/videelow )");
+
+/// Lowest AcoustID score (0-1) taken as the same recording
+const MIN_ACOUSTID_SCORE: f64 = 0.8;
+
+/// Lowest MusicBrainz search score (0-100) taken as the same recording
+const MIN_SEARCH_SCORE: u32 = 90;
+
+/// Words marking a bracketed part of a video title as noise rather than part of the song's name
+const NOISE: &[&str] = &[
+    "official", "video", "audio", "lyric", "visuali", "4k", "hd", "hq", "mv", "m/v", "clip", "remaster", "explicit",
+    "full song", "music",
+];
+
+/// Tags found for a track
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct Track {
+    pub title: String,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    /// Release date as YYYY, YYYY-MM or YYYY-MM-DD
+    pub date: Option<String>,
+    /// MusicBrainz recording ID
+    pub recording_id: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Fingerprint {
+    duration: f64,
+    fingerprint: String,
+}
+
+/// An artist of a credit; MusicBrainz and AcoustID use the same shape
+#[derive(Deserialize)]
+struct Credit {
+    name: String,
+    joinphrase: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct AcoustIdOutput {
+    #[serde(default)]
+    results: Vec<AcoustIdResult>,
+}
+
+#[derive(Deserialize)]
+struct AcoustIdResult {
+    score: f64,
+    #[serde(default)]
+    recordings: Vec<AcoustIdRecording>,
+}
+
+#[derive(Deserialize)]
+struct AcoustIdRecording {
+    id: String,
+    title: Option<String>,
+    #[serde(default)]
+    artists: Vec<Credit>,
+    #[serde(default)]
+    releasegroups: Vec<ReleaseGroup>,
+}
+
+#[derive(Deserialize)]
+struct ReleaseGroup {
+    title: String,
+    #[serde(rename = "type")]
+    kind: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct SearchOutput {
+    #[serde(default)]
+    recordings: Vec<SearchRecording>,
+}
+
+#[derive(Deserialize)]
+struct SearchRecording {
+    id: String,
+    score: u32,
+    title: String,
+    #[serde(rename = "artist-credit", default)]
+    artist_credit: Vec<Credit>,
+    #[serde(default)]
+    releases: Vec<SearchRelease>,
+}
+
+#[derive(Deserialize)]
+struct SearchRelease {
+    title: String,
+    date: Option<String>,
+    #[serde(rename = "release-group")]
+    release_group: Option<SearchReleaseGroup>,
+}
+
+#[derive(Deserialize)]
+struct SearchReleaseGroup {
+    #[serde(rename = "primary-type")]
+    primary_type: Option<String>,
+}
+
+/// One name for a list of artists, joined as the credit says
+fn credit(artists: &[Credit]) -> Option<String> {
+    let mut name = String::new();
+    for (index, artist) in artists.iter().enumerate() {
+        name.push_str(&artist.name);
+        let last = index + 1 == artists.len();
+        name.push_str(artist.joinphrase.as_deref().unwrap_or(if last { "" } else { ", " }));
+    }
+    (!name.is_empty()).then_some(name)
+}
+
+/// Remove `(...)` and `[...]` parts that describe the upload rather than the song
+fn strip_noise(title: &str) -> String {
+    let mut cleaned = String::new();
+    let mut rest = title;
+    while let Some(start) = rest.find(['(', '[']) {
+        let close = if rest[start..].starts_with('(') { ')' } else { ']' };
+        let Some(length) = rest[start..].find(close) else { break };
+        let inner = rest[start + 1..start + length].to_lowercase();
+        cleaned.push_str(&rest[..start]);
+        if !NOISE.iter().any(|word| inner.contains(word)) {
+            cleaned.push_str(&rest[start..=start + length]);
+        }
+        rest = &rest[start + length + 1..];
+    }
+    cleaned.push_str(rest);
+    cleaned.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Artist and song of a video title such as "Artist - Song (Official Video) [4K]", with the
+/// uploader as artist when the title names none
+pub(crate) fn parse_title(title: &str, uploader: Option<&str>) -> Track {
+    // Channels often append their own name or a hashtag after a bar
+    let title = title.split(" | ").next().unwrap_or(title);
+    let title = strip_noise(title);
+    let separator = [" - ", " – ", " — "].into_iter().find_map(|separator| title.split_once(separator));
+    let (artist, song) = match separator {
+        Some((artist, song)) => (Some(artist.trim().to_string()), song.trim()),
+        // YouTube's auto-generated music channels are named "Artist - Topic"
+        None => {
+            let uploader = uploader.map(|uploader| uploader.trim_end_matches(" - Topic").trim_end_matches("VEVO").to_string());
+            (uploader, title.trim())
+        }
+    };
+    let song = song.trim_matches(['"', '\'', '“', '”']).trim();
+    Track { title: song.to_string(), artist: artist.filter(|artist| !artist.is_empty()), ..Track::default() }
+}
+
+/// Chromaprint fingerprint of `path` as `fpcalc` computes it
+fn fingerprint(path: &Path) -> Result<Fingerprint, VideoConversionError> {
+    let output = command_output(Command::new("fpcalc").arg("-json").arg(path).stderr(logging::child_stderr()))?;
+    if !output.status.success() {
+        return Err(VideoConversionError::io(format!("fpcalc exited with {}", output.status)));
+    }
+    serde_json::from_slice(&output.stdout)
+        .map_err(|e| VideoConversionError::io("unexpected fpcalc output").caused_by(e))
+}
+
+/// GET `url`, keeping to MusicBrainz' limit of one request per second across threads
+fn get<T: DeserializeOwned>(url: &str, params: &[(&str, &str)]) -> Result<T, VideoConversionError> {
+    static LAST: Mutex<Option<Instant>> = Mutex::new(None);
+    let failed = |reason: String| VideoConversionError::extraction("music lookup failed").caused_by(reason);
+    let unreachable = |e: reqwest::Error| VideoConversionError::network("music lookup failed").caused_by(e);
+    {
+        let mut last = LAST.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(wait) = last.map(|last| Duration::from_secs(1).saturating_sub(last.elapsed())) {
+            std::thread::sleep(wait);
+        }
+        *last = Some(Instant::now());
+    }
+    let client = reqwest::blocking::Client::builder().user_agent(USER_AGENT).build().map_err(|e| failed(e.to_string()))?;
+    let response = client.get(url).query(params).send().map_err(unreachable)?;
+    let status = response.status();
+    let body = response.text().map_err(unreachable)?;
+    if !status.is_success() {
+        return Err(failed(format!("{}: {}", status, body.trim())));
+    }
+    serde_json::from_str(&body).map_err(|e| failed(format!("unexpected response: {}", e)))
+}
+
+/// The recording AcoustID knows for `path`'s fingerprint, if it is confident
+fn lookup_fingerprint(path: &Path, key: &str) -> Result<Option<Track>, VideoConversionError> {
+    let print = fingerprint(path)?;
+    let duration = (print.duration.round() as u64).to_string();
+    let output: AcoustIdOutput = get(
+        ACOUSTID_URL,
+        &[
+            ("client", key),
+            ("meta", "recordings releasegroups compress"),
+            ("duration", &duration),
+            ("fingerprint", &print.fingerprint),
+        ],
+    )?;
+    let best = output.results.into_iter().max_by(|a, b| a.score.total_cmp(&b.score)).filter(|best| best.score >= MIN_ACOUSTID_SCORE);
+    let Some(result) = best else { return Ok(None) };
+    debug!(score = result.score, "AcoustID match");
+    let Some(recording) = result.recordings.into_iter().find(|recording| recording.title.is_some()) else { return Ok(None) };
+    // Studio albums name a track better than the compilations it also appears on
+    let album = recording
+        .releasegroups
+        .iter()
+        .find(|group| group.kind.as_deref() == Some("Album"))
+        .or(recording.releasegroups.first())
+        .map(|group| group.title.clone());
+    Ok(Some(Track {
+        title: recording.title.unwrap_or_default(),
+        artist: credit(&recording.artists),
+        album,
+        date: None,
+        recording_id: Some(recording.id),
+    }))
+}
+
+/// Quote a term for MusicBrainz' Lucene search syntax
+fn quote(term: &str) -> String {
+    format!("\"{}\"", term.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// The recording MusicBrainz finds for an artist and title, if the search is confident
+fn search(parsed: &Track) -> Result<Option<Track>, VideoConversionError> {
+    let mut query = format!("recording:{}", quote(&parsed.title));
+    if let Some(artist) = &parsed.artist {
+        query.push_str(&format!(" AND artist:{}", quote(artist)));
+    }
+    let output: SearchOutput = get(MUSICBRAINZ_URL, &[("query", &query), ("fmt", "json"), ("limit", "5")])?;
+    let Some(recording) = output.recordings.into_iter().find(|recording| recording.score >= MIN_SEARCH_SCORE) else {
+        return Ok(None);
+    };
+    debug!(score = recording.score, "MusicBrainz match");
+    // The earliest album release is the original; singles and compilations come after
+    let mut releases: Vec<&SearchRelease> = recording.releases.iter().collect();
+    releases.sort_by_key(|release| {
+        let album = release.release_group.as_ref().and_then(|group| group.primary_type.as_deref()) == Some("Album");
+        (!album, release.date.clone().filter(|date| !date.is_empty()).unwrap_or_else(|| "9999".to_string()))
+    });
+    let release = releases.first();
+    Ok(Some(Track {
+        title: recording.title,
+        artist: credit(&recording.artist_credit).or(parsed.artist.clone()),
+        album: release.map(|release| release.title.clone()),
+        date: release.and_then(|release| release.date.clone()).filter(|date| !date.is_empty()),
+        recording_id: Some(recording.id),
+    }))
+}
+
+/// Write `tags` into an MP3 in place, keeping its streams, cover art and other tags
+pub(crate) fn write_tags(path: &Path, tags: &[(&str, String)]) -> Result<(), VideoConversionError> {
+    let temp_path = path.with_extension("tags.mp3");
+    let mut command = Command::new("ffmpeg");
+    command.args(["-nostdin", "-v", "error", "-y", "-i"]).arg(path).args(["-map", "0", "-c", "copy", "-id3v2_version", "3"]);
+    for (key, value) in tags {
+        command.arg("-metadata").arg(format!("{}={}", key, value));
+    }
+    let result = run_command(command.arg(&temp_path).stderr(logging::child_stderr())).and_then(|()| {
+        fs::rename(&temp_path, path)
+            .map_err(|e| VideoConversionError::io(format!("Failed to replace {}", path.display())).caused_by(e))
+    });
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    result
+}
+
+/// Find proper tags for a downloaded track, by its AcoustID fingerprint when a key is given, else
+/// by searching MusicBrainz for the artist and title in the video's title; falls back to that
+/// cleaned-up title when neither finds the recording
+pub(crate) fn identify(path: &Path, info: &VideoInfo, acoustid_key: Option<&str>) -> Result<Track, VideoConversionError> {
+    let _span = info_span!("musicbrainz", path = %path.display()).entered();
+    if let Some(key) = acoustid_key {
+        match lookup_fingerprint(path, key) {
+            Ok(Some(track)) => return Ok(track),
+            Ok(None) => debug!("no confident AcoustID match"),
+            Err(e) => console!(Warning, "could not look up the fingerprint of {}: {}", path.display(), e),
+        }
+    }
+    let parsed = parse_title(info.title.as_deref().unwrap_or_default(), info.uploader.as_deref().or(info.channel.as_deref()));
+    if parsed.title.is_empty() {
+        return Err(VideoConversionError::config("the video has no title to search for"));
+    }
+    match search(&parsed)? {
+        Some(track) => Ok(track),
+        None => {
+            console!(Warning, "no MusicBrainz match for {:?}; tagging it from the video title", parsed.title);
+            Ok(parsed)
+        }
+    }
+}
+
+/// Tag a downloaded MP3 with what `identify` finds
+pub(crate) fn tag(path: &Path, info: &VideoInfo, acoustid_key: Option<&str>) -> Result<(), VideoConversionError> {
+    let track = identify(path, info, acoustid_key)?;
+    let mut tags = vec![("title", track.title.clone())];
+    tags.extend(track.artist.clone().map(|artist| ("artist", artist)));
+    tags.extend(track.album.clone().map(|album| ("album", album)));
+    tags.extend(track.date.clone().map(|date| ("date", date)));
+    // Written as a TXXX frame under the name Picard and beets read
+    tags.extend(track.recording_id.clone().map(|id| ("MusicBrainz Track Id", id)));
+    write_tags(path, &tags)?;
+    info!(title = %track.title, artist = ?track.artist, album = ?track.album, "tagged");
+    console!(
+        Success,
+        "Tagged {}: {} - {}{}",
+        path.display(),
+        track.artist.as_deref().unwrap_or("unknown artist"),
+        track.title,
+        track.album.as_deref().map_or_else(String::new, |album| format!(" ({})", album))
+    );
+    Ok(())
+}
@@ -0,0 +1,108 @@
+use std::fmt::Write as _;
+
+use clap::ValueEnum;
+
+use crate::dates::Date;
+use crate::metadata::VideoInfo;
+
+/// Kind of NFO document media servers expect for a library
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum, Debug)]
+pub enum NfoKind {
+    /// `<episodedetails>` with the channel as the show, for TV-style libraries
+    Episode,
+    /// `<movie>`, for movie libraries
+    Movie,
+}
+
+/// Render a Kodi-style NFO document (also read by Jellyfin, Emby and Plex agents)
+pub fn render(info: &VideoInfo, kind: NfoKind) -> String {
+    let channel = info.channel.as_ref().or(info.uploader.as_ref());
+    let date = info.upload_date.as_deref().and_then(Date::from_compact);
+    let root = match kind {
+        NfoKind::Episode => "episodedetails",
+        NfoKind::Movie => "movie",
+    };
+
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n");
+    let _ = writeln!(xml, "<{}>", root);
+    element(&mut xml, "title", info.title.as_deref());
+    if kind == NfoKind::Episode {
+        element(&mut xml, "showtitle", channel.map(String::as_str));
+    }
+    element(&mut xml, "plot", info.description.as_deref());
+    let date_tag = match kind {
+        NfoKind::Episode => "aired",
+        NfoKind::Movie => "premiered",
+    };
+    element(&mut xml, date_tag, date.map(|d| d.to_string()).as_deref());
+    element(&mut xml, "year", date.map(|d| d.year.to_string()).as_deref());
+    element(&mut xml, "studio", channel.map(String::as_str));
+    element(&mut xml, "runtime", info.duration.map(|d| ((d / 60.0).round() as u64).to_string()).as_deref());
+    for tag in &info.tags {
+        element(&mut xml, "tag", Some(tag));
+    }
+    if let Some(id) = &info.id {
+        let source = info.extractor_key.as_deref().unwrap_or("web").to_ascii_lowercase();
+        let _ = writeln!(xml, "  <uniqueid type=\"{}\" default=\"true\">{}</uniqueid>", escape(&source), escape(id));
+    }
+    let _ = writeln!(xml, "</{}>", root);
+    xml
+}
+
+/// Append `<name>value</name>` when a value is present
+fn element(xml: &mut String, name: &str, value: Option<&str>) {
+    if let Some(value) = value.filter(|v| !v.is_empty()) {
+        let _ = writeln!(xml, "  <{}>{}</{}>", name, escape(value), name);
+    }
+}
+
+/// Escape text for use in XML element content and attribute values
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            // Control characters other than whitespace are not allowed in XML 1.0
+            c if c.is_control() && !matches!(c, '\n' | '\r' | '\t') => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Read back what `render` writes: title, channel, upload date, description and ID
+pub fn parse(xml: &str) -> VideoInfo {
+    let text = |name: &str| {
+        let start = xml.find(&format!("<{}>", name))? + name.len() + 2;
+        let end = xml[start..].find(&format!("</{}>", name))? + start;
+        Some(unescape(&xml[start..end])).filter(|value| !value.is_empty())
+    };
+    let id = xml.find("<uniqueid").and_then(|start| {
+        let start = xml[start..].find('>')? + start + 1;
+        let end = xml[start..].find("</uniqueid>")? + start;
+        Some(unescape(&xml[start..end]))
+    });
+    let channel = text("showtitle").or_else(|| text("studio"));
+    VideoInfo {
+        id,
+        title: text("title"),
+        description: text("plot"),
+        uploader: channel.clone(),
+        channel,
+        upload_date: text("aired").or_else(|| text("premiered")).and_then(|date| Date::parse(&date).ok()).map(Date::compact),
+        ..VideoInfo::default()
+    }
+}
+
+/// Inverse of `escape`
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
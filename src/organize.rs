@@ -0,0 +1,287 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fs::{self, read_dir};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use serde::Deserialize;
+use tracing::{debug, info, info_span};
+
+use crate::convert_dir::VIDEO_EXTENSIONS;
+use crate::dates::Date;
+use crate::metadata::VideoInfo;
+use crate::quota::{self, AUDIO_EXTENSIONS};
+use crate::sites::sanitize_filename;
+use crate::{command_output, console, logging, nfo, VideoConversionError};
+
+/// Placeholders `--template` understands
+const PLACEHOLDERS: &[&str] = &["id", "title", "uploader", "channel", "upload_date", "year", "month", "day", "ext", "name"];
+
+/// What `organize` restructures and how
+#[derive(clap::Args, Debug, Clone)]
+pub struct OrganizeOptions {
+    /// Library folder to restructure, including its subfolders
+    dir: String,
+
+    /// Path of each file below `dir`, from {id}, {title}, {uploader}, {channel}, {upload_date},
+    /// {year}, {month}, {day}, {ext} and {name} (the current file name without extension)
+    #[arg(long, default_value = "{uploader}/{year}/{title}.{ext}")]
+    template: String,
+
+    /// Print the moves without making them
+    #[arg(long)]
+    dry_run: bool,
+}
+
+/// What is known about a file, from its sidecars and embedded tags
+#[derive(Debug, Clone, Default)]
+struct Fields {
+    id: Option<String>,
+    title: Option<String>,
+    uploader: Option<String>,
+    channel: Option<String>,
+    date: Option<Date>,
+    /// Year of uploads dated no more precisely
+    year: Option<i32>,
+}
+
+impl Fields {
+    fn from_info(info: &VideoInfo) -> Fields {
+        let date = info.upload_date.as_deref().and_then(Date::from_compact);
+        Fields {
+            id: info.id.clone(),
+            title: info.title.clone(),
+            uploader: info.uploader.clone().or_else(|| info.channel.clone()),
+            channel: info.channel.clone().or_else(|| info.uploader.clone()),
+            date,
+            year: date.map(|date| date.year),
+        }
+    }
+
+    /// Fill what is still unknown from a less trusted source
+    fn or(self, other: Fields) -> Fields {
+        Fields {
+            id: self.id.or(other.id),
+            title: self.title.or(other.title),
+            uploader: self.uploader.or(other.uploader),
+            channel: self.channel.or(other.channel),
+            date: self.date.or(other.date),
+            year: self.year.or(other.year),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.id.is_none() && self.title.is_none() && self.uploader.is_none() && self.channel.is_none() && self.year.is_none()
+    }
+}
+
+#[derive(Deserialize, Default)]
+struct ProbeOutput {
+    format: Option<ProbeFormat>,
+}
+
+#[derive(Deserialize, Default)]
+struct ProbeFormat {
+    #[serde(default)]
+    tags: BTreeMap<String, String>,
+}
+
+/// Tags muxed into the file, as yt-dlp's `--embed-metadata` and most taggers write them
+fn embedded_fields(path: &Path) -> Fields {
+    let output = command_output(
+        Command::new("ffprobe")
+            .args(["-v", "error", "-show_entries", "format_tags", "-of", "json"])
+            .arg(path)
+            .stderr(logging::child_stderr()),
+    );
+    let parsed: ProbeOutput = match output {
+        Ok(output) if output.status.success() => serde_json::from_slice(&output.stdout).unwrap_or_default(),
+        _ => return Fields::default(),
+    };
+    // Tag names differ in case between containers
+    let tags: BTreeMap<String, String> = parsed
+        .format
+        .map(|format| format.tags.into_iter().map(|(key, value)| (key.to_ascii_lowercase(), value)).collect())
+        .unwrap_or_default();
+    let tag = |names: &[&str]| names.iter().find_map(|name| tags.get(*name).filter(|value| !value.trim().is_empty()).cloned());
+    let date_tag = tag(&["date", "creation_date", "year"]);
+    let date = date_tag.as_deref().and_then(|date| Date::parse(date.get(..10).unwrap_or(date)).ok());
+    let year = date.map(|date| date.year).or_else(|| date_tag.as_deref()?.get(..4)?.parse().ok());
+    Fields {
+        id: tag(&["episode_id"]),
+        title: tag(&["title"]),
+        uploader: tag(&["artist", "album_artist", "author"]),
+        channel: tag(&["album_artist", "artist"]),
+        date,
+        year,
+    }
+}
+
+/// Metadata of `path` from `<name>.info.json`, then `<name>.nfo`, then the file's own tags
+fn read_fields(path: &Path) -> Fields {
+    let info_json = path.with_extension("info.json");
+    let from_json = fs::read_to_string(&info_json)
+        .ok()
+        .and_then(|text| serde_json::from_str(&text).ok())
+        .and_then(|raw| VideoInfo::from_raw(raw).ok())
+        .map_or_else(Fields::default, |info| Fields::from_info(&info));
+    let from_nfo = fs::read_to_string(path.with_extension("nfo"))
+        .map_or_else(|_| Fields::default(), |xml| Fields::from_info(&nfo::parse(&xml)));
+    from_json.or(from_nfo).or(embedded_fields(path))
+}
+
+/// Reject placeholders the template does not know before touching any file
+fn check_template(template: &str) -> Result<(), VideoConversionError> {
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let Some(end) = rest[start..].find('}') else { break };
+        let name = &rest[start + 1..start + end];
+        if !PLACEHOLDERS.contains(&name) {
+            return Err(VideoConversionError::config(format!(
+                "unknown placeholder {{{}}} in --template (known: {})",
+                name,
+                PLACEHOLDERS.join(", ")
+            )));
+        }
+        rest = &rest[start + end + 1..];
+    }
+    if !template.contains("{ext}") {
+        return Err(VideoConversionError::config("--template must end in {ext} to keep file types"));
+    }
+    Ok(())
+}
+
+/// Path of `path` below `dir` as `template` lays it out, each folder and file name sanitized
+fn render(template: &str, dir: &Path, path: &Path, fields: &Fields) -> PathBuf {
+    let field = |value: Option<String>| value.unwrap_or_else(|| "NA".to_string());
+    let extension = path.extension().map_or_else(String::new, |ext| ext.to_string_lossy().into_owned());
+    let name = path.file_stem().map_or_else(String::new, |stem| stem.to_string_lossy().into_owned());
+    // Placeholders are filled per component, so a `/` in a title cannot add a folder level
+    template
+        .split('/')
+        .filter(|component| !component.is_empty())
+        .map(|component| {
+            let rendered = component
+                .replace("{id}", &field(fields.id.clone()))
+                .replace("{title}", &field(fields.title.clone()))
+                .replace("{uploader}", &field(fields.uploader.clone()))
+                .replace("{channel}", &field(fields.channel.clone()))
+                .replace("{upload_date}", &field(fields.date.map(Date::compact)))
+                .replace("{year}", &field(fields.year.map(|year| year.to_string())))
+                .replace("{month}", &field(fields.date.map(|date| format!("{:02}", date.month))))
+                .replace("{day}", &field(fields.date.map(|date| format!("{:02}", date.day))))
+                .replace("{name}", &name)
+                .replace("{ext}", &extension);
+            sanitize_filename(&rendered)
+        })
+        .fold(dir.to_path_buf(), |path, component| path.join(component))
+}
+
+/// Videos and audio below `dir`, without following symbolic links to folders
+fn collect_media(dir: &Path, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    let mut entries: Vec<_> = read_dir(dir)?.collect::<Result<_, _>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        let path = entry.path();
+        if entry.file_type()?.is_dir() {
+            collect_media(&path, files)?;
+        } else if path.is_file()
+            && path.extension().and_then(|ext| ext.to_str()).is_some_and(|ext| {
+                VIDEO_EXTENSIONS.iter().chain(AUDIO_EXTENSIONS).any(|known| known.eq_ignore_ascii_case(ext))
+            })
+        {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// One file to move, with the sidecars that follow it
+struct Move {
+    from: PathBuf,
+    to: PathBuf,
+}
+
+/// Moves for `video` and its sidecars, which keep their suffix after the new name
+fn plan(video: &Path, target: &Path) -> Vec<Move> {
+    let stem = video.file_stem().map_or_else(String::new, |stem| stem.to_string_lossy().into_owned());
+    let target_stem = target.file_stem().map_or_else(String::new, |stem| stem.to_string_lossy().into_owned());
+    let target_dir = target.parent().unwrap_or(Path::new("."));
+    let mut moves = vec![Move { from: video.to_path_buf(), to: target.to_path_buf() }];
+    for sidecar in quota::sidecars(video) {
+        let name = sidecar.file_name().map_or_else(String::new, |name| name.to_string_lossy().into_owned());
+        if let Some(suffix) = name.strip_prefix(stem.as_str()) {
+            moves.push(Move { to: target_dir.join(format!("{}{}", target_stem, suffix)), from: sidecar });
+        }
+    }
+    moves
+}
+
+/// Remove the folders below `dir` that moving files out of left empty
+fn remove_empty_dirs(dir: &Path, top: bool) {
+    if let Ok(entries) = read_dir(dir) {
+        for entry in entries.flatten() {
+            if entry.file_type().is_ok_and(|file_type| file_type.is_dir()) {
+                remove_empty_dirs(&entry.path(), false);
+            }
+        }
+    }
+    if !top && fs::remove_dir(dir).is_ok() {
+        debug!(dir = %dir.display(), "removed empty folder");
+    }
+}
+
+/// Move and rename the videos below a folder, with their sidecars, into a layout built from their metadata
+pub fn run(options: &OrganizeOptions) -> Result<(), VideoConversionError> {
+    let _span = info_span!("organize", dir = %options.dir).entered();
+    check_template(&options.template)?;
+    let dir = Path::new(&options.dir);
+    let mut files = Vec::new();
+    collect_media(dir, &mut files)
+        .map_err(|e| VideoConversionError::io(format!("Failed to read {}", dir.display())).caused_by(e))?;
+
+    let mut claimed = HashSet::new();
+    let (mut moved, mut in_place, mut skipped) = (0, 0, 0);
+    for file in files {
+        let fields = read_fields(&file);
+        if fields.is_empty() {
+            console!(Warning, "no metadata for {}; leaving it in place", file.display());
+            skipped += 1;
+            continue;
+        }
+        let target = render(&options.template, dir, &file, &fields);
+        if target == file {
+            in_place += 1;
+            continue;
+        }
+        let moves = plan(&file, &target);
+        // Neither an existing file nor one moved earlier in this run is overwritten
+        if let Some(conflict) = moves.iter().find(|m| m.to.exists() || claimed.contains(&m.to)) {
+            console!(Warning, "{} already exists; leaving {} in place", conflict.to.display(), file.display());
+            skipped += 1;
+            continue;
+        }
+        for m in &moves {
+            claimed.insert(m.to.clone());
+            if options.dry_run {
+                println!("{} -> {}", m.from.display(), m.to.display());
+                continue;
+            }
+            if let Some(parent) = m.to.parent() {
+                fs::create_dir_all(parent)
+                    .map_err(|e| VideoConversionError::io(format!("Failed to create {}", parent.display())).caused_by(e))?;
+            }
+            fs::rename(&m.from, &m.to).map_err(|e| {
+                VideoConversionError::io(format!("Failed to move {} to {}", m.from.display(), m.to.display())).caused_by(e)
+            })?;
+            info!(from = %m.from.display(), to = %m.to.display(), "moved");
+            println!("Moved {} -> {}", m.from.display(), m.to.display());
+        }
+        moved += 1;
+    }
+    if !options.dry_run {
+        remove_empty_dirs(dir, true);
+    }
+    let verb = if options.dry_run { "Would move" } else { "Moved" };
+    console!(Success, "{} {} file(s); {} already in place, {} skipped", verb, moved, in_place, skipped);
+    Ok(())
+}
@@ -0,0 +1,469 @@
+use std::fs::{read_dir, remove_dir_all, remove_file};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use tracing::{error, info, info_span};
+
+use crate::backend::{Backends, Transcoder};
+use crate::codecs::Bitrate;
+use crate::filtergraph::FilterChain;
+use crate::progress::{Progress, ProgressEvent, Stage};
+use crate::sites::SiteProfile;
+use crate::timestamp::MediaTimestamp;
+use crate::ytdlp::YtDlpOptions;
+use crate::{console, diagnostics, pool, record, report, verify, AbrOptions, EncodeOptions, HlsOptions, VideoConversionError};
+
+/// State shared by the steps of one pipeline run
+pub struct PipelineContext<'a> {
+    pub progress: &'a Progress,
+    /// File produced by the most recent step, which the next step works on
+    pub current: Option<PathBuf>,
+    /// Files and directories this run created, deleted again if a step fails
+    created: Vec<PathBuf>,
+    /// Files only needed until the pipeline finishes
+    intermediates: Vec<PathBuf>,
+}
+
+impl PipelineContext<'_> {
+    /// Register a file or directory the running step is about to create, so a failure removes it
+    /// again; paths that already exist are left alone
+    pub fn track(&mut self, path: impl Into<PathBuf>) {
+        let path = path.into();
+        if !path.exists() {
+            self.created.push(path);
+        }
+    }
+
+    /// The file the running step should work on
+    pub fn input(&self) -> Result<PathBuf, VideoConversionError> {
+        match &self.current {
+            Some(path) if path.exists() => Ok(path.clone()),
+            Some(path) => Err(VideoConversionError::FileNotFound(path.display().to_string())),
+            None => Err(VideoConversionError::config("pipeline step has no input file")),
+        }
+    }
+
+    /// Hand a new output to the next step, marking the previous one for cleanup
+    pub fn replace_current(&mut self, path: impl Into<PathBuf>) {
+        if let Some(previous) = self.current.replace(path.into()) {
+            self.intermediates.push(previous);
+        }
+    }
+}
+
+/// One stage of a pipeline, such as downloading or encoding
+pub trait Step {
+    /// Stage reported in progress events while the step runs
+    fn stage(&self) -> Stage;
+
+    fn run(&self, context: &mut PipelineContext) -> Result<(), VideoConversionError>;
+
+    /// Undo side effects other than tracked files; called in reverse order when this or a later step fails
+    fn rollback(&self, _context: &PipelineContext) {}
+}
+
+/// Steps executed in order with shared progress reporting; a failing step rolls back the whole run
+#[derive(Default)]
+pub struct Pipeline<'a> {
+    steps: Vec<Box<dyn Step + 'a>>,
+}
+
+impl<'a> Pipeline<'a> {
+    pub fn new() -> Self {
+        Pipeline { steps: Vec::new() }
+    }
+
+    /// Append a step
+    pub fn then(mut self, step: impl Step + 'a) -> Self {
+        self.steps.push(Box::new(step));
+        self
+    }
+
+    /// Run every step, returning the final output
+    pub fn run(&self, progress: &Progress) -> Result<Option<PathBuf>, VideoConversionError> {
+        self.run_from(None, progress)
+    }
+
+    /// Run every step on an existing file, which stays in place even if a step fails; a `Cleanup`
+    /// step would delete it once replaced
+    pub fn run_on(&self, input: impl Into<PathBuf>, progress: &Progress) -> Result<Option<PathBuf>, VideoConversionError> {
+        self.run_from(Some(input.into()), progress)
+    }
+
+    fn run_from(&self, input: Option<PathBuf>, progress: &Progress) -> Result<Option<PathBuf>, VideoConversionError> {
+        let mut context = PipelineContext { progress, current: input, created: Vec::new(), intermediates: Vec::new() };
+
+        for (index, step) in self.steps.iter().enumerate() {
+            let _span = info_span!("step", stage = ?step.stage()).entered();
+            let _stage = diagnostics::enter_stage(step.stage());
+            let started = Instant::now();
+            info!("step started");
+            // Queues running items side by side share out the downloads and conversions
+            let result = pool::acquire(step.stage()).and_then(|_permit| {
+                progress.emit(ProgressEvent::StageStarted { stage: step.stage() });
+                step.run(&mut context)
+            });
+            if let Err(e) = result {
+                error!(error = %e, "step failed; rolling back");
+                report::record_partials(context.created.iter().chain(&context.current));
+                for step in self.steps[..=index].iter().rev() {
+                    step.rollback(&context);
+                }
+                for path in context.created.iter().rev().filter(|path| path.exists()) {
+                    let removed = if path.is_dir() { remove_dir_all(path) } else { remove_file(path) };
+                    if let Err(remove_error) = removed {
+                        console!(Warning, "could not remove {}: {}", path.display(), remove_error);
+                    }
+                }
+                return Err(e);
+            }
+            info!(elapsed_ms = started.elapsed().as_millis() as u64, "step finished");
+            progress.emit(ProgressEvent::StageFinished { stage: step.stage() });
+        }
+        Ok(context.current)
+    }
+}
+
+/// Record from capture devices into a file for the following steps to convert
+pub(crate) struct Record<'a> {
+    /// ffmpeg input arguments selecting the devices
+    pub input: Vec<String>,
+    pub output: &'a str,
+    /// Recording length; until Enter is pressed when unset
+    pub duration: Option<MediaTimestamp>,
+}
+
+impl Step for Record<'_> {
+    fn stage(&self) -> Stage {
+        Stage::Record
+    }
+
+    fn run(&self, context: &mut PipelineContext) -> Result<(), VideoConversionError> {
+        context.track(self.output);
+        record::capture(&self.input, self.output, self.duration, context.progress)?;
+        context.replace_current(self.output);
+        Ok(())
+    }
+}
+
+/// Download a video as MP4
+pub(crate) struct DownloadVideo<'a> {
+    pub url: &'a str,
+    pub output: &'a str,
+    pub site: &'a SiteProfile,
+    pub selector: &'a str,
+    pub ytdlp: &'a YtDlpOptions,
+    /// Duration from the metadata, to recognize truncated downloads
+    pub expected_duration: Option<f64>,
+    /// Times to download again when the result is corrupt
+    pub retries: u32,
+    pub backends: Backends<'a>,
+}
+
+impl Step for DownloadVideo<'_> {
+    fn stage(&self) -> Stage {
+        Stage::Download
+    }
+
+    fn run(&self, context: &mut PipelineContext) -> Result<(), VideoConversionError> {
+        context.track(self.output);
+        context.track(format!("{}.part", self.output));
+        console!(Download, "Downloading video from {} as MP4...", self.site.name);
+        fetch_verified(self.backends.transcoder, self.output, self.expected_duration, self.retries, || {
+            self.backends
+                .downloader
+                .video(self.url, self.output, self.selector, self.ytdlp, context.progress)
+                .map_err(VideoConversionError::download)
+        })?;
+        console!(Success, "Video downloaded successfully: {}", self.output);
+        context.replace_current(self.output);
+        Ok(())
+    }
+
+    fn rollback(&self, _context: &PipelineContext) {
+        remove_partial_downloads(self.output);
+    }
+}
+
+/// Download audio as MP3
+pub(crate) struct DownloadAudio<'a> {
+    pub url: &'a str,
+    pub output: &'a str,
+    pub site: &'a SiteProfile,
+    pub selector: &'a str,
+    pub bitrate: Bitrate,
+    pub ytdlp: &'a YtDlpOptions,
+    /// Duration from the metadata, to recognize truncated downloads
+    pub expected_duration: Option<f64>,
+    /// Times to download again when the result is corrupt
+    pub retries: u32,
+    pub backends: Backends<'a>,
+}
+
+impl Step for DownloadAudio<'_> {
+    fn stage(&self) -> Stage {
+        Stage::Download
+    }
+
+    fn run(&self, context: &mut PipelineContext) -> Result<(), VideoConversionError> {
+        context.track(self.output);
+        console!(Download, "Downloading audio from {} as MP3...", self.site.name);
+        fetch_verified(self.backends.transcoder, self.output, self.expected_duration, self.retries, || {
+            self.backends
+                .downloader
+                .audio(self.url, self.output, self.selector, self.bitrate, self.ytdlp, context.progress)
+                .map_err(VideoConversionError::download)
+        })?;
+        console!(Success, "Audio downloaded successfully as MP3: {}", self.output);
+        context.replace_current(self.output);
+        Ok(())
+    }
+
+    fn rollback(&self, _context: &PipelineContext) {
+        remove_partial_downloads(self.output);
+    }
+}
+
+/// Capture an HLS or DASH stream into an MP4 with ffmpeg
+pub(crate) struct DownloadStream<'a> {
+    pub url: &'a str,
+    pub output: &'a str,
+    /// Duration of the stream, unknown for live streams
+    pub expected_duration: Option<f64>,
+    /// Times to capture again when the result is corrupt
+    pub retries: u32,
+    pub transcoder: &'a dyn Transcoder,
+}
+
+impl Step for DownloadStream<'_> {
+    fn stage(&self) -> Stage {
+        Stage::Download
+    }
+
+    fn run(&self, context: &mut PipelineContext) -> Result<(), VideoConversionError> {
+        context.track(self.output);
+        console!(Download, "Capturing stream as MP4...");
+        fetch_verified(self.transcoder, self.output, self.expected_duration, self.retries, || {
+            self.transcoder
+                .capture(self.url, self.output, self.expected_duration, context.progress)
+                .map_err(VideoConversionError::download)
+        })?;
+        console!(Success, "Stream captured successfully: {}", self.output);
+        context.replace_current(self.output);
+        Ok(())
+    }
+}
+
+/// Re-encode the current file into a QuickTime-compatible MP4
+pub(crate) struct Encode<'a> {
+    pub output: &'a str,
+    pub encode: &'a EncodeOptions,
+    pub transcoder: &'a dyn Transcoder,
+}
+
+impl Step for Encode<'_> {
+    fn stage(&self) -> Stage {
+        Stage::Convert
+    }
+
+    fn run(&self, context: &mut PipelineContext) -> Result<(), VideoConversionError> {
+        let input = context.input()?;
+        context.track(self.output);
+        self.transcoder.transcode(&input.to_string_lossy(), self.output, self.encode, context.progress)?;
+        verify::check_encode(self.transcoder, &input, Path::new(self.output), self.encode)?;
+        context.replace_current(self.output);
+        Ok(())
+    }
+}
+
+/// Re-encode the current file into an HLS playlist with its segments in a directory of their own
+pub(crate) struct PackageHls<'a> {
+    pub playlist: &'a str,
+    pub encode: &'a EncodeOptions,
+    pub hls: &'a HlsOptions,
+    pub transcoder: &'a dyn Transcoder,
+}
+
+impl Step for PackageHls<'_> {
+    fn stage(&self) -> Stage {
+        Stage::Convert
+    }
+
+    fn run(&self, context: &mut PipelineContext) -> Result<(), VideoConversionError> {
+        let input = context.input()?;
+        let playlist = Path::new(self.playlist);
+        context.track(playlist.parent().unwrap_or(playlist));
+        context.track(playlist);
+        self.transcoder.package_hls(&input.to_string_lossy(), self.playlist, self.encode, self.hls, context.progress)?;
+        verify::check_encode(self.transcoder, &input, playlist, self.encode)?;
+        context.replace_current(playlist);
+        Ok(())
+    }
+}
+
+/// Re-encode the current file into several renditions behind an adaptive streaming manifest, all
+/// in a directory of their own
+pub(crate) struct PackageAbr<'a> {
+    pub manifest: &'a str,
+    pub encode: &'a EncodeOptions,
+    pub hls: &'a HlsOptions,
+    pub abr: &'a AbrOptions,
+    pub transcoder: &'a dyn Transcoder,
+}
+
+impl Step for PackageAbr<'_> {
+    fn stage(&self) -> Stage {
+        Stage::Convert
+    }
+
+    fn run(&self, context: &mut PipelineContext) -> Result<(), VideoConversionError> {
+        let input = context.input()?;
+        let manifest = Path::new(self.manifest);
+        context.track(manifest.parent().unwrap_or(manifest));
+        self.transcoder.package_abr(
+            &input.to_string_lossy(),
+            self.manifest,
+            self.encode,
+            self.hls,
+            self.abr,
+            context.progress,
+        )?;
+        verify::check_encode(self.transcoder, &input, manifest, self.encode)?;
+        context.replace_current(manifest);
+        Ok(())
+    }
+}
+
+/// Run the audio of the current file through a filter chain into a new MP3
+pub(crate) struct FilterAudio<'a> {
+    pub output: &'a str,
+    pub filters: FilterChain,
+    pub bitrate: Bitrate,
+    pub transcoder: &'a dyn Transcoder,
+}
+
+impl Step for FilterAudio<'_> {
+    fn stage(&self) -> Stage {
+        Stage::Convert
+    }
+
+    fn run(&self, context: &mut PipelineContext) -> Result<(), VideoConversionError> {
+        let input = context.input()?;
+        context.track(self.output);
+        self.transcoder
+            .filter_audio(&input.to_string_lossy(), self.output, &self.filters, self.bitrate, context.progress)
+            .map_err(VideoConversionError::conversion)?;
+        context.replace_current(self.output);
+        Ok(())
+    }
+}
+
+/// Remove the metadata of the current file, which keeps its name
+pub(crate) struct StripMetadata<'a> {
+    pub transcoder: &'a dyn Transcoder,
+}
+
+impl Step for StripMetadata<'_> {
+    fn stage(&self) -> Stage {
+        Stage::Convert
+    }
+
+    fn run(&self, context: &mut PipelineContext) -> Result<(), VideoConversionError> {
+        let input = context.input()?;
+        let extension = input.extension().map_or_else(String::new, |ext| ext.to_string_lossy().into_owned());
+        let stripped = input.with_extension(format!("stripped.{}", extension));
+        context.track(&stripped);
+        println!("Removing metadata from {}...", input.display());
+        self.transcoder
+            .strip_metadata(&input.to_string_lossy(), &stripped.to_string_lossy())
+            .map_err(VideoConversionError::conversion)?;
+        std::fs::rename(&stripped, &input).map_err(|e| {
+            VideoConversionError::io(format!("Failed to replace {}", input.display())).caused_by(e)
+        })?;
+        console!(Success, "Metadata removed: {}", input.display());
+        Ok(())
+    }
+}
+
+/// Delete files that earlier steps replaced; failures only warn, as the output is complete by now
+pub(crate) struct Cleanup;
+
+impl Step for Cleanup {
+    fn stage(&self) -> Stage {
+        Stage::Cleanup
+    }
+
+    fn run(&self, context: &mut PipelineContext) -> Result<(), VideoConversionError> {
+        for path in context.intermediates.drain(..) {
+            match remove_file(&path) {
+                Ok(()) => console!(Success, "Original file {} deleted after re-encoding.", path.display()),
+                Err(e) => console!(Warning, "could not delete {}: {}", path.display(), e),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Pause before downloading again after a network failure, growing with each attempt
+const NETWORK_RETRY_DELAY: Duration = Duration::from_secs(2);
+
+/// Run `download` until its output parses and is as long as expected, deleting a corrupt file
+/// before each of up to `retries` further attempts; a network failure gets the same attempts after
+/// a pause, keeping the partial file for yt-dlp to resume
+fn fetch_verified(
+    transcoder: &dyn Transcoder,
+    output: &str,
+    expected_duration: Option<f64>,
+    retries: u32,
+    mut download: impl FnMut() -> Result<(), VideoConversionError>,
+) -> Result<(), VideoConversionError> {
+    let mut attempt = 0;
+    loop {
+        match download() {
+            Ok(()) => {}
+            Err(e) if e.is_retryable() && attempt < retries => {
+                attempt += 1;
+                console!(Warning, "{}; downloading again ({}/{})", e, attempt, retries);
+                std::thread::sleep(NETWORK_RETRY_DELAY * attempt);
+                continue;
+            }
+            Err(e) => return Err(e),
+        }
+        let Some(problem) = verify::download_problem(transcoder, Path::new(output), expected_duration) else {
+            return Ok(());
+        };
+        if attempt == retries {
+            return Err(VideoConversionError::extraction(format!("{} is corrupt", output)).caused_by(problem));
+        }
+        attempt += 1;
+        console!(Warning, "{} is corrupt ({}); downloading again ({}/{})", output, problem, attempt, retries);
+        // yt-dlp skips files that already exist
+        if let Err(e) = remove_file(output) {
+            return Err(VideoConversionError::io(format!("Failed to remove {}", output)).caused_by(e));
+        }
+        remove_partial_downloads(output);
+    }
+}
+
+/// Remove what an interrupted yt-dlp leaves next to `output`: `.part` and `.ytdl` files and the
+/// separately downloaded format streams (`name.f137.mp4`) it had yet to merge
+fn remove_partial_downloads(output: &str) {
+    let path = Path::new(output);
+    let Some(stem) = path.file_stem().and_then(|stem| stem.to_str()) else { return };
+    let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let Ok(entries) = read_dir(dir) else { return };
+
+    for entry in entries.flatten() {
+        let name = entry.file_name();
+        let Some(rest) = name.to_str().and_then(|name| name.strip_prefix(stem)?.strip_prefix('.')) else { continue };
+        let format_stream = rest
+            .strip_prefix('f')
+            .and_then(|rest| rest.split_once('.'))
+            .is_some_and(|(id, _)| !id.is_empty() && id.chars().all(|c| c.is_ascii_digit()));
+        if format_stream || rest.ends_with(".part") || rest.ends_with(".ytdl") || rest.contains(".part-Frag") {
+            if let Err(e) = remove_file(entry.path()) {
+                console!(Warning, "could not remove {}: {}", entry.path().display(), e);
+            }
+        }
+    }
+}
@@ -0,0 +1,172 @@
+use std::io::IsTerminal;
+
+use dialoguer::MultiSelect;
+
+use crate::filters::ItemFilter;
+use crate::sites::{self, Site};
+use crate::ytdlp::YtDlpOptions;
+use crate::{command_output, console, logging, urls, youtube_api, ytdlp_error, VideoConversionError};
+
+/// One item of a playlist or channel listing
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlaylistEntry {
+    /// 1-based position in the playlist
+    pub index: usize,
+    pub url: String,
+    pub title: Option<String>,
+}
+
+/// Which playlist entries to fetch and in which order, mapped onto yt-dlp's own options
+#[derive(Debug, Clone, Default)]
+pub struct PlaylistSelection {
+    /// Item spec such as `1-10,15,20-`
+    pub items: Option<String>,
+    pub reverse: bool,
+    pub filter: ItemFilter,
+    /// Whether the listing is ordered newest first (channel uploads), so a date cutoff can end it early
+    pub newest_first: bool,
+}
+
+/// Validate a `--playlist-items` spec: comma-separated indices and `start-end` ranges with optional ends
+pub fn parse_items_spec(spec: &str) -> Result<String, String> {
+    let index = |s: &str| s.is_empty() || s.parse::<u32>().is_ok_and(|n| n > 0);
+    let valid = !spec.is_empty()
+        && spec.split(',').all(|part| match part.split_once('-') {
+            Some((start, end)) => index(start) && index(end) && !(start.is_empty() && end.is_empty()),
+            None => !part.is_empty() && index(part),
+        });
+    if valid {
+        Ok(spec.to_string())
+    } else {
+        Err(format!("invalid playlist items {:?} (expected e.g. 1-10,15,20-)", spec))
+    }
+}
+
+/// Whether the 1-based `index` is picked by a `--playlist-items` spec
+pub fn item_selected(spec: &str, index: usize) -> bool {
+    spec.split(',').any(|part| {
+        let bound = |s: &str| s.parse::<usize>().ok();
+        match part.split_once('-') {
+            Some((start, end)) => bound(start).is_none_or(|start| index >= start) && bound(end).is_none_or(|end| index <= end),
+            None => bound(part) == Some(index),
+        }
+    })
+}
+
+/// List the entries of a playlist or channel without extracting each video; YouTube listings go
+/// through the Data API when `api_key` is given, falling back to yt-dlp where the API cannot help
+pub fn list_entries(
+    url: &str,
+    selection: &PlaylistSelection,
+    ytdlp: &YtDlpOptions,
+    api_key: Option<&str>,
+) -> Result<Vec<PlaylistEntry>, VideoConversionError> {
+    if let Some(key) = api_key.filter(|_| sites::profile_for(url).site == Site::YouTube) {
+        let playlist_id = urls::normalize(url).ok().and_then(|source| source.playlist_id);
+        match youtube_api::list_entries(url, playlist_id.as_deref(), selection, key) {
+            Ok(Some(entries)) => return Ok(entries),
+            Ok(None) => {}
+            Err(e) => console!(Warning, "{}; listing with yt-dlp instead", e),
+        }
+    }
+    let mut command = ytdlp.command();
+    // Flat listings are fast but carry no upload dates, so date filters need full extraction
+    if !selection.filter.needs_full_extraction() {
+        command.arg("--flat-playlist");
+    }
+    command
+        .arg("--print")
+        .arg("%(playlist_index)s\t%(webpage_url,url)s\t%(title)s")
+        .args(selection.filter.ytdlp_args());
+    if let (Some(since), true) = (selection.filter.since, selection.newest_first) {
+        command.arg("--break-match-filters").arg(format!("upload_date>={}", since.compact()));
+    }
+    if let Some(items) = &selection.items {
+        // yt-dlp spells open-ended ranges with a colon
+        command.arg("--playlist-items").arg(items.replace('-', ":"));
+    }
+    if selection.reverse {
+        command.arg("--playlist-reverse");
+    }
+    let output = command_output(command.args(ytdlp.extra_args()).arg(url).stderr(logging::child_stderr()))?;
+
+    if !output.status.success() {
+        return Err(ytdlp_error(
+            format!("yt-dlp could not list playlist entries (exited with {})", output.status),
+            &String::from_utf8_lossy(&output.stderr),
+        ));
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let entries = stdout
+        .lines()
+        .enumerate()
+        .filter_map(|(position, line)| {
+            let mut fields = line.splitn(3, '\t');
+            let index = fields.next()?.parse().unwrap_or(position + 1);
+            let url = fields.next().filter(|u| *u != "NA")?.to_string();
+            let title = fields.next().filter(|t| *t != "NA").map(str::to_string);
+            Some(PlaylistEntry { index, url, title })
+        })
+        .collect();
+    Ok(entries)
+}
+
+/// Title of the playlist `url` belongs to, if it is one
+pub fn title(url: &str, ytdlp: &YtDlpOptions) -> Option<String> {
+    let mut command = ytdlp.command();
+    command.args(["--flat-playlist", "--playlist-items", "1", "--print", "playlist_title"]);
+    let output = command_output(command.args(ytdlp.extra_args()).arg(url).stderr(logging::child_stderr())).ok()?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let title = stdout.lines().next()?.trim();
+    (output.status.success() && !title.is_empty() && title != "NA").then(|| title.to_string())
+}
+
+/// Let the user tick the entries to download; returns the chosen entries in playlist order
+pub fn select_interactively(entries: Vec<PlaylistEntry>) -> Result<Vec<PlaylistEntry>, VideoConversionError> {
+    if !std::io::stdin().is_terminal() {
+        return Err(VideoConversionError::config("--interactive requires a terminal"));
+    }
+
+    let labels: Vec<String> = entries
+        .iter()
+        .map(|e| format!("{:>3}. {}", e.index, e.title.as_deref().unwrap_or(&e.url)))
+        .collect();
+    let defaults = vec![true; entries.len()];
+    let chosen = MultiSelect::new()
+        .with_prompt("Select videos to download (space toggles, enter confirms)")
+        .items(&labels)
+        .defaults(&defaults)
+        .interact()
+        .map_err(|e| VideoConversionError::config("selection aborted").caused_by(e))?;
+
+    Ok(entries
+        .into_iter()
+        .enumerate()
+        .filter(|(position, _)| chosen.contains(position))
+        .map(|(_, entry)| entry)
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validates_items_specs() {
+        for spec in ["1", "1-10,15,20-", "-5", "3-"] {
+            assert_eq!(parse_items_spec(spec).as_deref(), Ok(spec));
+        }
+        for spec in ["", "0", "-", "1,,2", "a-3", "1-2-3"] {
+            assert!(parse_items_spec(spec).is_err(), "{:?}", spec);
+        }
+    }
+
+    #[test]
+    fn selects_items_by_spec() {
+        let selected = |spec: &str| (1..=25).filter(|&index| item_selected(spec, index)).collect::<Vec<_>>();
+        assert_eq!(selected("1-3,15,24-"), [1, 2, 3, 15, 24, 25]);
+        assert_eq!(selected("-2"), [1, 2]);
+        assert_eq!(selected("7"), [7]);
+    }
+}
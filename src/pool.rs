@@ -0,0 +1,137 @@
+use std::sync::{Arc, Condvar, Mutex, RwLock};
+use std::thread;
+use std::time::Duration;
+
+use crate::progress::Stage;
+use crate::{shutdown, VideoConversionError};
+
+/// A counting semaphore
+struct Slots {
+    max: usize,
+    used: Mutex<usize>,
+    freed: Condvar,
+}
+
+impl Slots {
+    fn new(max: usize) -> Slots {
+        Slots { max, used: Mutex::new(0), freed: Condvar::new() }
+    }
+
+    /// Wait for a free slot, giving up when shutdown is requested
+    fn acquire(&self) -> Result<(), VideoConversionError> {
+        let mut used = self.used.lock().unwrap_or_else(|e| e.into_inner());
+        while *used >= self.max {
+            shutdown::check()?;
+            // Shutdown does not notify the condition variable, so look again now and then
+            used = self.freed.wait_timeout(used, Duration::from_millis(200)).unwrap_or_else(|e| e.into_inner()).0;
+        }
+        *used += 1;
+        Ok(())
+    }
+
+    fn release(&self) {
+        *self.used.lock().unwrap_or_else(|e| e.into_inner()) -= 1;
+        self.freed.notify_one();
+    }
+}
+
+/// How many pipeline steps of each stage may run at once
+struct Limits {
+    download: Slots,
+    convert: Slots,
+}
+
+impl Limits {
+    fn slots(&self, stage: Stage) -> Option<&Slots> {
+        match stage {
+            Stage::Download => Some(&self.download),
+            Stage::Convert => Some(&self.convert),
+            Stage::Record | Stage::Cleanup => None,
+        }
+    }
+}
+
+/// Limits in force for pipelines started by the running download queue
+static ACTIVE: RwLock<Option<Arc<Limits>>> = RwLock::new(None);
+
+/// Lifts the limits when dropped
+pub(crate) struct LimitGuard(());
+
+impl Drop for LimitGuard {
+    fn drop(&mut self) {
+        *ACTIVE.write().unwrap_or_else(|e| e.into_inner()) = None;
+    }
+}
+
+/// Let pipelines on other threads download one at a time and convert `converts` at a time until
+/// the guard is dropped
+pub(crate) fn limit(converts: usize) -> LimitGuard {
+    let limits = Limits { download: Slots::new(1), convert: Slots::new(converts.max(1)) };
+    *ACTIVE.write().unwrap_or_else(|e| e.into_inner()) = Some(Arc::new(limits));
+    LimitGuard(())
+}
+
+/// A running step's claim on its stage, given back when dropped
+pub(crate) struct Permit {
+    limits: Arc<Limits>,
+    stage: Stage,
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        if let Some(slots) = self.limits.slots(self.stage) {
+            slots.release();
+        }
+    }
+}
+
+/// Wait until a step of `stage` may run; `None` when nothing is limited
+pub(crate) fn acquire(stage: Stage) -> Result<Option<Permit>, VideoConversionError> {
+    let Some(limits) = ACTIVE.read().unwrap_or_else(|e| e.into_inner()).clone() else { return Ok(None) };
+    let Some(slots) = limits.slots(stage) else { return Ok(None) };
+    slots.acquire()?;
+    Ok(Some(Permit { limits, stage }))
+}
+
+/// Physical CPU cores, which simultaneous multithreading doubles in `available_parallelism`
+fn physical_cores() -> usize {
+    #[cfg(target_os = "linux")]
+    if let Ok(cpuinfo) = std::fs::read_to_string("/proc/cpuinfo") {
+        // Each core is listed once per hardware thread, under its package's physical id
+        let mut cores = std::collections::HashSet::new();
+        let mut package = "";
+        for (key, value) in cpuinfo.lines().filter_map(|line| line.split_once(':')) {
+            match key.trim() {
+                "physical id" => package = value.trim(),
+                "core id" => {
+                    cores.insert((package, value.trim()));
+                }
+                _ => {}
+            }
+        }
+        if !cores.is_empty() {
+            return cores.len();
+        }
+    }
+    #[cfg(target_os = "macos")]
+    if let Some(cores) = std::process::Command::new("sysctl")
+        .args(["-n", "hw.physicalcpu"])
+        .stderr(std::process::Stdio::null())
+        .output()
+        .ok()
+        .and_then(|output| String::from_utf8_lossy(&output.stdout).trim().parse().ok())
+    {
+        return cores;
+    }
+    thread::available_parallelism().map_or(1, |n| n.get())
+}
+
+/// Conversions to run at once when none were requested: one per hardware encoder, as a second
+/// session on the same chip only splits its throughput, otherwise half the physical cores, as
+/// each x264 process already keeps a couple of cores busy
+pub(crate) fn default_converts(hardware: bool) -> usize {
+    match hardware {
+        true => 1,
+        false => (physical_cores() / 2).max(1),
+    }
+}
@@ -0,0 +1,125 @@
+use std::path::Path;
+use std::process::Command;
+
+use clap::ValueEnum;
+use tracing::info_span;
+
+use crate::filtergraph::{Concat, FilterChain, FilterGraph, Fps, Scale};
+use crate::timestamp::MediaTimestamp;
+use crate::{console, logging, run_command, verify, VideoConversionError};
+
+/// Frame rate of previews; smooth enough for a hover preview at a fraction of the size
+const PREVIEW_FPS: f64 = 12.0;
+
+/// Image format of animated previews
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum, Debug)]
+pub enum PreviewFormat {
+    /// Animated WebP, which every current browser shows
+    Webp,
+    /// Animated AVIF, about half the size of WebP but slow to encode
+    Avif,
+}
+
+impl PreviewFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            PreviewFormat::Webp => "webp",
+            PreviewFormat::Avif => "avif",
+        }
+    }
+
+    /// Encoder arguments for a looping animation
+    fn encoder_args(self) -> &'static [&'static str] {
+        match self {
+            PreviewFormat::Webp => &["-c:v", "libwebp", "-quality", "60", "-compression_level", "4", "-loop", "0"],
+            PreviewFormat::Avif => {
+                &["-c:v", "libaom-av1", "-crf", "40", "-cpu-used", "8", "-row-mt", "1", "-pix_fmt", "yuv420p", "-loop", "0"]
+            }
+        }
+    }
+}
+
+/// Short animated previews of downloaded videos
+#[derive(clap::Args, Debug, Clone)]
+pub struct PreviewOptions {
+    /// Write a short, scaled-down animated preview next to each video as {name}.preview.webp or
+    /// .avif, for hover previews in self-hosted galleries
+    #[arg(long, value_enum, value_name = "FORMAT", num_args = 0..=1, default_missing_value = "webp")]
+    preview: Option<PreviewFormat>,
+
+    /// Length of the preview in seconds
+    #[arg(long, value_name = "SECONDS", default_value_t = 3, value_parser = clap::value_parser!(u32).range(1..=60), requires = "preview")]
+    preview_duration: u32,
+
+    /// Clips sampled evenly across the video, sharing the preview's length; 1 takes its start
+    #[arg(long, value_name = "N", default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..=20), requires = "preview")]
+    preview_segments: u32,
+
+    /// Width of the preview in pixels; the height follows the video's aspect ratio
+    #[arg(long, value_name = "PIXELS", default_value_t = 320, value_parser = clap::value_parser!(u32).range(16..=1920), requires = "preview")]
+    preview_width: u32,
+}
+
+impl PreviewOptions {
+    /// Whether previews were requested
+    pub fn enabled(&self) -> bool {
+        self.preview.is_some()
+    }
+}
+
+/// Start and length in seconds of the clips a preview of a `duration` second video is made of:
+/// the start, or clips from the middle of equal slices, which skips intros and end screens
+fn clips(duration: Option<f64>, options: &PreviewOptions) -> Vec<(f64, f64)> {
+    let length = f64::from(options.preview_duration);
+    match duration {
+        Some(duration) if options.preview_segments > 1 && duration > length => {
+            let count = options.preview_segments;
+            let clip = length / f64::from(count);
+            (0..count)
+                .map(|index| {
+                    let middle = duration * (f64::from(index) + 0.5) / f64::from(count);
+                    ((middle - clip / 2.0).clamp(0.0, duration - clip), clip)
+                })
+                .collect()
+        }
+        _ => vec![(0.0, length)],
+    }
+}
+
+/// Write an animated preview of `video` next to it, if one was requested
+pub(crate) fn write(video: &Path, options: &PreviewOptions) -> Result<(), VideoConversionError> {
+    let Some(format) = options.preview else { return Ok(()) };
+    let output = video.with_extension(format!("preview.{}", format.extension()));
+    let _span = info_span!("preview", output = %output.display()).entered();
+    let probe = verify::ffprobe(video)?;
+    if probe.video_streams == 0 {
+        return Err(VideoConversionError::config(format!("{} has no video", video.display())));
+    }
+
+    let clips = clips(probe.duration, options);
+    // Seeking each clip as its own input is far faster than decoding up to it
+    let mut command = Command::new("ffmpeg");
+    command.args(["-nostats", "-y"]);
+    for (start, length) in &clips {
+        let start = MediaTimestamp::from_secs_f64(*start).unwrap_or(MediaTimestamp::ZERO);
+        command.arg("-ss").arg(start.to_ffmpeg()).arg("-t").arg(format!("{:.3}", length)).arg("-i").arg(video);
+    }
+    let inputs: Vec<String> = (0..clips.len()).map(|index| format!("{}:v:0", index)).collect();
+    let inputs: Vec<&str> = inputs.iter().map(String::as_str).collect();
+    let mut chain = FilterChain::new();
+    if clips.len() > 1 {
+        chain = chain.then(Concat { segments: clips.len() });
+    }
+    let chain = chain.then(Fps(PREVIEW_FPS)).then(Scale::to_width(options.preview_width));
+    let graph = FilterGraph::new().chain(&inputs, chain, &["preview"]);
+    command
+        .args(graph.args())
+        .args(["-map", "[preview]", "-an"])
+        .args(format.encoder_args())
+        .arg(&output)
+        .stderr(logging::child_stderr());
+    run_command(&mut command).map_err(VideoConversionError::conversion)?;
+
+    console!(Success, "Preview saved: {}", output.display());
+    Ok(())
+}
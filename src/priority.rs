@@ -0,0 +1,36 @@
+use std::process::Command;
+
+/// Run `command` at a lower CPU priority: a Unix nice value from 0 (unchanged) to 19 (lowest),
+/// mapped onto the nearest process priority class on Windows
+pub fn set_niceness(command: &mut Command, nice: u8) {
+    if nice > 0 {
+        apply(command, nice);
+    }
+}
+
+#[cfg(unix)]
+fn apply(command: &mut Command, nice: u8) {
+    use std::os::unix::process::CommandExt;
+
+    // SAFETY: setpriority is async-signal-safe and only affects the freshly forked child
+    unsafe {
+        command.pre_exec(move || {
+            // Lowering priority is always permitted, so a failure here is not worth aborting over
+            libc::setpriority(libc::PRIO_PROCESS, 0, nice.into());
+            Ok(())
+        });
+    }
+}
+
+#[cfg(windows)]
+fn apply(command: &mut Command, nice: u8) {
+    use std::os::windows::process::CommandExt;
+
+    const BELOW_NORMAL_PRIORITY_CLASS: u32 = 0x0000_4000;
+    const IDLE_PRIORITY_CLASS: u32 = 0x0000_0040;
+
+    command.creation_flags(if nice >= 15 { IDLE_PRIORITY_CLASS } else { BELOW_NORMAL_PRIORITY_CLASS });
+}
+
+#[cfg(not(any(unix, windows)))]
+fn apply(_command: &mut Command, _nice: u8) {}
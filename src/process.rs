@@ -0,0 +1,160 @@
+use std::collections::VecDeque;
+use std::io::{self, ErrorKind, Read, Write};
+use std::process::{Child, ChildStderr, ChildStdin, ChildStdout, Command, ExitStatus, Output, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+use tracing::{debug, Span};
+
+use crate::diagnostics::{self, ToolRun};
+use crate::{jobs, report, shutdown, VideoConversionError};
+
+/// A running external tool that is killed, together with everything it spawned, when dropped
+/// before it exited; a panic or an early return can therefore never leave an orphaned ffmpeg behind
+pub(crate) struct ChildProcess {
+    child: Child,
+    exited: bool,
+    stderr: Option<JoinHandle<()>>,
+    /// Last lines of stderr, quoted should the tool fail
+    stderr_tail: Arc<Mutex<VecDeque<String>>>,
+    run: ToolRun,
+    report: Option<report::RunHandle>,
+    _job: jobs::ChildGuard,
+    _shutdown: shutdown::Registration,
+}
+
+impl ChildProcess {
+    /// Start an external tool; it never reads the terminal and is left alone by terminal signals,
+    /// as shutdown stops it instead
+    pub fn spawn(command: &mut Command) -> Result<ChildProcess, VideoConversionError> {
+        ChildProcess::spawn_with_stdin(command, Stdio::null())
+    }
+
+    /// Start an external tool like `spawn`, but with `stdin` instead of an empty input, for tools
+    /// controlled through it such as ffmpeg, which stops cleanly on `q`
+    pub fn spawn_with_stdin(command: &mut Command, stdin: Stdio) -> Result<ChildProcess, VideoConversionError> {
+        shutdown::check()?;
+        let program = command.get_program().to_string_lossy().into_owned();
+        debug!(command = ?command, "running {}", program);
+        shutdown::prepare(command);
+        let mut child = command.stdin(stdin).spawn().map_err(|e| match e.kind() {
+            ErrorKind::NotFound => VideoConversionError::ToolNotFound(program.clone()),
+            _ => VideoConversionError::io(e.to_string()),
+        })?;
+        let report = report::record_command(command);
+        let stderr_tail = Arc::new(Mutex::new(VecDeque::new()));
+        let stderr = child.stderr.take().map(|stderr| forward_stderr(stderr, program, report.clone(), stderr_tail.clone()));
+        Ok(ChildProcess {
+            _job: jobs::ChildGuard::new(child.id()),
+            _shutdown: shutdown::register(&child),
+            child,
+            exited: false,
+            stderr,
+            stderr_tail,
+            run: ToolRun::new(command),
+            report,
+        })
+    }
+
+    /// Take the piped standard input
+    pub fn stdin(&mut self) -> Option<ChildStdin> {
+        self.child.stdin.take()
+    }
+
+    /// Take the piped standard output
+    pub fn stdout(&mut self) -> Option<ChildStdout> {
+        self.child.stdout.take()
+    }
+
+    pub fn wait(&mut self) -> io::Result<ExitStatus> {
+        let status = self.child.wait()?;
+        self.exited = true;
+        // Log the tool's last words before whatever the caller logs about its exit
+        if let Some(stderr) = self.stderr.take() {
+            let _ = stderr.join();
+        }
+        if let Some(run) = &self.report {
+            report::record_exit(run, status);
+        }
+        if !status.success() && !shutdown::requested() {
+            self.run.failed(status, self.stderr_tail());
+        }
+        Ok(status)
+    }
+
+    /// Last lines the tool wrote to stderr, complete once it exited
+    pub fn stderr_tail(&self) -> Vec<String> {
+        self.stderr_tail.lock().unwrap_or_else(|e| e.into_inner()).iter().cloned().collect()
+    }
+
+    /// Read the piped standard output to the end and wait for the exit; the output's stderr holds
+    /// the last lines the tool wrote there
+    pub fn wait_with_output(mut self) -> io::Result<Output> {
+        let mut stdout = Vec::new();
+        if let Some(mut pipe) = self.stdout() {
+            pipe.read_to_end(&mut stdout)?;
+        }
+        let status = self.wait()?;
+        Ok(Output { status, stdout, stderr: self.stderr_tail().join("\n").into_bytes() })
+    }
+}
+
+impl Drop for ChildProcess {
+    fn drop(&mut self) {
+        if !self.exited {
+            shutdown::kill(&mut self.child);
+            // Reap the child so it does not linger as a zombie
+            let _ = self.child.wait();
+        }
+    }
+}
+
+/// Copy piped stderr to our own as it arrives, so progress lines still update in place, and log
+/// and report every complete line and keep the last ones in `tail`; segments ended by a carriage
+/// return are progress updates and are not recorded
+fn forward_stderr(
+    mut stderr: ChildStderr,
+    program: String,
+    report: Option<report::RunHandle>,
+    tail: Arc<Mutex<VecDeque<String>>>,
+) -> JoinHandle<()> {
+    let span = Span::current();
+    thread::spawn(move || {
+        let _span = span.entered();
+        let mut buffer = [0; 8192];
+        let mut line = Vec::new();
+        let record = |line: &[u8]| {
+            let line = String::from_utf8_lossy(line);
+            let line = line.trim_end();
+            debug!(target: "videelow::stderr", program = %program, "{}", line);
+            if let Some(run) = &report {
+                report::record_stderr(run, line);
+            }
+            let mut tail = tail.lock().unwrap_or_else(|e| e.into_inner());
+            if tail.len() == diagnostics::STDERR_EXCERPT_LINES {
+                tail.pop_front();
+            }
+            tail.push_back(line.to_string());
+        };
+        loop {
+            let read = match stderr.read(&mut buffer) {
+                Ok(0) | Err(_) => break,
+                Ok(read) => read,
+            };
+            let _ = io::stderr().write_all(&buffer[..read]);
+            for &byte in &buffer[..read] {
+                match byte {
+                    b'\n' => {
+                        record(&line);
+                        line.clear();
+                    }
+                    b'\r' => line.clear(),
+                    _ => line.push(byte),
+                }
+            }
+        }
+        if !line.is_empty() {
+            record(&line);
+        }
+    })
+}
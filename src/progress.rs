@@ -0,0 +1,519 @@
+use std::cell::Cell;
+use std::fs::File;
+use std::io::{self, Write};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+use crate::logging::{paint, Tone};
+use crate::VideoConversionError;
+
+/// Marker prefix of the machine-readable progress lines requested from yt-dlp
+pub const YTDLP_PROGRESS_PREFIX: &str = "videelow-progress";
+
+/// Progress template handed to yt-dlp; fields that are unknown are rendered as "NA"
+pub fn ytdlp_progress_template() -> String {
+    format!(
+        "download:{} %(progress.downloaded_bytes)s %(progress.total_bytes)s %(progress.total_bytes_estimate)s %(progress.speed)s %(progress.eta)s",
+        YTDLP_PROGRESS_PREFIX
+    )
+}
+
+/// Pipeline stages reported in progress events
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Stage {
+    Record,
+    Download,
+    Convert,
+    Cleanup,
+}
+
+/// A single machine-readable progress event, serialized as one JSON line
+#[derive(Serialize, Debug, Clone)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum ProgressEvent {
+    ItemStarted {
+        index: usize,
+        total: usize,
+        url: String,
+    },
+    StageStarted {
+        stage: Stage,
+    },
+    DownloadProgress {
+        downloaded_bytes: u64,
+        total_bytes: Option<u64>,
+        percent: Option<f64>,
+        speed: Option<f64>,
+        eta: Option<u64>,
+    },
+    ConvertProgress {
+        processed_seconds: f64,
+        total_seconds: Option<f64>,
+        frame: Option<u64>,
+        percent: Option<f64>,
+        speed: Option<f64>,
+        eta: Option<u64>,
+    },
+    StageFinished {
+        stage: Stage,
+    },
+    Finished {
+        output: String,
+    },
+    Failed {
+        error: String,
+        exit_code: u8,
+    },
+    /// Overall state while several items run at once, following the events of the single items
+    Aggregate {
+        /// Items finished, successfully or not, and queued in total
+        finished: usize,
+        total: usize,
+        /// Bytes received for all items so far
+        downloaded_bytes: u64,
+        /// Overall progress, counting the download and conversion of an item as one half each
+        percent: Option<f64>,
+        eta: Option<u64>,
+        running: Vec<ItemProgress>,
+    },
+}
+
+/// Progress of one of several items running at once
+#[derive(Serialize, Debug, Clone)]
+pub struct ItemProgress {
+    /// 1-based position in the queue, as in `item_started`
+    pub index: usize,
+    pub url: String,
+    pub stage: Option<Stage>,
+    /// Progress of the running stage in percent, when known
+    pub percent: Option<f64>,
+    pub downloaded_bytes: u64,
+    pub eta: Option<u64>,
+    /// Progress of the stream being downloaded, which restarts from zero for every stream
+    #[serde(skip)]
+    stream_bytes: u64,
+}
+
+impl ItemProgress {
+    /// Share of the item done, counting download and conversion as one half each
+    fn fraction(&self) -> f64 {
+        let percent = self.percent.unwrap_or(0.0) / 100.0;
+        match self.stage {
+            Some(Stage::Convert) | Some(Stage::Cleanup) => 0.5 + percent / 2.0,
+            _ => percent / 2.0,
+        }
+    }
+}
+
+/// Minimum time between aggregate events and status lines caused by progress alone
+const AGGREGATE_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Items of a parallel run and when the aggregate was last reported
+struct Aggregate {
+    total: usize,
+    finished: usize,
+    downloaded_bytes: u64,
+    running: Vec<ItemProgress>,
+    started: Instant,
+    reported: Option<Instant>,
+}
+
+impl Aggregate {
+    fn event(&self) -> ProgressEvent {
+        let done = self.finished as f64 + self.running.iter().map(ItemProgress::fraction).sum::<f64>();
+        let fraction = (self.total > 0).then(|| (done / self.total as f64).min(1.0));
+        // The rate so far is the best guess for the rest of the queue
+        let eta = fraction
+            .filter(|fraction| *fraction > 0.0)
+            .map(|fraction| (self.started.elapsed().as_secs_f64() * (1.0 - fraction) / fraction).round() as u64);
+        ProgressEvent::Aggregate {
+            finished: self.finished,
+            total: self.total,
+            downloaded_bytes: self.downloaded_bytes,
+            percent: fraction.map(|fraction| fraction * 100.0),
+            eta,
+            running: self.running.clone(),
+        }
+    }
+
+    /// Follow an event of the item running on this thread; returns whether the item list changed
+    fn observe(&mut self, event: &ProgressEvent) -> bool {
+        if let ProgressEvent::ItemStarted { index, url, .. } = event {
+            // An item skipped by a filter ends without an event of its own
+            self.finish_current();
+            self.running.push(ItemProgress {
+                index: *index,
+                url: url.clone(),
+                stage: None,
+                percent: None,
+                downloaded_bytes: 0,
+                eta: None,
+                stream_bytes: 0,
+            });
+            ITEM.set(Some(*index));
+            return true;
+        }
+        let Some(item) = ITEM.get().and_then(|index| self.running.iter_mut().find(|item| item.index == index)) else {
+            return false;
+        };
+        match event {
+            ProgressEvent::StageStarted { stage } => {
+                item.stage = Some(*stage);
+                item.percent = None;
+                item.eta = None;
+                item.stream_bytes = 0;
+            }
+            ProgressEvent::DownloadProgress { downloaded_bytes, percent, eta, .. } => {
+                let new = downloaded_bytes.checked_sub(item.stream_bytes).unwrap_or(*downloaded_bytes);
+                item.downloaded_bytes += new;
+                item.stream_bytes = *downloaded_bytes;
+                self.downloaded_bytes += new;
+                item.percent = *percent;
+                item.eta = *eta;
+            }
+            ProgressEvent::ConvertProgress { percent, eta, .. } => {
+                item.percent = *percent;
+                item.eta = *eta;
+            }
+            ProgressEvent::Finished { .. } | ProgressEvent::Failed { .. } => {
+                self.finish_current();
+                return true;
+            }
+            _ => {}
+        }
+        false
+    }
+
+    /// Count the item running on this thread as finished
+    fn finish_current(&mut self) {
+        if let Some(index) = ITEM.take() {
+            self.running.retain(|item| item.index != index);
+            self.finished += 1;
+        }
+    }
+}
+
+thread_local! {
+    /// Queue position of the item running on this thread during a parallel run
+    static ITEM: Cell<Option<usize>> = const { Cell::new(None) };
+}
+
+impl ProgressEvent {
+    /// Parse a progress line produced by `ytdlp_progress_template`
+    pub fn from_ytdlp_line(line: &str) -> Option<ProgressEvent> {
+        let mut fields = line.split_whitespace();
+        if fields.next()? != YTDLP_PROGRESS_PREFIX {
+            return None;
+        }
+        let mut next_number = || fields.next().and_then(|f| f.parse::<f64>().ok());
+
+        let downloaded = next_number()?;
+        let total_bytes = next_number();
+        let total_estimate = next_number();
+        let total = total_bytes.or(total_estimate);
+        let speed = next_number();
+        let eta = next_number();
+
+        let percent = total.filter(|t| *t > 0.0).map(|t| (downloaded / t * 100.0).min(100.0));
+
+        Some(ProgressEvent::DownloadProgress {
+            downloaded_bytes: downloaded as u64,
+            total_bytes: total.map(|t| t as u64),
+            percent,
+            speed,
+            eta: eta.map(|e| e as u64),
+        })
+    }
+}
+
+/// Accumulates the `key=value` blocks ffmpeg writes with `-progress pipe:1`, turning each completed
+/// block into a `ConvertProgress` event measured against the source duration
+pub struct FfmpegProgress {
+    total_seconds: Option<f64>,
+    processed_seconds: f64,
+    frame: Option<u64>,
+    speed: Option<f64>,
+}
+
+impl FfmpegProgress {
+    pub fn new(total_seconds: Option<f64>) -> FfmpegProgress {
+        FfmpegProgress { total_seconds: total_seconds.filter(|t| *t > 0.0), processed_seconds: 0.0, frame: None, speed: None }
+    }
+
+    /// Feed one line of ffmpeg's progress output; returns an event at the end of every block
+    pub fn parse_line(&mut self, line: &str) -> Option<ProgressEvent> {
+        let (key, value) = line.trim().split_once('=')?;
+        match key {
+            "frame" => self.frame = value.parse().ok(),
+            // Unknown before the first packet is muxed ("N/A") and negative for streams with a start offset
+            "out_time_us" | "out_time_ms" => {
+                if let Ok(micros) = value.parse::<i64>() {
+                    self.processed_seconds = micros.max(0) as f64 / 1_000_000.0;
+                }
+            }
+            "speed" => self.speed = value.trim().trim_end_matches('x').parse().ok().filter(|s: &f64| *s > 0.0),
+            "progress" => return Some(self.event(value == "end")),
+            _ => {}
+        }
+        None
+    }
+
+    fn event(&self, finished: bool) -> ProgressEvent {
+        let percent = match finished {
+            true => Some(100.0),
+            false => self.total_seconds.map(|t| (self.processed_seconds / t * 100.0).min(100.0)),
+        };
+        let remaining = self.total_seconds.map(|t| (t - self.processed_seconds).max(0.0));
+        let eta = match finished {
+            true => Some(0),
+            false => remaining.zip(self.speed).map(|(remaining, speed)| (remaining / speed).round() as u64),
+        };
+        ProgressEvent::ConvertProgress {
+            processed_seconds: self.processed_seconds,
+            total_seconds: self.total_seconds,
+            frame: self.frame,
+            percent,
+            speed: self.speed,
+            eta,
+        }
+    }
+}
+
+/// Where JSON-lines progress events are written, parsed from `fd:N` or a file path
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProgressTarget {
+    Fd(i32),
+    Path(String),
+}
+
+impl std::str::FromStr for ProgressTarget {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.strip_prefix("fd:") {
+            Some(fd) => fd
+                .parse::<i32>()
+                .ok()
+                .filter(|fd| *fd > 2)
+                .map(ProgressTarget::Fd)
+                .ok_or_else(|| format!("invalid file descriptor {:?} (use fd:3 or higher)", fd)),
+            None if s.is_empty() => Err("progress target must not be empty".to_string()),
+            None => Ok(ProgressTarget::Path(s.to_string())),
+        }
+    }
+}
+
+/// Receives every progress event, for code built on this crate
+pub type ProgressCallback = Box<dyn Fn(&ProgressEvent) + Send + Sync>;
+
+/// Progress reporter that mirrors events to an optional JSON-lines sink and callback
+#[derive(Default)]
+pub struct Progress {
+    sink: Option<Mutex<Box<dyn Write + Send>>>,
+    callback: Option<ProgressCallback>,
+    /// Stage running now, to label ffmpeg progress that may be a recording, capture or conversion
+    stage: Mutex<Option<Stage>>,
+    /// Items running at once, while a parallel run is in progress
+    aggregate: Mutex<Option<Aggregate>>,
+}
+
+impl Progress {
+    /// Open the sink described by `target`, or create a reporter that only renders human output
+    pub fn new(target: Option<&ProgressTarget>) -> Result<Progress, VideoConversionError> {
+        let sink: Option<Box<dyn Write + Send>> = match target {
+            None => None,
+            Some(ProgressTarget::Path(path)) => Some(Box::new(File::create(path).map_err(|e| {
+                VideoConversionError::config(format!("cannot open progress file {}", path)).caused_by(e)
+            })?)),
+            Some(ProgressTarget::Fd(fd)) => Some(Box::new(open_fd(*fd)?)),
+        };
+        Ok(Progress { sink: sink.map(Mutex::new), ..Progress::default() })
+    }
+
+    /// Also hand every event to `callback`, including the `aggregate` events of parallel runs
+    pub fn with_callback(mut self, callback: impl Fn(&ProgressEvent) + Send + Sync + 'static) -> Progress {
+        self.callback = Some(Box::new(callback));
+        self
+    }
+
+    /// Start following `total` items that run on several threads at once: their progress is
+    /// rendered as one status line and reported as `aggregate` events until `end_parallel`
+    pub(crate) fn start_parallel(&self, total: usize) {
+        *self.aggregate.lock().unwrap_or_else(|e| e.into_inner()) = Some(Aggregate {
+            total,
+            finished: 0,
+            downloaded_bytes: 0,
+            running: Vec::new(),
+            started: Instant::now(),
+            reported: None,
+        });
+    }
+
+    /// Stop following a parallel run, ending its status line
+    pub(crate) fn end_parallel(&self) {
+        self.aggregate.lock().unwrap_or_else(|e| e.into_inner()).take();
+        crate::logging::set_status(None);
+    }
+
+    /// Emit an event to the JSON sink, the job record and the metrics, and render download progress for humans
+    pub fn emit(&self, event: ProgressEvent) {
+        crate::jobs::observe(&event);
+        crate::metrics::observe(&event);
+
+        let mut aggregate = self.aggregate.lock().unwrap_or_else(|e| e.into_inner());
+        let Some(state) = aggregate.as_mut() else {
+            drop(aggregate);
+            self.render(&event);
+            self.publish(&event);
+            return;
+        };
+        let changed = state.observe(&event);
+        let due = state.reported.is_none_or(|reported| reported.elapsed() >= AGGREGATE_INTERVAL);
+        let summary = (changed || due).then(|| {
+            state.reported = Some(Instant::now());
+            state.event()
+        });
+        // Rendering under the lock keeps the workers from writing over each other's lines
+        if let Some(summary) = &summary {
+            render_aggregate_line(summary);
+        }
+        drop(aggregate);
+        self.publish(&event);
+        if let Some(summary) = &summary {
+            self.publish(summary);
+        }
+    }
+
+    /// Render the status line of a single running item
+    fn render(&self, event: &ProgressEvent) {
+        if let ProgressEvent::DownloadProgress { downloaded_bytes, total_bytes, percent, speed, eta } = &event {
+            render_download_line(*downloaded_bytes, *total_bytes, *percent, *speed, *eta);
+        }
+        let mut stage = self.stage.lock().unwrap_or_else(|e| e.into_inner());
+        match event {
+            ProgressEvent::StageStarted { stage: started } => *stage = Some(*started),
+            ProgressEvent::ConvertProgress { processed_seconds, percent, speed, eta, .. } => {
+                let label = match *stage {
+                    Some(Stage::Record) => "record",
+                    Some(Stage::Download) => "download",
+                    _ => "convert",
+                };
+                render_ffmpeg_line(label, *processed_seconds, *percent, *speed, *eta);
+            }
+            _ => {}
+        }
+    }
+
+    /// Hand an event to the JSON sink and the callback
+    fn publish(&self, event: &ProgressEvent) {
+        if let Some(sink) = &self.sink {
+            let mut sink = sink.lock().unwrap_or_else(|e| e.into_inner());
+            // A broken progress consumer must never abort the actual work
+            if let Ok(line) = serde_json::to_string(event) {
+                let _ = writeln!(sink, "{}", line).and_then(|_| sink.flush());
+            }
+        }
+        if let Some(callback) = &self.callback {
+            callback(event);
+        }
+    }
+}
+
+/// Overwrite the current terminal line with a compact download status
+fn render_download_line(downloaded: u64, total: Option<u64>, percent: Option<f64>, speed: Option<f64>, eta: Option<u64>) {
+    let mut line = match (percent, total) {
+        (Some(p), Some(t)) => format!("[download] {:5.1}% of {}", p, human_bytes(t as f64)),
+        _ => format!("[download] {}", human_bytes(downloaded as f64)),
+    };
+    if let Some(speed) = speed {
+        line.push_str(&format!(" at {}/s", human_bytes(speed)));
+    }
+    if let Some(eta) = eta {
+        line.push_str(&format!(", ETA {}s", eta));
+    }
+    print!("\r{}", paint(Tone::Download, &format!("{:<60}", line)));
+    let _ = io::stdout().flush();
+    if percent.is_some_and(|p| p >= 100.0) {
+        println!();
+    }
+}
+
+/// Overwrite the current terminal line with a compact status of an ffmpeg run
+fn render_ffmpeg_line(label: &str, processed: f64, percent: Option<f64>, speed: Option<f64>, eta: Option<u64>) {
+    let mut line = match percent {
+        Some(p) => format!("[{}] {:5.1}%", label, p),
+        None => format!("[{}] {:.1}s processed", label, processed),
+    };
+    if let Some(speed) = speed {
+        line.push_str(&format!(" at {:.2}x", speed));
+    }
+    if let Some(eta) = eta {
+        line.push_str(&format!(", ETA {}s", eta));
+    }
+    print!("\r{}", paint(Tone::Download, &format!("{:<60}", line)));
+    let _ = io::stdout().flush();
+    if percent.is_some_and(|p| p >= 100.0) {
+        println!();
+    }
+}
+
+/// Show the overall progress of a parallel run with a short bar for every running item as the
+/// status line
+fn render_aggregate_line(summary: &ProgressEvent) {
+    let ProgressEvent::Aggregate { finished, total, downloaded_bytes, percent, eta, running } = summary else { return };
+    let mut line = format!("[{}/{} done]", finished, total);
+    if let Some(percent) = percent {
+        line.push_str(&format!(" {:5.1}%", percent));
+    }
+    line.push_str(&format!(" {}", human_bytes(*downloaded_bytes as f64)));
+    if let Some(eta) = eta {
+        line.push_str(&format!(", ETA {}s", eta));
+    }
+    for item in running {
+        let label = match item.stage {
+            Some(Stage::Convert) => "convert",
+            Some(Stage::Record) => "record",
+            Some(Stage::Cleanup) => "cleanup",
+            Some(Stage::Download) | None => "download",
+        };
+        let filled = (item.percent.unwrap_or(0.0) / 10.0).round().clamp(0.0, 10.0) as usize;
+        line.push_str(&format!(" | #{} {} [{}{}]", item.index, label, "#".repeat(filled), "-".repeat(10 - filled)));
+    }
+    crate::logging::set_status(Some(paint(Tone::Download, &line)));
+}
+
+/// Format a byte count using binary units
+pub fn human_bytes(bytes: f64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
+
+#[cfg(unix)]
+fn open_fd(fd: i32) -> Result<File, VideoConversionError> {
+    use std::os::unix::io::FromRawFd;
+
+    // Validate the descriptor before taking ownership of it
+    let path = format!("/dev/fd/{}", fd);
+    if std::fs::metadata(&path).is_err() {
+        return Err(VideoConversionError::config(format!("file descriptor {} is not open", fd)));
+    }
+    // SAFETY: the descriptor was handed to us by the parent process and is owned by this sink from now on
+    Ok(unsafe { File::from_raw_fd(fd) })
+}
+
+#[cfg(not(unix))]
+fn open_fd(fd: i32) -> Result<File, VideoConversionError> {
+    Err(VideoConversionError::config(format!(
+        "fd:{} progress targets are only supported on Unix; pass a file path instead",
+        fd
+    )))
+}
@@ -0,0 +1,126 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::process::Command;
+
+use clap::ValueEnum;
+use serde::Deserialize;
+use tracing::{debug, info_span};
+
+use crate::filtergraph::{FilterChain, Scale};
+use crate::progress::Progress;
+use crate::{command_output, console, logging, run_ffmpeg, verify, VideoConversionError};
+
+/// Editing proxy codec
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum, Debug, Default)]
+pub enum ProxyCodec {
+    /// ProRes 422 Proxy in a MOV, all-intra and read natively by Final Cut, Premiere and Resolve
+    #[default]
+    Prores,
+    /// All-intra H.264 tuned for fast decoding in an MP4, far smaller than ProRes
+    H264,
+}
+
+impl ProxyCodec {
+    fn extension(self) -> &'static str {
+        match self {
+            ProxyCodec::Prores => "mov",
+            ProxyCodec::H264 => "mp4",
+        }
+    }
+
+    /// Encoder arguments; every frame is a keyframe so editors can scrub and cut anywhere
+    fn encoder_args(self) -> &'static [&'static str] {
+        match self {
+            ProxyCodec::Prores => &["-c:v", "prores_ks", "-profile:v", "0", "-pix_fmt", "yuv422p10le", "-c:a", "pcm_s16le"],
+            ProxyCodec::H264 => &[
+                "-c:v", "libx264", "-preset", "veryfast", "-tune", "fastdecode", "-g", "1", "-crf", "23", "-pix_fmt", "yuv420p",
+                "-c:a", "aac", "-movflags", "+faststart",
+            ],
+        }
+    }
+}
+
+/// Low-resolution proxies for video editors
+#[derive(clap::Args, Debug, Clone)]
+pub struct ProxyOptions {
+    /// Also write a small all-intra editing proxy next to each video as {name}.proxy.mov (ProRes
+    /// Proxy) or .mp4 (H.264), with the source's timecode so editors relink it to the original
+    #[arg(long, value_enum, value_name = "CODEC", num_args = 0..=1, default_missing_value = "prores")]
+    make_proxy: Option<ProxyCodec>,
+
+    /// Height of the proxy in pixels; the width follows the video's aspect ratio
+    #[arg(
+        long,
+        value_name = "PIXELS",
+        default_value_t = 540,
+        value_parser = clap::value_parser!(u32).range(144..=2160),
+        requires = "make_proxy"
+    )]
+    proxy_height: u32,
+}
+
+impl ProxyOptions {
+    /// Whether proxies were requested
+    pub fn enabled(&self) -> bool {
+        self.make_proxy.is_some()
+    }
+}
+
+#[derive(Deserialize, Default)]
+struct ProbeOutput {
+    #[serde(default)]
+    streams: Vec<ProbeTags>,
+    format: Option<ProbeTags>,
+}
+
+#[derive(Deserialize, Default)]
+struct ProbeTags {
+    #[serde(default)]
+    tags: BTreeMap<String, String>,
+}
+
+/// Start timecode of `path`, such as `01:00:00:00`, from its container or a stream; camera files
+/// carry one, web downloads rarely do
+fn timecode(path: &Path) -> Option<String> {
+    let output = command_output(
+        Command::new("ffprobe")
+            .args(["-v", "error", "-show_entries", "format_tags=timecode:stream_tags=timecode", "-of", "json"])
+            .arg(path)
+            .stderr(logging::child_stderr()),
+    )
+    .ok()?;
+    let parsed: ProbeOutput = serde_json::from_slice(&output.stdout).unwrap_or_default();
+    parsed.format.into_iter().chain(parsed.streams).find_map(|probe| {
+        probe.tags.into_iter().find(|(key, _)| key.eq_ignore_ascii_case("timecode")).map(|(_, value)| value)
+    })
+}
+
+/// Write an editing proxy of `video` next to it, if one was requested
+pub(crate) fn write(video: &Path, options: &ProxyOptions, progress: &Progress) -> Result<(), VideoConversionError> {
+    let Some(codec) = options.make_proxy else { return Ok(()) };
+    let output = video.with_extension(format!("proxy.{}", codec.extension()));
+    let _span = info_span!("proxy", output = %output.display()).entered();
+    let probe = verify::ffprobe(video)?;
+    if probe.video_streams == 0 {
+        return Err(VideoConversionError::config(format!("{} has no video", video.display())));
+    }
+    println!("Writing {}p editing proxy...", options.proxy_height);
+
+    let mut command = Command::new("ffmpeg");
+    command
+        .args(["-progress", "pipe:1", "-nostats", "-y", "-i"])
+        .arg(video)
+        // Clips and their proxy must line up frame for frame, so no frames are dropped or duplicated
+        .args(["-map", "0:v:0", "-map", "0:a?", "-map_metadata", "0", "-fps_mode", "passthrough"])
+        .arg("-vf")
+        .arg(FilterChain::new().then(Scale::to_height(options.proxy_height)).to_string())
+        .args(codec.encoder_args());
+    if let Some(timecode) = timecode(video) {
+        debug!(%timecode, "keeping timecode");
+        command.arg("-timecode").arg(timecode);
+    }
+    run_ffmpeg(command.arg(&output), probe.duration, progress).map_err(VideoConversionError::conversion)?;
+
+    console!(Success, "Proxy saved: {}", output.display());
+    Ok(())
+}
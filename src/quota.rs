@@ -0,0 +1,219 @@
+use std::collections::HashMap;
+use std::fs::{self, read_dir};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use clap::ValueEnum;
+use tracing::{debug, info, info_span};
+
+use crate::convert_dir::VIDEO_EXTENSIONS;
+use crate::progress::human_bytes;
+use crate::{console, jobs, VideoConversionError};
+
+/// Extensions of audio files, next to the video ones
+pub(crate) const AUDIO_EXTENSIONS: &[&str] = &["mp3", "m4a", "aac", "opus", "ogg", "flac", "wav"];
+
+/// Files in a folder that make it one streaming output, kept or deleted as a whole
+const MANIFEST_EXTENSIONS: &[&str] = &["m3u8", "mpd"];
+
+/// Opening a file this long after its download finished counts as watching it, leaving out the
+/// probes and thumbnails of the download itself
+const WATCH_GRACE_SECONDS: u64 = 120;
+
+/// Which videos `--max-library-size` deletes first
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum, Debug, Default)]
+pub enum Eviction {
+    /// Those downloaded longest ago
+    #[default]
+    Oldest,
+    /// Those downloaded with the lowest --library-priority, oldest first among equals
+    Priority,
+    /// Only those opened since they were downloaded, oldest first; needs access times, which
+    /// `noatime` mounts do not keep
+    Watched,
+}
+
+/// A video or streaming output with its sidecars, deleted together
+#[derive(Debug)]
+struct Entry {
+    paths: Vec<PathBuf>,
+    bytes: u64,
+    /// Unix time the download finished, `None` for files the download history lacks, which are
+    /// never deleted
+    downloaded: Option<u64>,
+    priority: i32,
+    watched: bool,
+}
+
+/// What the download history knows about one output file
+struct Downloaded {
+    finished: u64,
+    priority: i32,
+}
+
+fn unix_time(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+/// Finish time and priority of every output the job records know, by canonical path
+fn download_history() -> HashMap<PathBuf, Downloaded> {
+    let records = match jobs::list() {
+        Ok(records) => records,
+        Err(e) => {
+            debug!(error = %e, "cannot read download history");
+            return HashMap::new();
+        }
+    };
+    let mut history = HashMap::new();
+    for record in records {
+        for item in record.items {
+            let (Some(output), Some(finished)) = (item.output, item.finished) else { continue };
+            // Outputs are recorded relative to the directory the job ran in
+            let output = match &record.dir {
+                Some(dir) => dir.join(output),
+                None => PathBuf::from(output),
+            };
+            if let Ok(path) = fs::canonicalize(&output) {
+                history.insert(path, Downloaded { finished, priority: record.library_priority });
+            }
+        }
+    }
+    history
+}
+
+fn has_extension(path: &Path, extensions: &[&str]) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| extensions.iter().any(|known| known.eq_ignore_ascii_case(ext)))
+}
+
+/// Total size of the files below `path`
+fn size_of(path: &Path) -> u64 {
+    let Ok(metadata) = fs::symlink_metadata(path) else { return 0 };
+    if !metadata.is_dir() {
+        return metadata.len();
+    }
+    read_dir(path).map_or(0, |entries| entries.flatten().map(|entry| size_of(&entry.path())).sum())
+}
+
+/// Whether `path` belongs to the video named `stem`, like `name.nfo`, `name.info.json` and the
+/// `name-thumb.jpg` of library layouts
+pub(crate) fn is_sidecar(stem: &str, path: &Path) -> bool {
+    path.file_name().is_some_and(|name| {
+        let name = name.to_string_lossy();
+        name.strip_prefix(stem).is_some_and(|rest| rest.starts_with('.') || rest.starts_with("-thumb."))
+    })
+}
+
+/// Files next to `video` that belong to it
+pub(crate) fn sidecars(video: &Path) -> Vec<PathBuf> {
+    let (Some(dir), Some(stem)) = (video.parent(), video.file_stem()) else { return Vec::new() };
+    let stem = stem.to_string_lossy();
+    let Ok(entries) = read_dir(if dir.as_os_str().is_empty() { Path::new(".") } else { dir }) else { return Vec::new() };
+    let mut sidecars: Vec<PathBuf> = entries
+        .flatten()
+        .map(|entry| dir.join(entry.file_name()))
+        .filter(|path| path.as_path() != video && path.is_file() && is_sidecar(&stem, path))
+        .collect();
+    sidecars.sort();
+    sidecars
+}
+
+/// Videos, audio and streaming outputs below `dir`, each with the sidecars sharing its name
+fn collect(dir: &Path, history: &HashMap<PathBuf, Downloaded>, entries: &mut Vec<Entry>) -> std::io::Result<()> {
+    let mut paths: Vec<PathBuf> = read_dir(dir)?.flatten().map(|entry| entry.path()).collect();
+    paths.sort();
+    let (media, others): (Vec<PathBuf>, Vec<PathBuf>) = paths
+        .into_iter()
+        .partition(|path| path.is_file() && has_extension(path, &[VIDEO_EXTENSIONS, AUDIO_EXTENSIONS].concat()));
+
+    for path in &others {
+        // Symbolic links to folders are not followed, as they may loop or lead out of the library
+        if !path.is_dir() || path.is_symlink() {
+            continue;
+        }
+        let is_stream = read_dir(path)?.flatten().any(|entry| has_extension(&entry.path(), MANIFEST_EXTENSIONS));
+        match is_stream {
+            true => entries.push(entry(vec![path.clone()], history)),
+            false => collect(path, history, entries)?,
+        }
+    }
+    for path in &media {
+        let Some(stem) = path.file_stem().map(|stem| stem.to_string_lossy().into_owned()) else { continue };
+        let sidecars = others.iter().filter(|other| other.is_file() && is_sidecar(&stem, other));
+        entries.push(entry(std::iter::once(path.clone()).chain(sidecars.cloned()).collect(), history));
+    }
+    Ok(())
+}
+
+/// Describe the output made of `paths`, the first being the video or streaming folder
+fn entry(paths: Vec<PathBuf>, history: &HashMap<PathBuf, Downloaded>) -> Entry {
+    let known = fs::canonicalize(&paths[0]).ok().and_then(|path| history.get(&path));
+    let accessed = fs::metadata(&paths[0]).ok().and_then(|m| m.accessed().ok()).map(unix_time);
+    let watched = known.is_some_and(|known| accessed.is_some_and(|accessed| accessed > known.finished + WATCH_GRACE_SECONDS));
+    Entry {
+        bytes: paths.iter().map(|path| size_of(path)).sum(),
+        paths,
+        downloaded: known.map(|known| known.finished),
+        priority: known.map_or(0, |known| known.priority),
+        watched,
+    }
+}
+
+/// Delete videos from `dir` as `policy` picks them until it holds at most `max_bytes`, sparing
+/// everything downloaded since `since` and every file the download history does not know, such
+/// as the user's own videos
+pub(crate) fn enforce(dir: &Path, max_bytes: u64, policy: Eviction, since: SystemTime) -> Result<(), VideoConversionError> {
+    let _span = info_span!("quota", dir = %dir.display(), max_bytes).entered();
+    let total = size_of(dir);
+    if total <= max_bytes {
+        debug!(total, "library within quota");
+        return Ok(());
+    }
+    let history = download_history();
+    let mut entries = Vec::new();
+    collect(dir, &history, &mut entries)
+        .map_err(|e| VideoConversionError::io(format!("Failed to read {}", dir.display())).caused_by(e))?;
+
+    let since = unix_time(since);
+    let (mut entries, untracked): (Vec<Entry>, Vec<Entry>) = entries.into_iter().partition(|entry| entry.downloaded.is_some());
+    entries.retain(|entry| entry.downloaded < Some(since) && (policy != Eviction::Watched || entry.watched));
+    match policy {
+        Eviction::Oldest | Eviction::Watched => entries.sort_by_key(|entry| entry.downloaded),
+        Eviction::Priority => entries.sort_by_key(|entry| (entry.priority, entry.downloaded)),
+    }
+
+    let mut total = total;
+    for entry in entries {
+        if total <= max_bytes {
+            break;
+        }
+        for path in &entry.paths {
+            let removed = if path.is_dir() { fs::remove_dir_all(path) } else { fs::remove_file(path) };
+            if let Err(e) = removed {
+                console!(Warning, "could not delete {}: {}", path.display(), e);
+            }
+        }
+        total = total.saturating_sub(entry.bytes);
+        info!(path = %entry.paths[0].display(), bytes = entry.bytes, "evicted");
+        println!("Deleted {} ({}) to stay under --max-library-size", entry.paths[0].display(), human_bytes(entry.bytes as f64));
+    }
+    if total > max_bytes {
+        console!(
+            Warning,
+            "{} holds {}, over --max-library-size {}, with nothing left that --evict allows deleting",
+            dir.display(),
+            human_bytes(total as f64),
+            human_bytes(max_bytes as f64)
+        );
+        let untracked_bytes: u64 = untracked.iter().map(|entry| entry.bytes).sum();
+        if !untracked.is_empty() {
+            println!(
+                "Not evictable: {} files ({}) that videelow did not download, or whose download history is gone",
+                untracked.len(),
+                human_bytes(untracked_bytes as f64)
+            );
+        }
+    }
+    Ok(())
+}
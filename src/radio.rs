@@ -0,0 +1,357 @@
+use std::fs::{create_dir_all, remove_file};
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::process::{ChildStdin, Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+use tracing::{debug, info_span};
+
+use crate::codecs::{AudioCodec, Bitrate};
+use crate::process::ChildProcess;
+use crate::progress::Progress;
+use crate::record;
+use crate::sites::sanitize_filename;
+use crate::timestamp::MediaTimestamp;
+use crate::{console, exit_error, logging, shutdown, urls, VideoConversionError};
+
+/// What `record radio` captures
+#[derive(clap::Args, Debug, Clone)]
+pub struct RadioOptions {
+    /// Stream URL: an Icecast/SHOUTcast stream or an HLS playlist
+    url: String,
+
+    /// Output directory; the tracks are saved in a folder named after the station
+    #[arg(short, long, default_value = "Processed")]
+    output_dir: String,
+
+    /// Station name for the folder and the album tag (default: the name the station announces,
+    /// else its host)
+    #[arg(short, long)]
+    name: Option<String>,
+
+    /// Stop after this long (seconds, MM:SS or HH:MM:SS) instead of waiting for Enter
+    #[arg(long, value_name = "DURATION")]
+    duration: Option<MediaTimestamp>,
+
+    /// Keep the whole recording in one file instead of starting a new track whenever the station
+    /// announces another title
+    #[arg(long)]
+    no_split: bool,
+
+    /// Bitrate such as 128k for streams re-encoded to MP3 (default: 192k); MP3 streams are kept as
+    /// they are unless this is set
+    #[arg(long, value_name = "RATE")]
+    audio_bitrate: Option<Bitrate>,
+}
+
+/// What the next read from an Icecast stream produced
+#[derive(Debug, PartialEq, Eq)]
+enum IcyRead {
+    /// This many bytes of audio
+    Audio(usize),
+    /// A metadata block, with the stream title unless it was empty
+    Metadata(Option<String>),
+    /// The station closed the stream
+    End,
+}
+
+/// Separates the audio of an Icecast stream from the metadata blocks the server inserts every
+/// `interval` bytes when asked with `Icy-MetaData: 1`
+struct IcyStream<R> {
+    inner: R,
+    interval: Option<usize>,
+    until_metadata: usize,
+}
+
+impl<R: Read> IcyStream<R> {
+    fn new(inner: R, interval: Option<usize>) -> IcyStream<R> {
+        IcyStream { inner, interval, until_metadata: interval.unwrap_or(0) }
+    }
+
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<IcyRead> {
+        if let (Some(interval), 0) = (self.interval, self.until_metadata) {
+            // One length byte counting 16-byte units, then the block padded with NULs
+            let mut length = [0; 1];
+            if self.inner.read(&mut length)? == 0 {
+                return Ok(IcyRead::End);
+            }
+            let mut block = vec![0; length[0] as usize * 16];
+            self.inner.read_exact(&mut block)?;
+            self.until_metadata = interval;
+            return Ok(IcyRead::Metadata(stream_title(&block)));
+        }
+        let limit = match self.interval {
+            Some(_) => self.until_metadata.min(buffer.len()),
+            None => buffer.len(),
+        };
+        match self.inner.read(&mut buffer[..limit])? {
+            0 => Ok(IcyRead::End),
+            read => {
+                self.until_metadata = self.until_metadata.saturating_sub(read);
+                Ok(IcyRead::Audio(read))
+            }
+        }
+    }
+}
+
+/// The title in a metadata block such as `StreamTitle='Artist - Title';StreamUrl='';`
+fn stream_title(block: &[u8]) -> Option<String> {
+    let text = String::from_utf8_lossy(block);
+    let rest = text.split_once("StreamTitle='")?.1;
+    let title = rest.split_once("';").map_or(rest.trim_end_matches(['\0', '\'', ';']), |(title, _)| title).trim();
+    (!title.is_empty()).then(|| title.to_string())
+}
+
+/// `path` with ` (2)`, ` (3)`... added before the extension until no file has that name
+fn unique_path(dir: &str, stem: &str) -> String {
+    let mut path = format!("{}/{}.mp3", dir, stem);
+    let mut copy = 1;
+    while Path::new(&path).exists() {
+        copy += 1;
+        path = format!("{}/{} ({}).mp3", dir, stem, copy);
+    }
+    path
+}
+
+/// ID3 tags for a track: station titles are usually `Artist - Title`
+fn track_tags(station: &str, title: Option<&str>, number: Option<u32>) -> Vec<(&'static str, String)> {
+    let mut tags = vec![("album", station.to_string())];
+    match title.and_then(|title| title.split_once(" - ")) {
+        Some((artist, title)) => tags.extend([("artist", artist.trim().to_string()), ("title", title.trim().to_string())]),
+        None => tags.push(("title", title.unwrap_or(station).to_string())),
+    }
+    if let Some(number) = number {
+        tags.push(("track", number.to_string()));
+    }
+    tags
+}
+
+/// An ffmpeg writing the audio piped into it to a tagged MP3
+struct Track {
+    process: ChildProcess,
+    stdin: Option<ChildStdin>,
+    command: Command,
+    path: String,
+}
+
+impl Track {
+    /// Start writing `path`, copying MP3 audio when `bitrate` is unset
+    fn start(path: String, tags: &[(&str, String)], bitrate: Option<Bitrate>) -> Result<Track, VideoConversionError> {
+        let mut command = Command::new("ffmpeg");
+        // A new ffmpeg starts for every track, so only its errors are worth showing
+        command.args(["-hide_banner", "-loglevel", "error", "-nostats", "-n", "-i", "pipe:0", "-vn"]);
+        match bitrate {
+            Some(bitrate) => command.args(["-c:a", AudioCodec::Mp3.encoder(), "-b:a"]).arg(bitrate.to_string()),
+            None => command.args(["-c:a", "copy"]),
+        };
+        for (key, value) in tags {
+            command.arg("-metadata").arg(format!("{}={}", key, value));
+        }
+        command
+            .args(["-id3v2_version", "3", "-f", "mp3"])
+            .arg(&path)
+            .stdout(Stdio::null())
+            .stderr(logging::child_stderr());
+        let mut process = ChildProcess::spawn_with_stdin(&mut command, Stdio::piped())?;
+        let stdin = process.stdin();
+        Ok(Track { process, stdin, command, path })
+    }
+
+    fn write(&mut self, audio: &[u8]) -> Result<(), VideoConversionError> {
+        let stdin = self.stdin.as_mut().expect("track is open");
+        stdin.write_all(audio).map_err(|e| {
+            shutdown::check()
+                .err()
+                .unwrap_or_else(|| VideoConversionError::io(format!("ffmpeg stopped writing {}", self.path)).caused_by(e))
+        })
+    }
+
+    /// Close the input and wait for ffmpeg to finish the file
+    fn finish(mut self) -> Result<(), VideoConversionError> {
+        drop(self.stdin.take());
+        let status = self.process.wait().map_err(|e| VideoConversionError::io(e.to_string()))?;
+        if !status.success() {
+            return Err(exit_error(&self.command, status));
+        }
+        console!(Success, "Track saved: {}", self.path);
+        Ok(())
+    }
+
+    /// Stop ffmpeg and remove the unfinished file
+    fn discard(self) {
+        let path = self.path.clone();
+        drop(self);
+        if let Err(e) = remove_file(&path) {
+            debug!(path = path.as_str(), error = %e, "cannot remove unfinished track");
+        }
+    }
+}
+
+/// Bitrate to re-encode at, or `None` to copy the audio of an MP3 stream
+fn reencode_bitrate(options: &RadioOptions, mp3: bool) -> Option<Bitrate> {
+    match (options.audio_bitrate, mp3) {
+        (Some(bitrate), _) => Some(bitrate),
+        (None, true) => None,
+        (None, false) => Some(AudioCodec::Mp3.default_bitrate()),
+    }
+}
+
+/// Record an HLS radio stream into a single MP3; its tracks are not announced in-band
+fn record_playlist(options: &RadioOptions, station: &str, progress: &Progress) -> Result<(), VideoConversionError> {
+    let dir = format!("{}/{}", options.output_dir, sanitize_filename(station));
+    create_dir_all(&dir).map_err(|e| VideoConversionError::io(e.to_string()))?;
+    let output = unique_path(&dir, &record::default_name("radio"));
+    let bitrate = options.audio_bitrate.unwrap_or(AudioCodec::Mp3.default_bitrate());
+
+    let mut command = Command::new("ffmpeg");
+    command
+        .args(["-progress", "pipe:1", "-nostats", "-n", "-i", &options.url, "-vn", "-c:a", AudioCodec::Mp3.encoder(), "-b:a"])
+        .arg(bitrate.to_string());
+    for (key, value) in track_tags(station, None, None) {
+        command.arg("-metadata").arg(format!("{}={}", key, value));
+    }
+    command.args(["-id3v2_version", "3"]);
+    if let Some(duration) = options.duration {
+        command.arg("-t").arg(duration.to_ffmpeg());
+    }
+    command.arg(&output);
+    record::run_capture(&mut command, &output, options.duration, progress)
+}
+
+/// Record a radio stream until Enter is pressed, the duration is up or the station stops,
+/// saving a tagged MP3 for every title the station announces
+pub fn record_radio(options: &RadioOptions, progress: &Progress) -> Result<(), VideoConversionError> {
+    let _span = info_span!("record", source = "radio", url = options.url.as_str()).entered();
+    if urls::is_manifest(&options.url) {
+        let station = options.name.clone().unwrap_or_else(|| urls::manifest_name(&options.url));
+        return record_playlist(options, &station, progress);
+    }
+
+    let response = reqwest::blocking::Client::builder()
+        .timeout(None)
+        .build()
+        .and_then(|client| client.get(&options.url).header("Icy-MetaData", "1").send())
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| VideoConversionError::network(format!("cannot open {}", options.url)).caused_by(e))?;
+    let header = |name: &str| {
+        let value = response.headers().get(name).and_then(|value| value.to_str().ok()).map(str::trim);
+        value.filter(|value| !value.is_empty()).map(str::to_string)
+    };
+    let content_type = header("content-type").unwrap_or_default().to_ascii_lowercase();
+    let interval = header("icy-metaint").and_then(|value| value.parse::<usize>().ok()).filter(|n| *n > 0);
+    let station = options.name.clone().or_else(|| header("icy-name")).unwrap_or_else(|| {
+        let url = url::Url::parse(&options.url).ok();
+        url.and_then(|url| url.host_str().map(str::to_string)).unwrap_or_else(|| "radio".to_string())
+    });
+    // Playlists served without an .m3u8 extension
+    if content_type.contains("mpegurl") {
+        drop(response);
+        return record_playlist(options, &station, progress);
+    }
+    if !options.no_split && interval.is_none() {
+        console!(Warning, "{} does not announce titles; recording into a single file", station);
+    }
+
+    let dir = format!("{}/{}", options.output_dir, sanitize_filename(&station));
+    create_dir_all(&dir).map_err(|e| VideoConversionError::io(e.to_string()))?;
+    let session = Session {
+        bitrate: reencode_bitrate(options, content_type.starts_with("audio/mpeg")),
+        split: !options.no_split && interval.is_some(),
+        duration: options.duration,
+        stop: Arc::new(AtomicBool::new(false)),
+        station,
+        dir,
+    };
+    let stop = Arc::clone(&session.stop);
+    record::on_enter(move || stop.store(true, Ordering::SeqCst));
+    match options.duration {
+        Some(duration) => println!("Recording {} for {} (press Enter to stop early)...", session.station, duration),
+        None => println!("Recording {}... press Enter to stop (Ctrl-C discards the track in progress)", session.station),
+    }
+
+    let mut track = None;
+    let outcome = save_tracks(IcyStream::new(response, interval), &session, &mut track);
+    match (outcome, track) {
+        (Err(VideoConversionError::Interrupted), Some(track)) => {
+            track.discard();
+            Err(VideoConversionError::Interrupted)
+        }
+        // Keep what was recorded before the stream failed
+        (outcome, track) => outcome.and(track.map_or(Ok(()), Track::finish)),
+    }
+}
+
+/// Where and how the tracks of a recording are saved
+struct Session {
+    station: String,
+    dir: String,
+    split: bool,
+    bitrate: Option<Bitrate>,
+    duration: Option<MediaTimestamp>,
+    stop: Arc<AtomicBool>,
+}
+
+impl Session {
+    /// Start the next track: numbered and named after its title when splitting
+    fn open(&self, number: u32, title: Option<&str>) -> Result<Track, VideoConversionError> {
+        if !self.split {
+            return Track::start(unique_path(&self.dir, &record::default_name("radio")), &track_tags(&self.station, None, None), self.bitrate);
+        }
+        let stem = format!("{:03} - {}", number, sanitize_filename(title.unwrap_or(&self.station)));
+        println!("Recording track {}: {}", number, title.unwrap_or("untitled"));
+        Track::start(unique_path(&self.dir, &stem), &track_tags(&self.station, title, Some(number)), self.bitrate)
+    }
+}
+
+/// Pipe the stream's audio into `track`, switching tracks as titles change, until stopped; the
+/// track in progress is left for the caller to finish or discard
+fn save_tracks<R: Read>(mut stream: IcyStream<R>, session: &Session, track: &mut Option<Track>) -> Result<(), VideoConversionError> {
+    let started = Instant::now();
+    let mut buffer = vec![0; 16 * 1024];
+    // Audio before the first metadata block belongs to the first title
+    let mut pending = Vec::new();
+    let mut seen_metadata = stream.interval.is_none();
+    let mut title: Option<String> = None;
+    let mut number = 0;
+    loop {
+        shutdown::check()?;
+        let elapsed = MediaTimestamp::from_millis(started.elapsed().as_millis() as u64);
+        if session.stop.load(Ordering::SeqCst) || session.duration.is_some_and(|duration| elapsed >= duration) {
+            return Ok(());
+        }
+        let read = stream.read(&mut buffer).map_err(|e| {
+            shutdown::check().err().unwrap_or_else(|| {
+                VideoConversionError::network(format!("{} stopped streaming", session.station)).caused_by(e)
+            })
+        })?;
+        match read {
+            IcyRead::End => {
+                console!(Warning, "{} closed the stream", session.station);
+                return Ok(());
+            }
+            IcyRead::Metadata(announced) => {
+                seen_metadata = true;
+                if announced.is_some() && announced != title {
+                    debug!(title = announced.as_deref(), "stream title changed");
+                    if session.split {
+                        track.take().map_or(Ok(()), Track::finish)?;
+                    }
+                    title = announced;
+                }
+            }
+            IcyRead::Audio(read) if !seen_metadata => pending.extend_from_slice(&buffer[..read]),
+            IcyRead::Audio(read) => {
+                if track.is_none() {
+                    number += 1;
+                    *track = Some(session.open(number, title.as_deref())?);
+                }
+                if let Some(track) = track.as_mut() {
+                    track.write(&std::mem::take(&mut pending))?;
+                    track.write(&buffer[..read])?;
+                }
+            }
+        }
+    }
+}
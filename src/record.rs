@@ -0,0 +1,435 @@
+use std::fs::create_dir_all;
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use tracing::info_span;
+
+use crate::backend::Ffmpeg;
+use crate::dates::Date;
+use crate::pipeline::{Cleanup, Encode, Pipeline, Record};
+use crate::process::ChildProcess;
+use crate::progress::{FfmpegProgress, Progress};
+use crate::timestamp::MediaTimestamp;
+use crate::{console, exit_error, logging, EncodeOptions, VideoConversionError};
+
+/// Settings shared by every recording source
+#[derive(clap::Args, Debug, Clone)]
+pub struct RecordOptions {
+    /// Output directory where the recording will be saved
+    #[arg(short, long, default_value = "Processed")]
+    output_dir: String,
+
+    /// Output file name without extension (default: the source and the UTC start time)
+    #[arg(short, long)]
+    name: Option<String>,
+
+    /// Stop after this long (seconds, MM:SS or HH:MM:SS) instead of waiting for Enter
+    #[arg(long, value_name = "DURATION")]
+    duration: Option<MediaTimestamp>,
+
+    /// Frames per second to capture
+    #[arg(long, default_value_t = 30, value_parser = clap::value_parser!(u32).range(1..=240))]
+    framerate: u32,
+
+    #[command(flatten)]
+    encode: EncodeOptions,
+}
+
+/// What `record screen` captures
+#[derive(clap::Args, Debug, Clone)]
+pub struct ScreenOptions {
+    /// Screen to capture: an X11 display such as :0.0 on Linux (default: $DISPLAY), a screen
+    /// index on macOS (default: 0); Windows always captures the whole desktop
+    #[arg(long, value_name = "SCREEN")]
+    screen: Option<String>,
+
+    /// Also record sound from an input device: a PulseAudio source on Linux, a device index on
+    /// macOS or a DirectShow device name on Windows (default: the system default, except on Windows)
+    #[arg(long, value_name = "DEVICE", num_args = 0..=1, default_missing_value = "default")]
+    audio: Option<String>,
+
+    #[command(flatten)]
+    record: RecordOptions,
+}
+
+/// What `record camera` captures
+#[derive(clap::Args, Debug, Clone)]
+pub struct CameraOptions {
+    /// List the cameras and microphones ffmpeg can record from, with the IDs to pass
+    #[arg(long)]
+    list: bool,
+
+    /// Camera to record, as listed by --list (default: /dev/video0 on Linux, the first camera elsewhere)
+    #[arg(long, value_name = "ID")]
+    camera: Option<String>,
+
+    /// Microphone to record, as listed by --list (default: the system default on Linux, the first
+    /// microphone elsewhere)
+    #[arg(long, value_name = "ID", conflicts_with = "no_audio")]
+    microphone: Option<String>,
+
+    /// Record video only
+    #[arg(long)]
+    no_audio: bool,
+
+    #[command(flatten)]
+    record: RecordOptions,
+}
+
+/// Kinds of capture devices
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DeviceKind {
+    Video,
+    Audio,
+}
+
+/// A camera or microphone as reported by ffmpeg
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct CaptureDevice {
+    kind: DeviceKind,
+    /// What to pass to ffmpeg: a device path, index or name depending on the platform
+    id: String,
+    /// Human readable description
+    name: String,
+}
+
+/// Run an ffmpeg device query and return all it printed, as device lists go to stderr; the query
+/// opens no real input, so ffmpeg failing is expected and not an error
+fn ffmpeg_query(args: &[&str]) -> Result<String, VideoConversionError> {
+    let output = Command::new("ffmpeg").arg("-hide_banner").args(args).stdin(Stdio::null()).output().map_err(|e| {
+        match e.kind() {
+            std::io::ErrorKind::NotFound => VideoConversionError::ToolNotFound("ffmpeg".to_string()),
+            _ => VideoConversionError::io(e.to_string()),
+        }
+    })?;
+    let mut text = String::from_utf8_lossy(&output.stdout).into_owned();
+    text.push_str(&String::from_utf8_lossy(&output.stderr));
+    Ok(text)
+}
+
+/// Parse `ffmpeg -sources` output: `* NAME [DESCRIPTION] (TYPES)`, the star marking the default
+fn parse_sources(output: &str, kind: DeviceKind) -> Vec<CaptureDevice> {
+    output
+        .lines()
+        .filter(|line| line.starts_with(['*', ' ']))
+        .filter_map(|line| {
+            let line = line.trim_start_matches(['*', ' ']);
+            let (id, rest) = line.split_once(" [")?;
+            let name = rest.rsplit_once(']').map_or(rest, |(name, _)| name);
+            Some(CaptureDevice { kind, id: id.to_string(), name: name.to_string() })
+        })
+        .collect()
+}
+
+/// Parse avfoundation's `[0] FaceTime HD Camera` lines under its video and audio headings
+fn parse_avfoundation(output: &str) -> Vec<CaptureDevice> {
+    let mut kind = None;
+    let mut devices = Vec::new();
+    for line in output.lines() {
+        // Every line starts with the logging context, e.g. "[AVFoundation indev @ 0x7f8]"
+        let message = line.split_once("] ").map_or(line, |(_, message)| message).trim();
+        if message.ends_with("video devices:") {
+            kind = Some(DeviceKind::Video);
+        } else if message.ends_with("audio devices:") {
+            kind = Some(DeviceKind::Audio);
+        } else if let (Some(kind), Some((index, name))) = (kind, message.strip_prefix('[').and_then(|m| m.split_once("] "))) {
+            // Screens are listed as cameras but belong to `record screen`
+            if index.chars().all(|c| c.is_ascii_digit()) && !name.starts_with("Capture screen") {
+                devices.push(CaptureDevice { kind, id: index.to_string(), name: name.to_string() });
+            }
+        }
+    }
+    devices
+}
+
+/// Parse DirectShow's `"Integrated Camera" (video)` lines; older ffmpeg versions list the devices
+/// under video and audio headings instead
+fn parse_dshow(output: &str) -> Vec<CaptureDevice> {
+    let mut section = None;
+    let mut devices = Vec::new();
+    for line in output.lines() {
+        let message = line.split_once("] ").map_or(line, |(_, message)| message).trim();
+        if message.contains("DirectShow video devices") {
+            section = Some(DeviceKind::Video);
+        } else if message.contains("DirectShow audio devices") {
+            section = Some(DeviceKind::Audio);
+        } else if let Some(rest) = message.strip_prefix('"') {
+            let Some((name, suffix)) = rest.split_once('"') else { continue };
+            let kind = match suffix.trim() {
+                "(video)" => Some(DeviceKind::Video),
+                "(audio)" => Some(DeviceKind::Audio),
+                _ => section,
+            };
+            if let Some(kind) = kind {
+                devices.push(CaptureDevice { kind, id: name.to_string(), name: name.to_string() });
+            }
+        }
+    }
+    devices
+}
+
+/// Cameras and microphones available for recording
+fn list_devices() -> Result<Vec<CaptureDevice>, VideoConversionError> {
+    if cfg!(target_os = "macos") {
+        Ok(parse_avfoundation(&ffmpeg_query(&["-f", "avfoundation", "-list_devices", "true", "-i", ""])?))
+    } else if cfg!(windows) {
+        Ok(parse_dshow(&ffmpeg_query(&["-list_devices", "true", "-f", "dshow", "-i", "dummy"])?))
+    } else {
+        let mut devices = parse_sources(&ffmpeg_query(&["-sources", "v4l2"])?, DeviceKind::Video);
+        devices.push(CaptureDevice { kind: DeviceKind::Audio, id: "default".to_string(), name: "System default".to_string() });
+        devices.extend(parse_sources(&ffmpeg_query(&["-sources", "pulse"])?, DeviceKind::Audio));
+        Ok(devices)
+    }
+}
+
+/// Print the devices grouped by kind
+fn print_devices(devices: &[CaptureDevice]) {
+    for (kind, heading) in [(DeviceKind::Video, "Cameras"), (DeviceKind::Audio, "Microphones")] {
+        println!("{}:", heading);
+        let matching: Vec<&CaptureDevice> = devices.iter().filter(|device| device.kind == kind).collect();
+        if matching.is_empty() {
+            println!("  (none found)");
+        }
+        for device in matching {
+            match device.id == device.name {
+                true => println!("  {}", device.id),
+                false => println!("  {}  {}", device.id, device.name),
+            }
+        }
+    }
+}
+
+/// The device to record: the requested one, else the platform default or the first one found
+fn pick_device(
+    requested: Option<&str>,
+    kind: DeviceKind,
+    linux_default: &str,
+    devices: &mut Option<Vec<CaptureDevice>>,
+) -> Result<String, VideoConversionError> {
+    if let Some(requested) = requested {
+        return Ok(requested.to_string());
+    }
+    if !cfg!(any(target_os = "macos", windows)) {
+        return Ok(linux_default.to_string());
+    }
+    if devices.is_none() {
+        *devices = Some(list_devices()?);
+    }
+    let found = devices.iter().flatten().find(|device| device.kind == kind);
+    let what = match kind {
+        DeviceKind::Video => "camera",
+        DeviceKind::Audio => "microphone",
+    };
+    found.map(|device| device.id.clone()).ok_or_else(|| {
+        VideoConversionError::config(format!("no {} found; see `videelow record camera --list`", what))
+    })
+}
+
+/// ffmpeg input arguments recording the camera, and the microphone unless audio is off
+fn camera_input(options: &CameraOptions) -> Result<Vec<String>, VideoConversionError> {
+    let framerate = options.record.framerate.to_string();
+    let mut devices = None;
+    let camera = pick_device(options.camera.as_deref(), DeviceKind::Video, "/dev/video0", &mut devices)?;
+    let microphone = match options.no_audio {
+        true => None,
+        false => Some(pick_device(options.microphone.as_deref(), DeviceKind::Audio, "default", &mut devices)?),
+    };
+    let mut args: Vec<String> = ["-thread_queue_size", "1024"].map(String::from).to_vec();
+    // avfoundation and DirectShow open camera and microphone as one input, which keeps them in sync
+    if cfg!(target_os = "macos") {
+        args.extend(["-f", "avfoundation", "-framerate", &framerate, "-i"].map(String::from));
+        args.push(format!("{}:{}", camera, microphone.as_deref().unwrap_or("none")));
+    } else if cfg!(windows) {
+        args.extend(["-f", "dshow", "-framerate", &framerate, "-i"].map(String::from));
+        args.push(match &microphone {
+            Some(microphone) => format!("video={}:audio={}", camera, microphone),
+            None => format!("video={}", camera),
+        });
+    } else {
+        args.extend(["-f", "v4l2", "-framerate", &framerate, "-i", &camera].map(String::from));
+        if let Some(microphone) = &microphone {
+            args.extend(["-thread_queue_size", "1024", "-f", "pulse", "-i", microphone].map(String::from));
+        }
+    }
+    Ok(args)
+}
+
+/// ffmpeg input arguments grabbing the screen, and the audio device if one was requested; live
+/// inputs get deep packet queues so a busy encoder does not drop frames
+fn screen_input(options: &ScreenOptions) -> Result<Vec<String>, VideoConversionError> {
+    let framerate = options.record.framerate.to_string();
+    let mut args: Vec<String> = Vec::new();
+    if cfg!(target_os = "macos") {
+        // avfoundation takes video and audio as one "VIDEO:AUDIO" input
+        let screen = options.screen.clone().unwrap_or_else(|| "0".to_string());
+        let screen = if screen.chars().all(|c| c.is_ascii_digit()) { format!("Capture screen {}", screen) } else { screen };
+        let audio = match options.audio.as_deref() {
+            None => "none",
+            Some("default") => "0",
+            Some(device) => device,
+        };
+        args.extend(["-thread_queue_size", "1024", "-f", "avfoundation", "-capture_cursor", "1"].map(String::from));
+        args.extend(["-framerate", &framerate, "-i"].map(String::from));
+        args.push(format!("{}:{}", screen, audio));
+    } else if cfg!(windows) {
+        args.extend(["-thread_queue_size", "1024", "-f", "gdigrab", "-draw_mouse", "1"].map(String::from));
+        args.extend(["-framerate", &framerate, "-i", "desktop"].map(String::from));
+        match options.audio.as_deref() {
+            None => {}
+            Some("default") => {
+                return Err(VideoConversionError::config(
+                    "name the DirectShow audio device to record, e.g. --audio \"Microphone (USB Audio)\"".to_string(),
+                ))
+            }
+            Some(device) => {
+                args.extend(["-thread_queue_size", "1024", "-f", "dshow", "-i"].map(String::from));
+                args.push(format!("audio={}", device));
+            }
+        }
+    } else {
+        let display = options.screen.clone().or_else(|| std::env::var("DISPLAY").ok()).unwrap_or_else(|| ":0.0".to_string());
+        args.extend(["-thread_queue_size", "1024", "-f", "x11grab", "-draw_mouse", "1"].map(String::from));
+        args.extend(["-framerate", &framerate, "-i", &display].map(String::from));
+        if let Some(device) = &options.audio {
+            args.extend(["-thread_queue_size", "1024", "-f", "pulse", "-i", device].map(String::from));
+        }
+    }
+    Ok(args)
+}
+
+/// Default recording name: `SOURCE-YYYY-MM-DD-HHMMSS` at the current UTC time
+pub(crate) fn default_name(source: &str) -> String {
+    let seconds = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let date = Date::from_days_since_epoch((seconds / 86_400) as i64);
+    let time = seconds % 86_400;
+    format!(
+        "{}-{:04}-{:02}-{:02}-{:02}{:02}{:02}",
+        source,
+        date.year,
+        date.month,
+        date.day,
+        time / 3600,
+        time / 60 % 60,
+        time % 60
+    )
+}
+
+/// Record the screen until Enter is pressed or the duration is up, then convert the recording into
+/// a QuickTime-compatible MP4
+pub fn record_screen(options: &ScreenOptions, progress: &Progress) -> Result<(), VideoConversionError> {
+    let input = screen_input(options)?;
+    record(&options.record, "screen", input, progress)
+}
+
+/// List capture devices, or record the camera until Enter is pressed or the duration is up and
+/// convert the recording into a QuickTime-compatible MP4
+pub fn record_camera(options: &CameraOptions, progress: &Progress) -> Result<(), VideoConversionError> {
+    if options.list {
+        print_devices(&list_devices()?);
+        return Ok(());
+    }
+    let input = camera_input(options)?;
+    record(&options.record, "camera", input, progress)
+}
+
+/// Capture `input` into a lossless intermediate file, then run it through the usual encode
+fn record(options: &RecordOptions, source: &str, input: Vec<String>, progress: &Progress) -> Result<(), VideoConversionError> {
+    let name = match &options.name {
+        Some(name) if name.is_empty() || name.contains(['/', '\\']) => {
+            return Err(VideoConversionError::config(format!(
+                "name must be a plain file name without path separators: {:?}",
+                name
+            )))
+        }
+        Some(name) => name.clone(),
+        None => default_name(source),
+    };
+    let capture_path = format!("{}/{}.mkv", options.output_dir, name);
+    let output_path = format!("{}/{}.mp4", options.output_dir, name);
+    let _span = info_span!("record", source = source, output = output_path.as_str()).entered();
+    if Path::new(&output_path).exists() {
+        return Err(VideoConversionError::FileConflict(output_path));
+    }
+    create_dir_all(&options.output_dir).map_err(|e| VideoConversionError::io(e.to_string()))?;
+
+    Pipeline::new()
+        .then(Record { input, output: &capture_path, duration: options.duration })
+        .then(Encode { output: &output_path, encode: &options.encode, transcoder: &Ffmpeg })
+        .then(Cleanup)
+        .run(progress)?;
+    Ok(())
+}
+
+/// Run ffmpeg on capture `input` until Enter is pressed or `duration` is up; the recording is
+/// encoded for speed rather than size, as it is converted right after
+pub(crate) fn capture(
+    input: &[String],
+    output: &str,
+    duration: Option<MediaTimestamp>,
+    progress: &Progress,
+) -> Result<(), VideoConversionError> {
+    let mut command = Command::new("ffmpeg");
+    command
+        .args(["-progress", "pipe:1", "-nostats", "-n"])
+        .args(input)
+        // Encoders for 4:2:0 video need even dimensions
+        .args(["-vf", "scale=trunc(iw/2)*2:trunc(ih/2)*2", "-pix_fmt", "yuv420p"])
+        .args(["-c:v", "libx264", "-preset", "ultrafast", "-crf", "0", "-c:a", "pcm_s16le"]);
+    if let Some(duration) = duration {
+        command.arg("-t").arg(duration.to_ffmpeg());
+    }
+    command.arg(output);
+    run_capture(&mut command, output, duration, progress)
+}
+
+/// Run a capturing ffmpeg `command`, which must report `-progress pipe:1` and stop by itself after
+/// `duration`, until it ends or Enter is pressed
+pub(crate) fn run_capture(
+    command: &mut Command,
+    output: &str,
+    duration: Option<MediaTimestamp>,
+    progress: &Progress,
+) -> Result<(), VideoConversionError> {
+    command.stdout(Stdio::piped()).stderr(logging::child_stderr());
+    let mut child = ChildProcess::spawn_with_stdin(command, Stdio::piped())?;
+    match duration {
+        Some(duration) => println!("Recording for {} (press Enter to stop early, Ctrl-C to discard)...", duration),
+        None => println!("Recording... press Enter to stop (Ctrl-C discards the recording)"),
+    }
+    if let Some(mut stdin) = child.stdin() {
+        // ffmpeg finishes the file properly when it reads `q`
+        on_enter(move || {
+            let _ = stdin.write_all(b"q").and_then(|_| stdin.flush());
+        });
+    }
+
+    if let Some(stdout) = child.stdout() {
+        let mut parser = FfmpegProgress::new(duration.map(|d| d.as_secs_f64()));
+        for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+            if let Some(event) = parser.parse_line(&line) {
+                progress.emit(event);
+            }
+        }
+    }
+
+    let status = child.wait().map_err(|e| VideoConversionError::io(e.to_string()))?;
+    if !status.success() {
+        return Err(exit_error(command, status));
+    }
+    console!(Success, "Recording saved: {}", output);
+    Ok(())
+}
+
+/// Call `stop` once Enter is pressed; the thread outlives a recording that ends on its own,
+/// blocked on a line that never comes
+pub(crate) fn on_enter(stop: impl FnOnce() + Send + 'static) {
+    thread::spawn(move || {
+        let mut line = String::new();
+        if matches!(std::io::stdin().lock().read_line(&mut line), Ok(read) if read > 0) {
+            stop();
+        }
+    });
+}
@@ -0,0 +1,86 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use tracing::{debug, info_span};
+
+use crate::album::read_tags;
+use crate::analyze;
+use crate::musicbrainz::write_tags;
+use crate::progress::Progress;
+use crate::{console, segmented, VideoConversionError};
+
+/// Loudness ReplayGain 2.0 levels tracks to, in LUFS
+const REFERENCE_LUFS: f64 = -18.0;
+
+const TRACK_GAIN: &str = "REPLAYGAIN_TRACK_GAIN";
+const TRACK_PEAK: &str = "REPLAYGAIN_TRACK_PEAK";
+const ALBUM_GAIN: &str = "REPLAYGAIN_ALBUM_GAIN";
+const ALBUM_PEAK: &str = "REPLAYGAIN_ALBUM_PEAK";
+
+/// Gain as ReplayGain tags spell it, such as `-4.52 dB`
+fn format_gain(gain: f64) -> String {
+    format!("{:.2} dB", gain)
+}
+
+/// Peak as a linear sample value, where 1.0 is full scale
+fn format_peak(peak_dbtp: f64) -> String {
+    format!("{:.6}", 10f64.powf(peak_dbtp / 20.0))
+}
+
+/// Gain in dB from a tag such as `-4.52 dB`
+fn parse_gain(value: &str) -> Option<f64> {
+    value.trim().trim_end_matches("dB").trim().parse().ok()
+}
+
+/// Peak in dBTP from a linear tag value
+fn parse_peak(value: &str) -> Option<f64> {
+    value.trim().parse::<f64>().ok().filter(|peak| *peak > 0.0).map(|peak| 20.0 * peak.log10())
+}
+
+/// Measure `path` and tag it with its ReplayGain track gain and peak; the audio is left alone
+pub(crate) fn tag_track(path: &Path, progress: &Progress) -> Result<(), VideoConversionError> {
+    let _span = info_span!("replaygain", path = %path.display()).entered();
+    let loudness = analyze::loudness(path, progress)?;
+    let gain = REFERENCE_LUFS - loudness.integrated_lufs;
+    debug!(?loudness, gain, "track gain");
+    write_tags(path, &[(TRACK_GAIN, format_gain(gain)), (TRACK_PEAK, format_peak(loudness.true_peak_dbtp))])?;
+    println!("ReplayGain {:+.2} dB (peak {:.1} dBTP): {}", gain, loudness.true_peak_dbtp, path.display());
+    Ok(())
+}
+
+/// Track tags of a file whose audio was just turned up by `gain` dB, for files tagged before
+pub(crate) fn after_gain(tags: &BTreeMap<String, String>, gain: f64) -> Vec<(&'static str, String)> {
+    let mut updated = Vec::new();
+    if let Some(old) = tags.get(&TRACK_GAIN.to_ascii_lowercase()).and_then(|value| parse_gain(value)) {
+        updated.push((TRACK_GAIN, format_gain(old - gain)));
+    }
+    if let Some(old) = tags.get(&TRACK_PEAK.to_ascii_lowercase()).and_then(|value| parse_peak(value)) {
+        updated.push((TRACK_PEAK, format_peak(old + gain)));
+    }
+    updated
+}
+
+/// Tag `tracks` with the ReplayGain album gain and peak, from the track tags `tag_track` wrote
+/// and the tracks' lengths, so players keep the loudness differences between them
+pub(crate) fn tag_album(tracks: &[&Path]) -> Result<(), VideoConversionError> {
+    let _span = info_span!("replaygain_album", tracks = tracks.len()).entered();
+    let (mut parts, mut peak) = (Vec::new(), f64::NEG_INFINITY);
+    for track in tracks {
+        let tags = read_tags(track);
+        let gain = tags.get(&TRACK_GAIN.to_ascii_lowercase()).and_then(|value| parse_gain(value));
+        let track_peak = tags.get(&TRACK_PEAK.to_ascii_lowercase()).and_then(|value| parse_peak(value));
+        let (Some(gain), Some(track_peak)) = (gain, track_peak) else {
+            return Err(VideoConversionError::encoding(format!("{} has no ReplayGain track tags", track.display())));
+        };
+        let duration = segmented::probe_duration(&track.to_string_lossy()).unwrap_or(1.0);
+        parts.push((REFERENCE_LUFS - gain, duration));
+        peak = peak.max(track_peak);
+    }
+    let gain = REFERENCE_LUFS - analyze::combined_loudness(&parts);
+    debug!(gain, peak, "album gain");
+    for track in tracks {
+        write_tags(track, &[(ALBUM_GAIN, format_gain(gain)), (ALBUM_PEAK, format_peak(peak))])?;
+    }
+    console!(Success, "ReplayGain album gain {:+.2} dB on {} tracks", gain, tracks.len());
+    Ok(())
+}
@@ -0,0 +1,210 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::fs::{create_dir, create_dir_all, write};
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::VideoConversionError;
+
+/// stderr lines kept per tool run; the end of the output is where tools explain their failure
+const STDERR_LINES: usize = 200;
+
+/// Whether tool runs are recorded for failure reports
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+thread_local! {
+    /// Recording of the download running on this thread; concurrent downloads each have their own
+    static RECORDING: RefCell<Recording> = RefCell::default();
+}
+
+/// What one download recorded, shared with the threads working for it
+#[derive(Clone, Default)]
+pub(crate) struct Recording(Arc<Mutex<Recorded>>);
+
+#[derive(Default)]
+struct Recorded {
+    /// Tool runs of the download
+    runs: Vec<ToolRun>,
+    /// ffprobe output of the files a failing pipeline left behind, captured before rollback removes them
+    partials: Vec<(PathBuf, String)>,
+}
+
+impl Recording {
+    fn lock(&self) -> std::sync::MutexGuard<'_, Recorded> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// A recorded tool run, for its stderr and exit status
+#[derive(Clone)]
+pub(crate) struct RunHandle {
+    recording: Recording,
+    index: usize,
+}
+
+struct ToolRun {
+    program: String,
+    command: String,
+    status: Option<ExitStatus>,
+    stderr: VecDeque<String>,
+}
+
+/// Record tool runs from now on, so a failing download can be reported
+pub(crate) fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+pub(crate) fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Start a new recording for the download about to run on this thread, leaving those of other
+/// threads alone
+pub(crate) fn reset() {
+    RECORDING.set(Recording::default());
+}
+
+/// Recording of this thread's download, for helper threads to `attach` to
+pub(crate) fn current() -> Recording {
+    RECORDING.with_borrow(Recording::clone)
+}
+
+/// Record the tools this thread runs into `recording`, as part of the download it belongs to
+pub(crate) fn attach(recording: Recording) {
+    RECORDING.set(recording);
+}
+
+/// Record a tool about to run; returns the handle for its stderr and exit status
+pub(crate) fn record_command(command: &Command) -> Option<RunHandle> {
+    if !enabled() {
+        return None;
+    }
+    let recording = current();
+    let mut recorded = recording.lock();
+    recorded.runs.push(ToolRun {
+        // Only the base name, as it becomes part of a file name
+        program: Path::new(command.get_program()).file_name().unwrap_or_default().to_string_lossy().into_owned(),
+        command: format!("{:?}", command),
+        status: None,
+        stderr: VecDeque::new(),
+    });
+    let index = recorded.runs.len() - 1;
+    drop(recorded);
+    Some(RunHandle { recording, index })
+}
+
+pub(crate) fn record_stderr(run: &RunHandle, line: &str) {
+    if let Some(run) = run.recording.lock().runs.get_mut(run.index) {
+        if run.stderr.len() == STDERR_LINES {
+            run.stderr.pop_front();
+        }
+        run.stderr.push_back(line.to_string());
+    }
+}
+
+pub(crate) fn record_exit(run: &RunHandle, status: ExitStatus) {
+    if let Some(run) = run.recording.lock().runs.get_mut(run.index) {
+        run.status = Some(status);
+    }
+}
+
+/// Probe the files of a failed pipeline before they are removed
+pub(crate) fn record_partials<'a>(paths: impl IntoIterator<Item = &'a PathBuf>) {
+    if !enabled() {
+        return;
+    }
+    let mut paths: Vec<&PathBuf> = paths.into_iter().filter(|path| path.is_file()).collect();
+    paths.sort();
+    paths.dedup();
+    let probes: Vec<(PathBuf, String)> = paths
+        .into_iter()
+        .map(|path| {
+            let probe = diagnostic_output(
+                Command::new("ffprobe").args(["-v", "error", "-show_format", "-show_streams", "-of", "json"]).arg(path),
+            );
+            (path.clone(), probe)
+        })
+        .collect();
+    current().lock().partials.extend(probes);
+}
+
+/// Run a diagnostic command directly rather than as a tracked tool, so it neither shows up in the
+/// report it is gathering data for nor fails it; returns stdout and stderr, or why it did not run
+fn diagnostic_output(command: &mut Command) -> String {
+    match command.stdin(Stdio::null()).output() {
+        Ok(output) => {
+            let mut text = String::from_utf8_lossy(&output.stdout).into_owned();
+            text.push_str(&String::from_utf8_lossy(&output.stderr));
+            text
+        }
+        Err(e) => format!("{:?} failed: {}\n", command, e),
+    }
+}
+
+/// Write everything recorded for the failed download of `url` to a new directory in `dir`,
+/// returning that directory
+pub(crate) fn write_report(dir: &str, url: &str, error: &VideoConversionError) -> Result<PathBuf, VideoConversionError> {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let name = format!("videelow-failure-{}-{}", now, std::process::id());
+    // Downloads failing at once in the same second each get their own bundle
+    let mut bundle = Path::new(dir).join(&name);
+    let mut attempt = 1;
+    let io_error = |path: &Path, e: std::io::Error| {
+        VideoConversionError::io(format!("Failed to write {}", path.display())).caused_by(e)
+    };
+    create_dir_all(dir).map_err(|e| io_error(Path::new(dir), e))?;
+    loop {
+        match create_dir(&bundle) {
+            Ok(()) => break,
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+                attempt += 1;
+                bundle = Path::new(dir).join(format!("{}-{}", name, attempt));
+            }
+            Err(e) => return Err(io_error(&bundle, e)),
+        }
+    }
+    let save = |name: &str, contents: &str| {
+        let path = bundle.join(name);
+        write(&path, contents).map_err(|e| io_error(&path, e))
+    };
+
+    let mut summary = String::new();
+    let _ = writeln!(summary, "url: {}", url);
+    let _ = writeln!(summary, "error: {}", error);
+    let _ = writeln!(summary, "exit code: {}", error.exit_code());
+    let _ = writeln!(summary, "videelow: {}", env!("CARGO_PKG_VERSION"));
+    let _ = writeln!(summary, "platform: {}-{}", std::env::consts::ARCH, std::env::consts::OS);
+    let _ = writeln!(summary, "time: {} (Unix seconds)", now);
+    let _ = writeln!(summary, "arguments: {:?}", std::env::args().collect::<Vec<_>>());
+    save("summary.txt", &summary)?;
+
+    let mut versions = String::new();
+    for (tool, flag) in [("yt-dlp", "--version"), ("ffmpeg", "-version"), ("ffprobe", "-version")] {
+        let output = diagnostic_output(Command::new(tool).arg(flag));
+        let _ = writeln!(versions, "{}: {}", tool, output.lines().next().unwrap_or_default());
+    }
+    save("versions.txt", &versions)?;
+
+    let Recorded { runs, partials } = std::mem::take(&mut *current().lock());
+    let mut commands = String::new();
+    for (index, run) in runs.iter().enumerate() {
+        let status = run.status.map_or_else(|| "did not finish".to_string(), |status| status.to_string());
+        let _ = writeln!(commands, "[{}] {}\n    {}", index + 1, run.command, status);
+        if !run.stderr.is_empty() {
+            let lines: Vec<&str> = run.stderr.iter().map(String::as_str).collect();
+            save(&format!("stderr-{:02}-{}.log", index + 1, run.program), &(lines.join("\n") + "\n"))?;
+        }
+    }
+    save("commands.txt", &commands)?;
+
+    for (index, (path, probe)) in partials.iter().enumerate() {
+        save(&format!("probe-{:02}.txt", index + 1), &format!("{}\n\n{}", path.display(), probe))?;
+    }
+
+    Ok(bundle)
+}
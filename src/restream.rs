@@ -0,0 +1,238 @@
+use std::path::Path;
+use std::process::Command;
+
+use clap::ValueEnum;
+use serde::Deserialize;
+use serde_json::Value;
+use tracing::{debug, info, info_span};
+
+use crate::codecs::Bitrate;
+use crate::filtergraph::{FilterChain, Scale};
+use crate::progress::Progress;
+use crate::ytdlp::YtDlpOptions;
+use crate::{command_output, console, logging, metadata, run_ffmpeg, urls, VideoConversionError};
+
+/// Seconds between keyframes of a transcoded stream, which most ingest servers ask for
+const KEYFRAME_INTERVAL_SECONDS: u32 = 2;
+
+/// Whether the source is re-encoded on its way to the ingest
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum, Debug, Default)]
+pub enum RestreamMode {
+    /// Copy H.264 video with AAC audio as it is and transcode anything else
+    #[default]
+    Auto,
+    /// Pass the streams through untouched; the ingest must accept their codecs
+    Copy,
+    /// Re-encode to H.264 and AAC at the requested bitrates
+    Transcode,
+}
+
+/// What `restream` pulls and where it pushes it
+#[derive(clap::Args, Debug, Clone)]
+pub struct RestreamOptions {
+    /// Video page, live stream or manifest URL, or a local file
+    source: String,
+
+    /// RTMP ingest URL including the stream key, e.g. rtmp://localhost/live/KEY
+    target: String,
+
+    /// Copy or re-encode the streams
+    #[arg(long, value_enum, value_name = "MODE", default_value = "auto")]
+    mode: RestreamMode,
+
+    /// Video bitrate when transcoding
+    #[arg(long, value_name = "RATE", default_value = "4500k")]
+    video_bitrate: Bitrate,
+
+    /// Audio bitrate when transcoding
+    #[arg(long, value_name = "RATE", default_value = "160k")]
+    audio_bitrate: Bitrate,
+
+    /// Pick a source no taller than this and scale down to it when transcoding
+    #[arg(long, value_name = "PIXELS", value_parser = clap::value_parser!(u32).range(144..=4320))]
+    max_height: Option<u32>,
+
+    #[command(flatten)]
+    ytdlp: YtDlpOptions,
+}
+
+/// A media input of the source: a file or URL, with the HTTP headers a URL must be fetched with
+#[derive(Debug, Clone)]
+struct Input {
+    location: String,
+    headers: Option<String>,
+}
+
+/// What the source turned out to be
+#[derive(Debug, Clone)]
+struct Source {
+    /// One input carrying everything, or a video input followed by an audio input
+    inputs: Vec<Input>,
+    video_codec: Option<String>,
+    audio_codec: Option<String>,
+    /// Live sources arrive in real time; everything else has to be paced
+    live: bool,
+    duration: Option<f64>,
+}
+
+impl Source {
+    /// Whether FLV, and with it every RTMP ingest, carries the streams without re-encoding
+    fn copyable(&self) -> bool {
+        let video = self.video_codec.as_deref().is_some_and(|codec| codec.starts_with("h264") || codec.starts_with("avc1"));
+        let audio = self.audio_codec.as_deref().is_none_or(|codec| codec.starts_with("aac") || codec.starts_with("mp4a"));
+        video && audio
+    }
+}
+
+#[derive(Deserialize, Default)]
+struct ProbeOutput {
+    #[serde(default)]
+    streams: Vec<ProbeStream>,
+    format: Option<ProbeFormat>,
+}
+
+#[derive(Deserialize)]
+struct ProbeStream {
+    codec_type: Option<String>,
+    codec_name: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ProbeFormat {
+    duration: Option<String>,
+}
+
+/// A local file as the source, with the codecs ffprobe finds in it
+fn file_source(path: &Path) -> Result<Source, VideoConversionError> {
+    let output = command_output(
+        Command::new("ffprobe")
+            .args(["-v", "error", "-show_entries", "stream=codec_type,codec_name:format=duration", "-of", "json"])
+            .arg(path)
+            .stderr(logging::child_stderr()),
+    )?;
+    if !output.status.success() {
+        return Err(VideoConversionError::encoding(format!("ffprobe cannot read {}", path.display())));
+    }
+    let parsed: ProbeOutput = serde_json::from_slice(&output.stdout)
+        .map_err(|e| VideoConversionError::io("unexpected ffprobe output").caused_by(e))?;
+    let codec = |kind: &str| {
+        parsed.streams.iter().find(|stream| stream.codec_type.as_deref() == Some(kind)).and_then(|stream| stream.codec_name.clone())
+    };
+    Ok(Source {
+        inputs: vec![Input { location: path.to_string_lossy().into_owned(), headers: None }],
+        video_codec: codec("video"),
+        audio_codec: codec("audio"),
+        live: false,
+        duration: parsed.format.and_then(|format| format.duration).and_then(|duration| duration.parse().ok()),
+    })
+}
+
+/// HTTP headers yt-dlp says a format must be fetched with, in ffmpeg's `-headers` form
+fn headers(format: &Value) -> Option<String> {
+    let headers = format.get("http_headers")?.as_object()?;
+    let lines: String = headers.iter().filter_map(|(name, value)| Some(format!("{}: {}\r\n", name, value.as_str()?))).collect();
+    (!lines.is_empty()).then_some(lines)
+}
+
+/// A page or manifest URL as the source, resolved by yt-dlp to the media URLs behind it
+fn url_source(url: &str, options: &RestreamOptions) -> Result<Source, VideoConversionError> {
+    let selector = match options.max_height {
+        Some(height) => format!("bv*[height<={0}]+ba/b[height<={0}]/bv*+ba/b", height),
+        None => "bv*+ba/b".to_string(),
+    };
+    let info = metadata::fetch_raw_info(url, &selector, &options.ytdlp)?;
+    // Merged formats list their parts; single formats are the info itself
+    let formats: Vec<&Value> = match info.get("requested_formats").and_then(Value::as_array) {
+        Some(formats) if !formats.is_empty() => formats.iter().collect(),
+        _ => vec![&info],
+    };
+    let codec = |key: &str| {
+        formats
+            .iter()
+            .filter_map(|format| format.get(key).and_then(Value::as_str))
+            .find(|codec| *codec != "none")
+            .map(str::to_string)
+    };
+    let inputs = formats
+        .iter()
+        .map(|format| {
+            let location = format.get("url").and_then(Value::as_str).ok_or_else(|| {
+                VideoConversionError::extraction(format!("yt-dlp found no stream URL for {}", url))
+            })?;
+            Ok(Input { location: location.to_string(), headers: headers(format) })
+        })
+        .collect::<Result<Vec<_>, VideoConversionError>>()?;
+    Ok(Source {
+        inputs,
+        video_codec: codec("vcodec"),
+        audio_codec: codec("acodec"),
+        live: info.get("is_live").and_then(Value::as_bool).unwrap_or(false),
+        duration: info.get("duration").and_then(Value::as_f64),
+    })
+}
+
+/// Pull `options.source` and push it to the RTMP ingest at `options.target` until the source ends
+/// or the run is interrupted
+pub fn run(options: &RestreamOptions, progress: &Progress) -> Result<(), VideoConversionError> {
+    let target = options.target.to_ascii_lowercase();
+    if !target.starts_with("rtmp://") && !target.starts_with("rtmps://") {
+        return Err(VideoConversionError::config(format!("{} is not an rtmp:// or rtmps:// URL", options.target)));
+    }
+    let _span = info_span!("restream", source = options.source.as_str()).entered();
+    let path = Path::new(&options.source);
+    let source = match path.is_file() {
+        true => file_source(path)?,
+        false => url_source(&urls::normalize(&options.source)?.url, options)?,
+    };
+    debug!(?source, "resolved source");
+    let transcode = match options.mode {
+        RestreamMode::Auto => !source.copyable() || options.max_height.is_some() && source.live,
+        RestreamMode::Copy => false,
+        RestreamMode::Transcode => true,
+    };
+
+    let mut command = Command::new("ffmpeg");
+    command.args(["-progress", "pipe:1", "-nostats"]);
+    for input in &source.inputs {
+        if !source.live {
+            // Send recordings at playback speed, as a live ingest expects
+            command.arg("-re");
+        }
+        if let Some(headers) = &input.headers {
+            command.arg("-headers").arg(headers);
+        }
+        command.arg("-i").arg(&input.location);
+    }
+    match source.inputs.len() {
+        1 => command.args(["-map", "0:v:0", "-map", "0:a:0?"]),
+        _ => command.args(["-map", "0:v:0", "-map", "1:a:0?"]),
+    };
+    if transcode {
+        let video_bitrate = options.video_bitrate.to_string();
+        let buffer = Bitrate::from_bits_per_second(options.video_bitrate.bits_per_second() * 2).to_string();
+        if let Some(height) = options.max_height {
+            command.arg("-vf").arg(FilterChain::new().then(Scale::to_height(height)).to_string());
+        }
+        command
+            .args(["-c:v", "libx264", "-preset", "veryfast", "-tune", "zerolatency", "-pix_fmt", "yuv420p"])
+            .args(["-b:v", &video_bitrate, "-maxrate", &video_bitrate, "-bufsize", &buffer])
+            .arg("-force_key_frames")
+            .arg(format!("expr:gte(t,n_forced*{})", KEYFRAME_INTERVAL_SECONDS))
+            .args(["-c:a", "aac", "-ar", "44100", "-b:a"])
+            .arg(options.audio_bitrate.to_string());
+    } else {
+        command.args(["-c", "copy"]);
+    }
+    command.args(["-f", "flv"]).arg(&options.target);
+
+    info!(transcode, live = source.live, "restreaming");
+    println!(
+        "{} {} to {} (Ctrl+C stops)...",
+        if transcode { "Transcoding" } else { "Relaying" },
+        if source.live { "live stream" } else { "video" },
+        options.target
+    );
+    run_ffmpeg(&mut command, source.duration.filter(|_| !source.live), progress).map_err(VideoConversionError::conversion)?;
+    console!(Success, "Restream finished");
+    Ok(())
+}
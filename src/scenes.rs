@@ -0,0 +1,170 @@
+use std::fs::{self, create_dir_all};
+use std::path::Path;
+use std::process::Command;
+
+use tracing::{debug, info_span};
+
+use crate::codecs::Container;
+use crate::progress::Progress;
+use crate::timestamp::MediaTimestamp;
+use crate::{console, logging, run_command, run_ffmpeg, verify, EncodeOptions, VideoConversionError};
+
+/// What `scenes` looks for and where it cuts
+#[derive(clap::Args, Debug, Clone)]
+pub struct SceneOptions {
+    /// Video to analyze
+    file: String,
+
+    /// How different consecutive frames must be to count as a cut, from 0 (any change) to 1
+    #[arg(long, default_value_t = 0.4, value_parser = parse_threshold)]
+    threshold: f64,
+
+    /// Merge scenes shorter than this (seconds, MM:SS or HH:MM:SS) into the one before
+    #[arg(long, value_name = "DURATION", default_value = "1")]
+    min_length: MediaTimestamp,
+
+    /// Also write every scene to its own file in a directory named after the video
+    #[arg(long)]
+    split: bool,
+
+    /// Split by copying the streams instead of re-encoding; fast, but cuts move to the keyframes
+    /// before the scene changes
+    #[arg(long, requires = "split")]
+    copy: bool,
+
+    #[command(flatten)]
+    encode: EncodeOptions,
+}
+
+fn parse_threshold(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(threshold) if (0.0..=1.0).contains(&threshold) => Ok(threshold),
+        _ => Err(format!("invalid threshold {:?} (expected a number from 0 to 1)", s)),
+    }
+}
+
+/// Times in seconds of the frames ffmpeg's metadata filter printed, one `pts_time:` per frame
+fn parse_frame_times(text: &str) -> Vec<f64> {
+    text.lines()
+        .filter_map(|line| line.split_whitespace().find_map(|field| field.strip_prefix("pts_time:")))
+        .filter_map(|time| time.parse().ok())
+        .collect()
+}
+
+/// Drop cuts that would leave a scene shorter than `min_length`, including the last one
+fn merge_short(cuts: &[f64], duration: f64, min_length: f64) -> Vec<f64> {
+    let mut kept: Vec<f64> = Vec::new();
+    for &cut in cuts {
+        let previous = kept.last().copied().unwrap_or(0.0);
+        if cut - previous >= min_length && duration - cut >= min_length {
+            kept.push(cut);
+        }
+    }
+    kept
+}
+
+/// Times of the scene changes in `input`, decoding the whole video once
+fn detect(input: &Path, threshold: f64, duration: f64, progress: &Progress) -> Result<Vec<f64>, VideoConversionError> {
+    // ffmpeg runs in the temporary directory so that the metadata file name needs no escaping
+    // inside the filter graph
+    let dir = std::env::temp_dir();
+    let list_name = format!("videelow-scenes-{}.txt", std::process::id());
+    let input = fs::canonicalize(input)
+        .map_err(|e| VideoConversionError::io(format!("Failed to resolve {}", input.display())).caused_by(e))?;
+    let mut command = Command::new("ffmpeg");
+    command
+        .current_dir(&dir)
+        .args(["-progress", "pipe:1", "-nostats", "-i"])
+        .arg(input)
+        .args(["-map", "0:v:0", "-an", "-sn", "-dn", "-vf"])
+        .arg(format!("select='gt(scene,{})',metadata=print:file={}", threshold, list_name))
+        .args(["-f", "null", "-"]);
+    let result = run_ffmpeg(&mut command, Some(duration), progress);
+    let list_path = dir.join(&list_name);
+    let text = fs::read_to_string(&list_path).unwrap_or_default();
+    let _ = fs::remove_file(&list_path);
+    result.map_err(VideoConversionError::conversion)?;
+    Ok(parse_frame_times(&text))
+}
+
+/// Write the part of `input` from `start` to `end` seconds to `output`
+fn write_scene(input: &Path, output: &Path, start: f64, end: f64, options: &SceneOptions) -> Result<(), VideoConversionError> {
+    let position = |seconds: f64| MediaTimestamp::from_secs_f64(seconds).unwrap_or(MediaTimestamp::ZERO).to_ffmpeg();
+    let mut command = options.encode.ffmpeg_command();
+    command.args(["-nostats", "-n", "-ss"]).arg(position(start)).arg("-to").arg(position(end)).arg("-i").arg(input);
+    if options.copy {
+        command.args(["-map", "0", "-c", "copy"]);
+    } else {
+        command
+            .args(["-map", "0:v:0", "-map", "0:a:0?"])
+            .args(options.encode.video_filter_args())
+            .args(options.encode.video_args())
+            .args(options.encode.video_codec().mp4_tag().map(|tag| ["-tag:v", tag]).into_iter().flatten())
+            .args(options.encode.audio_args(Container::Mp4));
+        if let Some(threads) = options.encode.threads {
+            command.arg("-threads").arg(threads.to_string());
+        }
+    }
+    command.args(["-map_chapters", "-1"]);
+    if output.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("mp4") || ext.eq_ignore_ascii_case("mov")) {
+        command.args(["-movflags", "+faststart"]);
+    }
+    command
+        .args(options.encode.ffmpeg_arg.iter().flatten())
+        .arg(output)
+        .stderr(logging::child_stderr());
+    run_command(&mut command).map_err(VideoConversionError::conversion)
+}
+
+/// List the scene changes in a video, and with `--split` cut it into one file per scene
+pub fn run(options: &SceneOptions, progress: &Progress) -> Result<(), VideoConversionError> {
+    let input = Path::new(&options.file);
+    if !input.is_file() {
+        return Err(VideoConversionError::FileNotFound(options.file.clone()));
+    }
+    let _span = info_span!("scenes", input = options.file.as_str()).entered();
+    let split_dir = input.with_extension("scenes");
+    if options.split && split_dir.exists() {
+        return Err(VideoConversionError::FileConflict(split_dir.display().to_string()));
+    }
+    let duration = verify::ffprobe(input)?
+        .duration
+        .filter(|duration| *duration > 0.0)
+        .ok_or_else(|| VideoConversionError::encoding(format!("cannot tell how long {} is", options.file)))?;
+
+    println!("Detecting scene changes in {}...", options.file);
+    let detected = detect(input, options.threshold, duration, progress)?;
+    let cuts = merge_short(&detected, duration, options.min_length.as_secs_f64());
+    debug!(detected = detected.len(), kept = cuts.len(), "scene changes");
+
+    let bounds: Vec<f64> = std::iter::once(0.0).chain(cuts.iter().copied()).chain(std::iter::once(duration)).collect();
+    let timestamp = |seconds: f64| MediaTimestamp::from_secs_f64(seconds).unwrap_or(MediaTimestamp::ZERO);
+    println!("{} scenes:", bounds.len() - 1);
+    for (index, pair) in bounds.windows(2).enumerate() {
+        println!(
+            "{:>4}  {:>12} - {:<12} {:>8.1}s",
+            index + 1,
+            timestamp(pair[0]).to_string(),
+            timestamp(pair[1]).to_string(),
+            pair[1] - pair[0]
+        );
+    }
+
+    if options.split {
+        create_dir_all(&split_dir)
+            .map_err(|e| VideoConversionError::io(format!("Failed to create {}", split_dir.display())).caused_by(e))?;
+        let extension = input.extension().map_or_else(|| "mp4".to_string(), |ext| ext.to_string_lossy().into_owned());
+        let extension = if options.copy { extension } else { Container::Mp4.extension().to_string() };
+        for (index, pair) in bounds.windows(2).enumerate() {
+            let output = split_dir.join(format!("scene-{:03}.{}", index + 1, extension));
+            println!("Writing scene {} of {}...", index + 1, bounds.len() - 1);
+            if let Err(e) = write_scene(input, &output, pair[0], pair[1], options) {
+                // Half a split is no use
+                let _ = fs::remove_dir_all(&split_dir);
+                return Err(e);
+            }
+        }
+        console!(Success, "Split into {} scenes: {}", bounds.len() - 1, split_dir.display());
+    }
+    Ok(())
+}
@@ -0,0 +1,197 @@
+use std::fs::{create_dir_all, remove_dir_all};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
+use std::thread;
+
+use tracing::{info, Span};
+
+use crate::codecs::Container;
+use crate::{command_output, console, logging, report, run_command, EncodeOptions, VideoConversionError};
+
+/// Inputs shorter than this are encoded in one piece, as splitting costs more than it saves
+pub const MIN_DURATION_SECONDS: f64 = 600.0;
+
+/// Chunks per concurrent encoder, so encoders that finish early pick up remaining work
+const CHUNKS_PER_JOB: usize = 3;
+
+/// Duration of a media file in seconds, as reported by ffprobe
+pub fn probe_duration(path: &str) -> Option<f64> {
+    let output = command_output(
+        Command::new("ffprobe")
+            .args(["-v", "error", "-show_entries", "format=duration", "-of", "default=noprint_wrappers=1:nokey=1"])
+            .arg(path)
+            .stderr(Stdio::null()),
+    )
+    .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    String::from_utf8_lossy(&output.stdout).trim().parse().ok()
+}
+
+/// Pixel format of the first video stream of a media file, such as `yuv420p10le`, as reported by ffprobe
+pub fn probe_pixel_format(path: &str) -> Option<String> {
+    let output = command_output(
+        Command::new("ffprobe")
+            .args(["-v", "error", "-select_streams", "v:0", "-show_entries", "stream=pix_fmt"])
+            .args(["-of", "default=noprint_wrappers=1:nokey=1"])
+            .arg(path)
+            .stderr(Stdio::null()),
+    )
+    .ok()?;
+    let format = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (output.status.success() && !format.is_empty()).then_some(format)
+}
+
+/// Concurrent encoders to run when none were requested; x264 already scales well up to a few threads each
+fn default_jobs() -> usize {
+    thread::available_parallelism().map_or(2, |n| (n.get() / 4).clamp(2, 8))
+}
+
+/// Re-encode `input` by splitting its video at keyframes, encoding the chunks on `jobs` concurrent
+/// encoders (0 picks a count from the CPU count) and joining them with the audio encoded once;
+/// `pixel_args` are passed to every encoder after the encoder settings
+pub fn convert(
+    input: &str,
+    output: &str,
+    encode: &EncodeOptions,
+    pixel_args: &[String],
+    jobs: usize,
+    duration: f64,
+) -> Result<(), VideoConversionError> {
+    let jobs = if jobs == 0 { default_jobs() } else { jobs };
+    let work_dir = PathBuf::from(format!("{}.parts", output));
+    create_dir_all(&work_dir).map_err(|e| {
+        VideoConversionError::io(format!("Failed to create {}", work_dir.display())).caused_by(e)
+    })?;
+
+    let result = convert_in(&work_dir, input, output, encode, pixel_args, jobs, duration);
+    let _ = remove_dir_all(&work_dir);
+    result
+}
+
+fn convert_in(
+    work_dir: &Path,
+    input: &str,
+    output: &str,
+    encode: &EncodeOptions,
+    pixel_args: &[String],
+    jobs: usize,
+    duration: f64,
+) -> Result<(), VideoConversionError> {
+    // The segment muxer only cuts at keyframes, so chunks end up roughly this long
+    let chunk_seconds = (duration / (jobs * CHUNKS_PER_JOB) as f64).ceil().max(30.0);
+    println!("Splitting video into chunks of about {}s at keyframes...", chunk_seconds);
+    run_command(
+        encode
+            .ffmpeg_command()
+            .args(["-nostdin", "-v", "error", "-i"])
+            .arg(input)
+            .args(["-map", "0:v:0", "-c", "copy", "-f", "segment", "-reset_timestamps", "1", "-segment_time"])
+            .arg(chunk_seconds.to_string())
+            .arg(work_dir.join("source%04d.mkv"))
+            .stdin(Stdio::null())
+            .stderr(logging::child_stderr()),
+    )
+    .map_err(VideoConversionError::conversion)?;
+
+    let mut chunks: Vec<PathBuf> = std::fs::read_dir(work_dir)
+        .map_err(|e| VideoConversionError::io(format!("Failed to read {}", work_dir.display())).caused_by(e))?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.file_name().is_some_and(|name| name.to_string_lossy().starts_with("source")))
+        .collect();
+    chunks.sort();
+    if chunks.is_empty() {
+        return Err(VideoConversionError::encoding(format!("ffmpeg produced no chunks from {}", input)));
+    }
+
+    let encoded: Vec<PathBuf> = (0..chunks.len()).map(|i| work_dir.join(format!("encoded{:04}.mkv", i))).collect();
+    // Share the thread budget between encoders instead of letting each claim every core
+    let budget = encode.threads.map_or_else(|| thread::available_parallelism().map_or(1, |n| n.get()), |t| t as usize);
+    let threads = budget.div_ceil(jobs).max(1);
+    println!("Encoding {} chunks on {} encoders...", chunks.len(), jobs.min(chunks.len()));
+
+    let queue = Mutex::new(chunks.iter().zip(&encoded).enumerate());
+    let first_error = Mutex::new(None);
+    // Encoder threads log under the caller's job and download spans, and record into its failure report
+    let span = Span::current();
+    let recording = report::current();
+    thread::scope(|scope| {
+        for _ in 0..jobs.min(chunks.len()) {
+            scope.spawn(|| loop {
+                let _entered = span.enter();
+                report::attach(recording.clone());
+                let next = queue.lock().unwrap_or_else(|e| e.into_inner()).next();
+                let Some((index, (chunk, target))) = next else { break };
+                if first_error.lock().unwrap_or_else(|e| e.into_inner()).is_some() {
+                    break;
+                }
+                info!(chunk = index + 1, chunks = chunks.len(), "encoding chunk");
+                console!(Plain, "Encoding chunk {}/{}...", index + 1, chunks.len());
+                if let Err(e) = encode_chunk(chunk, target, encode, pixel_args, threads) {
+                    first_error.lock().unwrap_or_else(|e| e.into_inner()).get_or_insert(e);
+                    break;
+                }
+            });
+        }
+    });
+    if let Some(e) = first_error.into_inner().unwrap_or_else(|e| e.into_inner()) {
+        return Err(e);
+    }
+
+    // The concat demuxer resolves relative entries against the list file's directory
+    let list_path = work_dir.join("chunks.txt");
+    let list: String = encoded
+        .iter()
+        .filter_map(|path| path.file_name())
+        .map(|name| format!("file '{}'\n", name.to_string_lossy()))
+        .collect();
+    std::fs::write(&list_path, list)
+        .map_err(|e| VideoConversionError::io(format!("Failed to write {}", list_path.display())).caused_by(e))?;
+
+    println!("Joining chunks and encoding audio...");
+    run_command(
+        encode
+            .ffmpeg_command()
+            .args(["-nostdin", "-v", "error", "-f", "concat", "-safe", "0", "-i"])
+            .arg(&list_path)
+            .arg("-i")
+            .arg(input)
+            .args(["-map", "0:v:0", "-map", "1:a?", "-c:v", "copy"])
+            .args(encode.video_codec().mp4_tag().map(|tag| ["-tag:v", tag]).into_iter().flatten())
+            .args(encode.audio_args(Container::Mp4))
+            .args(["-movflags", "+faststart"])
+            .arg(output)
+            .stdin(Stdio::null())
+            .stderr(logging::child_stderr()),
+    )
+    .map_err(VideoConversionError::conversion)
+}
+
+/// Encode one video-only chunk with the requested encoder settings
+fn encode_chunk(
+    chunk: &Path,
+    target: &Path,
+    encode: &EncodeOptions,
+    pixel_args: &[String],
+    threads: usize,
+) -> Result<(), VideoConversionError> {
+    run_command(
+        encode
+            .ffmpeg_command()
+            .args(["-nostdin", "-v", "error", "-i"])
+            .arg(chunk)
+            .args(encode.video_filter_args())
+            .args(encode.video_args())
+            .args(pixel_args)
+            .arg("-threads")
+            .arg(threads.to_string())
+            .arg("-an")
+            .args(encode.ffmpeg_arg.iter().flatten())
+            .arg(target)
+            .stdin(Stdio::null())
+            .stderr(logging::child_stderr()),
+    )
+    .map_err(VideoConversionError::conversion)
+}
@@ -0,0 +1,346 @@
+#![cfg_attr(not(unix), allow(dead_code))]
+
+use std::fs::{create_dir_all, remove_file};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::timestamp::MediaTimestamp;
+use crate::VideoConversionError;
+
+/// A recurring `videelow download` or `videelow sync` run managed by the system's service manager
+#[derive(Debug, Clone)]
+pub struct ServiceSpec {
+    /// Service name; letters, digits, `-` and `_`
+    pub name: String,
+    /// Time between the end of one run and the start of the next
+    pub interval: MediaTimestamp,
+    /// Subcommand each run executes: `download` or `sync`
+    pub command: &'static str,
+    /// Arguments following the subcommand
+    pub args: Vec<String>,
+    /// Directory the download runs in, which relative output paths resolve against
+    pub working_dir: PathBuf,
+    /// Directories the sandboxed run may write to
+    pub writable: Vec<PathBuf>,
+}
+
+/// Reject names that would need escaping in unit file names and launchd labels
+pub fn validate_name(name: &str) -> Result<(), VideoConversionError> {
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+        return Err(VideoConversionError::config(format!(
+            "invalid service name {:?} (use letters, digits, - and _)",
+            name
+        )));
+    }
+    Ok(())
+}
+
+/// Write the service definition, register it with the service manager and start its schedule
+pub fn install(spec: &ServiceSpec) -> Result<(), VideoConversionError> {
+    validate_name(&spec.name)?;
+    if spec.interval < MediaTimestamp::from_secs(60) {
+        return Err(VideoConversionError::config("service interval must be at least one minute"));
+    }
+    let program = std::env::current_exe()
+        .map_err(|e| VideoConversionError::io("cannot locate the videelow binary").caused_by(e))?;
+    platform::install(spec, &program)
+}
+
+/// Print what the service manager knows about the service
+pub fn status(name: &str) -> Result<(), VideoConversionError> {
+    validate_name(name)?;
+    platform::status(name)
+}
+
+/// Stop the schedule and remove the service definition
+pub fn uninstall(name: &str) -> Result<(), VideoConversionError> {
+    validate_name(name)?;
+    platform::uninstall(name)
+}
+
+fn home() -> Result<PathBuf, VideoConversionError> {
+    std::env::var_os("HOME")
+        .map(PathBuf::from)
+        .ok_or_else(|| VideoConversionError::config("cannot locate the home directory; set HOME"))
+}
+
+fn write_file(path: &Path, contents: &str) -> Result<(), VideoConversionError> {
+    if let Some(dir) = path.parent() {
+        create_dir_all(dir)
+            .map_err(|e| VideoConversionError::io(format!("Failed to create {}", dir.display())).caused_by(e))?;
+    }
+    std::fs::write(path, contents)
+        .map_err(|e| VideoConversionError::io(format!("Failed to write {}", path.display())).caused_by(e))?;
+    println!("Wrote {}", path.display());
+    Ok(())
+}
+
+fn remove(path: &Path) -> Result<bool, VideoConversionError> {
+    match remove_file(path) {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(VideoConversionError::io(format!("Failed to remove {}", path.display())).caused_by(e)),
+    }
+}
+
+/// Run a service manager command, passing its output through
+fn manage(command: &mut Command) -> Result<(), VideoConversionError> {
+    let program = command.get_program().to_string_lossy().into_owned();
+    let status = command.status().map_err(|e| match e.kind() {
+        std::io::ErrorKind::NotFound => VideoConversionError::ToolNotFound(program.clone()),
+        _ => VideoConversionError::io(e.to_string()),
+    })?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(VideoConversionError::io(format!("{} exited with {}", program, status)))
+    }
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+mod platform {
+    use std::path::{Path, PathBuf};
+    use std::process::Command;
+
+    use super::{home, manage, remove, write_file, ServiceSpec};
+    use crate::VideoConversionError;
+
+    fn unit_dir() -> Result<PathBuf, VideoConversionError> {
+        Ok(match std::env::var_os("XDG_CONFIG_HOME") {
+            Some(dir) => PathBuf::from(dir),
+            None => home()?.join(".config"),
+        }
+        .join("systemd")
+        .join("user"))
+    }
+
+    fn unit_name(name: &str) -> String {
+        format!("videelow-{}", name)
+    }
+
+    /// Escape `%`, which starts a specifier in every unit setting
+    fn escape(value: &str) -> String {
+        value.replace('%', "%%")
+    }
+
+    /// Quote an argument for ExecStart=, which also expands `$` variables
+    fn quote(arg: &str) -> String {
+        let escaped = escape(arg).replace('\\', "\\\\").replace('"', "\\\"").replace('$', "$$");
+        format!("\"{}\"", escaped)
+    }
+
+    fn service_unit(spec: &ServiceSpec, program: &Path) -> String {
+        let exec: Vec<String> = std::iter::once(program.to_string_lossy().into_owned())
+            .chain(std::iter::once(spec.command.to_string()))
+            .chain(spec.args.iter().cloned())
+            .map(|arg| quote(&arg))
+            .collect();
+        let writable: Vec<String> =
+            spec.writable.iter().map(|dir| format!("\"{}\"", escape(&dir.to_string_lossy()))).collect();
+        format!(
+            "[Unit]\n\
+             Description=videelow {command}: {name}\n\
+             Wants=network-online.target\n\
+             After=network-online.target\n\
+             \n\
+             [Service]\n\
+             Type=oneshot\n\
+             WorkingDirectory={working_dir}\n\
+             ExecStart={exec}\n\
+             # SIGTERM lets videelow stop its tools and remove partial files itself\n\
+             KillMode=mixed\n\
+             TimeoutStopSec=60\n\
+             Nice=10\n\
+             IOSchedulingClass=idle\n\
+             NoNewPrivileges=yes\n\
+             PrivateTmp=yes\n\
+             ProtectSystem=strict\n\
+             ProtectHome=read-only\n\
+             ReadWritePaths={writable}\n\
+             RestrictSUIDSGID=yes\n\
+             LockPersonality=yes\n\
+             RestrictRealtime=yes\n",
+            command = spec.command,
+            name = spec.name,
+            working_dir = escape(&spec.working_dir.to_string_lossy()),
+            exec = exec.join(" "),
+            writable = writable.join(" "),
+        )
+    }
+
+    fn timer_unit(spec: &ServiceSpec) -> String {
+        format!(
+            "[Unit]\n\
+             Description=Run videelow {command} {name} every {interval}\n\
+             \n\
+             [Timer]\n\
+             OnBootSec=5min\n\
+             OnUnitInactiveSec={seconds}s\n\
+             RandomizedDelaySec=60\n\
+             \n\
+             [Install]\n\
+             WantedBy=timers.target\n",
+            command = spec.command,
+            name = spec.name,
+            interval = spec.interval,
+            seconds = spec.interval.as_secs(),
+        )
+    }
+
+    fn systemctl() -> Command {
+        let mut command = Command::new("systemctl");
+        command.arg("--user");
+        command
+    }
+
+    pub fn install(spec: &ServiceSpec, program: &Path) -> Result<(), VideoConversionError> {
+        let dir = unit_dir()?;
+        let unit = unit_name(&spec.name);
+        let service = dir.join(format!("{}.service", unit));
+        let timer = dir.join(format!("{}.timer", unit));
+        write_file(&service, &service_unit(spec, program))?;
+        write_file(&timer, &timer_unit(spec))?;
+
+        manage(systemctl().arg("daemon-reload"))?;
+        manage(systemctl().args(["enable", "--now"]).arg(format!("{}.timer", unit)))?;
+        println!("Runs continue after logout only with lingering enabled: loginctl enable-linger");
+        Ok(())
+    }
+
+    pub fn status(name: &str) -> Result<(), VideoConversionError> {
+        let unit = unit_name(name);
+        if !unit_dir()?.join(format!("{}.timer", unit)).exists() {
+            return Err(VideoConversionError::config(format!("service {:?} is not installed", name)));
+        }
+        // systemctl status exits non-zero for inactive units, which is the normal state between runs
+        let _ = manage(
+            systemctl()
+                .args(["status", "--no-pager"])
+                .arg(format!("{}.timer", unit))
+                .arg(format!("{}.service", unit)),
+        );
+        Ok(())
+    }
+
+    pub fn uninstall(name: &str) -> Result<(), VideoConversionError> {
+        let dir = unit_dir()?;
+        let unit = unit_name(name);
+        let timer = dir.join(format!("{}.timer", unit));
+        if !timer.exists() {
+            return Err(VideoConversionError::config(format!("service {:?} is not installed", name)));
+        }
+        manage(systemctl().args(["disable", "--now"]).arg(format!("{}.timer", unit)))?;
+        remove(&timer)?;
+        remove(&dir.join(format!("{}.service", unit)))?;
+        manage(systemctl().arg("daemon-reload"))
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use std::path::{Path, PathBuf};
+    use std::process::Command;
+
+    use super::{home, manage, remove, write_file, ServiceSpec};
+    use crate::VideoConversionError;
+
+    fn label(name: &str) -> String {
+        format!("videelow.{}", name)
+    }
+
+    fn plist_path(name: &str) -> Result<PathBuf, VideoConversionError> {
+        Ok(home()?.join("Library").join("LaunchAgents").join(format!("{}.plist", label(name))))
+    }
+
+    fn xml_escape(s: &str) -> String {
+        s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+    }
+
+    fn plist(spec: &ServiceSpec, program: &Path, log: &Path) -> String {
+        let arguments: String = std::iter::once(program.to_string_lossy().into_owned())
+            .chain(std::iter::once(spec.command.to_string()))
+            .chain(spec.args.iter().cloned())
+            .map(|arg| format!("        <string>{}</string>\n", xml_escape(&arg)))
+            .collect();
+        let log = xml_escape(&log.to_string_lossy());
+        format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+             <!DOCTYPE plist PUBLIC \"-//Apple//DTD PLIST 1.0//EN\" \"http://www.apple.com/DTDs/PropertyList-1.0.dtd\">\n\
+             <plist version=\"1.0\">\n\
+             <dict>\n    \
+                 <key>Label</key>\n    <string>{label}</string>\n    \
+                 <key>ProgramArguments</key>\n    <array>\n{arguments}    </array>\n    \
+                 <key>WorkingDirectory</key>\n    <string>{working_dir}</string>\n    \
+                 <key>StartInterval</key>\n    <integer>{seconds}</integer>\n    \
+                 <key>RunAtLoad</key>\n    <true/>\n    \
+                 <key>ProcessType</key>\n    <string>Background</string>\n    \
+                 <key>LowPriorityIO</key>\n    <true/>\n    \
+                 <key>Nice</key>\n    <integer>10</integer>\n    \
+                 <key>ExitTimeOut</key>\n    <integer>60</integer>\n    \
+                 <key>StandardOutPath</key>\n    <string>{log}</string>\n    \
+                 <key>StandardErrorPath</key>\n    <string>{log}</string>\n\
+             </dict>\n\
+             </plist>\n",
+            label = label(&spec.name),
+            working_dir = xml_escape(&spec.working_dir.to_string_lossy()),
+            seconds = spec.interval.as_secs(),
+        )
+    }
+
+    pub fn install(spec: &ServiceSpec, program: &Path) -> Result<(), VideoConversionError> {
+        let path = plist_path(&spec.name)?;
+        let log = home()?.join("Library").join("Logs").join("videelow").join(format!("{}.log", spec.name));
+        if let Some(dir) = log.parent() {
+            std::fs::create_dir_all(dir)
+                .map_err(|e| VideoConversionError::io(format!("Failed to create {}", dir.display())).caused_by(e))?;
+        }
+        if path.exists() {
+            // Reloading picks up the new definition
+            let _ = manage(Command::new("launchctl").arg("unload").arg(&path));
+        }
+        write_file(&path, &plist(spec, program, &log))?;
+        manage(Command::new("launchctl").args(["load", "-w"]).arg(&path))?;
+        println!("Output is logged to {}", log.display());
+        Ok(())
+    }
+
+    pub fn status(name: &str) -> Result<(), VideoConversionError> {
+        if !plist_path(name)?.exists() {
+            return Err(VideoConversionError::config(format!("service {:?} is not installed", name)));
+        }
+        manage(Command::new("launchctl").arg("list").arg(label(name)))
+    }
+
+    pub fn uninstall(name: &str) -> Result<(), VideoConversionError> {
+        let path = plist_path(name)?;
+        if !path.exists() {
+            return Err(VideoConversionError::config(format!("service {:?} is not installed", name)));
+        }
+        manage(Command::new("launchctl").args(["unload", "-w"]).arg(&path))?;
+        remove(&path).map(|_| ())
+    }
+}
+
+#[cfg(not(unix))]
+mod platform {
+    use std::path::Path;
+
+    use super::ServiceSpec;
+    use crate::VideoConversionError;
+
+    fn unsupported() -> VideoConversionError {
+        VideoConversionError::config("services are only supported with systemd or launchd")
+    }
+
+    pub fn install(_spec: &ServiceSpec, _program: &Path) -> Result<(), VideoConversionError> {
+        Err(unsupported())
+    }
+
+    pub fn status(_name: &str) -> Result<(), VideoConversionError> {
+        Err(unsupported())
+    }
+
+    pub fn uninstall(_name: &str) -> Result<(), VideoConversionError> {
+        Err(unsupported())
+    }
+}
@@ -0,0 +1,124 @@
+use std::fmt::Write as _;
+use std::path::Path;
+use std::process::Command;
+use std::str::FromStr;
+
+use tracing::info_span;
+
+use crate::timestamp::MediaTimestamp;
+use crate::{console, logging, run_command, verify, VideoConversionError};
+
+/// Columns and rows of a contact sheet, written like `4x4`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Grid {
+    columns: u32,
+    rows: u32,
+}
+
+impl FromStr for Grid {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid grid {:?} (expected COLUMNSxROWS such as 4x4)", s);
+        let (columns, rows) = s.split_once(['x', 'X']).ok_or_else(invalid)?;
+        let columns: u32 = columns.trim().parse().map_err(|_| invalid())?;
+        let rows: u32 = rows.trim().parse().map_err(|_| invalid())?;
+        if !(1..=20).contains(&columns) || !(1..=20).contains(&rows) {
+            return Err(format!("grid {:?} out of range (1 to 20 columns and rows)", s));
+        }
+        Ok(Grid { columns, rows })
+    }
+}
+
+/// What `sheet` renders
+#[derive(clap::Args, Debug, Clone)]
+pub struct SheetOptions {
+    /// Video to sample
+    file: String,
+
+    /// Columns and rows of frames
+    #[arg(long, default_value = "4x4")]
+    grid: Grid,
+
+    /// Width of the whole sheet in pixels; the height follows from the video's aspect ratio
+    #[arg(long, default_value_t = 1920, value_parser = clap::value_parser!(u32).range(64..=16384))]
+    width: u32,
+
+    /// Print each frame's position in its corner (needs an ffmpeg built with libfreetype)
+    #[arg(long)]
+    timestamps: bool,
+
+    /// Image to write; the format follows the extension (default: {file}.sheet.jpg)
+    #[arg(short, long)]
+    output: Option<String>,
+}
+
+/// Gap between frames and around the sheet, in pixels
+const PADDING: u32 = 4;
+
+/// Sample frames evenly across `options.file` and tile them into a single image
+pub fn run(options: &SheetOptions) -> Result<(), VideoConversionError> {
+    let input = Path::new(&options.file);
+    if !input.is_file() {
+        return Err(VideoConversionError::FileNotFound(options.file.clone()));
+    }
+    let output = options
+        .output
+        .clone()
+        .unwrap_or_else(|| input.with_extension("sheet.jpg").to_string_lossy().into_owned());
+    let _span = info_span!("sheet", input = options.file.as_str(), output = output.as_str()).entered();
+    if Path::new(&output).exists() {
+        return Err(VideoConversionError::FileConflict(output));
+    }
+    let probe = verify::ffprobe(input)?;
+    if probe.video_streams == 0 {
+        return Err(VideoConversionError::config(format!("{} has no video", options.file)));
+    }
+    let duration = probe
+        .duration
+        .filter(|duration| *duration > 0.0)
+        .ok_or_else(|| VideoConversionError::encoding(format!("cannot tell how long {} is", options.file)))?;
+
+    let Grid { columns, rows } = options.grid;
+    let count = columns * rows;
+    // Cells share the width left after the padding; 4:2:0 output needs even sizes
+    let cell_width = (options.width.saturating_sub(PADDING * (columns + 1)) / columns).max(16) & !1;
+    println!("Sampling {} frames from {}...", count, options.file);
+
+    // Seeking each frame as its own input is far faster than decoding the whole video
+    let mut command = Command::new("ffmpeg");
+    command.args(["-nostats", "-n"]);
+    let mut graph = String::new();
+    for index in 0..count {
+        // Frames from the middle of each slice, avoiding black first and last frames
+        let position = duration * (index as f64 + 0.5) / count as f64;
+        let timestamp = MediaTimestamp::from_secs_f64(position).unwrap_or(MediaTimestamp::ZERO);
+        command.arg("-ss").arg(timestamp.to_ffmpeg()).arg("-i").arg(input);
+        let _ = write!(graph, "[{}:v:0]trim=end_frame=1,setpts=PTS-STARTPTS,scale={}:-2,setsar=1", index, cell_width);
+        if options.timestamps {
+            // Colons separate filter options, so they are escaped once for the option and once for the graph
+            let label = MediaTimestamp::from_secs(position as u64).to_string().replace(':', "\\\\:");
+            let _ = write!(
+                graph,
+                ",drawtext=text={}:x=w-tw-6:y=h-th-6:fontsize={}:fontcolor=white:box=1:boxcolor=black@0.6:boxborderw=4",
+                label,
+                (cell_width / 20).max(10)
+            );
+        }
+        let _ = write!(graph, "[f{}];", index);
+    }
+    for index in 0..count {
+        let _ = write!(graph, "[f{}]", index);
+    }
+    let _ = write!(graph, "concat=n={}:v=1:a=0,tile={}x{}:padding={}:margin={}", count, columns, rows, PADDING, PADDING);
+    command
+        .arg("-filter_complex")
+        .arg(graph)
+        .args(["-frames:v", "1", "-q:v", "3", "-update", "1"])
+        .arg(&output)
+        .stderr(logging::child_stderr());
+    run_command(&mut command).map_err(VideoConversionError::conversion)?;
+
+    console!(Success, "Contact sheet written: {}", output);
+    Ok(())
+}
@@ -0,0 +1,209 @@
+use std::process::{Child, Command};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::VideoConversionError;
+
+/// Set once SIGINT/SIGTERM (or Ctrl-C/Ctrl-Break on Windows) arrives
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// Whether the process has been asked to stop
+pub fn requested() -> bool {
+    REQUESTED.load(Ordering::SeqCst)
+}
+
+/// Fail with `Interrupted` once shutdown was requested, so no new work starts
+pub fn check() -> Result<(), VideoConversionError> {
+    match requested() {
+        true => Err(VideoConversionError::Interrupted),
+        false => Ok(()),
+    }
+}
+
+/// Stop on SIGINT and SIGTERM by terminating the running yt-dlp/ffmpeg processes and letting the
+/// current download fail with `Interrupted`, which rolls back its partial files and finishes the job
+/// record; a second signal exits immediately
+pub fn install() -> Result<(), VideoConversionError> {
+    platform::install()
+}
+
+/// Start `command` detached from the terminal's signals, so that only this process decides
+/// when the tool stops and can clean up after it
+pub(crate) fn prepare(command: &mut Command) {
+    platform::prepare(command);
+}
+
+/// Registration of a running child to be terminated on shutdown; unregisters on drop
+pub(crate) struct Registration {
+    slot: Option<usize>,
+}
+
+/// Terminate `child` when shutdown is requested while the registration lives
+pub(crate) fn register(child: &Child) -> Registration {
+    Registration { slot: platform::register(child) }
+}
+
+/// Kill `child` and the tools it started, without waiting for it
+pub(crate) fn kill(child: &mut Child) {
+    platform::kill(child);
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        if let Some(slot) = self.slot {
+            platform::unregister(slot);
+        }
+    }
+}
+
+#[cfg(unix)]
+mod platform {
+    use std::os::unix::process::CommandExt;
+    use std::process::{Child, Command};
+    use std::sync::atomic::{AtomicI32, Ordering};
+
+    use super::REQUESTED;
+    use crate::VideoConversionError;
+
+    /// Process groups of running children; fixed slots, as the signal handler cannot allocate or lock
+    static GROUPS: [AtomicI32; 32] = [const { AtomicI32::new(0) }; 32];
+
+    extern "C" fn handle(signal: libc::c_int) {
+        if REQUESTED.swap(true, Ordering::SeqCst) {
+            // SAFETY: _exit is async-signal-safe
+            unsafe { libc::_exit(128 + signal) };
+        }
+        for group in &GROUPS {
+            let pgid = group.load(Ordering::SeqCst);
+            if pgid > 0 {
+                // SAFETY: kill is async-signal-safe; the group belongs to our own child
+                unsafe { libc::kill(-pgid, libc::SIGTERM) };
+            }
+        }
+    }
+
+    pub fn install() -> Result<(), VideoConversionError> {
+        for signal in [libc::SIGINT, libc::SIGTERM] {
+            // SAFETY: the handler only touches atomics and async-signal-safe calls
+            let previous = unsafe { libc::signal(signal, handle as extern "C" fn(libc::c_int) as libc::sighandler_t) };
+            if previous == libc::SIG_ERR {
+                return Err(VideoConversionError::io(format!(
+                    "Failed to install signal handler: {}",
+                    std::io::Error::last_os_error()
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Run the child in its own process group, which keeps terminal Ctrl-C away from it and lets
+    /// shutdown stop everything the tool itself spawned (ffmpeg under yt-dlp, aria2c)
+    pub fn prepare(command: &mut Command) {
+        command.process_group(0);
+    }
+
+    pub fn register(child: &Child) -> Option<usize> {
+        let pid = child.id() as i32;
+        let slot = GROUPS.iter().position(|group| group.compare_exchange(0, pid, Ordering::SeqCst, Ordering::SeqCst).is_ok())?;
+        // A signal that arrived between spawning and registering found nothing to stop
+        if REQUESTED.load(Ordering::SeqCst) {
+            // SAFETY: the group was just created for our own child
+            unsafe { libc::kill(-pid, libc::SIGTERM) };
+        }
+        Some(slot)
+    }
+
+    pub fn unregister(slot: usize) {
+        GROUPS[slot].store(0, Ordering::SeqCst);
+    }
+
+    pub fn kill(child: &mut Child) {
+        // SAFETY: the child leads its own process group, created in `prepare`
+        if unsafe { libc::kill(-(child.id() as i32), libc::SIGKILL) } != 0 {
+            let _ = child.kill();
+        }
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use std::os::windows::io::AsRawHandle;
+    use std::process::{Child, Command};
+    use std::sync::atomic::{AtomicPtr, Ordering};
+
+    use windows_sys::Win32::Foundation::{BOOL, FALSE, TRUE};
+    use windows_sys::Win32::System::Console::SetConsoleCtrlHandler;
+    use windows_sys::Win32::System::JobObjects::{AssignProcessToJobObject, CreateJobObjectW, TerminateJobObject};
+
+    use super::REQUESTED;
+    use crate::VideoConversionError;
+
+    /// Job Object holding every child, so one call stops them and whatever they spawned
+    static JOB: AtomicPtr<core::ffi::c_void> = AtomicPtr::new(std::ptr::null_mut());
+
+    unsafe extern "system" fn handle(_event: u32) -> BOOL {
+        if REQUESTED.swap(true, Ordering::SeqCst) {
+            // Fall through to the default handler, which exits the process
+            return FALSE;
+        }
+        let job = JOB.load(Ordering::SeqCst);
+        if !job.is_null() {
+            TerminateJobObject(job, 1);
+        }
+        TRUE
+    }
+
+    pub fn install() -> Result<(), VideoConversionError> {
+        // SAFETY: plain Win32 calls with valid arguments; the job handle lives for the rest of the process
+        unsafe {
+            let job = CreateJobObjectW(std::ptr::null(), std::ptr::null());
+            if job.is_null() || SetConsoleCtrlHandler(Some(handle), TRUE) == 0 {
+                return Err(VideoConversionError::io(format!(
+                    "Failed to install console control handler: {}",
+                    std::io::Error::last_os_error()
+                )));
+            }
+            JOB.store(job, Ordering::SeqCst);
+        }
+        Ok(())
+    }
+
+    pub fn prepare(_command: &mut Command) {}
+
+    pub fn register(child: &Child) -> Option<usize> {
+        let job = JOB.load(Ordering::SeqCst);
+        if !job.is_null() {
+            // SAFETY: both handles are valid for the duration of the call
+            unsafe { AssignProcessToJobObject(job, child.as_raw_handle()) };
+        }
+        None
+    }
+
+    pub fn unregister(_slot: usize) {}
+
+    pub fn kill(child: &mut Child) {
+        let _ = child.kill();
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+mod platform {
+    use std::process::{Child, Command};
+
+    use crate::VideoConversionError;
+
+    pub fn install() -> Result<(), VideoConversionError> {
+        Ok(())
+    }
+
+    pub fn prepare(_command: &mut Command) {}
+
+    pub fn register(_child: &Child) -> Option<usize> {
+        None
+    }
+
+    pub fn unregister(_slot: usize) {}
+
+    pub fn kill(child: &mut Child) {
+        let _ = child.kill();
+    }
+}
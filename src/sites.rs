@@ -0,0 +1,243 @@
+use url::Url;
+
+use crate::metadata::VideoInfo;
+use crate::codecs::Container;
+use crate::VideoConversionError;
+
+/// Sites with first-class handling; everything else goes through yt-dlp's generic extractors
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Site {
+    YouTube,
+    Vimeo,
+    Twitch,
+    SoundCloud,
+    Generic,
+}
+
+/// Per-site defaults for format selection and output naming
+#[derive(Debug)]
+pub struct SiteProfile {
+    pub site: Site,
+    /// Human readable site name used in log output
+    pub name: &'static str,
+    /// Hosts (without `www.`) served by this profile
+    hosts: &'static [&'static str],
+    /// yt-dlp format selector for MP4 output
+    pub video_format: &'static str,
+    /// yt-dlp format selector for MP3 output
+    pub audio_format: &'static str,
+    /// Output file name template; see `render_filename` for the placeholders
+    pub filename_template: &'static str,
+    /// Whether the site only serves audio, making MP4 output meaningless
+    pub audio_only: bool,
+}
+
+/// Known sites, checked in order; the generic profile must stay last
+pub const SITE_PROFILES: &[SiteProfile] = &[
+    SiteProfile {
+        site: Site::YouTube,
+        name: "YouTube",
+        hosts: &["youtube.com", "m.youtube.com", "music.youtube.com", "youtu.be", "youtube-nocookie.com"],
+        video_format: "bestvideo[ext=mp4]+bestaudio[ext=m4a]/best[ext=mp4]/best",
+        audio_format: "bestaudio",
+        filename_template: "{title} [{id}]",
+        audio_only: false,
+    },
+    SiteProfile {
+        site: Site::Vimeo,
+        name: "Vimeo",
+        hosts: &["vimeo.com", "player.vimeo.com"],
+        // Vimeo serves split DASH/HLS streams with varying containers
+        video_format: "bestvideo[ext=mp4]+bestaudio/best[ext=mp4]/best",
+        audio_format: "bestaudio/best",
+        filename_template: "{uploader} - {title}",
+        audio_only: false,
+    },
+    SiteProfile {
+        site: Site::Twitch,
+        name: "Twitch",
+        hosts: &["twitch.tv", "m.twitch.tv", "clips.twitch.tv"],
+        // Twitch only offers muxed HLS renditions plus an audio-only rendition
+        video_format: "best[ext=mp4]/best",
+        audio_format: "audio_only/bestaudio/best",
+        filename_template: "{uploader} - {title} [{id}]",
+        audio_only: false,
+    },
+    SiteProfile {
+        site: Site::SoundCloud,
+        name: "SoundCloud",
+        hosts: &["soundcloud.com", "m.soundcloud.com"],
+        video_format: "bestaudio/best",
+        audio_format: "bestaudio/best",
+        filename_template: "{uploader} - {title}",
+        audio_only: true,
+    },
+    SiteProfile {
+        site: Site::Generic,
+        name: "the web",
+        hosts: &[],
+        video_format: "bestvideo*+bestaudio/best",
+        audio_format: "bestaudio/best",
+        filename_template: "{title}",
+        audio_only: false,
+    },
+];
+
+/// Look up the profile for a (normalized) URL, falling back to the generic profile
+pub fn profile_for(url: &str) -> &'static SiteProfile {
+    let host = Url::parse(url)
+        .ok()
+        .and_then(|u| u.host_str().map(str::to_string))
+        .unwrap_or_default();
+    profile_for_host(&host)
+}
+
+/// Look up the profile serving `host`, falling back to the generic profile
+pub fn profile_for_host(host: &str) -> &'static SiteProfile {
+    let host = host.to_ascii_lowercase();
+    let host = host.strip_prefix("www.").unwrap_or(&host);
+
+    SITE_PROFILES
+        .iter()
+        .find(|profile| profile.hosts.contains(&host))
+        .unwrap_or(&SITE_PROFILES[SITE_PROFILES.len() - 1])
+}
+
+/// Video quality cap requested on the command line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Quality {
+    Best,
+    MaxHeight(u32),
+}
+
+impl std::str::FromStr for Quality {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("best") {
+            return Ok(Quality::Best);
+        }
+        s.trim_end_matches(['p', 'P'])
+            .parse::<u32>()
+            .ok()
+            .filter(|h| *h > 0)
+            .map(Quality::MaxHeight)
+            .ok_or_else(|| format!("invalid quality {:?} (use best or a height such as 720p)", s))
+    }
+}
+
+impl SiteProfile {
+    /// Format selector matching the requested output format and quality cap
+    pub fn format_selector(&self, format: Container, quality: Quality) -> String {
+        match format {
+            Container::Mp3 => self.audio_format.to_string(),
+            Container::Mp4 | Container::Hls | Container::Abr => match quality {
+                Quality::Best => self.video_format.to_string(),
+                Quality::MaxHeight(height) => cap_height(self.video_format, height),
+            },
+        }
+    }
+
+    /// Reject output formats the site cannot provide
+    pub fn check_format(&self, format: Container) -> Result<(), VideoConversionError> {
+        if self.audio_only && format != Container::Mp3 {
+            return Err(VideoConversionError::config(format!(
+                "{} only provides audio; use --format mp3",
+                self.name
+            )));
+        }
+        Ok(())
+    }
+
+    /// Render this profile's filename template for a video
+    pub fn filename(&self, info: &VideoInfo) -> String {
+        render_filename(self.filename_template, info)
+    }
+}
+
+/// Add a `[height<=N]` filter to every part of a selector that picks video
+fn cap_height(selector: &str, height: u32) -> String {
+    selector
+        .split('/')
+        .map(|alternative| {
+            alternative
+                .split('+')
+                .map(|part| {
+                    if part.starts_with("bestaudio") || part.starts_with("worstaudio") {
+                        part.to_string()
+                    } else {
+                        format!("{}[height<={}]", part, height)
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join("+")
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Prefer the audio in `language` (a code such as `de`, also matching `de-DE`) for every part of
+/// a selector that picks audio, falling back to the unfiltered selector for videos without that dub
+pub fn with_audio_language(selector: &str, language: &str) -> String {
+    let filtered = selector
+        .split('/')
+        .map(|alternative| {
+            let parts: Vec<&str> = alternative.split('+').collect();
+            let single = parts.len() == 1;
+            parts
+                .into_iter()
+                .map(|part| {
+                    // A lone format carries the audio too
+                    if single || part.starts_with("bestaudio") || part.starts_with("worstaudio") {
+                        format!("{}[language^={}]", part, language)
+                    } else {
+                        part.to_string()
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join("+")
+        })
+        .collect::<Vec<_>>()
+        .join("/");
+    format!("{}/{}", filtered, selector)
+}
+
+/// Fill `{id}`, `{title}`, `{uploader}` and `{upload_date}` placeholders and sanitize the result
+pub fn render_filename(template: &str, info: &VideoInfo) -> String {
+    let field = |value: &Option<String>| value.clone().unwrap_or_else(|| "NA".to_string());
+    let rendered = template
+        .replace("{id}", &field(&info.id))
+        .replace("{title}", &field(&info.title))
+        .replace("{uploader}", &field(&info.uploader.clone().or_else(|| info.channel.clone())))
+        .replace("{upload_date}", &field(&info.upload_date));
+    sanitize_filename(&rendered)
+}
+
+/// Replace characters that are invalid in file names on common filesystems and bound the length
+pub fn sanitize_filename(name: &str) -> String {
+    const MAX_BYTES: usize = 200;
+
+    let mut sanitized: String = name
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+
+    if sanitized.len() > MAX_BYTES {
+        let mut end = MAX_BYTES;
+        while !sanitized.is_char_boundary(end) {
+            end -= 1;
+        }
+        sanitized.truncate(end);
+    }
+
+    let trimmed = sanitized.trim_matches(|c: char| c == '.' || c.is_whitespace());
+    if trimmed.is_empty() {
+        "video".to_string()
+    } else {
+        trimmed.to_string()
+    }
+}
@@ -0,0 +1,151 @@
+use std::collections::BTreeMap;
+
+use clap::ValueEnum;
+use serde::Serialize;
+
+use crate::dates::Date;
+use crate::jobs::{self, Phase};
+use crate::progress::human_bytes;
+use crate::VideoConversionError;
+
+/// How `stats` groups the download history
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum, Debug)]
+pub enum Period {
+    Day,
+    /// Weeks starting on Monday
+    Week,
+}
+
+/// What `stats` summarizes
+#[derive(clap::Args, Debug, Clone)]
+pub struct StatsOptions {
+    /// Group usage by day or by week (UTC)
+    #[arg(long, value_enum, default_value = "day")]
+    by: Period,
+
+    /// Number of most recent days or weeks to show
+    #[arg(long, default_value_t = 7)]
+    last: usize,
+
+    /// Print the summary as JSON
+    #[arg(long)]
+    json: bool,
+}
+
+/// Usage over one day or week
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct Usage {
+    /// First day of the period, as YYYY-MM-DD
+    pub period: String,
+    pub succeeded: usize,
+    pub failed: usize,
+    /// Bytes received from media servers
+    pub bytes: u64,
+    pub download_seconds: f64,
+    pub convert_seconds: f64,
+    /// Time from start to finish of every item, which overlap when several run at once
+    pub wall_seconds: u64,
+}
+
+impl Usage {
+    /// Average download speed in bytes per second
+    pub fn speed(&self) -> Option<f64> {
+        (self.download_seconds > 0.0).then(|| self.bytes as f64 / self.download_seconds)
+    }
+
+    fn add(&mut self, other: &Usage) {
+        self.succeeded += other.succeeded;
+        self.failed += other.failed;
+        self.bytes += other.bytes;
+        self.download_seconds += other.download_seconds;
+        self.convert_seconds += other.convert_seconds;
+        self.wall_seconds += other.wall_seconds;
+    }
+}
+
+/// First day of the period `time` falls in
+fn period_start(period: Period, time: u64) -> Date {
+    let days = (time / 86_400) as i64;
+    match period {
+        Period::Day => Date::from_days_since_epoch(days),
+        // 1970-01-01 was a Thursday, three days after a Monday
+        Period::Week => Date::from_days_since_epoch(days - (days + 3).rem_euclid(7)),
+    }
+}
+
+/// Usage of the finished items in the job records, by period, oldest first
+pub fn usage(period: Period) -> Result<Vec<Usage>, VideoConversionError> {
+    let mut periods: BTreeMap<Date, Usage> = BTreeMap::new();
+    for item in jobs::list()?.into_iter().flat_map(|record| record.items) {
+        let Some(finished) = item.finished else { continue };
+        let start = period_start(period, finished);
+        let usage = periods.entry(start).or_insert_with(|| Usage { period: start.to_string(), ..Usage::default() });
+        match item.phase {
+            Phase::Done => usage.succeeded += 1,
+            _ => usage.failed += 1,
+        }
+        usage.bytes += item.bytes.unwrap_or(0);
+        usage.download_seconds += item.download_seconds.unwrap_or(0.0);
+        usage.convert_seconds += item.convert_seconds.unwrap_or(0.0);
+        usage.wall_seconds += item.elapsed().map_or(0, |elapsed| elapsed.as_secs());
+    }
+    Ok(periods.into_values().collect())
+}
+
+/// Format seconds as `H:MM:SS`
+fn clock(seconds: u64) -> String {
+    format!("{}:{:02}:{:02}", seconds / 3600, seconds / 60 % 60, seconds % 60)
+}
+
+fn print_row(label: &str, usage: &Usage) {
+    println!(
+        "{:<10}  {:>5}  {:>6}  {:>10}  {:>12}  {:>9}  {:>9}  {:>9}",
+        label,
+        usage.succeeded,
+        usage.failed,
+        human_bytes(usage.bytes as f64),
+        usage.speed().map_or_else(|| "-".to_string(), |speed| format!("{}/s", human_bytes(speed))),
+        clock(usage.download_seconds.round() as u64),
+        clock(usage.convert_seconds.round() as u64),
+        clock(usage.wall_seconds)
+    );
+}
+
+/// Summarize bytes downloaded, average speed and time spent over the last days or weeks
+pub fn run(options: &StatsOptions) -> Result<(), VideoConversionError> {
+    let usage = usage(options.by)?;
+    let recent = &usage[usage.len().saturating_sub(options.last)..];
+    let mut total = Usage { period: "total".to_string(), ..Usage::default() };
+    for usage in recent {
+        total.add(usage);
+    }
+    if options.json {
+        let report = serde_json::json!({
+            "periods": recent,
+            "total": total,
+            "speed": total.speed(),
+        });
+        let text = serde_json::to_string_pretty(&report).map_err(|e| VideoConversionError::io(e.to_string()))?;
+        println!("{}", text);
+        return Ok(());
+    }
+    if recent.is_empty() {
+        println!("No downloads recorded yet");
+        return Ok(());
+    }
+    let heading = match options.by {
+        Period::Day => "DAY",
+        Period::Week => "WEEK OF",
+    };
+    println!(
+        "{:<10}  {:>5}  {:>6}  {:>10}  {:>12}  {:>9}  {:>9}  {:>9}",
+        heading, "DONE", "FAILED", "RECEIVED", "AVG SPEED", "DOWNLOAD", "CONVERT", "WALL"
+    );
+    for usage in recent {
+        print_row(&usage.period, usage);
+    }
+    if recent.len() > 1 {
+        print_row("total", &total);
+    }
+    Ok(())
+}
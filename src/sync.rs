@@ -0,0 +1,180 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use tracing::{info, info_span};
+
+use crate::backend::Backends;
+use crate::config::{self, SyncSource};
+use crate::dates::Date;
+use crate::playlist::{self, PlaylistSelection};
+use crate::progress::Progress;
+use crate::{console, download_tracked, newest_first, urls, DownloadOptions, VideoConversionError};
+
+/// How far one source has been synced
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct SyncState {
+    /// ID of the newest video downloaded together with every older one
+    pub last_id: Option<String>,
+    /// Unix time of the last run
+    pub last_run: Option<u64>,
+    /// Videos newer than `last_id` that were downloaded while an older one failed
+    pub downloaded: BTreeSet<String>,
+}
+
+/// File keeping the sync state of every source, next to the job records
+fn state_path() -> Result<PathBuf, VideoConversionError> {
+    Ok(config::state_dir()?.join("sync.json"))
+}
+
+/// Sync state of every source, by name
+pub fn load_states() -> Result<BTreeMap<String, SyncState>, VideoConversionError> {
+    let path = state_path()?;
+    match std::fs::read_to_string(&path) {
+        Ok(text) => serde_json::from_str(&text)
+            .map_err(|e| VideoConversionError::io(format!("invalid sync state {}", path.display())).caused_by(e)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(BTreeMap::new()),
+        Err(e) => Err(VideoConversionError::io(format!("Failed to read {}", path.display())).caused_by(e)),
+    }
+}
+
+fn save_states(states: &BTreeMap<String, SyncState>) -> Result<(), VideoConversionError> {
+    let path = state_path()?;
+    let io_error = |e: std::io::Error| VideoConversionError::io(format!("Failed to write {}", path.display())).caused_by(e);
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(io_error)?;
+    }
+    let json = serde_json::to_string_pretty(states).map_err(|e| VideoConversionError::io(e.to_string()))?;
+    std::fs::write(&path, json + "\n").map_err(io_error)
+}
+
+/// Store the state of `name`, or forget it
+fn update_state(name: &str, state: Option<SyncState>) -> Result<(), VideoConversionError> {
+    let mut states = load_states()?;
+    match state {
+        Some(state) => states.insert(name.to_string(), state),
+        None => states.remove(name),
+    };
+    save_states(&states)
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+/// What identifies a listed video across runs: its ID where the URL carries one
+fn video_id(url: &str) -> String {
+    urls::normalize(url).ok().and_then(|source| source.video_id).unwrap_or_else(|| url.to_string())
+}
+
+/// URLs of the videos of a channel or playlist, oldest first; `since` narrows the listing to
+/// uploads from that day on, which needs a slower full extraction
+fn list_videos(url: &str, options: &DownloadOptions, since: Option<Date>) -> Result<Vec<String>, VideoConversionError> {
+    let source = urls::normalize(url)?;
+    if !source.listing {
+        return Err(VideoConversionError::config(format!("{} is not a channel or playlist", url)));
+    }
+    let mut filter = options.filter();
+    filter.since = filter.since.max(since);
+    let selection = PlaylistSelection { items: None, reverse: false, filter, newest_first: newest_first(&source) };
+    let mut entries = playlist::list_entries(&source.url, &selection, &options.ytdlp, options.youtube_api_key().as_deref())?;
+    if selection.newest_first {
+        entries.reverse();
+    }
+    Ok(entries.into_iter().map(|entry| entry.url).collect())
+}
+
+/// Remember the videos listed now as synced, so only later uploads are downloaded
+pub fn mark_synced(name: &str, source: &SyncSource, options: &DownloadOptions) -> Result<(), VideoConversionError> {
+    let listed = list_videos(&source.url, options, None)?;
+    let state = SyncState { last_id: listed.last().map(|url| video_id(url)), last_run: Some(now()), downloaded: BTreeSet::new() };
+    update_state(name, Some(state))?;
+    println!("Marked {} videos of {} as synced", listed.len(), name);
+    Ok(())
+}
+
+/// Forget how far `name` was synced
+pub fn forget(name: &str) -> Result<(), VideoConversionError> {
+    update_state(name, None)
+}
+
+/// Download the videos of `source` that are newer than the last one synced, all of them on the
+/// first run; videos after a failed one are kept track of, so the next run retries only the failures
+pub fn run(name: &str, source: &SyncSource, options: &DownloadOptions, progress: &Progress) -> Result<(), VideoConversionError> {
+    let _span = info_span!("sync", source = name).entered();
+    let mut state = load_states()?.remove(name).unwrap_or_default();
+    console!(Download, "Syncing {} ({})...", name, source.url);
+    let listed = list_videos(&source.url, options, None)?;
+    let candidates = match &state.last_id {
+        None => listed,
+        Some(last_id) => match listed.iter().position(|url| video_id(url) == *last_id) {
+            Some(position) => listed[position + 1..].to_vec(),
+            // The last synced video was deleted or fell off the listing; uploads since the day
+            // before the last run cover everything newer, whatever the time zone
+            None => {
+                let since = state.last_run.map(|time| Date::from_days_since_epoch((time / 86_400) as i64).previous_day());
+                console!(Warning, "{} no longer lists the last synced video; looking for uploads since the last run", name);
+                list_videos(&source.url, options, since)?
+            }
+        },
+    };
+    let new: Vec<String> = candidates.iter().filter(|url| !state.downloaded.contains(&video_id(url))).cloned().collect();
+
+    let result = match new.is_empty() {
+        true => {
+            println!("{}: no new videos", name);
+            Ok(())
+        }
+        false => {
+            println!("{}: {} new videos", name, new.len());
+            let succeeded = Mutex::new(Vec::new());
+            let result = download_tracked(&new, options, progress, Backends::default(), &succeeded);
+            let succeeded = succeeded.into_inner().unwrap_or_else(|e| e.into_inner());
+            state.downloaded.extend(succeeded.iter().map(|url| video_id(url)));
+            result
+        }
+    };
+    // Move the mark past the oldest videos downloaded without a gap
+    for url in &candidates {
+        let id = video_id(url);
+        if !state.downloaded.remove(&id) {
+            break;
+        }
+        state.last_id = Some(id);
+    }
+    state.last_run = Some(now());
+    info!(last_id = state.last_id.as_deref(), pending = state.downloaded.len(), "sync finished");
+    update_state(name, Some(state))?;
+    result
+}
+
+/// Print the saved sources with how far each is synced
+pub fn list(sources: &BTreeMap<String, SyncSource>) -> Result<(), VideoConversionError> {
+    if sources.is_empty() {
+        println!("No sources saved; add one with `videelow sync add NAME URL [OPTIONS...]`");
+        return Ok(());
+    }
+    let states = load_states()?;
+    for (name, source) in sources {
+        let options = match source.options.is_empty() {
+            true => String::new(),
+            false => format!(" {}", config::display_args(&source.options)),
+        };
+        println!("{}: {}{}", name, source.url, options);
+        match states.get(name) {
+            Some(state) => {
+                let last_run = state.last_run.map(|time| Date::from_days_since_epoch((time / 86_400) as i64).to_string());
+                println!(
+                    "  last synced video: {}, last run: {}",
+                    state.last_id.as_deref().unwrap_or("none"),
+                    last_run.as_deref().unwrap_or("never")
+                );
+            }
+            None => println!("  never synced"),
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,43 @@
+use std::process::Stdio;
+
+use clap::ValueEnum;
+
+use crate::ytdlp::YtDlpOptions;
+use crate::{logging, run_command, ytdlp_literal, VideoConversionError};
+
+/// Image formats thumbnails are converted to; sites often serve WebP, which media servers ignore
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum, Debug)]
+pub enum ThumbnailFormat {
+    Jpg,
+    Png,
+}
+
+impl ThumbnailFormat {
+    /// File extension, which is also the name yt-dlp converts to
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ThumbnailFormat::Jpg => "jpg",
+            ThumbnailFormat::Png => "png",
+        }
+    }
+}
+
+/// Save the video's best thumbnail as `{output_stem}.{format}`, converting it with yt-dlp's ffmpeg postprocessor
+pub fn download_thumbnail(url: &str, output_stem: &str, format: &str, ytdlp: &YtDlpOptions) -> Result<(), VideoConversionError> {
+    run_command(
+        ytdlp
+            .command()
+            .arg("--skip-download")
+            .arg("--no-playlist")
+            .arg("--write-thumbnail")
+            .arg("--convert-thumbnails")
+            .arg(format)
+            .arg("-o")
+            .arg(format!("thumbnail:{}.%(ext)s", ytdlp_literal(output_stem)))
+            .args(ytdlp.extra_args())
+            .arg(url)
+            .stdout(Stdio::null())
+            .stderr(logging::child_stderr()),
+    )
+    .map_err(VideoConversionError::download)
+}
@@ -0,0 +1,154 @@
+use std::fmt;
+use std::ops::{Add, Sub};
+use std::str::FromStr;
+use std::time::Duration;
+
+/// A position or length in a media file with millisecond precision, written as seconds (`90`),
+/// `MM:SS` or `HH:MM:SS`, each optionally with a fraction (`01:30:00.500`)
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Default)]
+pub struct MediaTimestamp {
+    millis: u64,
+}
+
+impl MediaTimestamp {
+    pub const ZERO: MediaTimestamp = MediaTimestamp { millis: 0 };
+
+    pub fn from_millis(millis: u64) -> Self {
+        MediaTimestamp { millis }
+    }
+
+    pub fn from_secs(seconds: u64) -> Self {
+        MediaTimestamp { millis: seconds * 1000 }
+    }
+
+    /// Convert fractional seconds as reported by ffprobe or yt-dlp, rounding to the nearest millisecond
+    pub fn from_secs_f64(seconds: f64) -> Option<Self> {
+        (seconds.is_finite() && seconds >= 0.0).then(|| MediaTimestamp { millis: (seconds * 1000.0).round() as u64 })
+    }
+
+    pub fn as_millis(&self) -> u64 {
+        self.millis
+    }
+
+    /// Whole seconds, rounding down
+    pub fn as_secs(&self) -> u64 {
+        self.millis / 1000
+    }
+
+    pub fn as_secs_f64(&self) -> f64 {
+        self.millis as f64 / 1000.0
+    }
+
+    /// Difference to an earlier timestamp, or `None` if `earlier` is later
+    pub fn checked_sub(self, earlier: MediaTimestamp) -> Option<MediaTimestamp> {
+        self.millis.checked_sub(earlier.millis).map(MediaTimestamp::from_millis)
+    }
+
+    /// Fixed `HH:MM:SS.mmm` form accepted by ffmpeg's `-ss`, `-to` and `-t`
+    pub fn to_ffmpeg(&self) -> String {
+        let (hours, minutes, seconds) = self.clock_parts();
+        format!("{:02}:{:02}:{:02}.{:03}", hours, minutes, seconds, self.millis % 1000)
+    }
+
+    fn clock_parts(&self) -> (u64, u64, u64) {
+        let seconds = self.as_secs();
+        (seconds / 3600, seconds / 60 % 60, seconds % 60)
+    }
+}
+
+impl FromStr for MediaTimestamp {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid timestamp {:?} (use seconds, MM:SS or HH:MM:SS, e.g. 1:30.5)", s);
+        let parts: Vec<&str> = s.trim().split(':').collect();
+        if parts.len() > 3 {
+            return Err(invalid());
+        }
+
+        let (whole, fraction) = parts[parts.len() - 1].split_once('.').unwrap_or((parts[parts.len() - 1], ""));
+        if fraction.len() > 3 || !fraction.chars().all(|c| c.is_ascii_digit()) {
+            return Err(invalid());
+        }
+        let millis = format!("{:0<3}", fraction).parse::<u64>().map_err(|_| invalid())?;
+
+        let mut fields = parts[..parts.len() - 1].to_vec();
+        fields.push(whole);
+        let seconds = fields.iter().enumerate().try_fold(0u64, |total, (i, field)| {
+            if field.is_empty() || !field.chars().all(|c| c.is_ascii_digit()) {
+                return Err(invalid());
+            }
+            let value: u64 = field.parse().map_err(|_| invalid())?;
+            // Only the leading field may exceed its unit, so 90 and 90:00 work but 1:90 is a typo
+            if i > 0 && value >= 60 {
+                return Err(invalid());
+            }
+            total.checked_mul(60).and_then(|t| t.checked_add(value)).ok_or_else(invalid)
+        })?;
+        seconds.checked_mul(1000).and_then(|s| s.checked_add(millis)).map(MediaTimestamp::from_millis).ok_or_else(invalid)
+    }
+}
+
+impl fmt::Display for MediaTimestamp {
+    /// Shortest readable form: `M:SS` or `H:MM:SS`, with milliseconds only when present
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let clock = match self.clock_parts() {
+            (0, minutes, seconds) => format!("{}:{:02}", minutes, seconds),
+            (hours, minutes, seconds) => format!("{}:{:02}:{:02}", hours, minutes, seconds),
+        };
+        match self.millis % 1000 {
+            0 => f.pad(&clock),
+            millis => f.pad(&format!("{}.{:03}", clock, millis)),
+        }
+    }
+}
+
+impl Add for MediaTimestamp {
+    type Output = MediaTimestamp;
+
+    fn add(self, other: MediaTimestamp) -> MediaTimestamp {
+        MediaTimestamp::from_millis(self.millis + other.millis)
+    }
+}
+
+impl Sub for MediaTimestamp {
+    type Output = MediaTimestamp;
+
+    /// Saturates at zero; use `checked_sub` to detect reversed ranges
+    fn sub(self, other: MediaTimestamp) -> MediaTimestamp {
+        MediaTimestamp::from_millis(self.millis.saturating_sub(other.millis))
+    }
+}
+
+impl From<MediaTimestamp> for Duration {
+    fn from(timestamp: MediaTimestamp) -> Duration {
+        Duration::from_millis(timestamp.millis)
+    }
+}
+
+impl From<Duration> for MediaTimestamp {
+    fn from(duration: Duration) -> MediaTimestamp {
+        MediaTimestamp::from_millis(duration.as_millis() as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_seconds_and_clock_forms() {
+        let millis = |s: &str| s.parse::<MediaTimestamp>().map(|t| t.as_millis());
+        assert_eq!(millis("90"), Ok(90_000));
+        assert_eq!(millis("1:30.5"), Ok(90_500));
+        assert_eq!(millis(" 01:02:03.004 "), Ok(3_723_004));
+        assert_eq!(millis("90:00"), Ok(5_400_000));
+    }
+
+    #[test]
+    fn rejects_malformed_timestamps() {
+        for input in ["", "1:90", "1::2", "1:2:3:4", "1.2345", "-5", "1:3é", "99999999999999999999"] {
+            assert!(input.parse::<MediaTimestamp>().is_err(), "{:?}", input);
+        }
+    }
+}
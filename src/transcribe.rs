@@ -0,0 +1,290 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use clap::ValueEnum;
+use serde::Deserialize;
+use tracing::{info, info_span};
+
+use crate::twitch::srt_timestamp;
+use crate::{console, logging, run_command, VideoConversionError};
+
+/// Environment variable naming the folder with whisper.cpp's `ggml-*.bin` models
+const MODELS_DIR_ENV: &str = "WHISPER_MODELS_DIR";
+
+/// Environment variable holding the key of an OpenAI-compatible endpoint
+const API_KEY_ENV: &str = "OPENAI_API_KEY";
+
+/// whisper.cpp command names, tried in order: upstream's current name, then the packaged one
+const WHISPER_COMMANDS: &[&str] = &["whisper-cli", "whisper-cpp"];
+
+/// Files `--transcribe` writes next to the output
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum, Debug)]
+pub enum TranscriptFormat {
+    /// SubRip subtitles
+    Srt,
+    /// WebVTT subtitles, for browsers
+    Vtt,
+    /// Plain text, one line per segment
+    Txt,
+}
+
+impl TranscriptFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            TranscriptFormat::Srt => "srt",
+            TranscriptFormat::Vtt => "vtt",
+            TranscriptFormat::Txt => "txt",
+        }
+    }
+}
+
+/// Speech-to-text run on each download
+#[derive(clap::Args, Debug, Clone)]
+pub struct TranscribeOptions {
+    /// Transcribe the speech with Whisper into subtitles or text next to the output
+    /// (comma-separated formats; default: srt)
+    #[arg(long, value_enum, value_name = "FORMATS", num_args = 0..=1, default_missing_value = "srt", value_delimiter = ',')]
+    transcribe: Vec<TranscriptFormat>,
+
+    /// Whisper model: a whisper.cpp model name (tiny, base, small, medium, large-v3...) looked up as
+    /// ggml-NAME.bin in $WHISPER_MODELS_DIR or ./models, or a model file; with --whisper-url, the
+    /// endpoint's model name (default: base, or whisper-1 with --whisper-url)
+    #[arg(long, value_name = "MODEL")]
+    whisper_model: Option<String>,
+
+    /// Spoken language as a code such as en or de, also added to the file names (default: detected)
+    #[arg(long, value_name = "LANG")]
+    whisper_language: Option<String>,
+
+    /// Base URL of an OpenAI-compatible transcription API, e.g. https://api.openai.com/v1, to use
+    /// instead of a local whisper.cpp; its key is read from OPENAI_API_KEY
+    #[arg(long, value_name = "URL")]
+    whisper_url: Option<String>,
+}
+
+/// A stretch of speech with its text
+#[derive(Debug, Clone, PartialEq)]
+struct Segment {
+    start: f64,
+    end: f64,
+    text: String,
+}
+
+#[derive(Deserialize)]
+struct CppOutput {
+    transcription: Vec<CppSegment>,
+}
+
+#[derive(Deserialize)]
+struct CppSegment {
+    offsets: CppOffsets,
+    text: String,
+}
+
+/// Milliseconds from the start
+#[derive(Deserialize)]
+struct CppOffsets {
+    from: u64,
+    to: u64,
+}
+
+#[derive(Deserialize)]
+struct ApiOutput {
+    segments: Option<Vec<ApiSegment>>,
+}
+
+#[derive(Deserialize)]
+struct ApiSegment {
+    start: f64,
+    end: f64,
+    text: String,
+}
+
+impl TranscribeOptions {
+    /// Whether any transcript was requested
+    pub fn enabled(&self) -> bool {
+        !self.transcribe.is_empty()
+    }
+
+    /// Path of the model file whisper.cpp loads
+    fn model_path(&self) -> Result<PathBuf, VideoConversionError> {
+        let model = self.whisper_model.as_deref().unwrap_or("base");
+        if model.ends_with(".bin") || model.contains(['/', '\\']) {
+            return Ok(PathBuf::from(model));
+        }
+        let dir = std::env::var_os(MODELS_DIR_ENV).map_or_else(|| PathBuf::from("models"), PathBuf::from);
+        let path = dir.join(format!("ggml-{}.bin", model));
+        if !path.is_file() {
+            return Err(VideoConversionError::config(format!(
+                "whisper.cpp model {} not found; download it with whisper.cpp's models/download-ggml-model.sh {} \
+                 and point {} at its folder",
+                path.display(),
+                model,
+                MODELS_DIR_ENV
+            )));
+        }
+        Ok(path)
+    }
+}
+
+/// Decode the audio of `input` into a small file for transcription: 16 kHz mono WAV for
+/// whisper.cpp, or Opus to stay under upload limits of APIs
+fn extract_audio(input: &Path, output: &Path) -> Result<(), VideoConversionError> {
+    let mut command = Command::new("ffmpeg");
+    command.args(["-nostdin", "-v", "error", "-y", "-i"]).arg(input).args(["-vn", "-ac", "1", "-ar", "16000"]);
+    match output.extension().and_then(|ext| ext.to_str()) {
+        Some("wav") => command.args(["-c:a", "pcm_s16le"]),
+        _ => command.args(["-c:a", "libopus", "-b:a", "24k"]),
+    };
+    run_command(command.arg(output).stdin(Stdio::null()).stderr(logging::child_stderr()))
+}
+
+/// Run whisper.cpp on a 16 kHz WAV file
+fn transcribe_local(audio: &Path, options: &TranscribeOptions) -> Result<Vec<Segment>, VideoConversionError> {
+    let model = options.model_path()?;
+    let json_base = audio.with_extension("");
+    let json_path = audio.with_extension("json");
+    let mut result = Err(VideoConversionError::ToolNotFound(WHISPER_COMMANDS.join(" or ")));
+    for name in WHISPER_COMMANDS {
+        let mut command = Command::new(name);
+        command
+            .arg("-m")
+            .arg(&model)
+            .arg("-f")
+            .arg(audio)
+            .args(["-l", options.whisper_language.as_deref().unwrap_or("auto"), "--output-json", "--no-prints", "-of"])
+            .arg(&json_base);
+        result = run_command(command.stdin(Stdio::null()).stdout(logging::child_stderr()).stderr(logging::child_stderr()));
+        if !matches!(result, Err(VideoConversionError::ToolNotFound(_))) {
+            break;
+        }
+    }
+    result?;
+    let text = fs::read_to_string(&json_path)
+        .map_err(|e| VideoConversionError::io("whisper.cpp wrote no transcript").caused_by(e));
+    let _ = fs::remove_file(&json_path);
+    let output: CppOutput = serde_json::from_str(&text?)
+        .map_err(|e| VideoConversionError::io("unexpected whisper.cpp output").caused_by(e))?;
+    Ok(output
+        .transcription
+        .into_iter()
+        .map(|segment| Segment {
+            start: segment.offsets.from as f64 / 1000.0,
+            end: segment.offsets.to as f64 / 1000.0,
+            text: segment.text,
+        })
+        .collect())
+}
+
+/// Upload the audio to an OpenAI-compatible `audio/transcriptions` endpoint
+fn transcribe_remote(audio: &Path, base_url: &str, options: &TranscribeOptions) -> Result<Vec<Segment>, VideoConversionError> {
+    let failed = |reason: String| VideoConversionError::network("transcription request failed").caused_by(reason);
+    let data = fs::read(audio).map_err(|e| VideoConversionError::io(format!("Failed to read {}", audio.display())).caused_by(e))?;
+
+    // Built by hand, as multipart support would pull in another dependency for one form
+    let boundary = format!("videelow-{:016x}", std::process::id() as u64 ^ data.len() as u64);
+    let mut body = Vec::with_capacity(data.len() + 1024);
+    let mut field = |name: &str, value: &str| {
+        body.extend(format!("--{}\r\nContent-Disposition: form-data; name=\"{}\"\r\n\r\n{}\r\n", boundary, name, value).bytes());
+    };
+    field("model", options.whisper_model.as_deref().unwrap_or("whisper-1"));
+    field("response_format", "verbose_json");
+    if let Some(language) = &options.whisper_language {
+        field("language", language);
+    }
+    body.extend(
+        format!(
+            "--{}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"audio.ogg\"\r\nContent-Type: audio/ogg\r\n\r\n",
+            boundary
+        )
+        .bytes(),
+    );
+    body.extend(data);
+    body.extend(format!("\r\n--{}--\r\n", boundary).bytes());
+
+    // Long recordings take minutes to transcribe
+    let client = reqwest::blocking::Client::builder().timeout(None).build().map_err(|e| failed(e.to_string()))?;
+    let mut request = client
+        .post(format!("{}/audio/transcriptions", base_url.trim_end_matches('/')))
+        .header("Content-Type", format!("multipart/form-data; boundary={}", boundary))
+        .body(body);
+    if let Ok(key) = std::env::var(API_KEY_ENV) {
+        request = request.bearer_auth(key);
+    }
+    let response = request.send().map_err(|e| failed(e.to_string()))?;
+    let status = response.status();
+    let text = response.text().map_err(|e| failed(e.to_string()))?;
+    if !status.is_success() {
+        return Err(failed(format!("{}: {}", status, text.trim())));
+    }
+    let output: ApiOutput = serde_json::from_str(&text).map_err(|e| failed(format!("unexpected response: {}", e)))?;
+    let segments = output.segments.ok_or_else(|| failed("the response has no timed segments".to_string()))?;
+    Ok(segments.into_iter().map(|segment| Segment { start: segment.start, end: segment.end, text: segment.text }).collect())
+}
+
+fn render(segments: &[Segment], format: TranscriptFormat) -> String {
+    let texts = segments.iter().map(|segment| segment.text.trim()).filter(|text| !text.is_empty());
+    match format {
+        TranscriptFormat::Txt => texts.map(|text| format!("{}\n", text)).collect(),
+        TranscriptFormat::Srt => segments
+            .iter()
+            .filter(|segment| !segment.text.trim().is_empty())
+            .enumerate()
+            .map(|(index, segment)| {
+                format!(
+                    "{}\n{} --> {}\n{}\n\n",
+                    index + 1,
+                    srt_timestamp(segment.start),
+                    srt_timestamp(segment.end),
+                    segment.text.trim()
+                )
+            })
+            .collect(),
+        TranscriptFormat::Vtt => {
+            let vtt_timestamp = |seconds: f64| srt_timestamp(seconds).replace(',', ".");
+            let cues: String = segments
+                .iter()
+                .filter(|segment| !segment.text.trim().is_empty())
+                .map(|segment| {
+                    format!("{} --> {}\n{}\n\n", vtt_timestamp(segment.start), vtt_timestamp(segment.end), segment.text.trim())
+                })
+                .collect();
+            format!("WEBVTT\n\n{}", cues)
+        }
+    }
+}
+
+/// Transcribe the speech of `media` into the requested files next to it, named after it
+pub(crate) fn transcribe(media: &Path, options: &TranscribeOptions) -> Result<(), VideoConversionError> {
+    let _span = info_span!("transcribe", media = %media.display()).entered();
+    let stem = media.with_extension("");
+    let stem = match &options.whisper_language {
+        Some(language) => format!("{}.{}", stem.display(), language),
+        None => stem.display().to_string(),
+    };
+    let audio = PathBuf::from(format!("{}.whisper.{}", stem, if options.whisper_url.is_some() { "ogg" } else { "wav" }));
+    match &options.whisper_url {
+        Some(url) => println!("Transcribing {} with {}...", media.display(), url),
+        None => println!(
+            "Transcribing {} with whisper.cpp ({} model)...",
+            media.display(),
+            options.whisper_model.as_deref().unwrap_or("base")
+        ),
+    }
+    let segments = extract_audio(media, &audio).and_then(|()| match &options.whisper_url {
+        Some(url) => transcribe_remote(&audio, url, options),
+        None => transcribe_local(&audio, options),
+    });
+    let _ = fs::remove_file(&audio);
+    let segments = segments?;
+    info!(segments = segments.len(), "transcribed");
+
+    for format in &options.transcribe {
+        let path = format!("{}.{}", stem, format.extension());
+        fs::write(&path, render(&segments, *format))
+            .map_err(|e| VideoConversionError::io(format!("Failed to write {}", path)).caused_by(e))?;
+        console!(Success, "Transcript saved: {}", path);
+    }
+    Ok(())
+}
@@ -0,0 +1,455 @@
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use clap::ValueEnum;
+use serde::Deserialize;
+use serde_json::json;
+use tracing::{info, info_span};
+
+use crate::codecs::Container;
+use crate::process::ChildProcess;
+use crate::quota;
+use crate::twitch::srt_timestamp;
+use crate::{command_output, console, logging, run_command, VideoConversionError};
+
+/// Environment variable holding the DeepL API key
+const DEEPL_KEY_ENV: &str = "DEEPL_AUTH_KEY";
+
+/// Environment variable holding the key of LibreTranslate instances that require one
+const LIBRETRANSLATE_KEY_ENV: &str = "LIBRETRANSLATE_API_KEY";
+
+/// Where LibreTranslate listens when run locally with its defaults
+const LIBRETRANSLATE_URL: &str = "http://localhost:5000";
+
+/// Cues sent per request, within what DeepL accepts at once
+const BATCH_SIZE: usize = 50;
+
+/// Translates batches of subtitle texts from one language into another
+pub trait Translator {
+    /// Name for messages
+    fn name(&self) -> &str;
+
+    /// `texts` in `target`, in the same order; `source` is detected when `None`
+    fn translate(&self, texts: &[String], source: Option<&str>, target: &str) -> Result<Vec<String>, VideoConversionError>;
+}
+
+/// Services `--translator` can use
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum, Debug)]
+pub enum Provider {
+    /// The DeepL API; its key is read from DEEPL_AUTH_KEY
+    Deepl,
+    /// A LibreTranslate instance, self-hosted or public
+    Libretranslate,
+    /// A local program such as a machine translation model, given the texts one per line on
+    /// standard input and printing the translations the same way
+    Command,
+}
+
+/// Which translation service to use and how to reach it
+#[derive(clap::Args, Debug, Clone)]
+pub struct TranslatorOptions {
+    /// Translation service
+    #[arg(long, value_enum, default_value = "libretranslate")]
+    translator: Provider,
+
+    /// Base URL of the service (default: https://api-free.deepl.com or https://api.deepl.com by
+    /// the key for DeepL, http://localhost:5000 for LibreTranslate)
+    #[arg(long, value_name = "URL")]
+    translator_url: Option<String>,
+
+    /// Program with its arguments for `--translator command`; {from} and {to} in the arguments
+    /// are replaced with the language codes, {from} with "auto" when unknown
+    #[arg(long, value_name = "COMMAND")]
+    translator_command: Option<String>,
+}
+
+/// Translation of the subtitles of each download
+#[derive(clap::Args, Debug, Clone)]
+pub struct SubtitleTranslationOptions {
+    /// Translate the subtitles next to each download, such as those of --transcribe, into these
+    /// languages (comma-separated codes such as de,fr), written as NAME.LANG.srt
+    #[arg(long, value_name = "LANGS", value_delimiter = ',')]
+    translate_to: Vec<String>,
+
+    /// Also add the translated subtitles to the video as soft subtitle tracks
+    #[arg(long, requires = "translate_to")]
+    mux_subtitles: bool,
+
+    #[command(flatten)]
+    translator: TranslatorOptions,
+}
+
+/// What `translate` translates and where the result goes
+#[derive(clap::Args, Debug, Clone)]
+pub struct TranslateOptions {
+    /// SRT or WebVTT subtitle file
+    file: String,
+
+    /// Languages to translate into, comma-separated
+    #[arg(long, value_name = "LANGS", value_delimiter = ',', required = true)]
+    to: Vec<String>,
+
+    /// Language of the subtitles (default: from a language code in the file name, or detected)
+    #[arg(long, value_name = "LANG")]
+    from: Option<String>,
+
+    /// Add the translations to this video as soft subtitle tracks
+    #[arg(long, value_name = "VIDEO")]
+    mux: Option<String>,
+
+    #[command(flatten)]
+    translator: TranslatorOptions,
+}
+
+/// One timed subtitle
+#[derive(Debug, Clone, PartialEq)]
+struct Cue {
+    start: f64,
+    end: f64,
+    text: String,
+}
+
+struct DeepL {
+    url: String,
+    key: String,
+}
+
+#[derive(Deserialize)]
+struct DeepLOutput {
+    translations: Vec<DeepLTranslation>,
+}
+
+#[derive(Deserialize)]
+struct DeepLTranslation {
+    text: String,
+}
+
+impl Translator for DeepL {
+    fn name(&self) -> &str {
+        "DeepL"
+    }
+
+    fn translate(&self, texts: &[String], source: Option<&str>, target: &str) -> Result<Vec<String>, VideoConversionError> {
+        let mut body = json!({ "text": texts, "target_lang": target.to_ascii_uppercase() });
+        if let Some(source) = source {
+            // DeepL knows source languages without regional variants only
+            body["source_lang"] = json!(source.split('-').next().unwrap_or(source).to_ascii_uppercase());
+        }
+        let request = reqwest::blocking::Client::new()
+            .post(format!("{}/v2/translate", self.url))
+            .header("Authorization", format!("DeepL-Auth-Key {}", self.key))
+            .json(&body);
+        let output: DeepLOutput = send(self.name(), request)?;
+        Ok(output.translations.into_iter().map(|translation| translation.text).collect())
+    }
+}
+
+struct LibreTranslate {
+    url: String,
+    key: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LibreTranslateOutput {
+    translated_text: Vec<String>,
+}
+
+impl Translator for LibreTranslate {
+    fn name(&self) -> &str {
+        "LibreTranslate"
+    }
+
+    fn translate(&self, texts: &[String], source: Option<&str>, target: &str) -> Result<Vec<String>, VideoConversionError> {
+        let mut body = json!({ "q": texts, "source": source.unwrap_or("auto"), "target": target, "format": "text" });
+        if let Some(key) = &self.key {
+            body["api_key"] = json!(key);
+        }
+        let request = reqwest::blocking::Client::new().post(format!("{}/translate", self.url)).json(&body);
+        let output: LibreTranslateOutput = send(self.name(), request)?;
+        Ok(output.translated_text)
+    }
+}
+
+struct LocalCommand {
+    args: Vec<String>,
+}
+
+impl Translator for LocalCommand {
+    fn name(&self) -> &str {
+        &self.args[0]
+    }
+
+    fn translate(&self, texts: &[String], source: Option<&str>, target: &str) -> Result<Vec<String>, VideoConversionError> {
+        let args = self.args[1..].iter().map(|arg| arg.replace("{from}", source.unwrap_or("auto")).replace("{to}", target));
+        let mut command = Command::new(&self.args[0]);
+        command.args(args).stdout(Stdio::piped()).stderr(logging::child_stderr());
+        let mut process = ChildProcess::spawn_with_stdin(&mut command, Stdio::piped())?;
+        // One line per text, so line breaks within a cue become spaces
+        let input: String = texts.iter().map(|text| format!("{}\n", text.replace('\n', " "))).collect();
+        let mut stdin = process.stdin();
+        // Written from another thread, as the program may print before it read everything
+        let writer = std::thread::spawn(move || stdin.as_mut().map(|stdin| stdin.write_all(input.as_bytes())));
+        let output = process.wait_with_output().map_err(|e| VideoConversionError::io(e.to_string()))?;
+        let _ = writer.join();
+        if !output.status.success() {
+            return Err(VideoConversionError::io(format!("{} exited with {}", self.name(), output.status)));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).lines().map(str::to_string).collect())
+    }
+}
+
+/// Send a translation request, turning HTTP errors into messages with the service's explanation
+fn send<T: serde::de::DeserializeOwned>(name: &str, request: reqwest::blocking::RequestBuilder) -> Result<T, VideoConversionError> {
+    let failed = |reason: String| VideoConversionError::network(format!("{} request failed", name)).caused_by(reason);
+    let response = request.send().map_err(|e| failed(e.to_string()))?;
+    let status = response.status();
+    let text = response.text().map_err(|e| failed(e.to_string()))?;
+    if !status.is_success() {
+        return Err(failed(format!("{}: {}", status, text.trim())));
+    }
+    serde_json::from_str(&text).map_err(|e| failed(format!("unexpected response: {}", e)))
+}
+
+impl TranslatorOptions {
+    /// The configured service, or why it cannot be used
+    pub fn translator(&self) -> Result<Box<dyn Translator>, VideoConversionError> {
+        let url = self.translator_url.as_deref().map(|url| url.trim_end_matches('/').to_string());
+        match self.translator {
+            Provider::Deepl => {
+                let key = std::env::var(DEEPL_KEY_ENV).map_err(|_| {
+                    VideoConversionError::config(format!("--translator deepl needs an API key in {}", DEEPL_KEY_ENV))
+                })?;
+                // Keys of the free plan end in ":fx" and only work with the free endpoint
+                let default = if key.ends_with(":fx") { "https://api-free.deepl.com" } else { "https://api.deepl.com" };
+                Ok(Box::new(DeepL { url: url.unwrap_or_else(|| default.to_string()), key }))
+            }
+            Provider::Libretranslate => Ok(Box::new(LibreTranslate {
+                url: url.unwrap_or_else(|| LIBRETRANSLATE_URL.to_string()),
+                key: std::env::var(LIBRETRANSLATE_KEY_ENV).ok(),
+            })),
+            Provider::Command => {
+                let args: Vec<String> =
+                    self.translator_command.as_deref().unwrap_or_default().split_whitespace().map(str::to_string).collect();
+                if args.is_empty() {
+                    return Err(VideoConversionError::config(
+                        "--translator command needs the program in --translator-command".to_string(),
+                    ));
+                }
+                Ok(Box::new(LocalCommand { args }))
+            }
+        }
+    }
+}
+
+impl SubtitleTranslationOptions {
+    /// Whether any translation was requested
+    pub fn enabled(&self) -> bool {
+        !self.translate_to.is_empty()
+    }
+
+    /// Reject a service that cannot be used before anything is downloaded
+    pub fn validate(&self, format: Container) -> Result<(), VideoConversionError> {
+        if self.mux_subtitles && format != Container::Mp4 {
+            return Err(VideoConversionError::config("--mux-subtitles requires --format mp4"));
+        }
+        if self.enabled() {
+            self.translator.translator()?;
+        }
+        Ok(())
+    }
+}
+
+/// Seconds of an SRT (`00:01:02,500`) or WebVTT (`01:02.500`) timestamp
+fn parse_timestamp(text: &str) -> Option<f64> {
+    let mut seconds = 0.0;
+    for part in text.trim().replace(',', ".").split(':') {
+        seconds = seconds * 60.0 + part.parse::<f64>().ok()?;
+    }
+    Some(seconds)
+}
+
+/// Cues of an SRT or WebVTT file; numbering, styles and notes are dropped
+fn parse_cues(text: &str) -> Vec<Cue> {
+    let text = text.replace("\r\n", "\n");
+    text.split("\n\n")
+        .filter_map(|block| {
+            let mut lines = block.lines().skip_while(|line| !line.contains("-->"));
+            let (start, end) = lines.next()?.split_once("-->")?;
+            // WebVTT puts cue settings such as `align:start` after the end time
+            let end = end.split_whitespace().next()?;
+            let text = lines.collect::<Vec<_>>().join("\n");
+            Some(Cue { start: parse_timestamp(start)?, end: parse_timestamp(end)?, text })
+        })
+        .filter(|cue| !cue.text.trim().is_empty())
+        .collect()
+}
+
+fn render_srt(cues: &[Cue]) -> String {
+    cues.iter()
+        .enumerate()
+        .map(|(index, cue)| {
+            format!("{}\n{} --> {}\n{}\n\n", index + 1, srt_timestamp(cue.start), srt_timestamp(cue.end), cue.text.trim())
+        })
+        .collect()
+}
+
+/// Whether a file name component looks like a language code such as `en`, `deu` or `pt-BR`
+fn is_language_code(text: &str) -> bool {
+    let (language, region) = text.split_once('-').unwrap_or((text, ""));
+    (2..=3).contains(&language.len())
+        && language.chars().all(|c| c.is_ascii_lowercase())
+        && (region.is_empty() || (2..=4).contains(&region.len()) && region.chars().all(|c| c.is_ascii_alphanumeric()))
+}
+
+/// Name of `subtitles` without its extension and language code, and that language code
+fn split_language(subtitles: &Path) -> (String, Option<String>) {
+    let stem = subtitles.with_extension("").to_string_lossy().into_owned();
+    match stem.rsplit_once('.') {
+        Some((base, language)) if is_language_code(language) => (base.to_string(), Some(language.to_string())),
+        _ => (stem, None),
+    }
+}
+
+/// Translate a subtitle file into each of `targets`, written next to it as NAME.LANG.srt
+pub(crate) fn translate_file(
+    subtitles: &Path,
+    source: Option<&str>,
+    targets: &[String],
+    translator: &dyn Translator,
+) -> Result<Vec<(PathBuf, String)>, VideoConversionError> {
+    let _span = info_span!("translate", subtitles = %subtitles.display()).entered();
+    let text = fs::read_to_string(subtitles)
+        .map_err(|e| VideoConversionError::config(format!("cannot read {}", subtitles.display())).caused_by(e))?;
+    let cues = parse_cues(&text);
+    if cues.is_empty() {
+        return Err(VideoConversionError::config(format!("no subtitles found in {}", subtitles.display())));
+    }
+    let (base, named) = split_language(subtitles);
+    let source = source.map(str::to_string).or(named);
+
+    let mut written = Vec::new();
+    for target in targets {
+        if source.as_deref().is_some_and(|source| source.eq_ignore_ascii_case(target)) {
+            continue;
+        }
+        println!("Translating {} into {} with {}...", subtitles.display(), target, translator.name());
+        let mut translated = Vec::with_capacity(cues.len());
+        for batch in cues.chunks(BATCH_SIZE) {
+            let texts: Vec<String> = batch.iter().map(|cue| cue.text.trim().to_string()).collect();
+            let texts = translator.translate(&texts, source.as_deref(), target)?;
+            if texts.len() != batch.len() {
+                return Err(VideoConversionError::io(format!(
+                    "{} returned {} translations for {} subtitles",
+                    translator.name(),
+                    texts.len(),
+                    batch.len()
+                )));
+            }
+            translated.extend(batch.iter().zip(texts).map(|(cue, text)| Cue { text, ..cue.clone() }));
+        }
+        let path = PathBuf::from(format!("{}.{}.srt", base, target));
+        fs::write(&path, render_srt(&translated))
+            .map_err(|e| VideoConversionError::io(format!("Failed to write {}", path.display())).caused_by(e))?;
+        info!(target = %target, cues = translated.len(), "translated");
+        console!(Success, "Translation saved: {}", path.display());
+        written.push((path, target.clone()));
+    }
+    Ok(written)
+}
+
+/// Subtitle codec `video`'s container stores text subtitles in
+fn subtitle_codec(video: &Path) -> Result<&'static str, VideoConversionError> {
+    match video.extension().and_then(|ext| ext.to_str()).map(str::to_ascii_lowercase).as_deref() {
+        Some("mp4" | "m4v" | "mov") => Ok("mov_text"),
+        Some("mkv") => Ok("srt"),
+        Some("webm") => Ok("webvtt"),
+        _ => Err(VideoConversionError::config(format!("cannot add subtitle tracks to {}", video.display()))),
+    }
+}
+
+/// Add `tracks` to `video` as soft subtitle tracks tagged with their language, keeping everything it has
+pub(crate) fn mux(video: &Path, tracks: &[(PathBuf, String)]) -> Result<(), VideoConversionError> {
+    if tracks.is_empty() {
+        return Ok(());
+    }
+    let codec = subtitle_codec(video)?;
+    // New tracks are numbered after the subtitle streams already in the file
+    let existing = command_output(
+        Command::new("ffprobe")
+            .args(["-v", "error", "-select_streams", "s", "-show_entries", "stream=index", "-of", "csv=p=0"])
+            .arg(video)
+            .stderr(logging::child_stderr()),
+    )
+    .map(|output| String::from_utf8_lossy(&output.stdout).lines().filter(|line| !line.trim().is_empty()).count())?;
+
+    let extension = video.extension().map_or_else(String::new, |ext| ext.to_string_lossy().into_owned());
+    let temp = video.with_extension(format!("subs.tmp.{}", extension));
+    let mut command = Command::new("ffmpeg");
+    command.args(["-nostdin", "-v", "error", "-y", "-i"]).arg(video);
+    for (path, _) in tracks {
+        command.arg("-i").arg(path);
+    }
+    command.args(["-map", "0"]);
+    for index in 1..=tracks.len() {
+        command.args(["-map", &index.to_string()]);
+    }
+    command.args(["-c", "copy", "-c:s", codec]);
+    for (offset, (_, language)) in tracks.iter().enumerate() {
+        command.arg(format!("-metadata:s:s:{}", existing + offset)).arg(format!("language={}", language));
+    }
+    let result = run_command(command.arg(&temp).stdin(Stdio::null()).stderr(logging::child_stderr())).and_then(|()| {
+        fs::rename(&temp, video)
+            .map_err(|e| VideoConversionError::io(format!("Failed to replace {}", video.display())).caused_by(e))
+    });
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result?;
+    console!(Success, "Added {} subtitle track(s) to {}", tracks.len(), video.display());
+    Ok(())
+}
+
+/// Subtitle files next to `media` to translate: SRT, or WebVTT without an SRT of the same name
+fn source_subtitles(media: &Path) -> Vec<PathBuf> {
+    let sidecars = quota::sidecars(media);
+    sidecars
+        .iter()
+        .filter(|path| match path.extension().and_then(|ext| ext.to_str()) {
+            Some("srt") => true,
+            Some("vtt") => !sidecars.contains(&path.with_extension("srt")),
+            _ => false,
+        })
+        .cloned()
+        .collect()
+}
+
+/// Translate the subtitles next to a download and optionally add the translations to it
+pub(crate) fn translate_download(media: &Path, options: &SubtitleTranslationOptions) -> Result<(), VideoConversionError> {
+    let sources = source_subtitles(media);
+    if sources.is_empty() {
+        console!(Warning, "no subtitles next to {} to translate; add --transcribe to create them", media.display());
+        return Ok(());
+    }
+    let translator = options.translator.translator()?;
+    let mut tracks = Vec::new();
+    for source in sources {
+        tracks.extend(translate_file(&source, None, &options.translate_to, translator.as_ref())?);
+    }
+    if options.mux_subtitles {
+        mux(media, &tracks)?;
+    }
+    Ok(())
+}
+
+/// Translate a subtitle file, as the `translate` command does
+pub fn run(options: &TranslateOptions) -> Result<(), VideoConversionError> {
+    let translator = options.translator.translator()?;
+    let tracks = translate_file(Path::new(&options.file), options.from.as_deref(), &options.to, translator.as_ref())?;
+    if let Some(video) = &options.mux {
+        mux(Path::new(video), &tracks)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,373 @@
+use std::fs::{create_dir_all, remove_dir_all};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use serde::Deserialize;
+use tracing::{debug, info, info_span};
+
+use crate::analyze;
+use crate::codecs::{Container, VideoCodec};
+use crate::hardware::Accelerator;
+use crate::progress::Progress;
+use crate::timestamp::MediaTimestamp;
+use crate::{command_output, console, logging, run_command, run_ffmpeg, verify, EncodeOptions, VideoConversionError};
+
+/// What `trim` cuts and how
+#[derive(clap::Args, Debug, Clone)]
+pub struct TrimOptions {
+    /// Video to cut a clip from
+    file: String,
+
+    /// Where the clip starts (seconds, MM:SS or HH:MM:SS)
+    #[arg(long, default_value = "0")]
+    start: MediaTimestamp,
+
+    /// Where the clip ends; the end of the video when omitted
+    #[arg(long)]
+    end: Option<MediaTimestamp>,
+
+    /// Output file; defaults to the input name with `.trim` before the extension
+    #[arg(short, long)]
+    output: Option<String>,
+
+    /// Copy the streams instead of re-encoding: instant and lossless, but the clip starts at the
+    /// keyframe before --start, up to a few seconds early
+    #[arg(long, conflicts_with = "smart")]
+    copy: bool,
+
+    /// Cut frame-accurately by re-encoding only from --start to the next keyframe and from the
+    /// last keyframe to --end, at --crf and --preset, and copying the video in between; needs
+    /// H.264 or H.265 video
+    #[arg(long)]
+    smart: bool,
+
+    #[command(flatten)]
+    encode: EncodeOptions,
+}
+
+/// Time of the last video keyframe of `input` at or before `at` seconds
+fn keyframe_before(input: &Path, at: f64) -> Result<Option<f64>, VideoConversionError> {
+    // A window before `at` suffices for all but the longest keyframe intervals, and ffprobe
+    // starting at the keyframe before the window covers those too
+    Ok(analyze::keyframes(input, Some((at - 30.0, at)))?.into_iter().filter(|time| *time <= at + 0.001).reduce(f64::max))
+}
+
+/// Time of the first video keyframe of `input` at or after `at` seconds and before `limit`
+fn keyframe_after(input: &Path, at: f64, limit: f64) -> Result<Option<f64>, VideoConversionError> {
+    Ok(analyze::keyframes(input, Some((at, limit)))?.into_iter().find(|time| *time >= at - 0.001 && *time < limit))
+}
+
+#[derive(Deserialize)]
+struct ProbeOutput {
+    #[serde(default)]
+    streams: Vec<ProbeStream>,
+}
+
+/// Settings of the source video that the re-encoded ends of a smart cut must share for the copied
+/// middle to join them
+#[derive(Deserialize)]
+struct ProbeStream {
+    codec_name: Option<String>,
+    profile: Option<String>,
+    level: Option<i64>,
+    pix_fmt: Option<String>,
+}
+
+fn probe_video(input: &Path) -> Result<Option<ProbeStream>, VideoConversionError> {
+    let output = command_output(
+        Command::new("ffprobe")
+            .args(["-v", "error", "-select_streams", "v:0", "-show_entries", "stream=codec_name,profile,level,pix_fmt"])
+            .args(["-of", "json"])
+            .arg(input)
+            .stderr(logging::child_stderr()),
+    )?;
+    if !output.status.success() {
+        return Err(VideoConversionError::encoding(format!("ffprobe cannot read {}", input.display())));
+    }
+    let probed: ProbeOutput = serde_json::from_slice(&output.stdout)
+        .map_err(|e| VideoConversionError::io("unexpected ffprobe output").caused_by(e))?;
+    Ok(probed.streams.into_iter().next())
+}
+
+/// Encoder arguments reproducing the codec, profile, level and pixel format of `stream`, or `None`
+/// for codecs without a matching encoder
+fn matching_encoder(stream: &ProbeStream, encode: &EncodeOptions) -> Option<(VideoCodec, Vec<String>)> {
+    let codec = match stream.codec_name.as_deref()? {
+        "h264" => VideoCodec::H264,
+        "hevc" => VideoCodec::H265,
+        _ => return None,
+    };
+    let mut args = vec!["-c:v".to_string(), codec.encoder().to_string()];
+    args.extend(Accelerator::Software.quality_args(encode.crf));
+    if let Some(preset) = Accelerator::Software.preset(encode.preset) {
+        args.extend(["-preset".to_string(), preset.to_string()]);
+    }
+    // ffprobe names profiles as in the specifications, the encoders as in their options
+    let profile = stream.profile.as_deref().map(|profile| match profile.to_ascii_lowercase().as_str() {
+        "constrained baseline" => "baseline".to_string(),
+        other => other.replace(' ', ""),
+    });
+    match codec {
+        VideoCodec::H264 => {
+            args.extend(profile.map(|profile| ["-profile:v".to_string(), profile]).into_iter().flatten());
+            if let Some(level) = stream.level.filter(|level| *level > 0) {
+                args.extend(["-level:v".to_string(), format!("{:.1}", level as f64 / 10.0)]);
+            }
+        }
+        VideoCodec::H265 => {
+            if let Some(profile) = profile {
+                args.extend(["-x265-params".to_string(), format!("profile={}", profile)]);
+            }
+        }
+    }
+    args.extend(stream.pix_fmt.clone().map(|format| ["-pix_fmt".to_string(), format]).into_iter().flatten());
+    Some((codec, args))
+}
+
+/// Where a smart cut re-encodes and where it copies
+struct SmartPlan {
+    start: f64,
+    /// First keyframe at or after the start, from which the video is copied
+    copy_from: f64,
+    /// Last keyframe at or before the end, up to which the video is copied; `None` copies to the end
+    copy_to: Option<f64>,
+    end: Option<f64>,
+}
+
+/// Write the video of `input` from `from` to `to` seconds to `target`, re-encoded with `encoder`
+/// or copied when it is `None`
+fn write_piece(
+    input: &Path,
+    target: &Path,
+    from: f64,
+    to: Option<f64>,
+    encoder: Option<&[String]>,
+    encode: &EncodeOptions,
+) -> Result<(), VideoConversionError> {
+    let position = |seconds: f64| MediaTimestamp::from_secs_f64(seconds).unwrap_or(MediaTimestamp::ZERO).to_ffmpeg();
+    let mut command = encode.ffmpeg_command();
+    command.args(["-nostdin", "-v", "error", "-ss"]).arg(position(from));
+    if let Some(to) = to {
+        command.arg("-to").arg(position(to));
+    }
+    command.arg("-i").arg(input).args(["-map", "0:v:0", "-an", "-sn", "-dn"]);
+    match encoder {
+        Some(args) => {
+            command.args(args);
+            if let Some(threads) = encode.threads {
+                command.arg("-threads").arg(threads.to_string());
+            }
+        }
+        None => {
+            command.args(["-c", "copy", "-avoid_negative_ts", "make_zero"]);
+        }
+    }
+    // MPEG-TS repeats the parameter sets at every keyframe, so each piece carries its own when joined
+    run_command(command.args(["-f", "mpegts"]).arg(target).stdin(Stdio::null()).stderr(logging::child_stderr()))
+        .map_err(VideoConversionError::conversion)
+}
+
+/// Cut `input` by the plan into `output`, working in a directory next to it
+fn smart_cut(
+    input: &Path,
+    output: &Path,
+    plan: &SmartPlan,
+    codec: VideoCodec,
+    encoder: &[String],
+    encode: &EncodeOptions,
+) -> Result<(), VideoConversionError> {
+    let work_dir = PathBuf::from(format!("{}.parts", output.display()));
+    create_dir_all(&work_dir)
+        .map_err(|e| VideoConversionError::io(format!("Failed to create {}", work_dir.display())).caused_by(e))?;
+    let result = smart_cut_in(&work_dir, input, output, plan, codec, encoder, encode);
+    let _ = remove_dir_all(&work_dir);
+    result
+}
+
+fn smart_cut_in(
+    work_dir: &Path,
+    input: &Path,
+    output: &Path,
+    plan: &SmartPlan,
+    codec: VideoCodec,
+    encoder: &[String],
+    encode: &EncodeOptions,
+) -> Result<(), VideoConversionError> {
+    let mut pieces = Vec::new();
+    if plan.copy_from - plan.start >= 0.001 {
+        println!("Re-encoding {:.1}s up to the first keyframe...", plan.copy_from - plan.start);
+        let head = work_dir.join("head.ts");
+        write_piece(input, &head, plan.start, Some(plan.copy_from), Some(encoder), encode)?;
+        pieces.push(head);
+    }
+    println!("Copying the video in between...");
+    let middle = work_dir.join("middle.ts");
+    write_piece(input, &middle, plan.copy_from, plan.copy_to, None, encode)?;
+    pieces.push(middle);
+    if let (Some(copy_to), Some(end)) = (plan.copy_to, plan.end) {
+        if end - copy_to >= 0.001 {
+            println!("Re-encoding {:.1}s after the last keyframe...", end - copy_to);
+            let tail = work_dir.join("tail.ts");
+            write_piece(input, &tail, copy_to, Some(end), Some(encoder), encode)?;
+            pieces.push(tail);
+        }
+    }
+
+    // The concat demuxer resolves relative entries against the list file's directory
+    let list_path = work_dir.join("pieces.txt");
+    let list: String = pieces
+        .iter()
+        .filter_map(|path| path.file_name())
+        .map(|name| format!("file '{}'\n", name.to_string_lossy()))
+        .collect();
+    std::fs::write(&list_path, list)
+        .map_err(|e| VideoConversionError::io(format!("Failed to write {}", list_path.display())).caused_by(e))?;
+
+    println!("Joining the video and encoding the audio...");
+    let position = |seconds: f64| MediaTimestamp::from_secs_f64(seconds).unwrap_or(MediaTimestamp::ZERO).to_ffmpeg();
+    let mut command = encode.ffmpeg_command();
+    command.args(["-nostdin", "-v", "error", "-n", "-f", "concat", "-safe", "0", "-i"]).arg(&list_path);
+    // The audio is cut at the exact times and encoded in one piece, so no gaps open at the joins
+    command.arg("-ss").arg(position(plan.start));
+    if let Some(end) = plan.end {
+        command.arg("-to").arg(position(end));
+    }
+    run_command(
+        command
+            .arg("-i")
+            .arg(input)
+            .args(["-map", "0:v:0", "-map", "1:a:0?", "-c:v", "copy"])
+            .args(codec.mp4_tag().map(|tag| ["-tag:v", tag]).into_iter().flatten())
+            .args(encode.audio_args(Container::Mp4))
+            .args(["-map_chapters", "-1", "-movflags", "+faststart"])
+            .args(encode.ffmpeg_arg.iter().flatten())
+            .arg(output)
+            .stdin(Stdio::null())
+            .stderr(logging::child_stderr()),
+    )
+    .map_err(VideoConversionError::conversion)
+}
+
+/// Plan a smart cut of `input`, or `None` when no keyframe lies between the cuts and the whole
+/// clip is re-encoded anyway
+fn plan_smart_cut(
+    input: &Path,
+    start: f64,
+    end: Option<f64>,
+    duration: Option<f64>,
+) -> Result<Option<SmartPlan>, VideoConversionError> {
+    // Keyframes are rarely more than a few seconds apart; a clip without one in its first minute
+    // is short or sparse enough to re-encode
+    let limit = end.or(duration).map_or(start + 60.0, |end| end.min(start + 60.0));
+    let Some(copy_from) = keyframe_after(input, start, limit)? else { return Ok(None) };
+    let copy_to = match end {
+        Some(end) => match keyframe_before(input, end)? {
+            Some(copy_to) if copy_to > copy_from => Some(copy_to),
+            _ => return Ok(None),
+        },
+        None => None,
+    };
+    Ok(Some(SmartPlan { start, copy_from, copy_to, end }))
+}
+
+/// The clip's path when no `--output` is given
+fn default_output(input: &Path, copy: bool) -> PathBuf {
+    let extension = match copy {
+        true => input.extension().map_or_else(|| "mp4".to_string(), |ext| ext.to_string_lossy().into_owned()),
+        false => Container::Mp4.extension().to_string(),
+    };
+    input.with_extension(format!("trim.{}", extension))
+}
+
+/// Cut the part of `options.file` between `--start` and `--end` into a new file, re-encoding it
+/// for frame-accurate cuts, copying the streams with `--copy`, or re-encoding only around the cuts
+/// with `--smart`
+pub fn run(options: &TrimOptions, progress: &Progress) -> Result<(), VideoConversionError> {
+    let input = Path::new(&options.file);
+    if !input.is_file() {
+        return Err(VideoConversionError::FileNotFound(options.file.clone()));
+    }
+    if options.end.is_some_and(|end| end <= options.start) {
+        return Err(VideoConversionError::config("--end must be after --start"));
+    }
+    let output = options.output.as_ref().map_or_else(|| default_output(input, options.copy), PathBuf::from);
+    if output.exists() {
+        return Err(VideoConversionError::FileConflict(output.display().to_string()));
+    }
+    if !options.copy {
+        Container::Mp4.check(options.encode.audio_codec(Container::Mp4), options.encode.audio_bitrate)?;
+    }
+    let _span = info_span!("trim", input = options.file.as_str(), copy = options.copy).entered();
+
+    let start = options.start.as_secs_f64();
+    let duration = verify::ffprobe(input)?.duration.filter(|duration| *duration > 0.0);
+    if duration.is_some_and(|duration| start >= duration) {
+        return Err(VideoConversionError::config(format!("--start is past the end of {}", options.file)));
+    }
+    let length = options.end.map(|end| end.as_secs_f64()).or(duration).map(|end| end - start);
+
+    if options.smart {
+        let Some(stream) = probe_video(input)? else {
+            return Err(VideoConversionError::config(format!("{} has no video", options.file)));
+        };
+        match matching_encoder(&stream, &options.encode) {
+            None => console!(Warning, "smart cuts need H.264 or H.265 video; re-encoding the whole clip"),
+            Some((codec, encoder)) => match plan_smart_cut(input, start, options.end.map(|end| end.as_secs_f64()), duration)? {
+                None => println!("No keyframe between --start and --end; re-encoding the whole clip"),
+                Some(plan) => {
+                    info!(copy_from = plan.copy_from, copy_to = plan.copy_to, "smart cut");
+                    smart_cut(input, &output, &plan, codec, &encoder, &options.encode)?;
+                    console!(Success, "Clip saved to {}", output.display());
+                    return Ok(());
+                }
+            },
+        }
+    }
+
+    let mut command = options.encode.ffmpeg_command();
+    command.args(["-progress", "pipe:1", "-nostats", "-n", "-ss"]).arg(options.start.to_ffmpeg());
+    if let Some(end) = options.end {
+        command.arg("-to").arg(end.to_ffmpeg());
+    }
+    command.arg("-i").arg(input);
+    if options.copy {
+        // Shift the clip to start at zero, as some players mishandle the negative timestamps of
+        // the frames before the seek point
+        command.args(["-map", "0", "-c", "copy", "-avoid_negative_ts", "make_zero"]);
+    } else {
+        command
+            .args(["-map", "0:v:0", "-map", "0:a:0?"])
+            .args(options.encode.video_filter_args())
+            .args(options.encode.video_args())
+            .args(options.encode.video_codec().mp4_tag().map(|tag| ["-tag:v", tag]).into_iter().flatten())
+            .args(options.encode.audio_args(Container::Mp4));
+        if let Some(threads) = options.encode.threads {
+            command.arg("-threads").arg(threads.to_string());
+        }
+    }
+    command.args(["-map_chapters", "-1"]);
+    if output.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("mp4") || ext.eq_ignore_ascii_case("mov")) {
+        command.args(["-movflags", "+faststart"]);
+    }
+    command.args(options.encode.ffmpeg_arg.iter().flatten()).arg(&output);
+
+    if options.copy {
+        match keyframe_before(input, start)? {
+            Some(keyframe) if start - keyframe >= 0.001 => console!(
+                Warning,
+                "--copy cuts on keyframes: the clip starts at {}, {:.1}s before --start",
+                MediaTimestamp::from_secs_f64(keyframe).unwrap_or(MediaTimestamp::ZERO),
+                start - keyframe
+            ),
+            Some(_) => debug!("--start falls on a keyframe"),
+            None => console!(Warning, "--copy cuts on keyframes: the clip may start up to a few seconds before --start"),
+        }
+    }
+    println!("Cutting {} to {}...", options.file, output.display());
+    if let Err(e) = run_ffmpeg(&mut command, length, progress) {
+        let _ = std::fs::remove_file(&output);
+        return Err(e.conversion());
+    }
+    console!(Success, "Clip saved to {}", output.display());
+    Ok(())
+}
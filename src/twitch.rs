@@ -0,0 +1,194 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::VideoConversionError;
+
+/// Public client ID used by Twitch's own web player for GraphQL requests
+const TWITCH_WEB_CLIENT_ID: &str = "kimne78kx3ncx6brgo4mv6wki5h1ko";
+
+/// Persisted query hash of Twitch's `VideoCommentsByOffsetOrCursor` operation
+const COMMENTS_QUERY_HASH: &str = "b70a3591ff0f4e0313d126c6a1502d79a1c02baebb288227c582044aa76adf6a";
+
+/// How long each chat message stays on screen in SRT output, in seconds
+const SRT_MESSAGE_SECONDS: f64 = 4.0;
+
+/// Sidecar format for saved chat replays
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum, Debug)]
+pub enum ChatFormat {
+    Json,
+    Srt,
+}
+
+impl ChatFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ChatFormat::Json => "chat.json",
+            ChatFormat::Srt => "chat.srt",
+        }
+    }
+}
+
+/// A single chat replay message
+#[derive(Serialize, Debug, Clone)]
+pub struct ChatMessage {
+    /// Seconds since the start of the VOD
+    pub offset: f64,
+    pub author: String,
+    pub message: String,
+}
+
+#[derive(Deserialize)]
+struct GqlResponse {
+    data: Option<GqlData>,
+}
+
+#[derive(Deserialize)]
+struct GqlData {
+    video: Option<GqlVideo>,
+}
+
+#[derive(Deserialize)]
+struct GqlVideo {
+    comments: Option<GqlComments>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GqlComments {
+    edges: Vec<GqlEdge>,
+    page_info: GqlPageInfo,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GqlPageInfo {
+    has_next_page: bool,
+}
+
+#[derive(Deserialize)]
+struct GqlEdge {
+    cursor: Option<String>,
+    node: GqlComment,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GqlComment {
+    content_offset_seconds: f64,
+    commenter: Option<GqlCommenter>,
+    message: Option<GqlMessage>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GqlCommenter {
+    display_name: String,
+}
+
+#[derive(Deserialize)]
+struct GqlMessage {
+    fragments: Vec<GqlFragment>,
+}
+
+#[derive(Deserialize)]
+struct GqlFragment {
+    text: String,
+}
+
+/// Fetch the complete chat replay of a VOD, following Twitch's pagination cursors
+pub fn fetch_chat(vod_id: &str) -> Result<Vec<ChatMessage>, VideoConversionError> {
+    let client = reqwest::blocking::Client::new();
+    let mut messages = Vec::new();
+    let mut cursor: Option<String> = None;
+
+    loop {
+        let variables = match &cursor {
+            Some(cursor) => json!({ "videoID": vod_id, "cursor": cursor }),
+            None => json!({ "videoID": vod_id, "contentOffsetSeconds": 0 }),
+        };
+        let body = json!([{
+            "operationName": "VideoCommentsByOffsetOrCursor",
+            "variables": variables,
+            "extensions": { "persistedQuery": { "version": 1, "sha256Hash": COMMENTS_QUERY_HASH } },
+        }]);
+
+        let responses: Vec<GqlResponse> = client
+            .post("https://gql.twitch.tv/gql")
+            .header("Client-Id", TWITCH_WEB_CLIENT_ID)
+            .json(&body)
+            .send()
+            .and_then(|r| r.error_for_status())
+            .and_then(|r| r.json())
+            .map_err(|e| VideoConversionError::network("Twitch chat request failed").caused_by(e))?;
+
+        let comments = responses
+            .into_iter()
+            .next()
+            .and_then(|r| r.data)
+            .and_then(|d| d.video)
+            .and_then(|v| v.comments)
+            .ok_or_else(|| VideoConversionError::extraction(format!("no chat replay available for VOD {}", vod_id)))?;
+
+        let next_cursor = comments.edges.last().and_then(|e| e.cursor.clone());
+        messages.extend(comments.edges.into_iter().map(|edge| ChatMessage {
+            offset: edge.node.content_offset_seconds,
+            author: edge.node.commenter.map_or_else(|| "unknown".to_string(), |c| c.display_name),
+            message: edge
+                .node
+                .message
+                .map(|m| m.fragments.into_iter().map(|f| f.text).collect())
+                .unwrap_or_default(),
+        }));
+
+        match next_cursor {
+            Some(next) if comments.page_info.has_next_page => cursor = Some(next),
+            _ => break,
+        }
+    }
+
+    Ok(messages)
+}
+
+/// Write chat messages next to the video in the requested sidecar format
+pub fn write_chat(messages: &[ChatMessage], path: &str, format: ChatFormat) -> Result<(), VideoConversionError> {
+    let io_error = |e: std::io::Error| VideoConversionError::io(format!("Failed to write {}", path)).caused_by(e);
+    let mut out = BufWriter::new(File::create(path).map_err(io_error)?);
+
+    match format {
+        ChatFormat::Json => {
+            serde_json::to_writer_pretty(&mut out, messages)
+                .map_err(|e| VideoConversionError::io(format!("Failed to write {}", path)).caused_by(e))?;
+        }
+        ChatFormat::Srt => {
+            for (index, message) in messages.iter().enumerate() {
+                writeln!(
+                    out,
+                    "{}\n{} --> {}\n{}: {}\n",
+                    index + 1,
+                    srt_timestamp(message.offset),
+                    srt_timestamp(message.offset + SRT_MESSAGE_SECONDS),
+                    message.author,
+                    message.message
+                )
+                .map_err(io_error)?;
+            }
+        }
+    }
+    out.flush().map_err(io_error)
+}
+
+/// Format seconds as an SRT `HH:MM:SS,mmm` timestamp
+pub(crate) fn srt_timestamp(seconds: f64) -> String {
+    let millis = (seconds.max(0.0) * 1000.0).round() as u64;
+    format!(
+        "{:02}:{:02}:{:02},{:03}",
+        millis / 3_600_000,
+        millis / 60_000 % 60,
+        millis / 1000 % 60,
+        millis % 1000
+    )
+}
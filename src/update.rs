@@ -0,0 +1,194 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use crate::VideoConversionError;
+
+/// GitHub repository whose releases carry the prebuilt binaries
+const REPOSITORY: &str = "mrtngranger/videelow";
+
+/// Release asset listing the SHA-256 of every binary, in `sha256sum` format. It comes from the
+/// same release as the binary, so it catches a truncated or corrupted download but not a
+/// tampered release: anyone able to replace the binary can replace this file too
+const CHECKSUMS_ASSET: &str = "SHA256SUMS";
+
+#[derive(Deserialize)]
+struct Release {
+    tag_name: String,
+    assets: Vec<Asset>,
+}
+
+#[derive(Deserialize)]
+struct Asset {
+    name: String,
+    browser_download_url: String,
+}
+
+/// Name of the release asset built for this platform, e.g. `videelow-x86_64-linux`
+fn asset_name() -> String {
+    format!("videelow-{}-{}{}", std::env::consts::ARCH, std::env::consts::OS, std::env::consts::EXE_SUFFIX)
+}
+
+/// Numeric components of a version such as `v1.2.3`; pre-release suffixes are ignored
+fn parse_version(version: &str) -> Option<Vec<u64>> {
+    let version = version.trim_start_matches('v');
+    let version = version.split(['-', '+']).next()?;
+    version.split('.').map(|part| part.parse().ok()).collect()
+}
+
+/// Replace the running binary with the latest release for this platform after checking it
+/// against the release's `SHA256SUMS`, an integrity check only that proves nothing about who
+/// built it; with `check_only`, just report whether an update is available
+pub fn run(check_only: bool) -> Result<(), VideoConversionError> {
+    let current = env!("CARGO_PKG_VERSION");
+    let client = reqwest::blocking::Client::builder()
+        .user_agent(concat!("videelow/", env!("CARGO_PKG_VERSION")))
+        .build()
+        .map_err(|e| VideoConversionError::io(e.to_string()))?;
+
+    let release: Release = client
+        .get(format!("https://api.github.com/repos/{}/releases/latest", REPOSITORY))
+        .header("Accept", "application/vnd.github+json")
+        .send()
+        .and_then(|r| r.error_for_status())
+        .and_then(|r| r.json())
+        .map_err(|e| VideoConversionError::network("cannot look up the latest release").caused_by(e))?;
+
+    let latest = release.tag_name.trim_start_matches('v');
+    match (parse_version(latest), parse_version(current)) {
+        (Some(latest_version), Some(current_version)) if latest_version <= current_version => {
+            println!("videelow {} is up to date", current);
+            return Ok(());
+        }
+        (None, _) => {
+            return Err(VideoConversionError::extraction(format!(
+                "latest release has an unrecognized version {:?}",
+                release.tag_name
+            )))
+        }
+        _ => {}
+    }
+    if check_only {
+        println!("videelow {} is available (installed: {}); run `videelow self-update` to install it", latest, current);
+        return Ok(());
+    }
+
+    let name = asset_name();
+    let find = |wanted: &str| {
+        release.assets.iter().find(|asset| asset.name == wanted).ok_or_else(|| {
+            VideoConversionError::extraction(format!("release {} has no {} asset", release.tag_name, wanted))
+        })
+    };
+    let binary = find(&name)?;
+    let checksums = find(CHECKSUMS_ASSET)?;
+
+    let download = |asset: &Asset| {
+        client
+            .get(&asset.browser_download_url)
+            .send()
+            .and_then(|r| r.error_for_status())
+            .and_then(|r| r.bytes())
+            .map_err(|e| VideoConversionError::network(format!("cannot download {}", asset.name)).caused_by(e))
+    };
+    let checksums = String::from_utf8_lossy(&download(checksums)?).into_owned();
+    let expected = expected_checksum(&checksums, &name).ok_or_else(|| {
+        VideoConversionError::extraction(format!("{} lists no checksum for {}", CHECKSUMS_ASSET, name))
+    })?;
+    let contents = download(binary)?;
+    let actual = hex(&Sha256::digest(&contents));
+    if !actual.eq_ignore_ascii_case(&expected) {
+        return Err(VideoConversionError::extraction(format!(
+            "checksum mismatch for {}: expected {}, got {}",
+            name, expected, actual
+        )));
+    }
+
+    let exe = std::env::current_exe()
+        .and_then(fs::canonicalize)
+        .map_err(|e| VideoConversionError::io("cannot locate the videelow binary").caused_by(e))?;
+    replace(&exe, &contents)?;
+    println!("Updated videelow {} -> {} ({})", current, latest, exe.display());
+    println!("The download matched the release's {} (an integrity check, not a signature)", CHECKSUMS_ASSET);
+    Ok(())
+}
+
+/// Find the checksum of `name` in `sha256sum` output, where binary-mode entries start with `*`
+fn expected_checksum(checksums: &str, name: &str) -> Option<String> {
+    checksums.lines().find_map(|line| {
+        let (hash, file) = line.trim().split_once(char::is_whitespace)?;
+        (file.trim().trim_start_matches('*') == name).then(|| hash.to_string())
+    })
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Swap the binary at `exe` for `contents`, staging the new file next to it so the final
+/// rename never crosses file systems and a failed update leaves the old binary in place
+fn replace(exe: &Path, contents: &[u8]) -> Result<(), VideoConversionError> {
+    let staged = sibling(exe, ".update");
+    let io_error = |path: &Path, e: std::io::Error| {
+        VideoConversionError::io(format!("Failed to update {}", path.display())).caused_by(e)
+    };
+    fs::write(&staged, contents).map_err(|e| io_error(&staged, e))?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = fs::metadata(exe).map(|m| m.permissions().mode()).unwrap_or(0o755);
+        if let Err(e) = fs::set_permissions(&staged, fs::Permissions::from_mode(mode)) {
+            let _ = fs::remove_file(&staged);
+            return Err(io_error(&staged, e));
+        }
+    }
+
+    // Windows cannot overwrite a running executable, but it can rename it out of the way
+    #[cfg(windows)]
+    {
+        let old = sibling(exe, ".old");
+        let _ = fs::remove_file(&old);
+        if let Err(e) = fs::rename(exe, &old) {
+            let _ = fs::remove_file(&staged);
+            return Err(io_error(exe, e));
+        }
+    }
+
+    fs::rename(&staged, exe).map_err(|e| {
+        let _ = fs::remove_file(&staged);
+        #[cfg(windows)]
+        let _ = fs::rename(sibling(exe, ".old"), exe);
+        io_error(exe, e)
+    })
+}
+
+/// `exe` with `suffix` appended to its file name
+fn sibling(exe: &Path, suffix: &str) -> PathBuf {
+    let mut name = exe.file_name().unwrap_or_default().to_os_string();
+    name.push(suffix);
+    exe.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn versions_compare_numerically() {
+        assert_eq!(parse_version("v1.2.3"), Some(vec![1, 2, 3]));
+        assert_eq!(parse_version("1.2.3-rc.1+build"), Some(vec![1, 2, 3]));
+        assert!(parse_version("1.10.0") > parse_version("1.9.9"));
+        assert_eq!(parse_version("latest"), None);
+        assert_eq!(parse_version("1..2"), None);
+    }
+
+    #[test]
+    fn finds_checksums_in_sha256sum_output() {
+        let checksums = "aaaa  videelow-x86_64-linux\nbbbb *videelow-x86_64-windows.exe\n\ncccc  videelow-aarch64-macos\n";
+        assert_eq!(expected_checksum(checksums, "videelow-x86_64-linux").as_deref(), Some("aaaa"));
+        assert_eq!(expected_checksum(checksums, "videelow-x86_64-windows.exe").as_deref(), Some("bbbb"));
+        assert_eq!(expected_checksum(checksums, "videelow-x86_64"), None);
+    }
+}
@@ -0,0 +1,237 @@
+use percent_encoding::percent_decode_str;
+use url::Url;
+
+use crate::sites::{self, Site};
+use crate::VideoConversionError;
+
+/// Query parameters that only carry tracking or UI state and never affect what gets downloaded
+const TRACKING_PARAMS: &[&str] = &["si", "feature", "pp", "ab_channel", "fbclid", "gclid", "igshid", "ref", "ref_src"];
+
+/// File extensions of the HLS and DASH manifests ffmpeg reads directly
+const MANIFEST_EXTENSIONS: &[&str] = &["m3u8", "mpd"];
+
+/// Manifest file names that say nothing about the stream, so its directory names the output instead
+const GENERIC_MANIFEST_NAMES: &[&str] = &["master", "index", "playlist", "manifest", "main", "stream", "chunklist"];
+
+/// An input URL after validation and canonicalization
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceUrl {
+    /// Canonical URL handed to yt-dlp
+    pub url: String,
+    /// Site-specific video ID (YouTube video or Twitch VOD), when the URL points at a single video
+    pub video_id: Option<String>,
+    /// YouTube playlist ID, when the URL points at a playlist
+    pub playlist_id: Option<String>,
+    /// Whether the URL lists several videos (playlist, channel, set) rather than a single one
+    pub listing: bool,
+}
+
+/// Validate `input` and rewrite it into the canonical form for its site
+pub fn normalize(input: &str) -> Result<SourceUrl, VideoConversionError> {
+    let input = input.trim();
+    let unsupported = |reason: &str| VideoConversionError::unsupported(format!("{} ({})", input, reason));
+
+    // Accept scheme-less input such as "youtu.be/abc" as typed into a terminal
+    let with_scheme = if input.contains("://") { input.to_string() } else { format!("https://{}", input) };
+    let mut url = Url::parse(&with_scheme).map_err(|e| unsupported(&e.to_string()))?;
+
+    if url.scheme() != "http" && url.scheme() != "https" {
+        return Err(unsupported("only http and https URLs are supported"));
+    }
+    let host = url.host_str().ok_or_else(|| unsupported("missing host"))?.to_ascii_lowercase();
+    let host = host.strip_prefix("www.").unwrap_or(&host).to_string();
+
+    // Manifest URLs are often signed, so any query parameter may be load-bearing
+    if manifest_extension(&url).is_some() {
+        url.set_fragment(None);
+        return Ok(SourceUrl { url: url.to_string(), video_id: None, playlist_id: None, listing: false });
+    }
+
+    match sites::profile_for_host(&host).site {
+        Site::YouTube => normalize_youtube(&url, &host).ok_or_else(|| unsupported("no video or playlist ID found")),
+        Site::Twitch => Ok(normalize_twitch(&url).unwrap_or_else(|| {
+            strip_tracking_params(&mut url);
+            let listing = is_listing_path(Site::Twitch, &url);
+            SourceUrl { url: url.to_string(), video_id: None, playlist_id: None, listing }
+        })),
+        site => {
+            strip_tracking_params(&mut url);
+            url.set_fragment(None);
+            let listing = is_listing_path(site, &url);
+            Ok(SourceUrl { url: url.to_string(), video_id: None, playlist_id: None, listing })
+        }
+    }
+}
+
+/// Whether `url` points straight at an HLS (`.m3u8`) or DASH (`.mpd`) manifest rather than a page
+pub fn is_manifest(url: &str) -> bool {
+    Url::parse(url).is_ok_and(|url| manifest_extension(&url).is_some())
+}
+
+fn manifest_extension(url: &Url) -> Option<&'static str> {
+    let (_, extension) = url.path().rsplit_once('.')?;
+    MANIFEST_EXTENSIONS.iter().copied().find(|known| known.eq_ignore_ascii_case(extension))
+}
+
+/// Output name for a manifest URL: the manifest's file name, or its directory's for generic names
+/// such as `master.m3u8`
+pub fn manifest_name(url: &str) -> String {
+    let segments: Vec<String> = Url::parse(url)
+        .ok()
+        .and_then(|url| url.path_segments().map(|s| s.filter(|s| !s.is_empty()).map(str::to_string).collect()))
+        .unwrap_or_default();
+    let stem = |segment: &String| segment.rsplit_once('.').map_or(segment.clone(), |(stem, _)| stem.to_string());
+    let name = match segments.as_slice() {
+        [.., dir, file] if GENERIC_MANIFEST_NAMES.contains(&stem(file).to_ascii_lowercase().as_str()) => dir.clone(),
+        [.., file] => stem(file),
+        [] => "stream".to_string(),
+    };
+    sites::sanitize_filename(&percent_decode_str(&name).decode_utf8_lossy())
+}
+
+/// Map the many YouTube URL shapes onto `watch?v=` or `playlist?list=` URLs
+fn normalize_youtube(url: &Url, host: &str) -> Option<SourceUrl> {
+    let query = |key: &str| url.query_pairs().find(|(k, _)| k == key).map(|(_, v)| v.into_owned());
+    let segments: Vec<&str> = url.path_segments().map(|s| s.filter(|s| !s.is_empty()).collect()).unwrap_or_default();
+
+    let video_id = match (host, segments.as_slice()) {
+        ("youtu.be", [id, ..]) => Some(id.to_string()),
+        (_, ["watch"]) => query("v"),
+        (_, ["shorts" | "embed" | "live" | "v", id, ..]) => Some(id.to_string()),
+        _ => None,
+    };
+
+    if let Some(id) = video_id {
+        if !is_valid_video_id(&id) {
+            return None;
+        }
+        return Some(SourceUrl {
+            url: format!("https://www.youtube.com/watch?v={}", id),
+            video_id: Some(id),
+            playlist_id: None,
+            listing: false,
+        });
+    }
+
+    // A watch URL with only a list, e.g. a copied link to a playlist's player, plays the list
+    if matches!(segments.as_slice(), ["playlist"] | ["watch"]) {
+        let list = query("list").filter(|l| !l.is_empty() && l.chars().all(is_id_char))?;
+        return Some(SourceUrl {
+            url: format!("https://www.youtube.com/playlist?list={}", list),
+            video_id: None,
+            playlist_id: Some(list),
+            listing: true,
+        });
+    }
+
+    // Channel pages (@handle, /channel/, /c/, /user/) are listings yt-dlp understands as-is
+    match segments.first() {
+        Some(first) if first.starts_with('@') || ["channel", "c", "user"].contains(first) => {
+            let mut url = url.clone();
+            url.set_query(None);
+            url.set_fragment(None);
+            Some(SourceUrl { url: url.to_string(), video_id: None, playlist_id: None, listing: true })
+        }
+        _ => None,
+    }
+}
+
+/// Map Twitch VOD URLs (`/videos/ID`, legacy `/channel/v/ID`, mobile host) onto `www.twitch.tv/videos/ID`
+fn normalize_twitch(url: &Url) -> Option<SourceUrl> {
+    let segments: Vec<&str> = url.path_segments().map(|s| s.filter(|s| !s.is_empty()).collect()).unwrap_or_default();
+    let id = match segments.as_slice() {
+        ["videos", id] | [_, "v", id] => id.trim_start_matches('v'),
+        _ => return None,
+    };
+    if id.is_empty() || !id.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    Some(SourceUrl {
+        url: format!("https://www.twitch.tv/videos/{}", id),
+        video_id: Some(id.to_string()),
+        playlist_id: None,
+        listing: false,
+    })
+}
+
+/// Recognize collection pages of the non-YouTube sites with first-class support
+fn is_listing_path(site: Site, url: &Url) -> bool {
+    let segments: Vec<&str> = url.path_segments().map(|s| s.filter(|s| !s.is_empty()).collect()).unwrap_or_default();
+    matches!(
+        (site, segments.as_slice()),
+        (Site::Vimeo, ["showcase" | "album" | "channels" | "groups", ..])
+            | (Site::Twitch, [_, "videos" | "clips"])
+            | (Site::Twitch, ["collections", _])
+            | (Site::SoundCloud, [_, "sets", _])
+            | (Site::SoundCloud, [_, "tracks" | "albums" | "likes"])
+    )
+}
+
+/// Remove `utm_*` and other tracking parameters, dropping the query entirely when nothing remains
+fn strip_tracking_params(url: &mut Url) {
+    let kept: Vec<(String, String)> = url
+        .query_pairs()
+        .filter(|(k, _)| !k.starts_with("utm_") && !TRACKING_PARAMS.contains(&k.as_ref()))
+        .map(|(k, v)| (k.into_owned(), v.into_owned()))
+        .collect();
+
+    if kept.is_empty() {
+        url.set_query(None);
+    } else {
+        url.query_pairs_mut().clear().extend_pairs(kept);
+    }
+}
+
+/// YouTube video IDs are exactly 11 URL-safe base64 characters
+fn is_valid_video_id(id: &str) -> bool {
+    id.len() == 11 && id.chars().all(is_id_char)
+}
+
+fn is_id_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '-' || c == '_'
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn youtube_video_shapes_become_watch_urls() {
+        for input in [
+            "https://www.youtube.com/watch?v=dQw4w9WgXcQ&t=42s",
+            "youtu.be/dQw4w9WgXcQ?si=tracking",
+            "https://m.youtube.com/shorts/dQw4w9WgXcQ",
+            "https://www.youtube.com/embed/dQw4w9WgXcQ",
+        ] {
+            let source = normalize(input).unwrap();
+            assert_eq!(source.url, "https://www.youtube.com/watch?v=dQw4w9WgXcQ", "{}", input);
+            assert_eq!(source.video_id.as_deref(), Some("dQw4w9WgXcQ"));
+            assert!(!source.listing);
+        }
+    }
+
+    #[test]
+    fn youtube_lists_become_playlist_urls() {
+        for input in ["https://www.youtube.com/playlist?list=PLabc_123", "https://www.youtube.com/watch?list=PLabc_123&index=2"] {
+            let source = normalize(input).unwrap();
+            assert_eq!(source.url, "https://www.youtube.com/playlist?list=PLabc_123", "{}", input);
+            assert_eq!(source.playlist_id.as_deref(), Some("PLabc_123"));
+            assert!(source.listing);
+        }
+    }
+
+    #[test]
+    fn rejects_urls_without_a_video() {
+        assert!(normalize("https://www.youtube.com/watch?v=short").is_err());
+        assert!(normalize("https://www.youtube.com/watch?list=").is_err());
+        assert!(normalize("https://www.youtube.com/feed/trending").is_err());
+        assert!(normalize("ftp://example.com/video.mp4").is_err());
+    }
+
+    #[test]
+    fn manifests_keep_their_query() {
+        let source = normalize("https://cdn.example.com/live/master.m3u8?token=abc#frag").unwrap();
+        assert_eq!(source.url, "https://cdn.example.com/live/master.m3u8?token=abc");
+        assert_eq!(manifest_name(&source.url), "live");
+    }
+}
@@ -0,0 +1,263 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+use std::process::Command;
+
+use clap::ValueEnum;
+use serde::Deserialize;
+use tracing::{debug, info};
+
+use crate::backend::{MediaProbe, Transcoder};
+use crate::timestamp::MediaTimestamp;
+use crate::{command_output, console, logging, EncodeOptions, VideoConversionError};
+
+/// Output may be this much shorter or longer than its source without being flagged
+const DURATION_TOLERANCE_SECONDS: f64 = 1.0;
+
+/// Relative tolerance for long videos, where container and priming differences add up
+const DURATION_TOLERANCE_RATIO: f64 = 0.01;
+
+/// A download shorter than this share of the duration announced in its metadata was cut off
+const TRUNCATED_RATIO: f64 = 0.9;
+
+/// What to do when an encoded file does not match its source
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum, Debug, Default)]
+pub enum VerifyMode {
+    /// Skip the check
+    Off,
+    /// Print a warning and keep the file
+    #[default]
+    Warn,
+    /// Fail the conversion and remove the file
+    Fail,
+}
+
+#[derive(Deserialize, Default)]
+struct ProbeOutput {
+    #[serde(default)]
+    streams: Vec<ProbeStream>,
+    format: Option<ProbeFormat>,
+}
+
+#[derive(Deserialize)]
+struct ProbeStream {
+    codec_type: Option<String>,
+    start_time: Option<String>,
+    duration: Option<String>,
+    #[serde(default)]
+    tags: BTreeMap<String, String>,
+}
+
+impl ProbeStream {
+    /// Length of the stream, which Matroska only states in a `DURATION` tag such as `00:01:00.040000000`
+    fn duration(&self) -> Option<f64> {
+        if let Some(duration) = self.duration.as_deref().and_then(|d| d.parse().ok()) {
+            return Some(duration);
+        }
+        let tag = self.tags.iter().find(|(key, _)| key.eq_ignore_ascii_case("duration"))?.1;
+        let (whole, fraction) = tag.split_once('.').unwrap_or((tag, ""));
+        let millis = &fraction[..fraction.len().min(3)];
+        format!("{}.{}", whole, millis).trim_end_matches('.').parse::<MediaTimestamp>().ok().map(|t| t.as_secs_f64())
+    }
+}
+
+#[derive(Deserialize)]
+struct ProbeFormat {
+    duration: Option<String>,
+}
+
+/// Read the duration and stream counts of `path`; fails when ffprobe cannot parse the container
+pub fn ffprobe(path: &Path) -> Result<MediaProbe, VideoConversionError> {
+    let output = command_output(
+        Command::new("ffprobe")
+            .args(["-v", "error", "-of", "json", "-show_entries"])
+            .arg("format=duration:stream=codec_type,start_time,duration:stream_tags=DURATION")
+            .arg(path)
+            .stderr(logging::child_stderr()),
+    )?;
+    if !output.status.success() {
+        return Err(VideoConversionError::encoding(format!("ffprobe cannot read {}", path.display())));
+    }
+    let parsed: ProbeOutput = serde_json::from_slice(&output.stdout)
+        .map_err(|e| VideoConversionError::io("unexpected ffprobe output").caused_by(e))?;
+    let count = |kind: &str| parsed.streams.iter().filter(|s| s.codec_type.as_deref() == Some(kind)).count();
+    let first = |kind: &str| parsed.streams.iter().find(|s| s.codec_type.as_deref() == Some(kind));
+    let start = |stream: &ProbeStream| stream.start_time.as_deref().and_then(|t| t.parse().ok());
+    let (video, audio) = (first("video"), first("audio"));
+    Ok(MediaProbe {
+        duration: parsed.format.as_ref().and_then(|f| f.duration.as_deref()).and_then(|d| d.parse().ok()),
+        video_streams: count("video"),
+        audio_streams: count("audio"),
+        video_start: video.and_then(start),
+        audio_start: audio.and_then(start),
+        video_duration: video.and_then(ProbeStream::duration),
+        audio_duration: audio.and_then(ProbeStream::duration),
+    })
+}
+
+/// Types of the top-level atoms of an MP4 file in the order they appear, such as `ftyp`,
+/// `moov`, `mdat`; stops at the first malformed header
+pub(crate) fn top_level_atoms(path: &Path) -> std::io::Result<Vec<[u8; 4]>> {
+    let mut file = File::open(path)?;
+    let length = file.metadata()?.len();
+    let mut atoms = Vec::new();
+    let mut offset = 0;
+    while offset + 8 <= length {
+        let mut header = [0; 8];
+        file.read_exact(&mut header)?;
+        let mut size = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as u64;
+        atoms.push([header[4], header[5], header[6], header[7]]);
+        let mut header_size = 8;
+        size = match size {
+            // The atom runs to the end of the file
+            0 => break,
+            1 => {
+                let mut large = [0; 8];
+                file.read_exact(&mut large)?;
+                header_size = 16;
+                u64::from_be_bytes(large)
+            }
+            size => size,
+        };
+        if size < header_size {
+            break;
+        }
+        offset += size;
+        file.seek(SeekFrom::Start(offset))?;
+    }
+    Ok(atoms)
+}
+
+/// Whether an MP4 file has a top-level `moov` atom, without which no player can open it
+fn has_moov(path: &Path) -> std::io::Result<bool> {
+    Ok(top_level_atoms(path)?.iter().any(|atom| atom == b"moov"))
+}
+
+/// Why a finished download looks truncated or corrupt: ffprobe cannot parse it, or it is far
+/// shorter than `expected_duration`; `None` when it looks fine or cannot be checked
+pub fn download_problem(transcoder: &dyn Transcoder, path: &Path, expected_duration: Option<f64>) -> Option<String> {
+    let downloaded = match transcoder.probe(path) {
+        Ok(downloaded) => downloaded,
+        Err(VideoConversionError::Encoding { message: reason, .. }) => return Some(reason),
+        Err(e) => {
+            debug!(error = %e, "cannot check download for corruption");
+            return None;
+        }
+    };
+    match (expected_duration, downloaded.duration) {
+        (Some(expected), Some(actual)) if actual < expected * TRUNCATED_RATIO => {
+            Some(format!("{:.1}s long instead of {:.1}s", actual, expected))
+        }
+        _ => None,
+    }
+}
+
+/// Compare an encoded file with its source; returns what is wrong with it
+fn problems(transcoder: &dyn Transcoder, source: &Path, output: &Path) -> Result<Vec<String>, VideoConversionError> {
+    let mut problems = Vec::new();
+    let is_mp4 = output.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("mp4"));
+    if is_mp4 && !has_moov(output).map_err(|e| VideoConversionError::io(e.to_string()))? {
+        problems.push("no moov atom".to_string());
+    }
+    let encoded = match transcoder.probe(output) {
+        Ok(encoded) => encoded,
+        Err(VideoConversionError::Encoding { message: reason, .. }) => {
+            problems.push(reason);
+            return Ok(problems);
+        }
+        Err(e) => return Err(e),
+    };
+    // Streams the source lacks cannot be expected in the output
+    let original = transcoder.probe(source).ok();
+    let (wants_video, wants_audio) = original.as_ref().map_or((true, false), |o| (o.video_streams > 0, o.audio_streams > 0));
+    if wants_video && encoded.video_streams == 0 {
+        problems.push("no video stream".to_string());
+    }
+    if wants_audio && encoded.audio_streams == 0 {
+        problems.push("no audio stream".to_string());
+    }
+    match (original.and_then(|o| o.duration), encoded.duration) {
+        (Some(expected), Some(actual)) => {
+            let tolerance = DURATION_TOLERANCE_SECONDS.max(expected * DURATION_TOLERANCE_RATIO);
+            if (expected - actual).abs() > tolerance {
+                problems.push(format!("duration {:.1}s differs from the source's {:.1}s", actual, expected));
+            }
+        }
+        (Some(_), None) => problems.push("unknown duration".to_string()),
+        _ => {}
+    }
+    Ok(problems)
+}
+
+/// How far audio and video of an encode drifted apart, comparing where their first timestamps and
+/// their ends lie; empty when they agree within `max_drift` seconds or a stream's timing is unknown
+fn sync_problems(probe: &MediaProbe, max_drift: f64) -> Vec<String> {
+    let (Some(video_start), Some(audio_start)) = (probe.video_start, probe.audio_start) else {
+        return Vec::new();
+    };
+    let mut problems = Vec::new();
+    let relation = |offset: f64| if offset > 0.0 { "after" } else { "before" };
+    let offset = audio_start - video_start;
+    if offset.abs() > max_drift {
+        problems.push(format!("audio starts {:.0} ms {} the video", offset.abs() * 1000.0, relation(offset)));
+    }
+    if let (Some(video_duration), Some(audio_duration)) = (probe.video_duration, probe.audio_duration) {
+        let offset = (audio_start + audio_duration) - (video_start + video_duration);
+        if offset.abs() > max_drift {
+            problems.push(format!("audio ends {:.0} ms {} the video", offset.abs() * 1000.0, relation(offset)));
+        }
+    }
+    problems
+}
+
+/// Warn when audio and video of `output` are out of step, as some sources come out of a re-encode
+/// with their audio offset; a player shows such a file without complaint, so it is never an error
+fn check_sync(transcoder: &dyn Transcoder, output: &Path, max_drift_ms: u32) {
+    let probe = match transcoder.probe(output) {
+        Ok(probe) => probe,
+        Err(e) => {
+            debug!(error = %e, "cannot check audio/video sync");
+            return;
+        }
+    };
+    let problems = sync_problems(&probe, f64::from(max_drift_ms) / 1000.0);
+    match problems.is_empty() {
+        true => debug!(output = %output.display(), "audio and video in sync"),
+        false => console!(Warning, "{} may be out of sync: {}", output.display(), problems.join(", ")),
+    }
+}
+
+/// Catch encodes that crashed or were cut short: the output must be readable, carry the source's
+/// kinds of streams and last as long as the source; audio and video drifting apart only warns
+pub fn check_encode(
+    transcoder: &dyn Transcoder,
+    source: &Path,
+    output: &Path,
+    encode: &EncodeOptions,
+) -> Result<(), VideoConversionError> {
+    let mode = encode.verify;
+    if mode == VerifyMode::Off {
+        return Ok(());
+    }
+    check_sync(transcoder, output, encode.max_av_drift);
+    let problems = match problems(transcoder, source, output) {
+        Ok(problems) => problems,
+        Err(e) => {
+            console!(Warning, "could not verify {}: {}", output.display(), e);
+            return Ok(());
+        }
+    };
+    if problems.is_empty() {
+        info!(output = %output.display(), "output verified");
+        return Ok(());
+    }
+    let message = format!("{} failed verification: {}", output.display(), problems.join(", "));
+    match mode {
+        VerifyMode::Fail => Err(VideoConversionError::encoding(message)),
+        _ => {
+            console!(Warning, "{}", message);
+            Ok(())
+        }
+    }
+}
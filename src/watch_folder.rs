@@ -0,0 +1,112 @@
+use std::fs::{create_dir_all, rename};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use tracing::{error, info, info_span};
+
+use crate::backend::Ffmpeg;
+use crate::codecs::Container;
+use crate::convert_dir::VIDEO_EXTENSIONS;
+use crate::pipeline::{Encode, Pipeline};
+use crate::progress::{Progress, ProgressEvent};
+use crate::{console, folder, shutdown, EncodeOptions, VideoConversionError};
+
+/// Subfolder of the drop folder receiving originals once converted
+const ORIGINALS_DIR: &str = "originals";
+
+/// Subfolder of the drop folder receiving files that failed to convert, so they are not retried
+const FAILED_DIR: &str = "failed";
+
+/// Where `watch-folder` looks for videos and where it puts them
+#[derive(clap::Args, Debug, Clone)]
+pub struct WatchFolderOptions {
+    /// Folder to watch; videos dropped in it are converted, then moved to its `originals`
+    /// subfolder, or to `failed` if they cannot be converted
+    dir: String,
+
+    /// Directory receiving the converted MP4 files
+    #[arg(short, long, default_value = "Processed")]
+    output_dir: String,
+
+    /// How often to scan the folder on systems without inotify, in milliseconds
+    #[arg(long, default_value_t = 2000)]
+    interval_ms: u64,
+
+    #[command(flatten)]
+    encode: EncodeOptions,
+}
+
+/// Move `path` into `subdir` of its folder, keeping its name
+fn move_into(path: &Path, subdir: &str) -> Result<PathBuf, VideoConversionError> {
+    let dir = path.parent().unwrap_or(Path::new(".")).join(subdir);
+    create_dir_all(&dir).map_err(|e| VideoConversionError::io(format!("Failed to create {}", dir.display())).caused_by(e))?;
+    let target = dir.join(path.file_name().unwrap_or_default());
+    rename(path, &target)
+        .map_err(|e| VideoConversionError::io(format!("Failed to move {} to {}", path.display(), dir.display())).caused_by(e))?;
+    Ok(target)
+}
+
+/// Encode `source` into the output directory under a temporary name, renamed once complete so
+/// nothing watching the output directory picks up a partial file
+fn convert(options: &WatchFolderOptions, source: &Path, progress: &Progress) -> Result<PathBuf, VideoConversionError> {
+    let stem = source.file_stem().unwrap_or_default().to_string_lossy();
+    let output = Path::new(&options.output_dir).join(format!("{}.{}", stem, Container::Mp4.extension()));
+    let partial = Path::new(&options.output_dir).join(format!(".{}.partial.{}", stem, Container::Mp4.extension()));
+    if output.exists() {
+        return Err(VideoConversionError::FileConflict(output.display().to_string()));
+    }
+    Pipeline::new()
+        .then(Encode { output: &partial.to_string_lossy(), encode: &options.encode, transcoder: &Ffmpeg })
+        .run_on(source, progress)?;
+    rename(&partial, &output).map_err(|e| VideoConversionError::io(format!("Failed to rename {}", partial.display())).caused_by(e))?;
+    Ok(output)
+}
+
+/// Convert every video dropped into `options.dir` until interrupted
+pub fn run(options: &WatchFolderOptions, progress: &Progress) -> Result<(), VideoConversionError> {
+    let dir = Path::new(&options.dir);
+    if !dir.is_dir() {
+        return Err(VideoConversionError::FileNotFound(options.dir.clone()));
+    }
+    Container::Mp4.check(options.encode.audio_codec(Container::Mp4), options.encode.audio_bitrate)?;
+    create_dir_all(&options.output_dir).map_err(|e| VideoConversionError::io(e.to_string()))?;
+    // Converted files would be picked up again
+    if Path::new(&options.output_dir).canonicalize().ok() == dir.canonicalize().ok() {
+        return Err(VideoConversionError::config("the output directory must differ from the watched folder"));
+    }
+    let _span = info_span!("watch_folder", dir = %dir.display(), output = %options.output_dir).entered();
+    let dropped = folder::watch(dir, Duration::from_millis(options.interval_ms))?;
+    println!("Watching {} for videos (Ctrl-C to stop)...", dir.display());
+
+    for source in dropped.iter() {
+        let is_video = source
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| VIDEO_EXTENSIONS.iter().any(|known| known.eq_ignore_ascii_case(ext)));
+        // Files are reported again when the watch loses track; those already moved are gone
+        if !is_video || !source.is_file() {
+            continue;
+        }
+        let name = source.file_name().unwrap_or_default().to_string_lossy().into_owned();
+        console!(Download, "Converting {}", name);
+        match convert(options, &source, progress) {
+            Ok(output) => {
+                let original = move_into(&source, ORIGINALS_DIR)?;
+                info!(output = %output.display(), original = %original.display(), "converted");
+                console!(Success, "Converted {} to {}", name, output.display());
+                progress.emit(ProgressEvent::Finished { output: output.display().to_string() });
+            }
+            Err(VideoConversionError::Interrupted) => break,
+            Err(e) => {
+                error!(path = %source.display(), error = %e, "conversion failed");
+                console!(Error, "{}: {}", name, e);
+                progress.emit(ProgressEvent::Failed { error: e.to_string(), exit_code: e.exit_code() });
+                let failed = move_into(&source, FAILED_DIR)?;
+                println!("Moved {} to {}", name, failed.display());
+            }
+        }
+    }
+
+    // The watch only ends on shutdown
+    shutdown::check()
+}
@@ -0,0 +1,284 @@
+use std::collections::HashMap;
+
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use tracing::{debug, info, info_span};
+use url::Url;
+
+use crate::dates::Date;
+use crate::playlist::{self, PlaylistEntry, PlaylistSelection};
+use crate::timestamp::MediaTimestamp;
+use crate::VideoConversionError;
+
+/// Endpoint of the YouTube Data API v3
+const API_BASE: &str = "https://www.googleapis.com/youtube/v3";
+
+/// Most results the API returns per page or request
+const PAGE_SIZE: usize = 50;
+
+#[derive(Deserialize)]
+struct ApiError {
+    error: ApiErrorBody,
+}
+
+#[derive(Deserialize)]
+struct ApiErrorBody {
+    message: String,
+}
+
+#[derive(Deserialize)]
+struct ChannelList {
+    #[serde(default)]
+    items: Vec<Channel>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Channel {
+    content_details: ChannelDetails,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ChannelDetails {
+    related_playlists: RelatedPlaylists,
+}
+
+#[derive(Deserialize)]
+struct RelatedPlaylists {
+    uploads: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PlaylistItemList {
+    #[serde(default)]
+    items: Vec<PlaylistItem>,
+    next_page_token: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PlaylistItem {
+    snippet: PlaylistItemSnippet,
+    content_details: PlaylistItemDetails,
+}
+
+#[derive(Deserialize)]
+struct PlaylistItemSnippet {
+    title: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PlaylistItemDetails {
+    video_id: String,
+    /// Missing for private and deleted videos
+    video_published_at: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct VideoList {
+    #[serde(default)]
+    items: Vec<Video>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Video {
+    id: String,
+    content_details: VideoDetails,
+}
+
+#[derive(Deserialize)]
+struct VideoDetails {
+    /// ISO 8601 duration such as `PT1H2M3S`
+    duration: Option<String>,
+}
+
+/// A listed video with the metadata the API includes
+struct ApiEntry {
+    index: usize,
+    id: String,
+    title: Option<String>,
+    published: Option<Date>,
+}
+
+/// GET `endpoint` with `params`, turning API errors such as an invalid key or exhausted quota into messages
+fn get<T: DeserializeOwned>(
+    client: &reqwest::blocking::Client,
+    endpoint: &str,
+    params: &[(&str, &str)],
+    key: &str,
+) -> Result<T, VideoConversionError> {
+    let failed = |reason: String| VideoConversionError::extraction(format!("YouTube Data API {}", endpoint)).caused_by(reason);
+    let unreachable = |e: reqwest::Error| VideoConversionError::network(format!("YouTube Data API {}", endpoint)).caused_by(e);
+    let response = client
+        .get(format!("{}/{}", API_BASE, endpoint))
+        .query(params)
+        .query(&[("key", key)])
+        .send()
+        .map_err(unreachable)?;
+    let status = response.status();
+    let body = response.text().map_err(unreachable)?;
+    if !status.is_success() {
+        let message = serde_json::from_str::<ApiError>(&body).map_or_else(|_| status.to_string(), |e| e.error.message);
+        return Err(failed(message));
+    }
+    serde_json::from_str(&body).map_err(|e| failed(format!("unexpected response: {}", e)))
+}
+
+/// Parse an ISO 8601 duration as the API reports them, e.g. `PT1H2M3S` or `P1DT2H`
+fn parse_duration(text: &str) -> Option<f64> {
+    let rest = text.strip_prefix('P')?;
+    let (days, time) = match rest.split_once('T') {
+        Some((days, time)) => (days, time),
+        None => (rest, ""),
+    };
+    let mut seconds = match days {
+        "" => 0.0,
+        days => days.strip_suffix('D')?.parse::<f64>().ok()? * 86_400.0,
+    };
+    let mut number = String::new();
+    for c in time.chars() {
+        match c {
+            'H' | 'M' | 'S' => {
+                let value: f64 = number.parse().ok()?;
+                number.clear();
+                seconds += value * match c {
+                    'H' => 3600.0,
+                    'M' => 60.0,
+                    _ => 1.0,
+                };
+            }
+            c => number.push(c),
+        }
+    }
+    number.is_empty().then_some(seconds)
+}
+
+/// The playlist holding the videos of a YouTube playlist or channel URL; `None` for pages the API
+/// cannot resolve (custom `/c/` names) or that list something else than uploads (shorts, live tabs)
+fn resolve_playlist(
+    client: &reqwest::blocking::Client,
+    url: &str,
+    playlist_id: Option<&str>,
+    key: &str,
+) -> Result<Option<String>, VideoConversionError> {
+    if let Some(id) = playlist_id {
+        return Ok(Some(id.to_string()));
+    }
+    let Ok(url) = Url::parse(url) else { return Ok(None) };
+    let segments: Vec<&str> = url.path_segments().map(|s| s.filter(|s| !s.is_empty()).collect()).unwrap_or_default();
+    let (filter, rest) = match segments.as_slice() {
+        ["channel", id, rest @ ..] => (("id", id.to_string()), rest),
+        ["user", name, rest @ ..] => (("forUsername", name.to_string()), rest),
+        [handle, rest @ ..] if handle.starts_with('@') => (("forHandle", handle.to_string()), rest),
+        _ => return Ok(None),
+    };
+    if !matches!(rest, [] | ["videos"] | ["featured"]) {
+        return Ok(None);
+    }
+    let channels: ChannelList = get(client, "channels", &[("part", "contentDetails"), (filter.0, &filter.1)], key)?;
+    match channels.items.into_iter().next() {
+        Some(channel) => Ok(Some(channel.content_details.related_playlists.uploads)),
+        None => Err(VideoConversionError::extraction(format!("YouTube Data API found no channel for {}", url))),
+    }
+}
+
+/// Durations of `ids` in seconds, fetched in batches
+fn durations(client: &reqwest::blocking::Client, ids: &[&str], key: &str) -> Result<HashMap<String, f64>, VideoConversionError> {
+    let mut durations = HashMap::new();
+    for batch in ids.chunks(PAGE_SIZE) {
+        let videos: VideoList = get(client, "videos", &[("part", "contentDetails"), ("id", &batch.join(","))], key)?;
+        durations.extend(
+            videos
+                .items
+                .into_iter()
+                .filter_map(|video| Some((video.id, parse_duration(&video.content_details.duration?)?))),
+        );
+    }
+    Ok(durations)
+}
+
+/// List a YouTube playlist or channel through the Data API, applying the selection like yt-dlp
+/// would; `None` when the URL is not one the API can list
+pub(crate) fn list_entries(
+    url: &str,
+    playlist_id: Option<&str>,
+    selection: &PlaylistSelection,
+    key: &str,
+) -> Result<Option<Vec<PlaylistEntry>>, VideoConversionError> {
+    let _span = info_span!("youtube_api", url).entered();
+    let client = reqwest::blocking::Client::new();
+    let Some(playlist) = resolve_playlist(&client, url, playlist_id, key)? else {
+        debug!("URL not supported by the YouTube Data API");
+        return Ok(None);
+    };
+
+    let filter = &selection.filter;
+    let mut entries = Vec::new();
+    let mut page_token: Option<String> = None;
+    'pages: loop {
+        let page_size = PAGE_SIZE.to_string();
+        let mut params = vec![("part", "snippet,contentDetails"), ("playlistId", playlist.as_str()), ("maxResults", &page_size)];
+        if let Some(token) = &page_token {
+            params.push(("pageToken", token));
+        }
+        let page: PlaylistItemList = get(&client, "playlistItems", &params, key)?;
+        for item in page.items {
+            let index = entries.len() + 1;
+            let published = item.content_details.video_published_at.as_deref().and_then(|time| time.get(..10));
+            // Private and deleted videos stay listed without a publication time
+            let Some(published) = published else {
+                entries.push(None);
+                continue;
+            };
+            let published = Date::parse(published).ok();
+            // Uploads are listed newest first, so nothing later passes --since either
+            if let (Some(date), Some(since), true) = (published, filter.since, selection.newest_first) {
+                if date < since {
+                    break 'pages;
+                }
+            }
+            entries.push(Some(ApiEntry { index, id: item.content_details.video_id, title: item.snippet.title, published }));
+        }
+        match page.next_page_token {
+            Some(token) => page_token = Some(token),
+            None => break,
+        }
+    }
+
+    let mut entries: Vec<ApiEntry> = entries
+        .into_iter()
+        .flatten()
+        .filter(|entry| selection.items.as_deref().is_none_or(|spec| playlist::item_selected(spec, entry.index)))
+        .filter(|entry| match entry.published {
+            Some(date) => filter.since.is_none_or(|since| date >= since) && filter.before.is_none_or(|before| date < before),
+            None => true,
+        })
+        .collect();
+    if filter.min_duration.is_some() || filter.max_duration.is_some() {
+        let ids: Vec<&str> = entries.iter().map(|entry| entry.id.as_str()).collect();
+        let durations = durations(&client, &ids, key)?;
+        // Videos of unknown length are checked again before downloading, like yt-dlp's `?` filters
+        entries.retain(|entry| {
+            let Some(duration) = durations.get(&entry.id).and_then(|d| MediaTimestamp::from_secs_f64(*d)) else { return true };
+            filter.min_duration.is_none_or(|min| duration >= min) && filter.max_duration.is_none_or(|max| duration <= max)
+        });
+    }
+    if selection.reverse {
+        entries.reverse();
+    }
+    info!(entries = entries.len(), "listed through the YouTube Data API");
+    Ok(Some(
+        entries
+            .into_iter()
+            .map(|entry| PlaylistEntry {
+                index: entry.index,
+                url: format!("https://www.youtube.com/watch?v={}", entry.id),
+                title: entry.title,
+            })
+            .collect(),
+    ))
+}
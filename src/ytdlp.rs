@@ -0,0 +1,165 @@
+use std::net::IpAddr;
+use std::process::{Command, Stdio};
+use std::sync::OnceLock;
+
+/// How yt-dlp fakes the `X-Forwarded-For` header to get around geo restrictions
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GeoBypass {
+    /// Only when the extractor knows it helps (yt-dlp's default)
+    Default,
+    /// Never fake the header
+    Never,
+    /// Pretend to be in a country, given as a two-letter ISO 3166-2 code
+    Country(String),
+    /// Pretend to come from an address in this CIDR block
+    IpBlock(String),
+}
+
+impl std::str::FromStr for GeoBypass {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "default" => return Ok(GeoBypass::Default),
+            "never" => return Ok(GeoBypass::Never),
+            _ => {}
+        }
+        if s.len() == 2 && s.chars().all(|c| c.is_ascii_alphabetic()) {
+            return Ok(GeoBypass::Country(s.to_ascii_uppercase()));
+        }
+        match s.split_once('/') {
+            Some((ip, prefix)) if ip.parse::<IpAddr>().is_ok() && prefix.parse::<u8>().is_ok() => {
+                Ok(GeoBypass::IpBlock(s.to_string()))
+            }
+            _ => Err(format!(
+                "invalid geo bypass {:?} (use default, never, a country code such as DE, or a CIDR block)",
+                s
+            )),
+        }
+    }
+}
+
+impl GeoBypass {
+    /// Value of yt-dlp's `--xff` option
+    fn xff_value(&self) -> &str {
+        match self {
+            GeoBypass::Default => "default",
+            GeoBypass::Never => "never",
+            GeoBypass::Country(code) => code,
+            GeoBypass::IpBlock(block) => block,
+        }
+    }
+}
+
+/// Connection settings and extra arguments applied to every yt-dlp invocation
+#[derive(clap::Args, Debug, Clone)]
+pub struct YtDlpOptions {
+    /// Geo restriction bypass: default, never, a country code (e.g. DE) or a CIDR block
+    #[arg(long, value_name = "MODE")]
+    pub geo_bypass: Option<GeoBypass>,
+
+    /// Local IP address to bind outgoing connections to
+    #[arg(long, value_name = "IP")]
+    pub source_address: Option<IpAddr>,
+
+    /// Make all connections over IPv4
+    #[arg(long, conflicts_with = "force_ipv6")]
+    pub force_ipv4: bool,
+
+    /// Make all connections over IPv6
+    #[arg(long)]
+    pub force_ipv6: bool,
+
+    /// Download with aria2c over multiple connections when it is installed (falls back to yt-dlp's downloader)
+    #[arg(long)]
+    pub aria2c: bool,
+
+    /// Connections per download when using aria2c
+    #[arg(long, value_name = "N", default_value_t = 16, value_parser = clap::value_parser!(u8).range(1..=16))]
+    pub aria2c_connections: u8,
+
+    /// Extra yt-dlp arguments appended to every invocation, split like a shell would (repeatable)
+    #[arg(long, value_name = "ARGS", allow_hyphen_values = true, value_parser = split_args)]
+    pub ytdlp_arg: Vec<Vec<String>>,
+}
+
+impl YtDlpOptions {
+    /// A yt-dlp command with these settings applied
+    pub fn command(&self) -> Command {
+        let mut command = Command::new("yt-dlp");
+        if let Some(geo_bypass) = &self.geo_bypass {
+            command.arg("--xff").arg(geo_bypass.xff_value());
+        }
+        if let Some(address) = self.source_address {
+            command.arg("--source-address").arg(address.to_string());
+        }
+        if self.force_ipv4 {
+            command.arg("--force-ipv4");
+        }
+        if self.force_ipv6 {
+            command.arg("--force-ipv6");
+        }
+        if self.aria2c && aria2c_available() {
+            let connections = self.aria2c_connections;
+            command
+                .arg("--downloader")
+                .arg("aria2c")
+                .arg("--downloader-args")
+                .arg(format!("aria2c:-x {0} -s {0} -k 1M --summary-interval=0", connections));
+        }
+        command
+    }
+
+    /// User-supplied arguments, placed after the generated ones so they take precedence
+    pub fn extra_args(&self) -> impl Iterator<Item = &String> {
+        self.ytdlp_arg.iter().flatten()
+    }
+}
+
+/// Whether aria2c can be run, checked once per process
+fn aria2c_available() -> bool {
+    static AVAILABLE: OnceLock<bool> = OnceLock::new();
+    *AVAILABLE.get_or_init(|| {
+        Command::new("aria2c")
+            .arg("--version")
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .is_ok_and(|status| status.success())
+    })
+}
+
+/// Split an argument string on whitespace, honoring single and double quotes and backslash escapes
+pub fn split_args(s: &str) -> Result<Vec<String>, String> {
+    let mut args = Vec::new();
+    let mut current: Option<String> = None;
+    let mut quote = None;
+    let mut chars = s.chars();
+
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some('"') | None, '\\') => {
+                let escaped = chars.next().ok_or_else(|| format!("trailing backslash in {:?}", s))?;
+                current.get_or_insert_with(String::new).push(escaped);
+            }
+            (Some(_), c) => current.get_or_insert_with(String::new).push(c),
+            (None, '\'' | '"') => {
+                quote = Some(c);
+                current.get_or_insert_with(String::new);
+            }
+            (None, c) if c.is_whitespace() => args.extend(current.take()),
+            (None, c) => current.get_or_insert_with(String::new).push(c),
+        }
+    }
+
+    if quote.is_some() {
+        return Err(format!("unterminated quote in {:?}", s));
+    }
+    args.extend(current);
+    if args.is_empty() {
+        return Err("argument must not be empty".to_string());
+    }
+    Ok(args)
+}
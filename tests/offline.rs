@@ -0,0 +1,47 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+use videelow::backend::mock::{MockDownloader, MockTranscoder};
+use videelow::backend::Backends;
+use videelow::progress::Progress;
+use videelow::{download_all_with, DownloadOptions};
+
+#[derive(Parser)]
+struct Cli {
+    #[command(flatten)]
+    options: DownloadOptions,
+}
+
+/// Fresh directory for this test's state and output
+fn scratch_dir() -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("videelow-offline-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(dir.join("bin")).unwrap();
+    dir
+}
+
+#[test]
+fn mock_backends_download_without_tools_or_network() {
+    let dir = scratch_dir();
+    // No yt-dlp, ffmpeg or ffprobe to fall back on, and job records kept out of the user's state
+    std::env::set_var("PATH", dir.join("bin"));
+    std::env::set_var("XDG_STATE_HOME", dir.join("state"));
+    std::env::set_var("VIDEELOW_CONFIG", dir.join("config.json"));
+
+    let output_dir = dir.join("out");
+    let downloader = MockDownloader::new();
+    let transcoder = MockTranscoder::new();
+    let backends = Backends { downloader: &downloader, transcoder: &transcoder };
+    let cli = Cli::parse_from(["videelow", "--output-dir", output_dir.to_str().unwrap()]);
+    let urls = ["https://www.youtube.com/watch?v=dQw4w9WgXcQ".to_string()];
+    download_all_with(&urls, &cli.options, &Progress::default(), backends).unwrap();
+
+    assert_eq!(downloader.requests(), urls);
+    let outputs: Vec<String> = std::fs::read_dir(&output_dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .collect();
+    assert!(outputs.iter().any(|name| name.ends_with("_complete.mp4")), "no _complete output in {:?}", outputs);
+
+    let _ = std::fs::remove_dir_all(&dir);
+}